@@ -0,0 +1,87 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use dsa_rs::arena::Arena;
+use std::cell::Cell;
+
+const NODES: u64 = 10_000;
+
+fn keys() -> impl Iterator<Item = u64> {
+    // A scrambled but deterministic insertion order keeps the trees shallow.
+    (0..NODES).map(|i| i.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 40)
+}
+
+struct BoxNode {
+    key: u64,
+    left: Option<Box<BoxNode>>,
+    right: Option<Box<BoxNode>>,
+}
+
+fn box_insert(mut slot: &mut Option<Box<BoxNode>>, key: u64) {
+    while let Some(node) = slot {
+        slot = if key < node.key {
+            &mut node.left
+        } else {
+            &mut node.right
+        };
+    }
+    *slot = Some(Box::new(BoxNode {
+        key,
+        left: None,
+        right: None,
+    }));
+}
+
+struct ArenaNode<'a> {
+    key: u64,
+    left: Cell<Option<&'a ArenaNode<'a>>>,
+    right: Cell<Option<&'a ArenaNode<'a>>>,
+}
+
+fn arena_insert<'a>(arena: &'a Arena<ArenaNode<'a>>, root: &'a ArenaNode<'a>, key: u64) {
+    let mut cur = root;
+    loop {
+        let child = if key < cur.key { &cur.left } else { &cur.right };
+        match child.get() {
+            Some(next) => cur = next,
+            None => {
+                child.set(Some(arena.alloc(ArenaNode {
+                    key,
+                    left: Cell::new(None),
+                    right: Cell::new(None),
+                })));
+                return;
+            }
+        }
+    }
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tree_build_and_drop");
+
+    group.bench_function("box", |b| {
+        b.iter(|| {
+            let mut root = None;
+            for key in keys() {
+                box_insert(&mut root, key);
+            }
+            black_box(&root);
+        })
+    });
+    group.bench_function("arena", |b| {
+        b.iter(|| {
+            let arena = Arena::new();
+            let root: &ArenaNode = arena.alloc(ArenaNode {
+                key: u64::MAX / 2,
+                left: Cell::new(None),
+                right: Cell::new(None),
+            });
+            for key in keys() {
+                arena_insert(&arena, root, key);
+            }
+            black_box(root);
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,74 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use dsa_rs::cache::{replay, Access, Cache, ConcurrentCache, ReplayStats};
+use dsa_rs::clock_cache::ClockCache;
+use dsa_rs::lru_k::LruKCache;
+use dsa_rs::s3fifo::S3FifoCache;
+use dsa_rs::{concurrent_lru, lru};
+use rand::prelude::*;
+
+const KEYS: usize = 10_000;
+const CAPACITY: usize = 500;
+const ACCESSES: usize = 100_000;
+
+/// Draws `len` keys from a Zipf(`s`) distribution over `n` keys by inverting
+/// the cumulative weights.
+fn zipf_trace(n: usize, s: f64, len: usize) -> Vec<Access> {
+    let mut cumulative = Vec::with_capacity(n);
+    let mut total = 0.0;
+    for rank in 1..=n {
+        total += 1.0 / (rank as f64).powf(s);
+        cumulative.push(total);
+    }
+    let mut rng = StdRng::seed_from_u64(214);
+    (0..len)
+        .map(|_| {
+            let x = rng.gen::<f64>() * total;
+            let key = cumulative.partition_point(|&c| c < x);
+            Access::new(&key.to_le_bytes())
+        })
+        .collect()
+}
+
+fn replay_concurrent<C: ConcurrentCache<[u8], u64>>(trace: &[Access], cache: &C) -> ReplayStats {
+    let mut stats = ReplayStats::default();
+    for access in trace {
+        if cache.get(&access.key).is_some() {
+            stats.hits += 1;
+        } else {
+            stats.misses += 1;
+            cache.insert(&access.key, 0);
+        }
+    }
+    stats
+}
+
+fn bench_policy<C: Cache<[u8], u64>>(
+    c: &mut Criterion,
+    name: &str,
+    trace: &[Access],
+    new: impl Fn() -> C,
+) {
+    let stats = replay(trace.iter().cloned(), &mut new());
+    println!("{name}: hit ratio {:.4}", stats.hit_ratio());
+    c.bench_function(name, |b| {
+        b.iter(|| replay(trace.iter().cloned(), &mut new()))
+    });
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let trace = zipf_trace(KEYS, 1.0, ACCESSES);
+
+    bench_policy(c, "zipf/lru", &trace, || lru::LRUCache::new(CAPACITY));
+    bench_policy(c, "zipf/clock", &trace, || ClockCache::new(CAPACITY));
+    bench_policy(c, "zipf/lru2", &trace, || LruKCache::new(CAPACITY, 2));
+    bench_policy(c, "zipf/s3fifo", &trace, || S3FifoCache::new(CAPACITY));
+
+    let stats = replay_concurrent(&trace, &concurrent_lru::LRUCache::new(CAPACITY));
+    println!("zipf/concurrent_lru: hit ratio {:.4}", stats.hit_ratio());
+    c.bench_function("zipf/concurrent_lru", |b| {
+        b.iter(|| replay_concurrent(&trace, &concurrent_lru::LRUCache::new(CAPACITY)))
+    });
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,46 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use dsa_rs::clock_cache::ClockCache;
+use dsa_rs::lru::{ByteLRUCache, LRUCache};
+use rand::prelude::*;
+
+const CAPACITY: i32 = 1024;
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("get_hit");
+
+    let mut clock: ClockCache<i32> = ClockCache::new(CAPACITY as usize);
+    // Byte keys, like the clock cache; `typed` skips the encoding.
+    let mut lru: ByteLRUCache<i32> = LRUCache::new(CAPACITY as usize);
+    let mut typed: LRUCache<i32, i32> = LRUCache::new(CAPACITY as usize);
+    for i in 0..CAPACITY {
+        clock.insert(&i.to_le_bytes(), i);
+        lru.insert(i.to_le_bytes().to_vec(), i);
+        typed.insert(i, i);
+    }
+
+    group.bench_function("clock", |b| {
+        let mut rng = rand::thread_rng();
+        b.iter(|| {
+            let i: i32 = rng.gen_range(0..CAPACITY);
+            clock.get(&i.to_le_bytes()).copied()
+        })
+    });
+    group.bench_function("lru", |b| {
+        let mut rng = rand::thread_rng();
+        b.iter(|| {
+            let i: i32 = rng.gen_range(0..CAPACITY);
+            lru.get(&i.to_le_bytes()[..]).copied()
+        })
+    });
+    group.bench_function("lru_typed_keys", |b| {
+        let mut rng = rand::thread_rng();
+        b.iter(|| {
+            let i: i32 = rng.gen_range(0..CAPACITY);
+            typed.get(&i).copied()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -1,5 +1,6 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 use dsa_rs::concurrent_lru::LRUCache;
+use dsa_rs::lru;
 use rand::prelude::*;
 
 fn criterion_benchmark(c: &mut Criterion) {
@@ -11,6 +12,23 @@ fn criterion_benchmark(c: &mut Criterion) {
             cache.insert(&i.to_le_bytes(), i);
         })
     });
+
+    // New keys into a full cache, so every insert evicts: with the spare
+    // node the evicted entry's node is reused, without it each insert
+    // allocates and each eviction frees.
+    let mut group = c.benchmark_group("lru_full_insert");
+    for (name, nodes) in [("reused_node", 1), ("no_reuse", 0)] {
+        let mut cache = lru::LRUCache::new(1_000);
+        cache.set_node_pool(nodes);
+        let mut next = 0_u64;
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                cache.insert(next, next);
+                next += 1;
+            })
+        });
+    }
+    group.finish();
 }
 
 criterion_group!(benches, criterion_benchmark);
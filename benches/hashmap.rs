@@ -0,0 +1,66 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use dsa_rs::hashmap::RobinHoodMap;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+
+const KEYS: usize = 100_000;
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(239);
+    let ints: Vec<u64> = (0..KEYS).map(|_| rng.gen()).collect();
+    let strings: Vec<String> = ints.iter().map(|k| format!("key-{k:x}")).collect();
+
+    let mut group = c.benchmark_group("hashmap_u64");
+    group.bench_function("std_insert_get", |b| {
+        b.iter(|| {
+            let mut map = HashMap::new();
+            for &k in &ints {
+                map.insert(k, k);
+            }
+            for k in &ints {
+                black_box(map.get(k));
+            }
+        })
+    });
+    group.bench_function("robin_hood_insert_get", |b| {
+        b.iter(|| {
+            let mut map = RobinHoodMap::new();
+            for &k in &ints {
+                map.insert(k, k);
+            }
+            for k in &ints {
+                black_box(map.get(k));
+            }
+        })
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("hashmap_string");
+    group.bench_function("std_insert_get", |b| {
+        b.iter(|| {
+            let mut map = HashMap::new();
+            for k in &strings {
+                map.insert(k.clone(), ());
+            }
+            for k in &strings {
+                black_box(map.get(k));
+            }
+        })
+    });
+    group.bench_function("robin_hood_insert_get", |b| {
+        b.iter(|| {
+            let mut map = RobinHoodMap::new();
+            for k in &strings {
+                map.insert(k.clone(), ());
+            }
+            for k in &strings {
+                black_box(map.get(k));
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,126 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use dsa_rs::{lru, singly_list::SinglyLinkedList, slab_list};
+use rand::prelude::*;
+
+const LEN: u32 = 10_000;
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("linked_list_build");
+    group.bench_function("pointer", |b| {
+        b.iter(|| {
+            let mut list = lru::LinkedList::new();
+            for i in 0..LEN {
+                list.insert_front(i);
+            }
+            black_box(&list);
+        })
+    });
+    group.bench_function("slab", |b| {
+        b.iter(|| {
+            let mut list = slab_list::LinkedList::new();
+            for i in 0..LEN {
+                list.insert_front(i);
+            }
+            black_box(&list);
+        })
+    });
+    group.finish();
+
+    // Insert at the front and evict from the back, as an LRU cache does.
+    let mut group = c.benchmark_group("linked_list_churn");
+    let mut pointer: lru::LinkedList<u32> = (0..LEN).collect();
+    let mut slab: slab_list::LinkedList<u32> = (0..LEN).collect();
+    group.bench_function("pointer", |b| {
+        b.iter(|| {
+            let val = pointer.remove_tail().unwrap();
+            pointer.insert_front(black_box(val));
+        })
+    });
+    // A pool of one node is enough for each eviction to feed the insert.
+    let mut pooled = lru::LinkedList::with_pool(1);
+    pooled.extend(0..LEN);
+    group.bench_function("pointer_pooled", |b| {
+        b.iter(|| {
+            let val = pooled.remove_tail().unwrap();
+            pooled.insert_front(black_box(val));
+        })
+    });
+    group.bench_function("slab", |b| {
+        b.iter(|| {
+            let val = slab.remove_tail().unwrap();
+            slab.insert_front(black_box(val));
+        })
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("linked_list_iterate");
+    group.bench_function("pointer", |b| {
+        b.iter(|| pointer.iter().map(|&v| v as u64).sum::<u64>())
+    });
+    group.bench_function("slab", |b| {
+        b.iter(|| slab.iter().map(|&v| v as u64).sum::<u64>())
+    });
+    group.finish();
+
+    // Used as a stack: push a batch at the front, then pop it all.
+    let mut group = c.benchmark_group("linked_list_push_pop_front");
+    group.bench_function("doubly", |b| {
+        let mut list = lru::LinkedList::new();
+        b.iter(|| {
+            for i in 0..1_000u32 {
+                list.insert_front(i);
+            }
+            while let Some(val) = list.pop_front() {
+                black_box(val);
+            }
+        })
+    });
+    group.bench_function("singly", |b| {
+        let mut list = SinglyLinkedList::new();
+        b.iter(|| {
+            for i in 0..1_000u32 {
+                list.push_front(i);
+            }
+            while let Some(val) = list.pop_front() {
+                black_box(val);
+            }
+        })
+    });
+    group.finish();
+
+    // Indexed probes into a long list: `get` and `nth` may walk from
+    // either end, where a plain `next` loop always starts at the front.
+    let mut group = c.benchmark_group("linked_list_get");
+    let long: lru::LinkedList<u64> = (0..100_000).collect();
+    let mut rng = StdRng::seed_from_u64(289);
+    let probes: Vec<usize> = (0..64).map(|_| rng.gen_range(0..100_000)).collect();
+    group.bench_function("get", |b| {
+        b.iter(|| probes.iter().map(|&i| long.get(i).unwrap()).sum::<u64>())
+    });
+    group.bench_function("iter_nth", |b| {
+        b.iter(|| {
+            probes
+                .iter()
+                .map(|&i| long.iter().nth(i).unwrap())
+                .sum::<u64>()
+        })
+    });
+    group.bench_function("next_loop", |b| {
+        b.iter(|| {
+            probes
+                .iter()
+                .map(|&i| {
+                    let mut iter = long.iter();
+                    (0..i).for_each(|_| {
+                        iter.next();
+                    });
+                    iter.next().unwrap()
+                })
+                .sum::<u64>()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
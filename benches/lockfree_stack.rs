@@ -0,0 +1,59 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use dsa_rs::lockfree::Stack;
+use parking_lot::Mutex;
+use std::sync::Arc;
+use std::thread;
+
+const THREADS: usize = 4;
+const OPS: usize = 10_000;
+
+fn contended(
+    push: impl Fn(usize) + Sync + Send + Clone + 'static,
+    pop: impl Fn() + Sync + Send + Clone + 'static,
+) {
+    let handles: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let (push, pop) = (push.clone(), pop.clone());
+            thread::spawn(move || {
+                for i in 0..OPS {
+                    push(i);
+                    pop();
+                }
+            })
+        })
+        .collect();
+    handles.into_iter().for_each(|h| h.join().unwrap());
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("stack_push_pop");
+
+    group.bench_function("treiber", |b| {
+        b.iter(|| {
+            let stack = Arc::new(Stack::new());
+            let s = stack.clone();
+            contended(
+                move |i| s.push(i),
+                move || {
+                    stack.pop();
+                },
+            );
+        })
+    });
+    group.bench_function("mutex_vec", |b| {
+        b.iter(|| {
+            let stack = Arc::new(Mutex::new(Vec::new()));
+            let s = stack.clone();
+            contended(
+                move |i| s.lock().push(i),
+                move || {
+                    stack.lock().pop();
+                },
+            );
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
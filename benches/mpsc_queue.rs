@@ -0,0 +1,56 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use dsa_rs::mpsc_queue::MpscQueue;
+use std::sync::mpsc;
+use std::thread;
+
+const PRODUCERS: usize = 4;
+const PER_PRODUCER: usize = 10_000;
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("mpsc_fan_in");
+
+    group.bench_function("vyukov", |b| {
+        b.iter(|| {
+            let queue = MpscQueue::new();
+            let handles: Vec<_> = (0..PRODUCERS)
+                .map(|_| {
+                    let producer = queue.producer();
+                    thread::spawn(move || {
+                        for i in 0..PER_PRODUCER {
+                            producer.push(i);
+                        }
+                    })
+                })
+                .collect();
+            let mut received = 0;
+            while received < PRODUCERS * PER_PRODUCER {
+                if queue.pop().is_some() {
+                    received += 1;
+                }
+            }
+            handles.into_iter().for_each(|h| h.join().unwrap());
+        })
+    });
+    group.bench_function("std_mpsc", |b| {
+        b.iter(|| {
+            let (tx, rx) = mpsc::channel();
+            let handles: Vec<_> = (0..PRODUCERS)
+                .map(|_| {
+                    let tx = tx.clone();
+                    thread::spawn(move || {
+                        for i in 0..PER_PRODUCER {
+                            tx.send(i).unwrap();
+                        }
+                    })
+                })
+                .collect();
+            drop(tx);
+            assert_eq!(rx.iter().count(), PRODUCERS * PER_PRODUCER);
+            handles.into_iter().for_each(|h| h.join().unwrap());
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,31 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use dsa_rs::persistent::Vector;
+
+const LEN: usize = 100_000;
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("persistent_vector_build");
+
+    group.bench_function("push", |b| {
+        b.iter(|| {
+            let mut vector = Vector::new();
+            for i in 0..LEN {
+                vector = vector.push(i);
+            }
+            black_box(vector);
+        })
+    });
+    group.bench_function("builder", |b| {
+        b.iter(|| {
+            let mut builder = Vector::builder();
+            for i in 0..LEN {
+                builder.push(i);
+            }
+            black_box(builder.build());
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,33 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use dsa_rs::search::{interpolation_search, lower_bound};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const KEYS: usize = 1_000_000;
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(233);
+    let mut keys: Vec<u64> = (0..KEYS).map(|_| rng.gen()).collect();
+    keys.sort();
+    let probes: Vec<u64> = (0..1_000).map(|_| rng.gen()).collect();
+
+    let mut group = c.benchmark_group("search_uniform_u64");
+    group.bench_function("binary", |b| {
+        b.iter(|| {
+            for x in &probes {
+                black_box(lower_bound(&keys, x));
+            }
+        })
+    });
+    group.bench_function("interpolation", |b| {
+        b.iter(|| {
+            for &x in &probes {
+                black_box(interpolation_search(&keys, x));
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,51 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use dsa_rs::sort::{heap_sort, merge_sort, quick_sort, radix_sort_u64};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const LEN: u64 = 10_000;
+
+type Sort = fn(&mut [u64]);
+
+fn inputs() -> Vec<(&'static str, Vec<u64>)> {
+    let mut rng = StdRng::seed_from_u64(232);
+    vec![
+        ("random", (0..LEN).map(|_| rng.gen()).collect()),
+        ("sorted", (0..LEN).collect()),
+        ("reversed", (0..LEN).rev().collect()),
+        (
+            "few_unique",
+            (0..LEN).map(|_| rng.gen_range(0..8)).collect(),
+        ),
+    ]
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let sorts: [(&str, Sort); 6] = [
+        ("merge_sort", merge_sort),
+        ("quick_sort", quick_sort),
+        ("heap_sort", heap_sort),
+        ("radix_sort", radix_sort_u64),
+        ("std_stable", |v| v.sort()),
+        ("std_unstable", |v| v.sort_unstable()),
+    ];
+    for (input_name, input) in inputs() {
+        let mut group = c.benchmark_group(format!("sort_{input_name}"));
+        for (name, sort) in sorts {
+            group.bench_function(name, |b| {
+                b.iter_batched_ref(
+                    || input.clone(),
+                    |v| {
+                        sort(v);
+                        black_box(v);
+                    },
+                    BatchSize::SmallInput,
+                )
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
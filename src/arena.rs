@@ -0,0 +1,260 @@
+use std::cell::RefCell;
+use std::mem;
+
+const INITIAL_CHUNK_BYTES: usize = 1024;
+
+struct ChunkList<T> {
+    /// Full chunks, oldest first. Declared before `current` so values drop in
+    /// allocation order.
+    rest: Vec<Vec<T>>,
+    current: Vec<T>,
+}
+
+impl<T> ChunkList<T> {
+    /// Retires `current` and starts a chunk with room for at least
+    /// `additional` more values, doubling the chunk size each time.
+    fn grow(&mut self, additional: usize) {
+        let capacity = (self.current.capacity() * 2).max(additional);
+        let full = mem::replace(&mut self.current, Vec::with_capacity(capacity));
+        self.rest.push(full);
+    }
+}
+
+/// A typed arena: values are moved into large chunks and live until the
+/// arena is dropped, at which point they all drop together in allocation
+/// order.
+///
+/// `alloc` takes `&self` and still hands out `&mut T`. That is sound because
+/// a chunk is never pushed to beyond its capacity, so its buffer never moves
+/// and every reference stays unique and valid; a full chunk is retired and a
+/// new one, twice as large, started. The tests run clean under
+/// `cargo +nightly miri test --lib arena`.
+pub struct Arena<T> {
+    chunks: RefCell<ChunkList<T>>,
+}
+
+impl<T> Arena<T> {
+    pub fn new() -> Self {
+        let size = mem::size_of::<T>().max(1);
+        Self::with_capacity((INITIAL_CHUNK_BYTES / size).max(1))
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            chunks: RefCell::new(ChunkList {
+                rest: Vec::new(),
+                current: Vec::with_capacity(capacity.max(1)),
+            }),
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc(&self, value: T) -> &mut T {
+        let mut chunks = self.chunks.borrow_mut();
+        if chunks.current.len() == chunks.current.capacity() {
+            chunks.grow(1);
+        }
+        let len = chunks.current.len();
+        chunks.current.push(value);
+        // The push stayed within capacity, so the slot is never moved or
+        // handed out again while the arena lives.
+        unsafe { &mut *chunks.current.as_mut_ptr().add(len) }
+    }
+
+    /// Allocates every value of `iter` contiguously and returns them as a
+    /// slice. The iterator is drained before the arena is touched, so it may
+    /// itself allocate from this arena.
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_extend(&self, iter: impl IntoIterator<Item = T>) -> &mut [T] {
+        let values: Vec<T> = iter.into_iter().collect();
+        let mut chunks = self.chunks.borrow_mut();
+        if chunks.current.capacity() - chunks.current.len() < values.len() {
+            chunks.grow(values.len());
+        }
+        let start = chunks.current.len();
+        chunks.current.extend(values);
+        let len = chunks.current.len() - start;
+        unsafe { std::slice::from_raw_parts_mut(chunks.current.as_mut_ptr().add(start), len) }
+    }
+
+    /// Number of values allocated so far.
+    pub fn len(&self) -> usize {
+        let chunks = self.chunks.borrow();
+        chunks.rest.iter().map(Vec::len).sum::<usize>() + chunks.current.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Bytes reserved for values across all chunks, used or not.
+    pub fn allocated_bytes(&self) -> usize {
+        let chunks = self.chunks.borrow();
+        let slots =
+            chunks.rest.iter().map(Vec::capacity).sum::<usize>() + chunks.current.capacity();
+        slots * mem::size_of::<T>()
+    }
+
+    /// Consumes the arena, returning all values in allocation order.
+    pub fn into_vec(self) -> Vec<T> {
+        let ChunkList { rest, current } = self.chunks.into_inner();
+        let mut values =
+            Vec::with_capacity(rest.iter().map(Vec::len).sum::<usize>() + current.len());
+        for chunk in rest {
+            values.extend(chunk);
+        }
+        values.extend(current);
+        values
+    }
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Arena;
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    struct DropRecorder<'a>(u32, &'a RefCell<Vec<u32>>);
+
+    impl Drop for DropRecorder<'_> {
+        fn drop(&mut self) {
+            self.1.borrow_mut().push(self.0);
+        }
+    }
+
+    #[test]
+    fn test_references_survive_growth() {
+        let arena = Arena::with_capacity(1);
+        let refs: Vec<&mut u64> = (0..1000).map(|i| arena.alloc(i)).collect();
+        assert!(arena.chunks.borrow().rest.len() > 5);
+        for (i, r) in refs.into_iter().enumerate() {
+            assert_eq!(*r, i as u64);
+            *r += 1;
+        }
+        assert_eq!(arena.len(), 1000);
+        assert!(arena.into_vec().into_iter().eq(1..=1000));
+    }
+
+    #[test]
+    fn test_alloc_extend() {
+        let arena = Arena::with_capacity(4);
+        let a = arena.alloc(0);
+        let slice = arena.alloc_extend(1..10);
+        assert_eq!(slice, &[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        slice[0] = 100;
+        let empty = arena.alloc_extend(std::iter::empty());
+        assert!(empty.is_empty());
+        *a = -1;
+        assert_eq!(arena.len(), 10);
+        assert_eq!(arena.into_vec(), vec![-1, 100, 2, 3, 4, 5, 6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn test_alloc_extend_reentrant() {
+        let arena = Arena::new();
+        let slice = arena.alloc_extend((0..3).map(|i| *arena.alloc(i * 10) + 1));
+        assert_eq!(slice, &[1, 11, 21]);
+        assert_eq!(arena.len(), 6);
+    }
+
+    #[test]
+    fn test_allocated_bytes() {
+        let arena: Arena<u64> = Arena::with_capacity(8);
+        assert_eq!(arena.allocated_bytes(), 64);
+        assert!(arena.is_empty());
+        for i in 0..9 {
+            arena.alloc(i);
+        }
+        // The second chunk doubles the first.
+        assert_eq!(arena.allocated_bytes(), 64 + 128);
+    }
+
+    #[test]
+    fn test_drop_order_and_count() {
+        let log = RefCell::new(Vec::new());
+        {
+            let arena = Arena::with_capacity(2);
+            for i in 0..7 {
+                arena.alloc(DropRecorder(i, &log));
+            }
+            arena.alloc_extend((7..10).map(|i| DropRecorder(i, &log)));
+            assert!(log.borrow().is_empty());
+        }
+        assert_eq!(*log.borrow(), (0..10).collect::<Vec<_>>());
+
+        let drops = Rc::new(Cell::new(0));
+        struct Counted(Rc<Cell<usize>>);
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+        let arena = Arena::new();
+        for _ in 0..500 {
+            arena.alloc(Counted(drops.clone()));
+        }
+        drop(arena);
+        assert_eq!(drops.get(), 500);
+    }
+
+    #[test]
+    fn test_tree_in_arena() {
+        struct Node<'a> {
+            key: i32,
+            left: Cell<Option<&'a Node<'a>>>,
+            right: Cell<Option<&'a Node<'a>>>,
+        }
+
+        fn insert<'a>(arena: &'a Arena<Node<'a>>, root: &'a Node<'a>, key: i32) {
+            let mut cur = root;
+            loop {
+                let child = if key < cur.key { &cur.left } else { &cur.right };
+                match child.get() {
+                    Some(next) => cur = next,
+                    None => {
+                        child.set(Some(arena.alloc(Node {
+                            key,
+                            left: Cell::new(None),
+                            right: Cell::new(None),
+                        })));
+                        return;
+                    }
+                }
+            }
+        }
+
+        fn in_order(node: Option<&Node>, out: &mut Vec<i32>) {
+            if let Some(node) = node {
+                in_order(node.left.get(), out);
+                out.push(node.key);
+                in_order(node.right.get(), out);
+            }
+        }
+
+        let arena = Arena::with_capacity(4);
+        let root = arena.alloc(Node {
+            key: 500,
+            left: Cell::new(None),
+            right: Cell::new(None),
+        });
+        // A scrambled but deterministic key order.
+        let keys: Vec<i32> = (0..1000)
+            .map(|i| (i * 7919) % 1000)
+            .filter(|&k| k != 500)
+            .collect();
+        for &key in &keys {
+            insert(&arena, root, key);
+        }
+
+        let mut out = Vec::new();
+        in_order(Some(root), &mut out);
+        assert_eq!(out, (0..1000).collect::<Vec<_>>());
+        assert_eq!(arena.len(), 1000);
+    }
+}
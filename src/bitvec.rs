@@ -0,0 +1,110 @@
+/// A growable sequence of bits packed into `u64` words, bit `i` being bit
+/// `i % 64` of word `i / 64`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BitVec {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl BitVec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(bits: usize) -> Self {
+        Self {
+            words: Vec::with_capacity(bits.div_ceil(64)),
+            len: 0,
+        }
+    }
+
+    pub fn push(&mut self, bit: bool) {
+        if self.len.is_multiple_of(64) {
+            self.words.push(0);
+        }
+        if bit {
+            self.words[self.len / 64] |= 1 << (self.len % 64);
+        }
+        self.len += 1;
+    }
+
+    pub fn get(&self, index: usize) -> Option<bool> {
+        (index < self.len).then(|| self.words[index / 64] >> (index % 64) & 1 == 1)
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn set(&mut self, index: usize, bit: bool) {
+        assert!(index < self.len, "bit index {index} out of bounds");
+        let mask = 1 << (index % 64);
+        if bit {
+            self.words[index / 64] |= mask;
+        } else {
+            self.words[index / 64] &= !mask;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The packed words. Bits past `len` in the last word are zero.
+    pub fn words(&self) -> &[u64] {
+        &self.words
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = bool> + '_ {
+        (0..self.len).map(|i| self.words[i / 64] >> (i % 64) & 1 == 1)
+    }
+}
+
+impl FromIterator<bool> for BitVec {
+    fn from_iter<I: IntoIterator<Item = bool>>(iter: I) -> Self {
+        let mut bits = BitVec::new();
+        bits.extend(iter);
+        bits
+    }
+}
+
+impl Extend<bool> for BitVec {
+    fn extend<I: IntoIterator<Item = bool>>(&mut self, iter: I) {
+        for bit in iter {
+            self.push(bit);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BitVec;
+
+    #[test]
+    fn test_push_get_set() {
+        let pattern: Vec<bool> = (0..200).map(|i| i % 3 == 0 || i % 7 == 0).collect();
+        let mut bits: BitVec = pattern.iter().copied().collect();
+        assert_eq!(bits.len(), 200);
+        assert_eq!(bits.words().len(), 4);
+        assert!(bits.iter().eq(pattern.iter().copied()));
+        assert_eq!(bits.get(200), None);
+
+        bits.set(64, true);
+        bits.set(63, false);
+        assert_eq!(bits.get(64), Some(true));
+        assert_eq!(bits.get(63), Some(false));
+        assert_eq!(bits.get(65), Some(pattern[65]));
+    }
+
+    #[test]
+    fn test_empty() {
+        let bits = BitVec::new();
+        assert!(bits.is_empty());
+        assert_eq!(bits.get(0), None);
+        assert_eq!(bits.iter().count(), 0);
+        assert!(bits.words().is_empty());
+    }
+}
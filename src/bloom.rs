@@ -0,0 +1,139 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A Bloom filter: a set that can answer "definitely absent" or "maybe
+/// present", in a fixed number of bits.
+///
+/// Each item sets `num_hashes` bits chosen by double hashing one 64-bit
+/// hash. Bits live in atomics, so items can be added through `&self` from
+/// several threads; an item added before a `contains` call that
+/// happens-after it is always found. Items cannot be removed; [`clear`]
+/// and re-adding is the only way to shed stale ones.
+///
+/// [`clear`]: BloomFilter::clear
+pub struct BloomFilter<S = RandomState> {
+    words: Vec<AtomicU64>,
+    num_bits: u64,
+    num_hashes: u32,
+    hasher: S,
+}
+
+impl BloomFilter {
+    /// A filter expected to reach a false positive rate of `fpr` once
+    /// `expected_items` distinct items are in it.
+    pub fn with_rate(expected_items: usize, fpr: f64) -> Self {
+        Self::with_rate_and_hasher(expected_items, fpr, RandomState::new())
+    }
+}
+
+impl<S: BuildHasher> BloomFilter<S> {
+    /// # Panics
+    ///
+    /// Panics unless `0 < fpr < 1`.
+    pub fn with_rate_and_hasher(expected_items: usize, fpr: f64, hasher: S) -> Self {
+        assert!(
+            fpr > 0.0 && fpr < 1.0,
+            "false positive rate must be in (0, 1)"
+        );
+        let n = expected_items.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-n * fpr.ln() / (ln2 * ln2)).ceil() as u64;
+        let words = bits.div_ceil(64).max(1);
+        let num_bits = words * 64;
+        let num_hashes = ((num_bits as f64 / n) * ln2).round().max(1.0) as u32;
+        Self {
+            words: (0..words).map(|_| AtomicU64::new(0)).collect(),
+            num_bits,
+            num_hashes,
+            hasher,
+        }
+    }
+
+    pub fn insert<T: Hash + ?Sized>(&self, item: &T) {
+        for bit in self.bits(item) {
+            self.words[(bit / 64) as usize].fetch_or(1 << (bit % 64), Ordering::Release);
+        }
+    }
+
+    /// False only if `item` was never inserted since the last clear.
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        self.bits(item).all(|bit| {
+            self.words[(bit / 64) as usize].load(Ordering::Acquire) & (1 << (bit % 64)) != 0
+        })
+    }
+
+    pub fn clear(&mut self) {
+        for word in &mut self.words {
+            *word.get_mut() = 0;
+        }
+    }
+
+    pub fn num_bits(&self) -> u64 {
+        self.num_bits
+    }
+
+    pub fn num_hashes(&self) -> u32 {
+        self.num_hashes
+    }
+
+    /// Fraction of bits set.
+    pub fn fill_ratio(&self) -> f64 {
+        let set: u64 = self
+            .words
+            .iter()
+            .map(|w| w.load(Ordering::Relaxed).count_ones() as u64)
+            .sum();
+        set as f64 / self.num_bits as f64
+    }
+
+    /// The chance that an item never inserted is reported present, given
+    /// how full the filter is now.
+    pub fn estimated_fpr(&self) -> f64 {
+        self.fill_ratio().powi(self.num_hashes as i32)
+    }
+
+    fn bits<T: Hash + ?Sized>(&self, item: &T) -> impl Iterator<Item = u64> {
+        let hash = self.hasher.hash_one(item);
+        let (h1, h2) = (hash & 0xFFFF_FFFF, (hash >> 32) | 1);
+        let num_bits = self.num_bits;
+        (0..self.num_hashes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % num_bits)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BloomFilter;
+
+    #[test]
+    fn test_no_false_negatives() {
+        let filter = BloomFilter::with_rate(1_000, 0.01);
+        for i in 0..1_000u32 {
+            filter.insert(&i);
+        }
+        assert!((0..1_000u32).all(|i| filter.contains(&i)));
+    }
+
+    #[test]
+    fn test_false_positive_rate() {
+        let filter = BloomFilter::with_rate(10_000, 0.01);
+        for i in 0..10_000u32 {
+            filter.insert(&i);
+        }
+        let false_positives = (10_000..110_000u32).filter(|i| filter.contains(i)).count();
+        let rate = false_positives as f64 / 100_000.0;
+        assert!(rate < 0.02, "rate {rate}");
+        assert!((filter.estimated_fpr() - 0.01).abs() < 0.005);
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut filter = BloomFilter::with_rate(10, 0.01);
+        filter.insert("key");
+        assert!(filter.contains("key"));
+        assert!(filter.fill_ratio() > 0.0);
+        filter.clear();
+        assert!(!filter.contains("key"));
+        assert_eq!(filter.fill_ratio(), 0.0);
+    }
+}
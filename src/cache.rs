@@ -0,0 +1,577 @@
+use crate::bloom::BloomFilter;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Operations shared by every single-threaded eviction policy in the crate.
+///
+/// `insert` follows the convention of the concrete caches: it returns the
+/// previous value when the key was already present, otherwise whatever value
+/// was evicted to make room (if any).
+pub trait Cache<K: ?Sized, V> {
+    fn insert(&mut self, key: &K, value: V) -> Option<V>;
+
+    fn get(&mut self, key: &K) -> Option<&V>;
+
+    fn remove(&mut self, key: &K) -> Option<V>;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn cap(&self) -> usize;
+
+    fn contains_key(&self, key: &K) -> bool;
+}
+
+/// A cache that can list the keys it holds, so that
+/// [`NegativeCacheGuard::rebuild_filter`] can find them itself.
+pub trait ResidentKeys {
+    type Key: Hash + ?Sized;
+
+    fn for_each_key(&self, f: impl FnMut(&Self::Key));
+}
+
+/// The `&self` counterpart of [`Cache`] for caches that synchronize
+/// internally.
+///
+/// `get` returns a clone of the value: another thread may evict or replace
+/// the entry at any time, so a reference into the cache could not be held.
+pub trait ConcurrentCache<K: ?Sized, V> {
+    fn insert(&self, key: &K, value: V) -> Option<V>;
+
+    fn get(&self, key: &K) -> Option<V>
+    where
+        V: Clone;
+
+    fn remove(&self, key: &K) -> Option<V>;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn cap(&self) -> usize;
+
+    fn contains_key(&self, key: &K) -> bool;
+}
+
+/// One request of a cache trace: look `key` up and fill it on a miss.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Access {
+    pub key: Vec<u8>,
+}
+
+impl Access {
+    pub fn new(key: &[u8]) -> Self {
+        Self { key: key.to_vec() }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplayStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl ReplayStats {
+    pub fn accesses(&self) -> u64 {
+        self.hits + self.misses
+    }
+
+    /// Fraction of accesses that hit, or 0 for an empty trace.
+    pub fn hit_ratio(&self) -> f64 {
+        if self.accesses() == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / self.accesses() as f64
+    }
+}
+
+/// Replays `trace` against `cache`, inserting `V::default()` on every miss.
+pub fn replay<V, C>(trace: impl Iterator<Item = Access>, cache: &mut C) -> ReplayStats
+where
+    V: Default,
+    C: Cache<[u8], V> + ?Sized,
+{
+    let mut stats = ReplayStats::default();
+    for access in trace {
+        if cache.get(&access.key).is_some() {
+            stats.hits += 1;
+        } else {
+            stats.misses += 1;
+            cache.insert(&access.key, V::default());
+        }
+    }
+    stats
+}
+
+/// Lookup counts of a [`NegativeCacheGuard`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GuardStats {
+    /// Calls to `get`, `contains_key` and `remove`.
+    pub lookups: u64,
+    /// Lookups the filter answered without touching the cache.
+    pub skipped: u64,
+    /// Lookups the filter let through that then missed in the cache.
+    pub false_positives: u64,
+}
+
+impl GuardStats {
+    /// Fraction of lookups answered by the filter alone.
+    pub fn skip_rate(&self) -> f64 {
+        if self.lookups == 0 {
+            return 0.0;
+        }
+        self.skipped as f64 / self.lookups as f64
+    }
+
+    /// Of the lookups for absent keys, the fraction the filter let through.
+    /// Both kinds are known exactly: a skipped key is certainly absent and a
+    /// false positive was found absent by the cache.
+    pub fn observed_fpr(&self) -> f64 {
+        let absent = self.skipped + self.false_positives;
+        if absent == 0 {
+            return 0.0;
+        }
+        self.false_positives as f64 / absent as f64
+    }
+}
+
+/// Puts a [`BloomFilter`] of every key ever inserted in front of a cache, so
+/// lookups of keys that were never there are answered without reaching the
+/// cache, or its lock for a [`ConcurrentCache`].
+///
+/// Keys that are evicted or removed stay in the filter, so under churn it
+/// fills up and lets more misses through; [`rebuild_filter`] resets it to
+/// the keys actually resident. A key in the cache is always in the filter,
+/// so the guard never reports a miss the cache would not.
+///
+/// [`rebuild_filter`]: NegativeCacheGuard::rebuild_filter
+pub struct NegativeCacheGuard<C> {
+    cache: C,
+    filter: BloomFilter,
+    lookups: AtomicU64,
+    skipped: AtomicU64,
+    false_positives: AtomicU64,
+}
+
+impl<C> NegativeCacheGuard<C> {
+    /// Guards `cache` with a filter sized for `expected_keys` keys at a 1%
+    /// false positive rate. The cache's capacity is a good start; churn
+    /// from evictions adds keys beyond it until the next rebuild.
+    pub fn new(cache: C, expected_keys: usize) -> Self {
+        Self::with_filter(cache, BloomFilter::with_rate(expected_keys, 0.01))
+    }
+
+    /// Guards `cache` with `filter`, which must be empty or already hold
+    /// every key in the cache.
+    pub fn with_filter(cache: C, filter: BloomFilter) -> Self {
+        Self {
+            cache,
+            filter,
+            lookups: AtomicU64::new(0),
+            skipped: AtomicU64::new(0),
+            false_positives: AtomicU64::new(0),
+        }
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.cache
+    }
+
+    pub fn filter(&self) -> &BloomFilter {
+        &self.filter
+    }
+
+    pub fn stats(&self) -> GuardStats {
+        GuardStats {
+            lookups: self.lookups.load(Ordering::Relaxed),
+            skipped: self.skipped.load(Ordering::Relaxed),
+            false_positives: self.false_positives.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn reset_stats(&self) {
+        self.lookups.store(0, Ordering::Relaxed);
+        self.skipped.store(0, Ordering::Relaxed);
+        self.false_positives.store(0, Ordering::Relaxed);
+    }
+
+    /// Clears the filter and refills it with `resident`, for a cache that
+    /// cannot list its keys. `resident` must include every key currently in
+    /// the cache: one left out would be reported as a miss. Extra keys are
+    /// harmless beyond the filter space they take.
+    pub fn rebuild_filter_from<'a, K, I>(&mut self, resident: I)
+    where
+        K: Hash + ?Sized + 'a,
+        I: IntoIterator<Item = &'a K>,
+    {
+        self.filter.clear();
+        for key in resident {
+            self.filter.insert(key);
+        }
+    }
+
+    /// Clears the filter and refills it with the keys in the cache.
+    pub fn rebuild_filter(&mut self)
+    where
+        C: ResidentKeys,
+    {
+        self.filter.clear();
+        self.cache.for_each_key(|key| self.filter.insert(key));
+    }
+
+    /// True if the lookup can stop here.
+    fn skip<K: Hash + ?Sized>(&self, key: &K) -> bool {
+        self.lookups.fetch_add(1, Ordering::Relaxed);
+        if self.filter.contains(key) {
+            return false;
+        }
+        self.skipped.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    fn record_miss(&self) {
+        self.false_positives.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl<K, V, C> Cache<K, V> for NegativeCacheGuard<C>
+where
+    K: Hash + ?Sized,
+    C: Cache<K, V>,
+{
+    fn insert(&mut self, key: &K, value: V) -> Option<V> {
+        self.filter.insert(key);
+        self.cache.insert(key, value)
+    }
+
+    fn get(&mut self, key: &K) -> Option<&V> {
+        if self.skip(key) {
+            return None;
+        }
+        let value = self.cache.get(key);
+        if value.is_none() {
+            // Not `record_miss`, which would borrow all of `self`.
+            self.false_positives.fetch_add(1, Ordering::Relaxed);
+        }
+        value
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        if self.skip(key) {
+            return None;
+        }
+        let value = self.cache.remove(key);
+        if value.is_none() {
+            self.record_miss();
+        }
+        value
+    }
+
+    fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    fn cap(&self) -> usize {
+        self.cache.cap()
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        if self.skip(key) {
+            return false;
+        }
+        let contained = self.cache.contains_key(key);
+        if !contained {
+            self.record_miss();
+        }
+        contained
+    }
+}
+
+impl<K, V, C> ConcurrentCache<K, V> for NegativeCacheGuard<C>
+where
+    K: Hash + ?Sized,
+    C: ConcurrentCache<K, V>,
+{
+    fn insert(&self, key: &K, value: V) -> Option<V> {
+        // Filter first, so a reader that finds the value in the cache could
+        // also have found the key in the filter.
+        self.filter.insert(key);
+        self.cache.insert(key, value)
+    }
+
+    fn get(&self, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        if self.skip(key) {
+            return None;
+        }
+        let value = self.cache.get(key);
+        if value.is_none() {
+            self.record_miss();
+        }
+        value
+    }
+
+    fn remove(&self, key: &K) -> Option<V> {
+        if self.skip(key) {
+            return None;
+        }
+        let value = self.cache.remove(key);
+        if value.is_none() {
+            self.record_miss();
+        }
+        value
+    }
+
+    fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    fn cap(&self) -> usize {
+        self.cache.cap()
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        if self.skip(key) {
+            return false;
+        }
+        let contained = self.cache.contains_key(key);
+        if !contained {
+            self.record_miss();
+        }
+        contained
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{replay, Access, Cache, ConcurrentCache, NegativeCacheGuard};
+    use crate::clock_cache::ClockCache;
+    use crate::concurrent_lru;
+    use crate::lru::{ByteLRUCache, LRUCache};
+    use crate::lru_k::LruKCache;
+    use crate::s3fifo::S3FifoCache;
+    use rand::prelude::*;
+    use std::collections::HashMap;
+
+    /// Behavior every policy must agree on, whatever it chooses to evict.
+    fn conformance<C: Cache<[u8], usize>>(new: impl Fn(usize) -> C) {
+        for capacity in [1, 2, 3, 8] {
+            let mut cache = new(capacity);
+            assert_eq!(cache.cap(), capacity);
+            assert!(cache.is_empty());
+
+            assert_eq!(cache.insert(b"a", 1), None);
+            assert_eq!(cache.len(), 1);
+            assert!(cache.contains_key(b"a"));
+            assert_eq!(cache.get(b"a"), Some(&1));
+
+            assert_eq!(cache.insert(b"a", 2), Some(1));
+            assert_eq!(cache.len(), 1);
+            assert_eq!(cache.get(b"a"), Some(&2));
+
+            assert_eq!(cache.remove(b"a"), Some(2));
+            assert_eq!(cache.remove(b"a"), None);
+            assert!(!cache.contains_key(b"a"));
+            assert_eq!(cache.get(b"a"), None);
+            assert!(cache.is_empty());
+
+            // Whatever survives must hold the last value written for its key.
+            let mut rng = StdRng::seed_from_u64(capacity as u64);
+            let mut latest = HashMap::new();
+            for op in 0..2000_usize {
+                let key = rng.gen_range(0..capacity * 3).to_le_bytes();
+                match rng.gen_range(0..3) {
+                    0 => {
+                        cache.insert(&key, op);
+                        latest.insert(key, op);
+                    }
+                    1 => {
+                        let contained = cache.contains_key(&key);
+                        let got = cache.get(&key).copied();
+                        assert_eq!(contained, got.is_some());
+                        if let Some(value) = got {
+                            assert_eq!(Some(&value), latest.get(&key));
+                        }
+                    }
+                    _ => {
+                        if let Some(value) = cache.remove(&key) {
+                            assert_eq!(Some(&value), latest.get(&key));
+                        }
+                        assert!(!cache.contains_key(&key));
+                    }
+                }
+                assert!(cache.len() <= capacity);
+            }
+        }
+    }
+
+    fn concurrent_conformance<C: ConcurrentCache<[u8], usize>>(new: impl Fn(usize) -> C) {
+        for capacity in [1, 2, 3, 8] {
+            let cache = new(capacity);
+            assert_eq!(cache.cap(), capacity);
+            assert!(cache.is_empty());
+
+            assert_eq!(cache.insert(b"a", 1), None);
+            assert_eq!(cache.len(), 1);
+            assert!(cache.contains_key(b"a"));
+            assert_eq!(cache.get(b"a"), Some(1));
+
+            assert_eq!(cache.insert(b"a", 2), Some(1));
+            assert_eq!(cache.len(), 1);
+
+            assert_eq!(cache.remove(b"a"), Some(2));
+            assert_eq!(cache.remove(b"a"), None);
+            assert!(!cache.contains_key(b"a"));
+            assert!(cache.is_empty());
+
+            for i in 0..capacity * 4 {
+                cache.insert(&i.to_le_bytes(), i);
+                assert!(cache.len() <= capacity);
+            }
+        }
+    }
+
+    #[test]
+    fn test_conformance_lru() {
+        conformance(LRUCache::new);
+    }
+
+    #[test]
+    fn test_conformance_clock() {
+        conformance(ClockCache::new);
+    }
+
+    #[test]
+    fn test_conformance_lru_k() {
+        conformance(|cap| LruKCache::new(cap, 1));
+        conformance(|cap| LruKCache::new(cap, 2));
+        conformance(|cap| LruKCache::new(cap, 3));
+    }
+
+    #[test]
+    fn test_conformance_s3fifo() {
+        conformance(S3FifoCache::new);
+    }
+
+    #[test]
+    fn test_conformance_concurrent_lru() {
+        concurrent_conformance(concurrent_lru::LRUCache::new);
+    }
+
+    #[test]
+    fn test_conformance_negative_guard() {
+        conformance(|cap| NegativeCacheGuard::new(LRUCache::new(cap), cap));
+        conformance(|cap| NegativeCacheGuard::new(ClockCache::new(cap), cap));
+        concurrent_conformance(|cap| {
+            NegativeCacheGuard::new(concurrent_lru::LRUCache::new(cap), cap)
+        });
+    }
+
+    fn key(i: u64) -> [u8; 8] {
+        i.to_le_bytes()
+    }
+
+    #[test]
+    fn test_negative_guard_never_misses_resident_keys() {
+        let mut guard = NegativeCacheGuard::new(LRUCache::new(500), 500);
+        let mut rng = StdRng::seed_from_u64(236);
+        let mut model = LRUCache::new(500);
+        for op in 0..20_000u64 {
+            let k = key(rng.gen_range(0..2_000));
+            if rng.gen_bool(0.5) {
+                guard.insert(&k, op);
+                model.insert(k, op);
+            } else {
+                // The unguarded twin sees the same operations, so any
+                // difference is the filter answering wrongly.
+                assert_eq!(guard.get(&k), model.get(&k));
+            }
+        }
+    }
+
+    #[test]
+    fn test_negative_guard_skips_unseen_keys() {
+        let mut guard = NegativeCacheGuard::new(LRUCache::new(1_000), 1_000);
+        for i in 0..1_000 {
+            guard.insert(&key(i), i);
+        }
+        for i in 1_000..11_000 {
+            assert_eq!(guard.get(&key(i)), None);
+        }
+        let stats = guard.stats();
+        assert_eq!(stats.lookups, 10_000);
+        assert_eq!(stats.skipped + stats.false_positives, 10_000);
+        assert!(stats.skip_rate() > 0.97, "{stats:?}");
+        assert!(stats.observed_fpr() < 0.03, "{stats:?}");
+        assert!(guard.filter().estimated_fpr() < 0.02);
+    }
+
+    #[test]
+    fn test_negative_guard_rebuild_after_churn() {
+        let mut guard = NegativeCacheGuard::new(LRUCache::new(100), 100);
+        // Fifty times the capacity passes through, saturating the filter.
+        for i in 0..5_000 {
+            guard.insert(&key(i), i);
+        }
+        let unseen = |guard: &mut NegativeCacheGuard<ByteLRUCache<u64>>| {
+            guard.reset_stats();
+            for i in 100_000..110_000 {
+                assert_eq!(guard.get(&key(i)), None);
+            }
+            guard.stats().skip_rate()
+        };
+        let saturated = unseen(&mut guard);
+        assert!(saturated < 0.5, "skip rate {saturated}");
+
+        // The LRU holds the last hundred keys and lists them itself.
+        guard.rebuild_filter();
+        let rebuilt = unseen(&mut guard);
+        assert!(rebuilt > 0.97, "skip rate {rebuilt}");
+        for i in 4_900..5_000 {
+            assert_eq!(guard.get(&key(i)), Some(&i));
+        }
+
+        // Given the keys instead, every one of them has to be there.
+        for i in 5_000..10_000 {
+            guard.insert(&key(i), i);
+        }
+        let resident: Vec<[u8; 8]> = (9_900..10_000).map(key).collect();
+        guard.rebuild_filter_from(resident.iter().map(|k| &k[..]));
+        assert!(unseen(&mut guard) > 0.97);
+        assert!((9_900..10_000).all(|i| guard.get(&key(i)) == Some(&i)));
+    }
+
+    #[test]
+    fn test_negative_guard_rebuild_concurrent() {
+        let mut guard = NegativeCacheGuard::new(concurrent_lru::LRUCache::new(10), 10);
+        for i in 0..1_000 {
+            ConcurrentCache::insert(&guard, &key(i), i);
+        }
+        guard.rebuild_filter();
+        assert!(guard.filter().estimated_fpr() < 0.02);
+        assert!((990..1_000).all(|i| ConcurrentCache::get(&guard, &key(i)) == Some(i)));
+    }
+
+    #[test]
+    fn test_replay() {
+        let trace = [b"a", b"b", b"a", b"c", b"a", b"b"].map(|k| Access::new(k));
+
+        let mut lru: ByteLRUCache<()> = LRUCache::new(2);
+        let stats = replay(trace.iter().cloned(), &mut lru);
+        assert_eq!((stats.hits, stats.misses), (2, 4));
+        assert!((stats.hit_ratio() - 2.0 / 6.0).abs() < 1e-9);
+
+        let mut lru: ByteLRUCache<()> = LRUCache::new(3);
+        let stats = replay(trace.iter().cloned(), &mut lru);
+        assert_eq!((stats.hits, stats.misses), (3, 3));
+
+        assert_eq!(replay(std::iter::empty(), &mut lru).hit_ratio(), 0.0);
+    }
+}
@@ -0,0 +1,418 @@
+use std::{fmt, iter::FusedIterator, marker::PhantomData, mem, ptr::NonNull};
+
+struct Node<T> {
+    val: T,
+    next: NonNull<Node<T>>,
+    prev: NonNull<Node<T>>,
+}
+
+/// A doubly linked ring with a hand resting on one of its elements.
+///
+/// There is no first or last element, only the one under the hand, which
+/// [`move_next`](Self::move_next) and [`move_prev`](Self::move_prev) carry
+/// around the ring without ever running off an end. The hand stays on its
+/// element through every insertion and through removals of other
+/// elements, which is what a CLOCK sweep needs: inspect the element under
+/// the hand, then either advance or evict.
+pub struct CircularList<T> {
+    len: usize,
+    current: Option<NonNull<Node<T>>>,
+    _marker: PhantomData<Box<Node<T>>>,
+}
+
+// SAFETY: the list owns its nodes as a `Box<Node<T>>` would, and they are
+// only reachable through it.
+unsafe impl<T: Send> Send for CircularList<T> {}
+unsafe impl<T: Sync> Sync for CircularList<T> {}
+
+impl<T> CircularList<T> {
+    pub fn new() -> Self {
+        Self {
+            len: 0,
+            current: None,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The element under the hand.
+    pub fn current(&self) -> Option<&T> {
+        self.current.map(|node| unsafe { &(*node.as_ptr()).val })
+    }
+
+    pub fn current_mut(&mut self) -> Option<&mut T> {
+        self.current
+            .map(|node| unsafe { &mut (*node.as_ptr()).val })
+    }
+
+    /// Moves the hand to the next element, wrapping around the ring.
+    pub fn move_next(&mut self) {
+        if let Some(node) = self.current {
+            self.current = Some(unsafe { (*node.as_ptr()).next });
+        }
+    }
+
+    /// Moves the hand to the previous element, wrapping around the ring.
+    pub fn move_prev(&mut self) {
+        if let Some(node) = self.current {
+            self.current = Some(unsafe { (*node.as_ptr()).prev });
+        }
+    }
+
+    /// Moves the hand `n` elements forward, taking the shorter way round.
+    pub fn rotate(&mut self, n: usize) {
+        if self.len == 0 {
+            return;
+        }
+        let n = n % self.len;
+        if n <= self.len / 2 {
+            (0..n).for_each(|_| self.move_next());
+        } else {
+            (n..self.len).for_each(|_| self.move_prev());
+        }
+    }
+
+    /// Inserts `val` just after the hand, so it is the next element
+    /// reached. In an empty list, the hand moves onto it.
+    pub fn insert_after(&mut self, val: T) {
+        match self.current {
+            Some(current) => {
+                let next = unsafe { (*current.as_ptr()).next };
+                self.link(val, current, next);
+            }
+            None => self.insert_first(val),
+        }
+    }
+
+    /// Inserts `val` just before the hand, so it is the last element
+    /// reached going round. In an empty list, the hand moves onto it.
+    pub fn insert_before(&mut self, val: T) {
+        match self.current {
+            Some(current) => {
+                let prev = unsafe { (*current.as_ptr()).prev };
+                self.link(val, prev, current);
+            }
+            None => self.insert_first(val),
+        }
+    }
+
+    /// Removes the element under the hand and moves the hand on to the
+    /// next one.
+    pub fn remove_current(&mut self) -> Option<T> {
+        let node = self.current?;
+        unsafe {
+            let (prev, next) = ((*node.as_ptr()).prev, (*node.as_ptr()).next);
+            self.current = if self.len == 1 {
+                None
+            } else {
+                (*prev.as_ptr()).next = next;
+                (*next.as_ptr()).prev = prev;
+                Some(next)
+            };
+            self.len -= 1;
+            Some(Box::from_raw(node.as_ptr()).val)
+        }
+    }
+
+    /// Every element once, starting at the hand and going forward.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            next: self.current,
+            len: self.len,
+            _marker: PhantomData,
+        }
+    }
+
+    fn insert_first(&mut self, val: T) {
+        let node = NonNull::new(Box::into_raw(Box::new(Node {
+            val,
+            next: NonNull::dangling(),
+            prev: NonNull::dangling(),
+        })))
+        .unwrap();
+        unsafe {
+            (*node.as_ptr()).next = node;
+            (*node.as_ptr()).prev = node;
+        }
+        self.current = Some(node);
+        self.len = 1;
+    }
+
+    /// Links a new node holding `val` in between `prev` and `next`, which
+    /// must be adjacent.
+    fn link(&mut self, val: T, prev: NonNull<Node<T>>, next: NonNull<Node<T>>) {
+        let node = NonNull::new(Box::into_raw(Box::new(Node { val, next, prev }))).unwrap();
+        unsafe {
+            (*prev.as_ptr()).next = node;
+            (*next.as_ptr()).prev = node;
+        }
+        self.len += 1;
+    }
+}
+
+impl<T> Default for CircularList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for CircularList<T> {
+    /// Frees one node per element, counting down `len` rather than
+    /// following the links, which never end.
+    fn drop(&mut self) {
+        struct DropGuard<'a, T>(&'a mut CircularList<T>);
+        impl<T> Drop for DropGuard<'_, T> {
+            fn drop(&mut self) {
+                while self.0.remove_current().is_some() {}
+            }
+        }
+
+        while let Some(val) = self.remove_current() {
+            let guard = DropGuard(self);
+            drop(val);
+            mem::forget(guard);
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for CircularList<T> {
+    /// Shows the elements starting at the hand.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T> FromIterator<T> for CircularList<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = CircularList::new();
+        list.extend(iter);
+        list
+    }
+}
+
+impl<T> Extend<T> for CircularList<T> {
+    /// Inserts each element before the hand, so going round from the hand
+    /// reaches them last, in order.
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for val in iter {
+            self.insert_before(val);
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a CircularList<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+pub struct Iter<'a, T> {
+    next: Option<NonNull<Node<T>>>,
+    len: usize,
+    _marker: PhantomData<&'a Node<T>>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.len == 0 {
+            return None;
+        }
+        self.next.map(|node| unsafe {
+            self.next = Some((*node.as_ptr()).next);
+            self.len -= 1;
+            &(*node.as_ptr()).val
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+impl<T> FusedIterator for Iter<'_, T> {}
+
+unsafe impl<T: Sync> Send for Iter<'_, T> {}
+unsafe impl<T: Sync> Sync for Iter<'_, T> {}
+
+#[cfg(test)]
+mod test {
+    use super::CircularList;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::cell::Cell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+
+    /// The elements from the hand, after checking that going backwards
+    /// visits the same ring.
+    fn contents(list: &mut CircularList<i32>) -> Vec<i32> {
+        let forward: Vec<i32> = list.iter().copied().collect();
+        assert_eq!(forward.len(), list.len());
+        let mut backward = Vec::new();
+        for _ in 0..list.len() {
+            list.move_prev();
+            backward.push(*list.current().unwrap());
+        }
+        backward.reverse();
+        assert_eq!(forward, backward);
+        forward
+    }
+
+    #[test]
+    fn test_against_vec_deque() {
+        let mut rng = StdRng::seed_from_u64(288);
+        let mut list = CircularList::new();
+        // The hand is always at the front of the model.
+        let mut plain = VecDeque::new();
+        for i in 0..5_000 {
+            match rng.gen_range(0..6) {
+                0 => {
+                    list.insert_after(i);
+                    if plain.is_empty() {
+                        plain.push_back(i);
+                    } else {
+                        plain.insert(1, i);
+                    }
+                }
+                1 => {
+                    list.insert_before(i);
+                    plain.push_back(i);
+                }
+                2 => assert_eq!(list.remove_current(), plain.pop_front()),
+                3 => {
+                    list.move_next();
+                    plain.rotate_left(plain.len().min(1));
+                }
+                4 => {
+                    list.move_prev();
+                    plain.rotate_right(plain.len().min(1));
+                }
+                _ => {
+                    let n = rng.gen_range(0..50);
+                    list.rotate(n);
+                    if !plain.is_empty() {
+                        let k = n % plain.len();
+                        plain.rotate_left(k);
+                    }
+                }
+            }
+            assert_eq!(list.current(), plain.front());
+            assert_eq!(list.len(), plain.len());
+        }
+        assert_eq!(contents(&mut list), Vec::from(plain));
+    }
+
+    #[test]
+    fn test_wraps_around() {
+        let mut list: CircularList<i32> = (0..4).collect();
+        assert_eq!(format!("{:?}", list), "[0, 1, 2, 3]");
+        let seen: Vec<i32> = (0..10)
+            .map(|_| {
+                let val = *list.current().unwrap();
+                list.move_next();
+                val
+            })
+            .collect();
+        assert_eq!(seen, [0, 1, 2, 3, 0, 1, 2, 3, 0, 1]);
+        list.move_prev();
+        list.move_prev();
+        assert_eq!(list.current(), Some(&0));
+        list.rotate(7);
+        assert_eq!(list.current(), Some(&3));
+        assert_eq!(contents(&mut list), [3, 0, 1, 2]);
+    }
+
+    #[test]
+    fn test_hand_survives_mutations() {
+        let mut list: CircularList<i32> = (0..3).collect();
+        list.move_next();
+        list.insert_before(10);
+        list.insert_after(11);
+        assert_eq!(list.current(), Some(&1));
+        assert_eq!(contents(&mut list), [1, 11, 2, 0, 10]);
+
+        list.move_next();
+        assert_eq!(list.remove_current(), Some(11));
+        assert_eq!(list.current(), Some(&2));
+        *list.current_mut().unwrap() = 20;
+        assert_eq!(contents(&mut list), [20, 0, 10, 1]);
+    }
+
+    #[test]
+    fn test_single_and_empty() {
+        let mut list = CircularList::new();
+        list.move_next();
+        list.rotate(3);
+        assert_eq!(list.current(), None);
+        assert_eq!(list.remove_current(), None);
+        assert_eq!(list.iter().next(), None);
+
+        list.insert_after(1);
+        list.move_next();
+        list.move_prev();
+        assert_eq!(contents(&mut list), [1]);
+        assert_eq!(list.remove_current(), Some(1));
+        assert!(list.is_empty());
+        list.insert_before(2);
+        assert_eq!(list.current(), Some(&2));
+    }
+
+    #[test]
+    fn test_clock_sweep() {
+        // (key, referenced): the hand clears bits until it finds an
+        // unreferenced entry, and evicts that one.
+        let mut ring: CircularList<(u8, bool)> = [(0, true), (1, false), (2, true), (3, true)]
+            .into_iter()
+            .collect();
+        let evict = |ring: &mut CircularList<(u8, bool)>| loop {
+            let entry = ring.current_mut().unwrap();
+            if !entry.1 {
+                return ring.remove_current().unwrap().0;
+            }
+            entry.1 = false;
+            ring.move_next();
+        };
+        assert_eq!(evict(&mut ring), 1);
+        ring.insert_before((4, true));
+        // 0 lost its bit on the first sweep, so it goes before 2 and 3.
+        assert_eq!(evict(&mut ring), 0);
+        assert_eq!(evict(&mut ring), 2);
+        assert_eq!(evict(&mut ring), 3);
+        assert_eq!(evict(&mut ring), 4);
+        assert!(ring.is_empty());
+    }
+
+    /// Counts its drops, and panics on drop if asked to.
+    struct Counted(Rc<Cell<usize>>, bool);
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+            assert!(!self.1, "drop failed");
+        }
+    }
+
+    #[test]
+    fn test_drop() {
+        let drops = Rc::new(Cell::new(0));
+        let mut list: CircularList<_> = (0..5).map(|i| Counted(drops.clone(), i == 3)).collect();
+        list.rotate(2);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| drop(list)));
+        assert!(result.is_err());
+        assert_eq!(drops.get(), 5);
+    }
+}
@@ -0,0 +1,303 @@
+use crate::cache::Cache;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+struct ClockEntry<T> {
+    key: Vec<u8>,
+    value: T,
+    referenced: AtomicBool,
+}
+
+impl<T> ClockEntry<T> {
+    fn new(key: &[u8], value: T) -> Self {
+        Self {
+            key: key.to_vec(),
+            value,
+            referenced: AtomicBool::new(false),
+        }
+    }
+}
+
+/// A CLOCK (second chance) cache.
+///
+/// Entries live in a fixed circular buffer, each with a reference bit. `get`
+/// only sets the bit; `insert` on a full cache advances the hand, clearing
+/// bits, until it reaches an entry whose bit is already clear and evicts it.
+pub struct ClockCache<T> {
+    map: HashMap<Vec<u8>, usize>,
+    slots: Vec<Option<ClockEntry<T>>>,
+    free: Vec<usize>,
+    hand: usize,
+    capacity: usize,
+}
+
+impl<T> ClockCache<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            map: HashMap::with_capacity(capacity),
+            slots: Vec::with_capacity(capacity),
+            free: Vec::new(),
+            hand: 0,
+            capacity,
+        }
+    }
+
+    /// Inserts `value` under `key`.
+    ///
+    /// Returns the previous value if the key was present, otherwise the value
+    /// of the evicted victim if the cache was full. A zero-capacity cache
+    /// stores nothing and hands `value` straight back.
+    pub fn insert(&mut self, key: &[u8], value: T) -> Option<T> {
+        if self.capacity == 0 {
+            return Some(value);
+        }
+
+        if let Some(&idx) = self.map.get(key) {
+            let entry = self.slots[idx].as_mut().unwrap();
+            entry.referenced.store(true, Ordering::Relaxed);
+            return Some(std::mem::replace(&mut entry.value, value));
+        }
+
+        if let Some(idx) = self.free.pop() {
+            self.map.insert(key.to_vec(), idx);
+            self.slots[idx] = Some(ClockEntry::new(key, value));
+            return None;
+        }
+
+        if self.slots.len() < self.capacity {
+            self.map.insert(key.to_vec(), self.slots.len());
+            self.slots.push(Some(ClockEntry::new(key, value)));
+            return None;
+        }
+
+        let victim = self.advance_hand();
+        let old = self.slots[victim]
+            .replace(ClockEntry::new(key, value))
+            .unwrap();
+        self.map.remove(&old.key);
+        self.map.insert(key.to_vec(), victim);
+        Some(old.value)
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&T> {
+        self.map.get(key).map(|&idx| {
+            let entry = self.slots[idx].as_ref().unwrap();
+            entry.referenced.store(true, Ordering::Relaxed);
+            &entry.value
+        })
+    }
+
+    /// Removes `key`, leaving its slot vacant for the next insert.
+    pub fn remove(&mut self, key: &[u8]) -> Option<T> {
+        let idx = self.map.remove(key)?;
+        self.free.push(idx);
+        self.slots[idx].take().map(|entry| entry.value)
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.map.contains_key(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Sweeps the hand forward, giving every referenced entry its second
+    /// chance, and returns the index of the first unreferenced one. The hand
+    /// is left just past the victim. Only called when every slot is occupied.
+    fn advance_hand(&mut self) -> usize {
+        loop {
+            let idx = self.hand;
+            self.hand = (self.hand + 1) % self.slots.len();
+            let entry = self.slots[idx].as_ref().unwrap();
+            if !entry.referenced.swap(false, Ordering::Relaxed) {
+                return idx;
+            }
+        }
+    }
+}
+
+impl<T> Cache<[u8], T> for ClockCache<T> {
+    fn insert(&mut self, key: &[u8], value: T) -> Option<T> {
+        ClockCache::insert(self, key, value)
+    }
+
+    fn get(&mut self, key: &[u8]) -> Option<&T> {
+        ClockCache::get(self, key)
+    }
+
+    fn remove(&mut self, key: &[u8]) -> Option<T> {
+        ClockCache::remove(self, key)
+    }
+
+    fn len(&self) -> usize {
+        ClockCache::len(self)
+    }
+
+    fn cap(&self) -> usize {
+        self.capacity
+    }
+
+    fn contains_key(&self, key: &[u8]) -> bool {
+        ClockCache::contains_key(self, key)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ClockCache;
+    use rand::prelude::*;
+
+    /// Straightforward CLOCK model over an operation log: a `Vec` scanned
+    /// linearly, used to cross-check the indexed implementation.
+    struct Model {
+        entries: Vec<(usize, usize, bool)>,
+        hand: usize,
+        capacity: usize,
+    }
+
+    impl Model {
+        fn insert(&mut self, key: usize, value: usize) -> Option<usize> {
+            if let Some(e) = self.entries.iter_mut().find(|e| e.0 == key) {
+                e.2 = true;
+                return Some(std::mem::replace(&mut e.1, value));
+            }
+            if self.entries.len() < self.capacity {
+                self.entries.push((key, value, false));
+                return None;
+            }
+            loop {
+                let idx = self.hand;
+                self.hand = (self.hand + 1) % self.capacity;
+                if self.entries[idx].2 {
+                    self.entries[idx].2 = false;
+                } else {
+                    let old = std::mem::replace(&mut self.entries[idx], (key, value, false));
+                    return Some(old.1);
+                }
+            }
+        }
+
+        fn get(&mut self, key: usize) -> Option<usize> {
+            self.entries.iter_mut().find(|e| e.0 == key).map(|e| {
+                e.2 = true;
+                e.1
+            })
+        }
+    }
+
+    #[test]
+    fn test_against_model() {
+        let mut rng = StdRng::seed_from_u64(213);
+        for capacity in 1..8 {
+            let mut cache = ClockCache::new(capacity);
+            let mut model = Model {
+                entries: Vec::new(),
+                hand: 0,
+                capacity,
+            };
+            for op in 0..5000_usize {
+                let key = rng.gen_range(0..capacity * 2);
+                if rng.gen_bool(0.5) {
+                    assert_eq!(
+                        cache.insert(&key.to_le_bytes(), op),
+                        model.insert(key, op),
+                        "insert {key} at op {op}"
+                    );
+                } else {
+                    assert_eq!(
+                        cache.get(&key.to_le_bytes()).copied(),
+                        model.get(key),
+                        "get {key} at op {op}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_hand_wraps() {
+        let mut cache = ClockCache::new(3);
+        for i in 0..3_i32 {
+            cache.insert(&i.to_le_bytes(), i);
+        }
+        // Nothing referenced: victims are taken in slot order and the hand
+        // wraps back to slot 0 after the third eviction.
+        assert_eq!(cache.insert(&3_i32.to_le_bytes(), 3), Some(0));
+        assert_eq!(cache.insert(&4_i32.to_le_bytes(), 4), Some(1));
+        assert_eq!(cache.insert(&5_i32.to_le_bytes(), 5), Some(2));
+        assert_eq!(cache.hand, 0);
+        assert_eq!(cache.insert(&6_i32.to_le_bytes(), 6), Some(3));
+        assert_eq!(cache.hand, 1);
+    }
+
+    #[test]
+    fn test_referenced_survives_one_pass() {
+        let mut cache = ClockCache::new(3);
+        for i in 0..3_i32 {
+            cache.insert(&i.to_le_bytes(), i);
+        }
+        assert_eq!(cache.get(&0_i32.to_le_bytes()), Some(&0));
+        assert_eq!(cache.get(&1_i32.to_le_bytes()), Some(&1));
+
+        // 0 and 1 have their second chance, so 2 goes first.
+        assert_eq!(cache.insert(&3_i32.to_le_bytes(), 3), Some(2));
+        // The pass cleared their bits: now they go in order.
+        assert_eq!(cache.insert(&4_i32.to_le_bytes(), 4), Some(0));
+        assert_eq!(cache.insert(&5_i32.to_le_bytes(), 5), Some(1));
+        assert_eq!(cache.get(&3_i32.to_le_bytes()), Some(&3));
+    }
+
+    #[test]
+    fn test_all_referenced_evicts_at_hand() {
+        let mut cache = ClockCache::new(3);
+        for i in 0..3_i32 {
+            cache.insert(&i.to_le_bytes(), i);
+            cache.get(&i.to_le_bytes());
+        }
+        // A full sweep clears every bit and comes back to where it started.
+        assert_eq!(cache.insert(&3_i32.to_le_bytes(), 3), Some(0));
+        assert_eq!(cache.hand, 1);
+    }
+
+    #[test]
+    fn test_capacity_one() {
+        let mut cache = ClockCache::new(1);
+        assert_eq!(cache.insert(b"a", 1), None);
+        assert_eq!(cache.get(b"a"), Some(&1));
+        assert_eq!(cache.insert(b"a", 2), Some(1));
+        assert_eq!(cache.insert(b"b", 3), Some(2));
+        assert_eq!(cache.get(b"a"), None);
+        assert_eq!(cache.get(b"b"), Some(&3));
+    }
+
+    #[test]
+    fn test_remove_reuses_slot() {
+        let mut cache = ClockCache::new(3);
+        for i in 0..3_i32 {
+            cache.insert(&i.to_le_bytes(), i);
+        }
+        assert_eq!(cache.remove(&1_i32.to_le_bytes()), Some(1));
+        assert_eq!(cache.len(), 2);
+        // The vacant slot is filled before anything is evicted.
+        assert_eq!(cache.insert(&3_i32.to_le_bytes(), 3), None);
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.insert(&4_i32.to_le_bytes(), 4), Some(0));
+        assert_eq!(cache.insert(&5_i32.to_le_bytes(), 5), Some(3));
+    }
+
+    #[test]
+    fn test_capacity_zero() {
+        let mut cache = ClockCache::new(0);
+        assert_eq!(cache.insert(b"a", 1), Some(1));
+        assert_eq!(cache.get(b"a"), None);
+    }
+}
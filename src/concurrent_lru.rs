@@ -1,3 +1,4 @@
+use crate::cache::{ConcurrentCache, ResidentKeys};
 use dashmap::DashMap;
 use parking_lot::Mutex;
 use std::sync::atomic::{AtomicUsize, Ordering};
@@ -322,11 +323,56 @@ where
         }
     }
 
-    pub fn remove(&self, key: &[u8]) {
-        if let Some(node) = self.map.get(key) {
-            self.list.remove(node.0.unwrap());
+    pub fn remove(&self, key: &[u8]) -> Option<T> {
+        // Taking the entry out of the map first means only one of several
+        // threads removing the same key gets the node to unlink and free.
+        let (_, node) = self.map.remove(key)?;
+        Some(self.list.remove(node.0.unwrap()).value)
+    }
+}
+
+impl<T> ResidentKeys for LRUCache<T>
+where
+    T: std::fmt::Debug,
+{
+    type Key = [u8];
+
+    fn for_each_key(&self, mut f: impl FnMut(&[u8])) {
+        for entry in self.map.iter() {
+            f(entry.key());
         }
-        self.map.remove(key);
+    }
+}
+
+impl<T> ConcurrentCache<[u8], T> for LRUCache<T>
+where
+    T: std::fmt::Debug,
+{
+    fn insert(&self, key: &[u8], value: T) -> Option<T> {
+        LRUCache::insert(self, key, value)
+    }
+
+    fn get(&self, key: &[u8]) -> Option<T>
+    where
+        T: Clone,
+    {
+        LRUCache::get(self, key).cloned()
+    }
+
+    fn remove(&self, key: &[u8]) -> Option<T> {
+        LRUCache::remove(self, key)
+    }
+
+    fn len(&self) -> usize {
+        self.list.length.load(Ordering::Relaxed)
+    }
+
+    fn cap(&self) -> usize {
+        self.capacity
+    }
+
+    fn contains_key(&self, key: &[u8]) -> bool {
+        self.map.contains_key(key)
     }
 }
 
@@ -397,5 +443,20 @@ mod test {
                 lru.insert(&n.to_le_bytes(), n);
             }
         }
+
+        #[test]
+        fn test_remove_same_key_from_threads() {
+            let lru = LRUCache::new(64);
+            for round in 0..200_usize {
+                lru.insert(&round.to_le_bytes(), round);
+                let removed: usize = std::thread::scope(|s| {
+                    let removers: Vec<_> = (0..4)
+                        .map(|_| s.spawn(|| lru.remove(&round.to_le_bytes()).is_some() as usize))
+                        .collect();
+                    removers.into_iter().map(|r| r.join().unwrap()).sum()
+                });
+                assert_eq!(removed, 1);
+            }
+        }
     }
 }
@@ -0,0 +1,540 @@
+//! Chase-Lev work-stealing deque.
+//!
+//! Loom models for this run with
+//! `RUSTFLAGS="--cfg loom --cfg crossbeam_loom" cargo test --release --lib deque`.
+
+use crate::sync::{fence, AtomicIsize, Ordering};
+use crossbeam_epoch::{self as epoch, Atomic, Owned};
+use std::cell::{Cell, UnsafeCell};
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::sync::Arc;
+
+const MIN_CAPACITY: usize = 16;
+
+/// A power-of-two circular buffer indexed by the deque's unbounded
+/// positions. Dropping it frees the slots without dropping their contents.
+struct Buffer<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+}
+
+impl<T> Buffer<T> {
+    fn new(capacity: usize) -> Self {
+        debug_assert!(capacity.is_power_of_two());
+        Self {
+            slots: (0..capacity)
+                .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+                .collect(),
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    fn slot(&self, index: isize) -> *mut MaybeUninit<T> {
+        self.slots[index as usize & (self.capacity() - 1)].get()
+    }
+
+    unsafe fn write(&self, index: isize, val: T) {
+        (*self.slot(index)).write(val);
+    }
+
+    /// A bitwise copy of the slot. Only the thread that then wins the
+    /// position may `assume_init` it; everyone else just drops the copy.
+    unsafe fn read(&self, index: isize) -> MaybeUninit<T> {
+        ptr::read_volatile(self.slot(index))
+    }
+}
+
+struct Inner<T> {
+    /// Next position to steal from. Only ever incremented, by a CAS.
+    top: AtomicIsize,
+    /// Next position the owner pushes to. Written by the owner only.
+    bottom: AtomicIsize,
+    /// Replaced by the owner only, when it grows the buffer. Old buffers are
+    /// retired through crossbeam-epoch since a stealer may still be reading.
+    buffer: Atomic<Buffer<T>>,
+}
+
+unsafe impl<T: Send> Send for Inner<T> {}
+unsafe impl<T: Send> Sync for Inner<T> {}
+
+impl<T> Drop for Inner<T> {
+    fn drop(&mut self) {
+        let top = self.top.load(Ordering::Relaxed);
+        let bottom = self.bottom.load(Ordering::Relaxed);
+        unsafe {
+            // Both handles are gone, so nobody else can touch the buffer.
+            let guard = epoch::unprotected();
+            let buffer = self.buffer.load(Ordering::Relaxed, guard).into_owned();
+            for i in top..bottom {
+                (*buffer.slot(i)).assume_init_drop();
+            }
+        }
+    }
+}
+
+/// The result of [`Stealer::steal`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum Steal<T> {
+    Empty,
+    Success(T),
+    /// Lost a race for the top element; the deque may still hold others.
+    Retry,
+}
+
+impl<T> Steal<T> {
+    pub fn success(self) -> Option<T> {
+        match self {
+            Steal::Success(val) => Some(val),
+            _ => None,
+        }
+    }
+}
+
+/// The owner end of a Chase-Lev work-stealing deque.
+///
+/// The owner pushes and pops at the bottom like a stack, so its hot path is
+/// a couple of relaxed accesses plus one fence; [`Stealer`]s take from the
+/// top with a CAS. The buffer doubles when a push finds it full. The handle
+/// is `Send` but not `Sync`, which is what keeps `push`/`pop` single-threaded.
+///
+/// Orderings follow Lê et al., "Correct and Efficient Work-Stealing for Weak
+/// Memory Models" (PPoPP 2013):
+///
+/// - `push` writes the slot, then a release fence, then a relaxed store of
+///   `bottom`. A stealer's acquire load of `bottom` therefore sees the slot
+///   (and any buffer swap done by the push).
+/// - `pop` publishes the decremented `bottom` and reads `top` on either side
+///   of a `SeqCst` fence, and `steal` reads `top` and `bottom` on either side
+///   of another. The two fences order the accesses so the owner and a
+///   stealer cannot both conclude they own the same, last element.
+/// - Contention for the last element, and among stealers, is settled by a
+///   `SeqCst` CAS on `top`.
+pub struct WorkStealingDeque<T> {
+    inner: Arc<Inner<T>>,
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+impl<T> WorkStealingDeque<T> {
+    pub fn new() -> Self {
+        Self::with_capacity(MIN_CAPACITY)
+    }
+
+    /// Creates a deque whose initial buffer holds at least `capacity`
+    /// elements.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let buffer = Buffer::new(capacity.max(1).next_power_of_two());
+        Self {
+            inner: Arc::new(Inner {
+                top: AtomicIsize::new(0),
+                bottom: AtomicIsize::new(0),
+                buffer: Atomic::new(buffer),
+            }),
+            _not_sync: PhantomData,
+        }
+    }
+
+    pub fn stealer(&self) -> Stealer<T> {
+        Stealer {
+            inner: self.inner.clone(),
+        }
+    }
+
+    pub fn push(&self, val: T) {
+        let inner = &*self.inner;
+        let b = inner.bottom.load(Ordering::Relaxed);
+        // Acquire pairs with stealers' CAS so the slots they vacated are free.
+        let t = inner.top.load(Ordering::Acquire);
+        // Only the owner replaces the buffer, so it needs no protection here.
+        let guard = unsafe { epoch::unprotected() };
+        let mut buffer = unsafe { inner.buffer.load(Ordering::Relaxed, guard).deref() };
+
+        if b - t >= buffer.capacity() as isize {
+            self.grow(buffer, t, b);
+            buffer = unsafe { inner.buffer.load(Ordering::Relaxed, guard).deref() };
+        }
+        unsafe { buffer.write(b, val) };
+        fence(Ordering::Release);
+        inner.bottom.store(b + 1, Ordering::Relaxed);
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let inner = &*self.inner;
+        let b = inner.bottom.load(Ordering::Relaxed) - 1;
+        let guard = unsafe { epoch::unprotected() };
+        let buffer = unsafe { inner.buffer.load(Ordering::Relaxed, guard).deref() };
+        inner.bottom.store(b, Ordering::Relaxed);
+        fence(Ordering::SeqCst);
+        let t = inner.top.load(Ordering::Relaxed);
+
+        if t > b {
+            // Empty; undo the reservation.
+            inner.bottom.store(b + 1, Ordering::Relaxed);
+            return None;
+        }
+        let val = unsafe { buffer.read(b) };
+        if t == b {
+            // The last element: race the stealers for it through `top`.
+            let won = inner
+                .top
+                .compare_exchange(t, t + 1, Ordering::SeqCst, Ordering::Relaxed)
+                .is_ok();
+            inner.bottom.store(b + 1, Ordering::Relaxed);
+            if !won {
+                return None;
+            }
+        }
+        Some(unsafe { val.assume_init() })
+    }
+
+    /// Number of elements, exact when called by the owner with no steals in
+    /// flight.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn grow(&self, old: &Buffer<T>, top: isize, bottom: isize) {
+        let new = Buffer::new(old.capacity() * 2);
+        for i in top..bottom {
+            unsafe { ptr::copy_nonoverlapping(old.slot(i), new.slot(i), 1) };
+        }
+        let guard = epoch::pin();
+        let new = Owned::new(new).into_shared(&guard);
+        // Release so a stealer that acquires the new pointer sees the copies.
+        let old = self.inner.buffer.swap(new, Ordering::Release, &guard);
+        unsafe {
+            // Stealers may still be reading the old buffer, but only ever
+            // take values from it that were also copied, so it is freed
+            // without dropping anything.
+            guard.defer_destroy(old);
+        }
+    }
+}
+
+impl<T> Default for WorkStealingDeque<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A handle that takes elements from the top of a [`WorkStealingDeque`].
+/// Cheap to clone and shareable across threads.
+pub struct Stealer<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Stealer<T> {
+    pub fn steal(&self) -> Steal<T> {
+        let inner = &*self.inner;
+        // Pin before reading `top` so the buffer holding `top` cannot be
+        // freed under us.
+        let guard = epoch::pin();
+        let t = inner.top.load(Ordering::Acquire);
+        fence(Ordering::SeqCst);
+        // Acquire pairs with the release fence in `push`.
+        let b = inner.bottom.load(Ordering::Acquire);
+        if b - t <= 0 {
+            return Steal::Empty;
+        }
+
+        let buffer = unsafe { inner.buffer.load(Ordering::Acquire, &guard).deref() };
+        let val = unsafe { buffer.read(t) };
+        if inner
+            .top
+            .compare_exchange(t, t + 1, Ordering::SeqCst, Ordering::Relaxed)
+            .is_err()
+        {
+            // Another stealer or the owner's last-element pop got there
+            // first; our copy of the value is not ours to keep.
+            return Steal::Retry;
+        }
+        Steal::Success(unsafe { val.assume_init() })
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Clone for Stealer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Inner<T> {
+    fn len(&self) -> usize {
+        let t = self.top.load(Ordering::Acquire);
+        let b = self.bottom.load(Ordering::Acquire);
+        (b - t).max(0) as usize
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::{Steal, WorkStealingDeque};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    struct DropCounter(Arc<AtomicUsize>);
+
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_owner_lifo_stealer_fifo() {
+        let deque = WorkStealingDeque::new();
+        let stealer = deque.stealer();
+        assert_eq!(deque.pop(), None);
+        assert_eq!(stealer.steal(), Steal::Empty);
+
+        for i in 0..5 {
+            deque.push(i);
+        }
+        assert_eq!(deque.len(), 5);
+        assert_eq!(stealer.steal(), Steal::Success(0));
+        assert_eq!(stealer.steal(), Steal::Success(1));
+        assert_eq!(deque.pop(), Some(4));
+        assert_eq!(deque.pop(), Some(3));
+        assert_eq!(deque.pop(), Some(2));
+        assert_eq!(deque.pop(), None);
+        assert!(stealer.is_empty());
+    }
+
+    #[test]
+    fn test_growth() {
+        let deque = WorkStealingDeque::with_capacity(2);
+        let stealer = deque.stealer();
+        for i in 0..1000 {
+            deque.push(i);
+        }
+        assert_eq!(deque.len(), 1000);
+        for i in 0..500 {
+            assert_eq!(stealer.steal(), Steal::Success(i));
+        }
+        // Grow again with a wrapped window.
+        for i in 1000..3000 {
+            deque.push(i);
+        }
+        for i in (500..3000).rev() {
+            assert_eq!(deque.pop(), Some(i));
+        }
+        assert!(deque.is_empty());
+    }
+
+    #[test]
+    fn test_growth_while_stealing() {
+        const TOTAL: usize = 100_000;
+
+        let deque = WorkStealingDeque::with_capacity(1);
+        let stealer = deque.stealer();
+        let thief = thread::spawn(move || {
+            let mut got = Vec::new();
+            while got.len() < TOTAL / 2 {
+                if let Steal::Success(v) = stealer.steal() {
+                    got.push(v);
+                }
+            }
+            got
+        });
+        // Push bursts keep the buffer growing while the thief reads from it.
+        for i in 0..TOTAL {
+            deque.push(i);
+        }
+        let stolen = thief.join().unwrap();
+        // A single stealer sees elements in push order.
+        assert!(stolen.windows(2).all(|w| w[0] < w[1]));
+
+        let mut rest = Vec::new();
+        while let Some(v) = deque.pop() {
+            rest.push(v);
+        }
+        let mut all: Vec<_> = stolen.into_iter().chain(rest).collect();
+        all.sort_unstable();
+        assert!(all.into_iter().eq(0..TOTAL));
+    }
+
+    #[test]
+    fn test_conservation() {
+        const STEALERS: usize = 4;
+        const TOTAL: usize = 200_000;
+
+        let deque = WorkStealingDeque::<usize>::new();
+        let seen: Arc<Vec<AtomicUsize>> =
+            Arc::new((0..TOTAL).map(|_| AtomicUsize::new(0)).collect());
+        let done = Arc::new(AtomicBool::new(false));
+
+        let thieves: Vec<_> = (0..STEALERS)
+            .map(|_| {
+                let (stealer, seen, done) = (deque.stealer(), seen.clone(), done.clone());
+                thread::spawn(move || loop {
+                    match stealer.steal() {
+                        Steal::Success(v) => {
+                            seen[v].fetch_add(1, Ordering::Relaxed);
+                        }
+                        Steal::Retry => {}
+                        Steal::Empty if done.load(Ordering::Acquire) => break,
+                        Steal::Empty => {}
+                    }
+                })
+            })
+            .collect();
+
+        // The owner mixes pushes with pops so it keeps racing the thieves.
+        for i in 0..TOTAL {
+            deque.push(i);
+            if i % 3 == 0 {
+                if let Some(v) = deque.pop() {
+                    seen[v].fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+        while let Some(v) = deque.pop() {
+            seen[v].fetch_add(1, Ordering::Relaxed);
+        }
+        done.store(true, Ordering::Release);
+        thieves.into_iter().for_each(|h| h.join().unwrap());
+
+        assert!(seen.iter().all(|c| c.load(Ordering::Relaxed) == 1));
+    }
+
+    #[test]
+    fn test_last_element_races() {
+        // Owner and stealer fight over a single element many times; exactly
+        // one of them gets it each round.
+        let deque = WorkStealingDeque::new();
+        let stealer = deque.stealer();
+        let rounds = 20_000;
+        let stolen = Arc::new(AtomicUsize::new(0));
+        let done = Arc::new(AtomicBool::new(false));
+
+        let thief = {
+            let (stolen, done) = (stolen.clone(), done.clone());
+            thread::spawn(move || {
+                while !done.load(Ordering::Acquire) {
+                    if stealer.steal().success().is_some() {
+                        stolen.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            })
+        };
+        let mut popped = 0;
+        for i in 0..rounds {
+            deque.push(i);
+            if deque.pop().is_some() {
+                popped += 1;
+            }
+        }
+        done.store(true, Ordering::Release);
+        thief.join().unwrap();
+
+        assert!(deque.is_empty());
+        assert_eq!(popped + stolen.load(Ordering::Relaxed), rounds);
+    }
+
+    #[test]
+    fn test_no_leaks() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        {
+            let deque = WorkStealingDeque::with_capacity(4);
+            let stealer = deque.stealer();
+            for _ in 0..50 {
+                deque.push(DropCounter(drops.clone()));
+            }
+            drop(stealer.steal());
+            drop(deque.pop());
+            assert_eq!(drops.load(Ordering::Relaxed), 2);
+            // The remaining elements outlive the owner through the stealer.
+            drop(deque);
+            assert_eq!(drops.load(Ordering::Relaxed), 2);
+        }
+        assert_eq!(drops.load(Ordering::Relaxed), 50);
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_test {
+    use super::{Steal, WorkStealingDeque};
+    use loom::thread;
+
+    fn model(f: impl Fn() + Sync + Send + 'static) {
+        let mut builder = loom::model::Builder::new();
+        builder.preemption_bound = Some(3);
+        builder.check(f);
+    }
+
+    fn steal_until_settled<T>(stealer: &super::Stealer<T>) -> Option<T> {
+        loop {
+            match stealer.steal() {
+                Steal::Retry => {}
+                other => return other.success(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_pop_races_steal() {
+        model(|| {
+            let deque = WorkStealingDeque::with_capacity(2);
+            deque.push(1);
+            deque.push(2);
+            let stealer = deque.stealer();
+            let thief = thread::spawn(move || steal_until_settled(&stealer));
+
+            let mut got: Vec<_> = [deque.pop(), deque.pop()].into_iter().flatten().collect();
+            got.extend(thief.join().unwrap());
+            got.sort();
+            assert_eq!(got, vec![1, 2]);
+            assert_eq!(deque.pop(), None);
+        });
+    }
+
+    #[test]
+    fn test_last_element() {
+        model(|| {
+            let deque = WorkStealingDeque::with_capacity(1);
+            deque.push(1);
+            let stealer = deque.stealer();
+            let thief = thread::spawn(move || stealer.steal().success());
+
+            let mine = deque.pop();
+            let theirs = thief.join().unwrap();
+            // Exactly one side gets the element.
+            assert_eq!(mine.is_some(), theirs.is_none());
+            assert_eq!(mine.or(theirs), Some(1));
+        });
+    }
+
+    #[test]
+    fn test_steal_during_growth() {
+        model(|| {
+            let deque = WorkStealingDeque::with_capacity(1);
+            deque.push(1);
+            let stealer = deque.stealer();
+            let thief = thread::spawn(move || steal_until_settled(&stealer));
+
+            deque.push(2);
+            let mut got: Vec<_> = [deque.pop(), deque.pop()].into_iter().flatten().collect();
+            got.extend(thief.join().unwrap());
+            got.sort();
+            assert_eq!(got, vec![1, 2]);
+        });
+    }
+}
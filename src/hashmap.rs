@@ -0,0 +1,361 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::mem;
+
+const MIN_CAPACITY: usize = 8;
+const DEFAULT_MAX_LOAD: f64 = 0.875;
+
+struct Slot<K, V> {
+    hash: u64,
+    key: K,
+    value: V,
+}
+
+/// An open-addressing hash map with Robin Hood displacement.
+///
+/// On insert, an entry that has travelled further from its home slot takes
+/// the place of one that has travelled less, which keeps probe lengths
+/// short and even. Removal shifts the following run back by one instead of
+/// leaving tombstones, so lookups never have to skip dead slots.
+pub struct RobinHoodMap<K, V, S = RandomState> {
+    slots: Vec<Option<Slot<K, V>>>,
+    len: usize,
+    max_load: f64,
+    hasher: S,
+}
+
+impl<K: Hash + Eq, V> RobinHoodMap<K, V> {
+    pub fn new() -> Self {
+        Self::with_max_load_factor(DEFAULT_MAX_LOAD)
+    }
+
+    pub fn with_max_load_factor(max_load: f64) -> Self {
+        Self::with_max_load_factor_and_hasher(max_load, RandomState::new())
+    }
+}
+
+impl<K: Hash + Eq, V> Default for RobinHoodMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher> RobinHoodMap<K, V, S> {
+    pub fn with_hasher(hasher: S) -> Self {
+        Self::with_max_load_factor_and_hasher(DEFAULT_MAX_LOAD, hasher)
+    }
+
+    /// # Panics
+    ///
+    /// Panics unless `0 < max_load < 1`. A full table would leave lookups
+    /// for absent keys nowhere to stop.
+    pub fn with_max_load_factor_and_hasher(max_load: f64, hasher: S) -> Self {
+        assert!(
+            max_load > 0.0 && max_load < 1.0,
+            "max load factor must be in (0, 1)"
+        );
+        Self {
+            slots: Vec::new(),
+            len: 0,
+            max_load,
+            hasher,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of slots, used or not.
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Makes room for `additional` more entries without resizing.
+    pub fn reserve(&mut self, additional: usize) {
+        let needed = self.len + additional;
+        if needed <= self.max_entries(self.slots.len()) {
+            return;
+        }
+        let mut capacity = self.slots.len().max(MIN_CAPACITY);
+        while needed > self.max_entries(capacity) {
+            capacity *= 2;
+        }
+        self.resize(capacity);
+    }
+
+    /// Inserts `value` under `key`, returning the value it replaced.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.reserve(1);
+        let hash = self.hasher.hash_one(&key);
+        let mask = self.mask();
+        let mut index = hash as usize & mask;
+        let mut dist = 0;
+        // Look for the key until reaching a slot whose entry is closer to
+        // home than we would be: the key would have displaced it if present.
+        loop {
+            match &self.slots[index] {
+                None => break,
+                Some(slot) if slot.hash == hash && slot.key == key => {
+                    let slot = self.slots[index].as_mut().unwrap();
+                    return Some(mem::replace(&mut slot.value, value));
+                }
+                Some(slot) if self.distance(slot.hash, index) < dist => break,
+                Some(_) => {}
+            }
+            index = (index + 1) & mask;
+            dist += 1;
+        }
+        self.len += 1;
+        self.place(index, dist, Slot { hash, key, value });
+        None
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let index = self.find(key)?;
+        self.slots[index].as_ref().map(|slot| &slot.value)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let index = self.find(key)?;
+        self.slots[index].as_mut().map(|slot| &mut slot.value)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.find(key).is_some()
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let mut hole = self.find(key)?;
+        let removed = self.slots[hole].take().map(|slot| slot.value);
+        self.len -= 1;
+        // Shift the rest of the run back, stopping at an empty slot or an
+        // entry already in its home slot.
+        let mask = self.mask();
+        loop {
+            let next = (hole + 1) & mask;
+            match &self.slots[next] {
+                Some(slot) if self.distance(slot.hash, next) > 0 => {
+                    self.slots[hole] = self.slots[next].take();
+                    hole = next;
+                }
+                _ => return removed,
+            }
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.slots
+            .iter()
+            .flatten()
+            .map(|slot| (&slot.key, &slot.value))
+    }
+
+    /// Average number of slots a successful lookup examines, 0 when empty.
+    pub fn mean_probe_length(&self) -> f64 {
+        if self.len == 0 {
+            return 0.0;
+        }
+        let total: usize = self.probe_lengths().sum();
+        total as f64 / self.len as f64
+    }
+
+    /// Most slots any successful lookup examines, 0 when empty.
+    pub fn max_probe_length(&self) -> usize {
+        self.probe_lengths().max().unwrap_or(0)
+    }
+
+    fn probe_lengths(&self) -> impl Iterator<Item = usize> + '_ {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(i, slot)| slot.as_ref().map(|s| self.distance(s.hash, i) + 1))
+    }
+
+    fn find(&self, key: &K) -> Option<usize> {
+        if self.len == 0 {
+            return None;
+        }
+        let hash = self.hasher.hash_one(key);
+        let mask = self.mask();
+        let mut index = hash as usize & mask;
+        let mut dist = 0;
+        loop {
+            match &self.slots[index] {
+                None => return None,
+                Some(slot) if slot.hash == hash && slot.key == *key => return Some(index),
+                Some(slot) if self.distance(slot.hash, index) < dist => return None,
+                Some(_) => {}
+            }
+            index = (index + 1) & mask;
+            dist += 1;
+        }
+    }
+
+    /// How far the entry with `hash` sits past its home slot at `index`.
+    fn distance(&self, hash: u64, index: usize) -> usize {
+        index.wrapping_sub(hash as usize) & self.mask()
+    }
+
+    fn mask(&self) -> usize {
+        self.slots.len().wrapping_sub(1)
+    }
+
+    fn max_entries(&self, capacity: usize) -> usize {
+        (capacity as f64 * self.max_load) as usize
+    }
+
+    /// Puts `carried` at `index`, `dist` past its home slot, carrying each
+    /// entry closer to home than the one in hand one step further along.
+    fn place(&mut self, mut index: usize, mut dist: usize, mut carried: Slot<K, V>) {
+        let mask = self.mask();
+        loop {
+            match &mut self.slots[index] {
+                empty @ None => {
+                    *empty = Some(carried);
+                    return;
+                }
+                Some(slot) => {
+                    let slot_dist = index.wrapping_sub(slot.hash as usize) & mask;
+                    if slot_dist < dist {
+                        mem::swap(slot, &mut carried);
+                        dist = slot_dist;
+                    }
+                }
+            }
+            index = (index + 1) & mask;
+            dist += 1;
+        }
+    }
+
+    fn resize(&mut self, capacity: usize) {
+        let old = mem::replace(&mut self.slots, (0..capacity).map(|_| None).collect());
+        let mask = self.mask();
+        for slot in old.into_iter().flatten() {
+            self.place(slot.hash as usize & mask, 0, slot);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RobinHoodMap;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::HashMap;
+    use std::hash::{BuildHasherDefault, Hasher};
+
+    /// Hashes everything to the same value.
+    #[derive(Default)]
+    struct Constant;
+
+    impl Hasher for Constant {
+        fn finish(&self) -> u64 {
+            42
+        }
+
+        fn write(&mut self, _: &[u8]) {}
+    }
+
+    fn check_against_std<S: std::hash::BuildHasher>(
+        mut map: RobinHoodMap<u32, u32, S>,
+        ops: usize,
+        key_range: u32,
+        seed: u64,
+    ) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut reference = HashMap::new();
+        for step in 0..ops {
+            let key = rng.gen_range(0..key_range);
+            // Removal-heavy mix, so backward shifts run constantly.
+            match rng.gen_range(0..10) {
+                0..=3 => assert_eq!(
+                    map.insert(key, step as u32),
+                    reference.insert(key, step as u32)
+                ),
+                4..=7 => assert_eq!(map.remove(&key), reference.remove(&key)),
+                _ => assert_eq!(map.get(&key), reference.get(&key)),
+            }
+            assert_eq!(map.len(), reference.len());
+        }
+        for key in 0..key_range {
+            assert_eq!(map.get(&key), reference.get(&key));
+        }
+        let mut entries: Vec<_> = map.iter().map(|(&k, &v)| (k, v)).collect();
+        entries.sort();
+        let mut expected: Vec<_> = reference.into_iter().collect();
+        expected.sort();
+        assert_eq!(entries, expected);
+    }
+
+    #[test]
+    fn test_differential() {
+        check_against_std(RobinHoodMap::new(), 200_000, 500, 239);
+        check_against_std(
+            RobinHoodMap::with_max_load_factor(0.95),
+            200_000,
+            5_000,
+            2390,
+        );
+    }
+
+    #[test]
+    fn test_all_keys_collide() {
+        let map = RobinHoodMap::with_hasher(BuildHasherDefault::<Constant>::default());
+        check_against_std(map, 5_000, 100, 2391);
+    }
+
+    #[test]
+    fn test_growth() {
+        let mut map = RobinHoodMap::new();
+        for i in 0..10_000u64 {
+            assert_eq!(map.insert(i, i * 2), None);
+        }
+        assert_eq!(map.len(), 10_000);
+        assert!(map.capacity() >= 10_000 && map.capacity().is_power_of_two());
+        assert!((0..10_000u64).all(|i| map.get(&i) == Some(&(i * 2))));
+        assert_eq!(map.get(&10_000), None);
+
+        let capacity = map.capacity();
+        map.reserve(capacity);
+        assert!(map.capacity() > capacity);
+        let capacity = map.capacity();
+        for i in 10_000..20_000u64 {
+            map.insert(i, i);
+        }
+        assert_eq!(map.capacity(), capacity);
+    }
+
+    #[test]
+    fn test_probe_stats() {
+        let mut map = RobinHoodMap::new();
+        assert_eq!(map.mean_probe_length(), 0.0);
+        assert_eq!(map.max_probe_length(), 0);
+        for i in 0..1_000u32 {
+            map.insert(i, ());
+        }
+        assert!(map.mean_probe_length() >= 1.0 && map.mean_probe_length() < 4.0);
+        assert!(map.max_probe_length() < 32);
+
+        let mut collide = RobinHoodMap::with_hasher(BuildHasherDefault::<Constant>::default());
+        for i in 0..10u32 {
+            collide.insert(i, ());
+        }
+        assert_eq!(collide.max_probe_length(), 10);
+        assert_eq!(collide.mean_probe_length(), 5.5);
+    }
+
+    #[test]
+    fn test_get_mut() {
+        let mut map = RobinHoodMap::new();
+        map.insert("a".to_string(), 1);
+        *map.get_mut(&"a".to_string()).unwrap() += 1;
+        assert_eq!(map.get(&"a".to_string()), Some(&2));
+        assert!(map.contains_key(&"a".to_string()));
+        assert!(!map.contains_key(&"b".to_string()));
+    }
+}
@@ -17,23 +17,87 @@ where
         }
         Self { data }
     }
+}
 
-    fn sift_down(data: &mut [T], start: usize, end: usize) {
-        let mut i = start;
-        while i < end {
-            let mut smallest = i;
-            if 2 * i + 1 < end && data[smallest] > data[2 * i + 1] {
-                smallest = 2 * i + 1;
-            }
-            if 2 * i + 2 < end && data[smallest] > data[2 * i + 2] {
-                smallest = 2 * i + 2;
-            }
-            if smallest == i {
+/// Used as a priority queue, `Heap` keeps the smallest element on top.
+impl<T> Heap<T>
+where
+    T: std::cmp::PartialEq + std::cmp::PartialOrd,
+{
+    pub fn new() -> Self {
+        Self { data: Vec::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.data.first()
+    }
+
+    pub fn push(&mut self, val: T) {
+        self.data.push(val);
+        let mut i = self.data.len() - 1;
+        while i > 0 {
+            let parent = (i - 1) / 2;
+            if self.data[parent] <= self.data[i] {
                 return;
             }
-            data.swap(smallest, i);
-            i = smallest;
+            self.data.swap(parent, i);
+            i = parent;
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.data.is_empty() {
+            return None;
+        }
+        let top = self.data.swap_remove(0);
+        let l = self.data.len();
+        Self::sift_down(&mut self.data, 0, l);
+        Some(top)
+    }
+
+    fn sift_down(data: &mut [T], start: usize, end: usize) {
+        sift_down_by(data, start, end, &mut |parent, child| parent > child);
+    }
+}
+
+/// Moves `data[start]` down the binary heap in `data[..end]` until neither
+/// child should sit above it, where `out_of_order(parent, child)` says that
+/// `child` should. Shared by [`Heap`], [`heap_sort`](crate::sort::heap_sort)
+/// and [`kway_merge`](crate::merge::kway_merge).
+pub(crate) fn sift_down_by<T, F>(data: &mut [T], start: usize, end: usize, out_of_order: &mut F)
+where
+    F: FnMut(&T, &T) -> bool,
+{
+    let mut i = start;
+    while i < end {
+        let mut top = i;
+        for child in [2 * i + 1, 2 * i + 2] {
+            if child < end && out_of_order(&data[top], &data[child]) {
+                top = child;
+            }
         }
+        if top == i {
+            return;
+        }
+        data.swap(top, i);
+        i = top;
+    }
+}
+
+impl<T> Default for Heap<T>
+where
+    T: std::cmp::PartialEq + std::cmp::PartialOrd,
+{
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -47,4 +111,22 @@ mod tests {
         let heap = Heap::build_max_heap(data);
         assert_eq!(vec![7, 6, 5, 4, 3, 2, 1], heap.data);
     }
+
+    #[test]
+    fn test_push_pop() {
+        let mut heap = Heap::new();
+        for i in [5, 3, 8, 1, 9, 2, 2, 7] {
+            heap.push(i);
+        }
+        assert_eq!(heap.len(), 8);
+        assert_eq!(heap.peek(), Some(&1));
+
+        let mut out = Vec::new();
+        while let Some(x) = heap.pop() {
+            out.push(x);
+        }
+        assert_eq!(vec![1, 2, 2, 3, 5, 7, 8, 9], out);
+        assert!(heap.is_empty());
+        assert_eq!(heap.pop(), None);
+    }
 }
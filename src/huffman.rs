@@ -0,0 +1,329 @@
+//! Huffman coding of bytes with canonical codes.
+//!
+//! Codes are canonical: within each length they are consecutive and in
+//! symbol order, and each length starts right after the previous one ends.
+//! The code lengths alone therefore determine every code, so a table is
+//! stored as `(symbol, length)` pairs and rebuilt with
+//! [`HuffmanTree::from_code_lengths`].
+
+use crate::bitvec::BitVec;
+use crate::heap::Heap;
+use std::fmt;
+
+/// Longest code that fits the `u64` codes of [`HuffmanTree::code_table`].
+pub const MAX_CODE_LEN: u16 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HuffmanError {
+    /// The bits ended in the middle of a code.
+    Truncated,
+    /// The bits start a code no symbol has. Only possible if the code does
+    /// not use its whole code space, as with a single symbol.
+    InvalidCode,
+    /// Code lengths that no prefix code can have: zero, longer than
+    /// [`MAX_CODE_LEN`], more codes of some length than fit, or a symbol
+    /// listed twice.
+    InvalidLengths,
+}
+
+impl fmt::Display for HuffmanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HuffmanError::Truncated => write!(f, "input ends in the middle of a code"),
+            HuffmanError::InvalidCode => write!(f, "input contains an unassigned code"),
+            HuffmanError::InvalidLengths => write!(f, "code lengths do not form a prefix code"),
+        }
+    }
+}
+
+impl std::error::Error for HuffmanError {}
+
+/// A canonical Huffman code over bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HuffmanTree {
+    /// `(len, code)` per byte, `len == 0` for bytes without a code.
+    codes: [(u16, u64); 256],
+    /// Coded symbols ordered by (length, symbol): canonical code order.
+    symbols: Vec<u8>,
+    /// How many codes have each length, indexed by length.
+    counts: Vec<u64>,
+}
+
+impl HuffmanTree {
+    /// Builds the optimal code for the given symbol frequencies by merging
+    /// the two lightest subtrees until one is left. Symbols with frequency
+    /// 0 get no code; a lone symbol gets the 1-bit code `0`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a symbol is listed twice, or if the frequencies are so
+    /// skewed that some code would exceed [`MAX_CODE_LEN`] bits, which
+    /// takes a total frequency in the tens of trillions.
+    pub fn from_frequencies(frequencies: &[(u8, u64)]) -> Self {
+        let leaves: Vec<(u8, u64)> = frequencies
+            .iter()
+            .copied()
+            .filter(|&(_, freq)| freq > 0)
+            .collect();
+        let mut lengths = Vec::with_capacity(leaves.len());
+        if leaves.len() == 1 {
+            lengths.push((leaves[0].0, 1));
+        } else if !leaves.is_empty() {
+            // Nodes are numbered leaves first; `parent[i]` is set as node
+            // `i` is merged away. Ties go to the lower number, so the tree
+            // does not depend on the heap's handling of equal keys.
+            let mut parent = vec![usize::MAX; 2 * leaves.len() - 1];
+            let mut heap = Heap::new();
+            for (i, &(_, freq)) in leaves.iter().enumerate() {
+                heap.push((freq, i));
+            }
+            let mut next = leaves.len();
+            while let (Some((a_freq, a)), Some((b_freq, b))) = (heap.pop(), heap.pop()) {
+                parent[a] = next;
+                parent[b] = next;
+                heap.push((a_freq + b_freq, next));
+                next += 1;
+            }
+            // Parents are numbered after their children, so a pass from the
+            // root down fills every depth from its parent's.
+            let mut depth = vec![0u16; parent.len()];
+            for node in (0..parent.len() - 1).rev() {
+                depth[node] = depth[parent[node]] + 1;
+            }
+            for (i, &(symbol, _)) in leaves.iter().enumerate() {
+                assert!(
+                    depth[i] <= MAX_CODE_LEN,
+                    "code for {symbol} would be {} bits long",
+                    depth[i]
+                );
+                lengths.push((symbol, depth[i]));
+            }
+        }
+        Self::from_code_lengths(&lengths).expect("symbol listed twice")
+    }
+
+    /// Rebuilds the canonical code with the given `(symbol, length)` pairs,
+    /// as taken from [`code_table`](Self::code_table).
+    pub fn from_code_lengths(lengths: &[(u8, u16)]) -> Result<Self, HuffmanError> {
+        let mut sorted = lengths.to_vec();
+        sorted.sort_unstable_by_key(|&(symbol, len)| (len, symbol));
+        let max_len = sorted.last().map_or(0, |&(_, len)| len);
+        if sorted.first().is_some_and(|&(_, len)| len == 0) || max_len > MAX_CODE_LEN {
+            return Err(HuffmanError::InvalidLengths);
+        }
+
+        let mut codes = [(0, 0); 256];
+        let mut counts = vec![0u64; max_len as usize + 1];
+        let mut code: u128 = 0;
+        let mut prev_len = 0;
+        for &(symbol, len) in &sorted {
+            code <<= len - prev_len;
+            prev_len = len;
+            if code >> len != 0 || codes[symbol as usize].0 != 0 {
+                return Err(HuffmanError::InvalidLengths);
+            }
+            codes[symbol as usize] = (len, code as u64);
+            counts[len as usize] += 1;
+            code += 1;
+        }
+        Ok(Self {
+            codes,
+            symbols: sorted.into_iter().map(|(symbol, _)| symbol).collect(),
+            counts,
+        })
+    }
+
+    /// `(symbol, length, code)` for every coded symbol, in canonical order.
+    /// The code is the low `length` bits, written most significant first.
+    pub fn code_table(&self) -> Vec<(u8, u16, u64)> {
+        self.symbols
+            .iter()
+            .map(|&symbol| {
+                let (len, code) = self.codes[symbol as usize];
+                (symbol, len, code)
+            })
+            .collect()
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `data` contains a byte with no code.
+    pub fn encode(&self, data: &[u8]) -> BitVec {
+        let mut bits = BitVec::new();
+        for &byte in data {
+            let (len, code) = self.codes[byte as usize];
+            assert!(len > 0, "byte {byte} has no code");
+            for shift in (0..len).rev() {
+                bits.push(code >> shift & 1 == 1);
+            }
+        }
+        bits
+    }
+
+    pub fn decode(&self, bits: &BitVec) -> Result<Vec<u8>, HuffmanError> {
+        let mut out = Vec::new();
+        let mut bits = bits.iter();
+        while let Some(bit) = bits.next() {
+            if self.symbols.is_empty() {
+                return Err(HuffmanError::InvalidCode);
+            }
+            // Walk the lengths, tracking the first code of each and how many
+            // symbols come before it in canonical order.
+            let mut code = bit as u64;
+            let mut first = 0u64;
+            let mut index = 0u64;
+            let mut len = 1;
+            loop {
+                let count = self.counts[len];
+                if code - first < count {
+                    out.push(self.symbols[(index + code - first) as usize]);
+                    break;
+                }
+                if len + 1 == self.counts.len() {
+                    return Err(HuffmanError::InvalidCode);
+                }
+                index += count;
+                first = (first + count) << 1;
+                let bit = bits.next().ok_or(HuffmanError::Truncated)?;
+                code = code << 1 | bit as u64;
+                len += 1;
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{HuffmanError, HuffmanTree};
+    use crate::bitvec::BitVec;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn frequencies(data: &[u8]) -> Vec<(u8, u64)> {
+        let mut counts = [0u64; 256];
+        for &byte in data {
+            counts[byte as usize] += 1;
+        }
+        (0..=255u8).map(|b| (b, counts[b as usize])).collect()
+    }
+
+    fn round_trip(data: &[u8]) -> HuffmanTree {
+        let tree = HuffmanTree::from_frequencies(&frequencies(data));
+        let bits = tree.encode(data);
+        assert_eq!(tree.decode(&bits).unwrap(), data);
+        tree
+    }
+
+    #[test]
+    fn test_round_trip_random() {
+        let mut rng = StdRng::seed_from_u64(244);
+        for len in [1, 2, 10, 1_000, 20_000] {
+            let data: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            round_trip(&data);
+        }
+    }
+
+    #[test]
+    fn test_skewed_compresses() {
+        let mut rng = StdRng::seed_from_u64(2440);
+        // Geometric: each byte about half as likely as the one before.
+        let data: Vec<u8> = (0..50_000)
+            .map(|_| (rng.gen::<u32>() | 1 << 31).trailing_zeros() as u8)
+            .collect();
+        let tree = round_trip(&data);
+        let bits_per_byte = tree.encode(&data).len() as f64 / data.len() as f64;
+        assert!(bits_per_byte < 2.1, "{bits_per_byte} bits per byte");
+    }
+
+    #[test]
+    fn test_single_symbol() {
+        let tree = round_trip(&[b'x'; 17]);
+        assert_eq!(tree.code_table(), [(b'x', 1, 0)]);
+        assert_eq!(tree.encode(b"xx").len(), 2);
+
+        let mut bits = BitVec::new();
+        bits.push(true);
+        assert_eq!(tree.decode(&bits), Err(HuffmanError::InvalidCode));
+    }
+
+    #[test]
+    fn test_empty() {
+        let tree = round_trip(&[]);
+        assert!(tree.code_table().is_empty());
+        let tree = HuffmanTree::from_frequencies(&[]);
+        assert!(tree.encode(&[]).is_empty());
+        assert_eq!(tree.decode(&BitVec::new()), Ok(vec![]));
+        let bits: BitVec = [false].into_iter().collect();
+        assert_eq!(tree.decode(&bits), Err(HuffmanError::InvalidCode));
+    }
+
+    #[test]
+    fn test_canonical_prefix_free() {
+        let mut rng = StdRng::seed_from_u64(2441);
+        for _ in 0..50 {
+            let freqs: Vec<(u8, u64)> = (0..=255u8)
+                .map(|b| {
+                    let bits = rng.gen_range(1..20);
+                    (b, rng.gen_range(0..1u64 << bits))
+                })
+                .collect();
+            let tree = HuffmanTree::from_frequencies(&freqs);
+            let table = tree.code_table();
+            for (i, &(_, len_a, code_a)) in table.iter().enumerate() {
+                for &(_, len_b, code_b) in &table[i + 1..] {
+                    // Canonical order is by length, so only the later code
+                    // can have the earlier one as a prefix.
+                    assert!(len_a <= len_b);
+                    assert_ne!(code_b >> (len_b - len_a), code_a);
+                }
+            }
+            // Kraft equality: a Huffman code wastes no code space.
+            let kraft: f64 = table
+                .iter()
+                .map(|&(_, len, _)| 0.5f64.powi(len as i32))
+                .sum();
+            assert!((kraft - 1.0).abs() < 1e-9);
+
+            let lengths: Vec<(u8, u16)> = table.iter().map(|&(s, len, _)| (s, len)).collect();
+            assert_eq!(HuffmanTree::from_code_lengths(&lengths), Ok(tree));
+        }
+    }
+
+    #[test]
+    fn test_known_code() {
+        let tree = HuffmanTree::from_frequencies(&[(b'a', 5), (b'b', 2), (b'c', 1), (b'd', 1)]);
+        assert_eq!(
+            tree.code_table(),
+            [
+                (b'a', 1, 0b0),
+                (b'b', 2, 0b10),
+                (b'c', 3, 0b110),
+                (b'd', 3, 0b111)
+            ]
+        );
+        let bits = tree.encode(b"abcd");
+        assert_eq!(bits.len(), 9);
+        let mut truncated: BitVec = bits.iter().take(8).collect();
+        assert_eq!(tree.decode(&truncated), Err(HuffmanError::Truncated));
+        truncated.push(true);
+        assert_eq!(tree.decode(&truncated).unwrap(), b"abcd");
+    }
+
+    #[test]
+    fn test_invalid_lengths() {
+        let too_many = [(0, 1), (1, 1), (2, 1)];
+        assert_eq!(
+            HuffmanTree::from_code_lengths(&too_many),
+            Err(HuffmanError::InvalidLengths)
+        );
+        assert_eq!(
+            HuffmanTree::from_code_lengths(&[(0, 0)]),
+            Err(HuffmanError::InvalidLengths)
+        );
+        assert_eq!(
+            HuffmanTree::from_code_lengths(&[(0, 1), (0, 2)]),
+            Err(HuffmanError::InvalidLengths)
+        );
+    }
+}
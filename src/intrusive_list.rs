@@ -0,0 +1,405 @@
+//! A doubly linked list threaded through the elements themselves.
+//!
+//! An element embeds a [`Links`] field and implements [`Linked`] to point
+//! the list at it; the list then links borrowed elements together without
+//! allocating or owning them. Elements are borrowed for the list's whole
+//! lifetime `'a`, so none can be dropped or moved while linked, and each
+//! `Links` records which list it is in, so pushing an element that is
+//! already in a list, or removing one through a list it is not in, panics
+//! instead of corrupting either list.
+
+use std::{
+    cell::Cell,
+    fmt,
+    iter::FusedIterator,
+    marker::PhantomData,
+    ptr::NonNull,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Hands out list ids; 0 marks an element that is in no list.
+static NEXT_LIST_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// The links an element embeds to be put in an [`IntrusiveList`].
+pub struct Links<T> {
+    prev: Cell<Option<NonNull<T>>>,
+    next: Cell<Option<NonNull<T>>>,
+    list: Cell<usize>,
+}
+
+impl<T> Links<T> {
+    pub const fn new() -> Self {
+        Self {
+            prev: Cell::new(None),
+            next: Cell::new(None),
+            list: Cell::new(0),
+        }
+    }
+
+    /// Whether the element is in a list right now.
+    pub fn is_linked(&self) -> bool {
+        self.list.get() != 0
+    }
+
+    fn reset(&self) {
+        self.prev.set(None);
+        self.next.set(None);
+        self.list.set(0);
+    }
+}
+
+impl<T> Default for Links<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for Links<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Links")
+            .field("linked", &self.is_linked())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Types that can be linked into an [`IntrusiveList`].
+///
+/// # Safety
+///
+/// `links` must always return the same [`Links`], owned by `self` and by
+/// no other element. The list relies on it to find its way between
+/// elements and to tell which list an element is in.
+pub unsafe trait Linked: Sized {
+    fn links(&self) -> &Links<Self>;
+}
+
+/// A list of elements borrowed for `'a`, linked through their [`Links`].
+pub struct IntrusiveList<'a, T: Linked> {
+    len: usize,
+    head: Option<NonNull<T>>,
+    tail: Option<NonNull<T>>,
+    id: usize,
+    _marker: PhantomData<&'a T>,
+}
+
+impl<'a, T: Linked> IntrusiveList<'a, T> {
+    pub fn new() -> Self {
+        Self {
+            len: 0,
+            head: None,
+            tail: None,
+            id: NEXT_LIST_ID.fetch_add(1, Ordering::Relaxed),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `elem` is already in a list.
+    pub fn push_front(&mut self, elem: &'a T) {
+        self.link(elem, None, self.head);
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `elem` is already in a list.
+    pub fn push_back(&mut self, elem: &'a T) {
+        self.link(elem, self.tail, None);
+    }
+
+    pub fn pop_front(&mut self) -> Option<&'a T> {
+        let elem = self.front()?;
+        self.unlink(elem);
+        Some(elem)
+    }
+
+    pub fn pop_back(&mut self) -> Option<&'a T> {
+        let elem = self.back()?;
+        self.unlink(elem);
+        Some(elem)
+    }
+
+    /// Unlinks `elem`, leaving it free to join another list.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `elem` is not in this list.
+    pub fn remove(&mut self, elem: &T) {
+        assert!(self.contains(elem), "element is not in this list");
+        self.unlink(elem);
+    }
+
+    /// Whether `elem` is in this list, in O(1).
+    pub fn contains(&self, elem: &T) -> bool {
+        elem.links().list.get() == self.id
+    }
+
+    pub fn front(&self) -> Option<&'a T> {
+        self.head.map(|elem| unsafe { Self::get(elem) })
+    }
+
+    pub fn back(&self) -> Option<&'a T> {
+        self.tail.map(|elem| unsafe { Self::get(elem) })
+    }
+
+    pub fn iter(&self) -> Iter<'_, 'a, T> {
+        Iter {
+            next: self.head,
+            len: self.len,
+            _marker: PhantomData,
+        }
+    }
+
+    /// # Safety
+    ///
+    /// `elem` must have been linked into this list and so be borrowed
+    /// for `'a`.
+    unsafe fn get(elem: NonNull<T>) -> &'a T {
+        unsafe { &*elem.as_ptr() }
+    }
+
+    fn link(&mut self, elem: &'a T, prev: Option<NonNull<T>>, next: Option<NonNull<T>>) {
+        let links = elem.links();
+        assert!(!links.is_linked(), "element is already in a list");
+        links.list.set(self.id);
+        links.prev.set(prev);
+        links.next.set(next);
+        let ptr = Some(NonNull::from(elem));
+        match prev {
+            Some(prev) => unsafe { Self::get(prev) }.links().next.set(ptr),
+            None => self.head = ptr,
+        }
+        match next {
+            Some(next) => unsafe { Self::get(next) }.links().prev.set(ptr),
+            None => self.tail = ptr,
+        }
+        self.len += 1;
+    }
+
+    /// Unlinks `elem`, which must be in this list.
+    fn unlink(&mut self, elem: &T) {
+        let links = elem.links();
+        let (prev, next) = (links.prev.get(), links.next.get());
+        match prev {
+            Some(prev) => unsafe { Self::get(prev) }.links().next.set(next),
+            None => self.head = next,
+        }
+        match next {
+            Some(next) => unsafe { Self::get(next) }.links().prev.set(prev),
+            None => self.tail = prev,
+        }
+        links.reset();
+        self.len -= 1;
+    }
+}
+
+impl<T: Linked> Default for IntrusiveList<'_, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Linked> Drop for IntrusiveList<'_, T> {
+    /// Unlinks every element, so each can join another list afterwards.
+    fn drop(&mut self) {
+        while self.pop_front().is_some() {}
+    }
+}
+
+impl<T: Linked + fmt::Debug> fmt::Debug for IntrusiveList<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, T: Linked> Extend<&'a T> for IntrusiveList<'a, T> {
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        for elem in iter {
+            self.push_back(elem);
+        }
+    }
+}
+
+impl<'b, 'a, T: Linked> IntoIterator for &'b IntrusiveList<'a, T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'b, 'a, T>;
+
+    fn into_iter(self) -> Iter<'b, 'a, T> {
+        self.iter()
+    }
+}
+
+/// Iterates front to back. Borrowing the list for `'b` keeps it from
+/// changing underneath; the elements themselves are borrowed for `'a`.
+pub struct Iter<'b, 'a, T> {
+    next: Option<NonNull<T>>,
+    len: usize,
+    _marker: PhantomData<(&'b (), &'a T)>,
+}
+
+impl<'a, T: Linked> Iterator for Iter<'_, 'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let elem = unsafe { IntrusiveList::<'a, T>::get(self.next?) };
+        self.next = elem.links().next.get();
+        self.len -= 1;
+        Some(elem)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T: Linked> ExactSizeIterator for Iter<'_, '_, T> {}
+
+impl<T: Linked> FusedIterator for Iter<'_, '_, T> {}
+
+#[cfg(test)]
+mod test {
+    use super::{IntrusiveList, Linked, Links};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::VecDeque;
+
+    #[derive(Default)]
+    struct Job {
+        id: usize,
+        links: Links<Job>,
+    }
+
+    unsafe impl Linked for Job {
+        fn links(&self) -> &Links<Job> {
+            &self.links
+        }
+    }
+
+    impl std::fmt::Debug for Job {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "{}", self.id)
+        }
+    }
+
+    fn jobs(n: usize) -> Vec<Job> {
+        (0..n)
+            .map(|id| Job {
+                id,
+                ..Job::default()
+            })
+            .collect()
+    }
+
+    fn ids(list: &IntrusiveList<Job>) -> Vec<usize> {
+        let forward: Vec<usize> = list.iter().map(|job| job.id).collect();
+        assert_eq!(forward.len(), list.len());
+        assert_eq!(list.front().map(|job| job.id), forward.first().copied());
+        assert_eq!(list.back().map(|job| job.id), forward.last().copied());
+        forward
+    }
+
+    #[test]
+    fn test_against_vec_deque() {
+        let mut rng = StdRng::seed_from_u64(287);
+        let jobs = jobs(64);
+        let mut list: IntrusiveList<Job> = IntrusiveList::new();
+        let mut plain: VecDeque<usize> = VecDeque::new();
+        for _ in 0..5_000 {
+            let job = &jobs[rng.gen_range(0..jobs.len())];
+            if job.links.is_linked() {
+                match rng.gen_range(0..3) {
+                    0 => assert_eq!(list.pop_front().map(|j| j.id), plain.pop_front()),
+                    1 => assert_eq!(list.pop_back().map(|j| j.id), plain.pop_back()),
+                    _ => {
+                        list.remove(job);
+                        plain.retain(|&id| id != job.id);
+                    }
+                }
+            } else if rng.gen_bool(0.5) {
+                list.push_front(job);
+                plain.push_front(job.id);
+            } else {
+                list.push_back(job);
+                plain.push_back(job.id);
+            }
+            assert_eq!(list.len(), plain.len());
+        }
+        assert_eq!(ids(&list), Vec::from(plain));
+    }
+
+    #[test]
+    fn test_basic() {
+        let jobs = jobs(4);
+        let mut list = IntrusiveList::new();
+        list.extend(&jobs[1..3]);
+        list.push_front(&jobs[0]);
+        list.push_back(&jobs[3]);
+        assert_eq!(format!("{:?}", list), "[0, 1, 2, 3]");
+        assert!(list.contains(&jobs[2]));
+
+        list.remove(&jobs[2]);
+        assert!(!list.contains(&jobs[2]));
+        assert!(!jobs[2].links.is_linked());
+        assert_eq!(ids(&list), [0, 1, 3]);
+        list.remove(&jobs[0]);
+        list.remove(&jobs[3]);
+        assert_eq!(ids(&list), [1]);
+        assert_eq!(list.pop_back().map(|job| job.id), Some(1));
+        assert!(list.is_empty());
+        assert!(list.pop_front().is_none());
+        assert!(ids(&list).is_empty());
+    }
+
+    #[test]
+    fn test_relink_after_drop() {
+        let jobs = jobs(3);
+        {
+            let mut list = IntrusiveList::new();
+            list.extend(&jobs);
+            assert!(jobs.iter().all(|job| job.links.is_linked()));
+        }
+        assert!(jobs.iter().all(|job| !job.links.is_linked()));
+
+        let mut list = IntrusiveList::new();
+        list.extend(jobs.iter().rev());
+        assert_eq!(ids(&list), [2, 1, 0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "element is already in a list")]
+    fn test_push_into_two_lists() {
+        let jobs = jobs(1);
+        let mut a = IntrusiveList::new();
+        let mut b = IntrusiveList::new();
+        a.push_back(&jobs[0]);
+        b.push_back(&jobs[0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "element is already in a list")]
+    fn test_push_twice() {
+        let jobs = jobs(1);
+        let mut list = IntrusiveList::new();
+        list.push_back(&jobs[0]);
+        list.push_front(&jobs[0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "element is not in this list")]
+    fn test_remove_from_other_list() {
+        let jobs = jobs(2);
+        let mut a = IntrusiveList::new();
+        let mut b = IntrusiveList::new();
+        a.push_back(&jobs[0]);
+        b.push_back(&jobs[1]);
+        a.remove(&jobs[1]);
+    }
+}
@@ -1,4 +1,42 @@
+pub mod arena;
+pub mod bitvec;
+pub mod bloom;
+pub mod cache;
+pub mod circular_list;
+pub mod clock;
+pub mod clock_cache;
 pub mod concurrent_lru;
+pub mod deque;
 mod double_linkedlist;
-mod heap;
-mod lru;
+pub mod hashmap;
+pub mod heap;
+pub mod huffman;
+pub mod intrusive_list;
+pub mod lockfree;
+pub mod lru;
+pub mod lru_k;
+pub mod merge;
+pub mod merkle;
+pub mod min_queue;
+pub mod min_stack;
+pub mod monotonic;
+pub mod mpmc;
+pub mod mpsc_queue;
+pub mod order_stat;
+pub mod persistent;
+pub mod range_set;
+pub mod rate_limit;
+pub mod rendezvous;
+pub mod rle;
+pub mod s3fifo;
+pub mod sample;
+pub mod search;
+pub mod singly_list;
+pub mod slab;
+pub mod slab_list;
+pub mod sort;
+pub mod succinct;
+mod sync;
+pub mod ttl_map;
+pub mod window;
+pub mod xor_filter;
@@ -0,0 +1,10 @@
+//! Lock-free structures built on epoch-based reclamation.
+//!
+//! Loom models for these run with
+//! `RUSTFLAGS="--cfg loom --cfg crossbeam_loom" cargo test --release --lib lockfree`.
+
+mod queue;
+mod stack;
+
+pub use queue::Queue;
+pub use stack::Stack;
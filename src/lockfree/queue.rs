@@ -0,0 +1,365 @@
+use crossbeam_epoch::{self as epoch, Atomic, Owned, Shared};
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::sync::atomic::Ordering;
+
+struct Node<T> {
+    /// Uninitialized in the dummy node at the head of the list, and moved out
+    /// by the `pop` that turns a node into the new dummy.
+    val: MaybeUninit<T>,
+    next: Atomic<Node<T>>,
+}
+
+/// The Michael-Scott lock-free FIFO queue.
+///
+/// `head` always points at a dummy node whose successor is the front of the
+/// queue, so push only ever touches the tail end and pop the head end. `tail`
+/// may lag one node behind the real last node; whoever observes that helps
+/// swing it forward before retrying. Unlinked dummies are freed through
+/// crossbeam-epoch.
+pub struct Queue<T> {
+    head: Atomic<Node<T>>,
+    tail: Atomic<Node<T>>,
+}
+
+unsafe impl<T: Send> Send for Queue<T> {}
+unsafe impl<T: Send> Sync for Queue<T> {}
+
+impl<T> Queue<T> {
+    pub fn new() -> Self {
+        let queue = Self {
+            head: Atomic::null(),
+            tail: Atomic::null(),
+        };
+        let dummy = Owned::new(Node {
+            val: MaybeUninit::uninit(),
+            next: Atomic::null(),
+        });
+        unsafe {
+            let guard = epoch::unprotected();
+            let dummy = dummy.into_shared(guard);
+            queue.head.store(dummy, Ordering::Relaxed);
+            queue.tail.store(dummy, Ordering::Relaxed);
+        }
+        queue
+    }
+
+    pub fn push(&self, val: T) {
+        let guard = epoch::pin();
+        let new = Owned::new(Node {
+            val: MaybeUninit::new(val),
+            next: Atomic::null(),
+        })
+        .into_shared(&guard);
+
+        loop {
+            let tail = self.tail.load(Ordering::Acquire, &guard);
+            let tail_ref = unsafe { tail.deref() };
+            let next = tail_ref.next.load(Ordering::Acquire, &guard);
+            if !next.is_null() {
+                // `tail` is lagging behind a push that linked its node but
+                // has not swung the tail yet.
+                let _ = self.tail.compare_exchange(
+                    tail,
+                    next,
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                    &guard,
+                );
+                continue;
+            }
+            // Release publishes the node's value to the popper that acquires
+            // the link.
+            if tail_ref
+                .next
+                .compare_exchange(
+                    Shared::null(),
+                    new,
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                    &guard,
+                )
+                .is_ok()
+            {
+                // Failure means another thread already helped.
+                let _ = self.tail.compare_exchange(
+                    tail,
+                    new,
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                    &guard,
+                );
+                return;
+            }
+        }
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let guard = epoch::pin();
+        loop {
+            let head = self.head.load(Ordering::Acquire, &guard);
+            let next = unsafe { head.deref() }.next.load(Ordering::Acquire, &guard);
+            let next_ref = unsafe { next.as_ref() }?;
+
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::Release, Ordering::Relaxed, &guard)
+                .is_ok()
+            {
+                // Never leave `tail` pointing at the dummy we are retiring.
+                let tail = self.tail.load(Ordering::Relaxed, &guard);
+                if head == tail {
+                    let _ = self.tail.compare_exchange(
+                        tail,
+                        next,
+                        Ordering::Release,
+                        Ordering::Relaxed,
+                        &guard,
+                    );
+                }
+                unsafe {
+                    // `next` becomes the dummy; its value is ours alone.
+                    let val = ptr::read(next_ref.val.as_ptr());
+                    guard.defer_destroy(head);
+                    return Some(val);
+                }
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let guard = epoch::pin();
+        let head = self.head.load(Ordering::Acquire, &guard);
+        unsafe { head.deref() }
+            .next
+            .load(Ordering::Acquire, &guard)
+            .is_null()
+    }
+}
+
+impl<T> Default for Queue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for Queue<T> {
+    fn drop(&mut self) {
+        unsafe {
+            // `&mut self` means no other thread can reach the nodes.
+            let guard = epoch::unprotected();
+            let dummy = self.head.load(Ordering::Relaxed, guard).into_owned();
+            let mut cur = dummy.next.load(Ordering::Relaxed, guard);
+            drop(dummy);
+            while !cur.is_null() {
+                let mut node = cur.into_owned();
+                cur = node.next.load(Ordering::Relaxed, guard);
+                node.val.assume_init_drop();
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::Queue;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    struct DropCounter(Arc<AtomicUsize>);
+
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_fifo() {
+        let queue = Queue::new();
+        assert!(queue.is_empty());
+        assert_eq!(queue.pop(), None);
+        for i in 0..5 {
+            queue.push(i);
+        }
+        assert!(!queue.is_empty());
+        for i in 0..5 {
+            assert_eq!(queue.pop(), Some(i));
+        }
+        assert_eq!(queue.pop(), None);
+        queue.push(5);
+        assert_eq!(queue.pop(), Some(5));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_per_producer_order() {
+        const PRODUCERS: usize = 4;
+        const PER_PRODUCER: usize = 20_000;
+
+        let queue = Arc::new(Queue::new());
+        let handles: Vec<_> = (0..PRODUCERS)
+            .map(|p| {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    for i in 0..PER_PRODUCER {
+                        queue.push((p, i));
+                    }
+                })
+            })
+            .collect();
+
+        // A single consumer racing the producers sees each producer's values
+        // in the order they were pushed.
+        let mut next = [0; PRODUCERS];
+        let mut received = 0;
+        while received < PRODUCERS * PER_PRODUCER {
+            if let Some((p, i)) = queue.pop() {
+                assert_eq!(i, next[p], "producer {p} out of order");
+                next[p] += 1;
+                received += 1;
+            }
+        }
+        handles.into_iter().for_each(|h| h.join().unwrap());
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_conservation() {
+        const THREADS: usize = 4;
+        const PER_THREAD: usize = 20_000;
+        const TOTAL: usize = THREADS * PER_THREAD;
+
+        let queue = Arc::new(Queue::new());
+        let seen: Arc<Vec<AtomicUsize>> =
+            Arc::new((0..TOTAL).map(|_| AtomicUsize::new(0)).collect());
+        let received = Arc::new(AtomicUsize::new(0));
+
+        let mut handles = Vec::new();
+        for t in 0..THREADS {
+            let producer = queue.clone();
+            handles.push(thread::spawn(move || {
+                for i in t * PER_THREAD..(t + 1) * PER_THREAD {
+                    producer.push(i);
+                }
+            }));
+            let (queue, seen, received) = (queue.clone(), seen.clone(), received.clone());
+            handles.push(thread::spawn(move || {
+                while received.load(Ordering::Relaxed) < TOTAL {
+                    if let Some(v) = queue.pop() {
+                        seen[v].fetch_add(1, Ordering::Relaxed);
+                        received.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }));
+        }
+        handles.into_iter().for_each(|h| h.join().unwrap());
+        assert!(seen.iter().all(|c| c.load(Ordering::Relaxed) == 1));
+    }
+
+    #[test]
+    fn test_empty_races() {
+        // Pops race single pushes so the queue keeps bouncing between empty
+        // and one element, exercising the dummy and lagging tail paths.
+        let queue = Arc::new(Queue::new());
+        let done = Arc::new(AtomicBool::new(false));
+        let popped = Arc::new(AtomicUsize::new(0));
+
+        let poppers: Vec<_> = (0..3)
+            .map(|_| {
+                let (queue, done, popped) = (queue.clone(), done.clone(), popped.clone());
+                thread::spawn(move || {
+                    while !done.load(Ordering::Acquire) {
+                        if queue.pop().is_some() {
+                            popped.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for i in 0..50_000 {
+            queue.push(i);
+        }
+        done.store(true, Ordering::Release);
+        poppers.into_iter().for_each(|h| h.join().unwrap());
+
+        let mut rest = 0;
+        while queue.pop().is_some() {
+            rest += 1;
+        }
+        assert_eq!(popped.load(Ordering::Relaxed) + rest, 50_000);
+    }
+
+    #[test]
+    fn test_no_leaks() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        {
+            let queue = Queue::new();
+            for _ in 0..100 {
+                queue.push(DropCounter(drops.clone()));
+            }
+            for _ in 0..30 {
+                drop(queue.pop());
+            }
+            assert_eq!(drops.load(Ordering::Relaxed), 30);
+        }
+        // The dummy holds no value, so exactly the remaining 70 drop here.
+        assert_eq!(drops.load(Ordering::Relaxed), 100);
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_test {
+    use super::Queue;
+    use loom::sync::Arc;
+    use loom::thread;
+
+    fn model(f: impl Fn() + Sync + Send + 'static) {
+        // The epoch collector adds a lot of atomics of its own; bounding
+        // preemptions keeps the search tractable.
+        let mut builder = loom::model::Builder::new();
+        builder.preemption_bound = Some(3);
+        builder.check(f);
+    }
+
+    #[test]
+    fn test_concurrent_push() {
+        model(|| {
+            let queue = Arc::new(Queue::new());
+            let other = {
+                let queue = queue.clone();
+                thread::spawn(move || queue.push(1))
+            };
+            queue.push(2);
+            other.join().unwrap();
+
+            let mut got = vec![queue.pop().unwrap(), queue.pop().unwrap()];
+            got.sort();
+            assert_eq!(got, vec![1, 2]);
+            assert_eq!(queue.pop(), None);
+        });
+    }
+
+    #[test]
+    fn test_push_races_pop() {
+        model(|| {
+            let queue = Arc::new(Queue::new());
+            queue.push(1);
+            let other = {
+                let queue = queue.clone();
+                thread::spawn(move || queue.push(2))
+            };
+            // 2 may or may not be visible yet, but it is never lost or
+            // duplicated and never overtakes 1.
+            assert_eq!(queue.pop(), Some(1));
+            let second = queue.pop();
+            other.join().unwrap();
+            match second {
+                Some(v) => assert_eq!(v, 2),
+                None => assert_eq!(queue.pop(), Some(2)),
+            }
+            assert_eq!(queue.pop(), None);
+        });
+    }
+}
@@ -0,0 +1,217 @@
+use crossbeam_epoch::{self as epoch, Atomic, Owned};
+use std::mem::ManuallyDrop;
+use std::ptr;
+use std::sync::atomic::Ordering;
+
+struct Node<T> {
+    /// Moved out by the `pop` that unlinks the node, so the node itself can
+    /// be freed later by the collector without touching the value.
+    val: ManuallyDrop<T>,
+    next: Atomic<Node<T>>,
+}
+
+/// Treiber's lock-free stack.
+///
+/// Push and pop are a CAS loop on the head pointer. A popped node is freed
+/// through crossbeam-epoch once no pinned thread can still be reading it,
+/// which is also what rules out ABA: an address cannot be reused while a
+/// thread that loaded it is still pinned.
+pub struct Stack<T> {
+    head: Atomic<Node<T>>,
+}
+
+unsafe impl<T: Send> Send for Stack<T> {}
+unsafe impl<T: Send> Sync for Stack<T> {}
+
+impl<T> Stack<T> {
+    pub fn new() -> Self {
+        Self {
+            head: Atomic::null(),
+        }
+    }
+
+    pub fn push(&self, val: T) {
+        let mut node = Owned::new(Node {
+            val: ManuallyDrop::new(val),
+            next: Atomic::null(),
+        });
+        let guard = epoch::pin();
+        loop {
+            let head = self.head.load(Ordering::Relaxed, &guard);
+            node.next.store(head, Ordering::Relaxed);
+            // Release publishes the node's contents to the popper that
+            // acquires it.
+            match self.head.compare_exchange(
+                head,
+                node,
+                Ordering::Release,
+                Ordering::Relaxed,
+                &guard,
+            ) {
+                Ok(_) => return,
+                Err(e) => node = e.new,
+            }
+        }
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let guard = epoch::pin();
+        loop {
+            let head = self.head.load(Ordering::Acquire, &guard);
+            let node = unsafe { head.as_ref() }?;
+            let next = node.next.load(Ordering::Relaxed, &guard);
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::Relaxed, Ordering::Relaxed, &guard)
+                .is_ok()
+            {
+                unsafe {
+                    // Only the thread whose CAS unlinked the node gets here,
+                    // so the value is moved out exactly once.
+                    let val = ManuallyDrop::into_inner(ptr::read(&node.val));
+                    guard.defer_destroy(head);
+                    return Some(val);
+                }
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let guard = epoch::pin();
+        self.head.load(Ordering::Acquire, &guard).is_null()
+    }
+}
+
+impl<T> Default for Stack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for Stack<T> {
+    fn drop(&mut self) {
+        unsafe {
+            // `&mut self` means no other thread can reach the nodes.
+            let guard = epoch::unprotected();
+            let mut cur = self.head.load(Ordering::Relaxed, guard);
+            while !cur.is_null() {
+                let mut node = cur.into_owned();
+                cur = node.next.load(Ordering::Relaxed, guard);
+                ManuallyDrop::drop(&mut node.val);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::Stack;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    struct DropCounter(Arc<AtomicUsize>);
+
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_lifo() {
+        let stack = Stack::new();
+        assert!(stack.is_empty());
+        for i in 0..5 {
+            stack.push(i);
+        }
+        assert!(!stack.is_empty());
+        for i in (0..5).rev() {
+            assert_eq!(stack.pop(), Some(i));
+        }
+        assert_eq!(stack.pop(), None);
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn test_conservation() {
+        const THREADS: usize = 8;
+        const PER_THREAD: usize = 10_000;
+
+        let stack = Arc::new(Stack::new());
+        let popped: Arc<Vec<AtomicUsize>> = Arc::new(
+            (0..THREADS * PER_THREAD)
+                .map(|_| AtomicUsize::new(0))
+                .collect(),
+        );
+
+        let handles: Vec<_> = (0..THREADS)
+            .map(|t| {
+                let (stack, popped) = (stack.clone(), popped.clone());
+                thread::spawn(move || {
+                    // Interleave pushes and pops so both race on the head.
+                    for i in t * PER_THREAD..(t + 1) * PER_THREAD {
+                        stack.push(i);
+                        if i % 2 == 0 {
+                            if let Some(v) = stack.pop() {
+                                popped[v].fetch_add(1, Ordering::Relaxed);
+                            }
+                        }
+                    }
+                })
+            })
+            .collect();
+        handles.into_iter().for_each(|h| h.join().unwrap());
+
+        while let Some(v) = stack.pop() {
+            popped[v].fetch_add(1, Ordering::Relaxed);
+        }
+        assert!(popped.iter().all(|c| c.load(Ordering::Relaxed) == 1));
+    }
+
+    #[test]
+    fn test_no_leaks() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        {
+            let stack = Stack::new();
+            for _ in 0..100 {
+                stack.push(DropCounter(drops.clone()));
+            }
+            for _ in 0..40 {
+                drop(stack.pop());
+            }
+            assert_eq!(drops.load(Ordering::Relaxed), 40);
+        }
+        // The 60 values still on the stack are dropped with it.
+        assert_eq!(drops.load(Ordering::Relaxed), 100);
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_test {
+    use super::Stack;
+    use loom::sync::Arc;
+    use loom::thread;
+
+    #[test]
+    fn test_push_pop_two_threads() {
+        loom::model(|| {
+            let stack = Arc::new(Stack::new());
+            stack.push(1);
+            let other = {
+                let stack = stack.clone();
+                thread::spawn(move || {
+                    stack.push(2);
+                    stack.pop()
+                })
+            };
+            let mine = stack.pop();
+            let theirs = other.join().unwrap();
+
+            let mut got: Vec<_> = [mine, theirs, stack.pop()].into_iter().flatten().collect();
+            got.sort();
+            assert_eq!(got, vec![1, 2]);
+            assert!(stack.is_empty());
+        });
+    }
+}
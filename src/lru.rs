@@ -1,291 +1,5471 @@
-use std::{collections::HashMap, marker::PhantomData, mem, ptr::NonNull};
+use crate::cache::{Cache, ResidentKeys};
+use crate::clock::{Clock, SystemClock};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    alloc::{self, Layout},
+    borrow::Borrow,
+    cmp::Ordering,
+    collections::HashMap,
+    convert::Infallible,
+    hash::{Hash, Hasher},
+    iter::FusedIterator,
+    marker::PhantomData,
+    mem,
+    panic::{self, AssertUnwindSafe},
+    ptr::{self, NonNull},
+    rc::Rc,
+    time::{Duration, Instant},
+};
 
+#[cfg(debug_assertions)]
+use std::sync::atomic::{self, AtomicUsize};
+
+// Nodes are only ever reached through `as_ptr`, one field at a time, and
+// never through a reference to a whole node. A `&T` or `&mut T` handed out
+// for a value therefore stays valid while the links next to it are
+// rewritten, as `LRUCache::get` relies on.
 pub struct Node<T> {
     val: T,
     next: Option<NonNull<Node<T>>>,
     prev: Option<NonNull<Node<T>>>,
+    /// The id of the list the node was last linked into, checked against
+    /// the list a [`NodeHandle`] is used with.
+    #[cfg(debug_assertions)]
+    list: usize,
+}
+
+impl<T> Node<T> {
+    fn new(val: T) -> Self {
+        Self {
+            val,
+            next: None,
+            prev: None,
+            #[cfg(debug_assertions)]
+            list: 0,
+        }
+    }
+
+    fn into_val(self) -> T {
+        self.val
+    }
+}
+
+pub struct LinkedList<T> {
+    length: usize,
+    head: Option<NonNull<Node<T>>>,
+    tail: Option<NonNull<Node<T>>>,
+    /// Freed nodes kept for reuse, chained through `next`. Their values
+    /// have been moved out, so only their links may be touched.
+    pool: Option<NonNull<Node<T>>>,
+    pool_capacity: usize,
+    stats: PoolStats,
+    _marker: PhantomData<Box<Node<T>>>,
+    #[cfg(debug_assertions)]
+    id: usize,
+}
+
+/// Where a [`LinkedList`]'s nodes have come from, as returned by
+/// [`LinkedList::pool_stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PoolStats {
+    /// Nodes taken from the allocator.
+    pub allocated: usize,
+    /// Nodes taken from the pool instead.
+    pub reused: usize,
+    /// Freed nodes in the pool now.
+    pub pooled: usize,
 }
 
-impl<T> Node<T> {
-    fn new(val: T) -> Self {
-        Self {
-            val,
-            next: None,
-            prev: None,
+/// Hands out list ids; 0 is left for nodes not yet in a list.
+#[cfg(debug_assertions)]
+static NEXT_LIST_ID: AtomicUsize = AtomicUsize::new(1);
+
+// SAFETY: the list owns its nodes exactly as a `Box<Node<T>>` would, and no
+// node is reachable except through the list, so sending the list sends the
+// `T`s and sharing it only hands out `&T`.
+unsafe impl<T: Send> Send for LinkedList<T> {}
+unsafe impl<T: Sync> Sync for LinkedList<T> {}
+
+impl<T> LinkedList<T> {
+    pub fn new() -> Self {
+        Self::with_pool(0)
+    }
+
+    /// A list that keeps up to `capacity` freed nodes and hands them out
+    /// again to later inserts, instead of going back to the allocator
+    /// each time.
+    pub fn with_pool(capacity: usize) -> Self {
+        Self {
+            length: 0,
+            head: None,
+            tail: None,
+            pool: None,
+            pool_capacity: capacity,
+            stats: PoolStats::default(),
+            _marker: PhantomData,
+            #[cfg(debug_assertions)]
+            id: NEXT_LIST_ID.fetch_add(1, atomic::Ordering::Relaxed),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn pool_stats(&self) -> PoolStats {
+        self.stats
+    }
+
+    /// Changes how many freed nodes the list keeps, handing any beyond that
+    /// back to the allocator.
+    pub fn set_pool_capacity(&mut self, capacity: usize) {
+        self.pool_capacity = capacity;
+        while self.stats.pooled > capacity {
+            let node = self.pool.unwrap();
+            unsafe {
+                self.pool = (*node.as_ptr()).next;
+                alloc::dealloc(node.as_ptr().cast(), Layout::new::<Node<T>>());
+            }
+            self.stats.pooled -= 1;
+        }
+    }
+
+    pub fn insert_front(&mut self, val: T) {
+        self.insert_front_handle(val);
+    }
+
+    /// Inserts `val` at the front and returns a handle to its node.
+    pub fn insert_front_handle(&mut self, val: T) -> NodeHandle<T> {
+        let node = self.alloc(val);
+        self.insert_front_raw(node);
+        NodeHandle { node }
+    }
+
+    /// Links a detached `node` in at the front.
+    fn insert_front_raw(&mut self, node: NonNull<Node<T>>) {
+        self.claim(node);
+        unsafe {
+            (*node.as_ptr()).next = self.head;
+            (*node.as_ptr()).prev = None;
+        }
+
+        match self.head {
+            None => self.tail = Some(node),
+            Some(head) => unsafe { (*head.as_ptr()).prev = Some(node) },
+        }
+
+        self.head = Some(node);
+        self.length += 1;
+    }
+
+    pub fn push_back(&mut self, val: T) {
+        self.push_back_handle(val);
+    }
+
+    /// Pushes `val` onto the back and returns a handle to its node.
+    pub fn push_back_handle(&mut self, val: T) -> NodeHandle<T> {
+        let node = self.alloc(val);
+        self.insert_back_raw(node);
+        NodeHandle { node }
+    }
+
+    /// Links a detached `node` in at the back.
+    fn insert_back_raw(&mut self, node: NonNull<Node<T>>) {
+        self.claim(node);
+        unsafe {
+            (*node.as_ptr()).next = None;
+            (*node.as_ptr()).prev = self.tail;
+        }
+
+        match self.tail {
+            None => self.head = Some(node),
+            Some(tail) => unsafe { (*tail.as_ptr()).next = Some(node) },
+        }
+
+        self.tail = Some(node);
+        self.length += 1;
+    }
+
+    /// Removes the node `handle` refers to and returns its value.
+    ///
+    /// # Safety
+    ///
+    /// `handle` must refer to a node that is in this list.
+    pub unsafe fn remove(&mut self, handle: NodeHandle<T>) -> T {
+        let node = self.check(handle);
+        self.free(node)
+    }
+
+    /// Moves the node `handle` refers to to the front.
+    ///
+    /// # Safety
+    ///
+    /// `handle` must refer to a node that is in this list.
+    pub unsafe fn reinsert_front(&mut self, handle: NodeHandle<T>) {
+        let node = self.check(handle);
+        self.unlink(node);
+        self.insert_front_raw(node);
+    }
+
+    /// Moves the node `handle` refers to to the back.
+    ///
+    /// # Safety
+    ///
+    /// `handle` must refer to a node that is in this list.
+    pub unsafe fn reinsert_back(&mut self, handle: NodeHandle<T>) {
+        let node = self.check(handle);
+        self.unlink(node);
+        self.insert_back_raw(node);
+    }
+
+    /// The value of the node `handle` refers to.
+    ///
+    /// # Safety
+    ///
+    /// `handle` must refer to a node that is in this list.
+    pub unsafe fn value(&self, handle: NodeHandle<T>) -> &T {
+        let node = self.check(handle);
+        unsafe { &(*node.as_ptr()).val }
+    }
+
+    /// # Safety
+    ///
+    /// `handle` must refer to a node that is in this list.
+    pub unsafe fn value_mut(&mut self, handle: NodeHandle<T>) -> &mut T {
+        let node = self.check(handle);
+        unsafe { &mut (*node.as_ptr()).val }
+    }
+
+    /// A pointer to the value of the node `handle` refers to. Unlike a
+    /// reference it can be kept while the list changes; it dangles once the
+    /// node is removed.
+    ///
+    /// # Safety
+    ///
+    /// `handle` must refer to a node that is in this list.
+    pub unsafe fn value_ptr(&self, handle: NodeHandle<T>) -> NonNull<T> {
+        let node = self.check(handle);
+        unsafe { NonNull::new_unchecked(ptr::addr_of_mut!((*node.as_ptr()).val)) }
+    }
+
+    /// Exchanges the positions of the nodes `a` and `b` refer to. The values
+    /// stay in their nodes, so handles and references to them stay valid.
+    ///
+    /// # Safety
+    ///
+    /// `a` and `b` must both refer to nodes that are in this list.
+    pub unsafe fn swap_nodes(&mut self, a: NodeHandle<T>, b: NodeHandle<T>) {
+        let (a, b) = (self.check(a), self.check(b));
+        if a == b {
+            return;
+        }
+        let links =
+            |node: NonNull<Node<T>>| unsafe { ((*node.as_ptr()).prev, (*node.as_ptr()).next) };
+        let ((a_prev, a_next), (b_prev, b_next)) = (links(a), links(b));
+        if a_next == Some(b) {
+            self.unlink(b);
+            self.link(b, a_prev, Some(a));
+        } else if b_next == Some(a) {
+            self.unlink(a);
+            self.link(a, b_prev, Some(b));
+        } else {
+            // Neither is the other's neighbour, so each one's old
+            // neighbours stay adjacent once it is unlinked.
+            self.unlink(a);
+            self.unlink(b);
+            self.link(a, b_prev, b_next);
+            self.link(b, a_prev, a_next);
+        }
+    }
+
+    /// Inserts `val` so that it ends up at `index`, walking from whichever
+    /// end is closer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index > len`.
+    pub fn insert(&mut self, index: usize, val: T) {
+        let len = self.length;
+        assert!(
+            index <= len,
+            "insertion index (is {index}) should be <= len (is {len})"
+        );
+        let node = self.alloc(val);
+        match self.node_at(index) {
+            Some(next) => {
+                let prev = unsafe { (*next.as_ptr()).prev };
+                self.link(node, prev, Some(next));
+            }
+            None => self.insert_back_raw(node),
+        }
+    }
+
+    /// Removes and returns the element at `index`, walking from whichever
+    /// end is closer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index >= len`.
+    pub fn remove_at(&mut self, index: usize) -> T {
+        let len = self.length;
+        match self.node_at(index) {
+            Some(node) => self.free(node),
+            None => panic!("removal index (is {index}) should be < len (is {len})"),
+        }
+    }
+
+    pub fn contains(&self, val: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.iter().any(|v| v == val)
+    }
+
+    pub fn front_handle(&self) -> Option<NodeHandle<T>> {
+        self.head.map(|node| NodeHandle { node })
+    }
+
+    pub fn back_handle(&self) -> Option<NodeHandle<T>> {
+        self.tail.map(|node| NodeHandle { node })
+    }
+
+    /// A handle to the first node whose value satisfies `pred`.
+    pub fn find(&self, mut pred: impl FnMut(&T) -> bool) -> Option<NodeHandle<T>> {
+        let mut cursor = self.head;
+        while let Some(node) = cursor {
+            unsafe {
+                if pred(&(*node.as_ptr()).val) {
+                    return Some(NodeHandle { node });
+                }
+                cursor = (*node.as_ptr()).next;
+            }
+        }
+        None
+    }
+
+    /// The node behind `handle`, which the caller promises is in this list.
+    /// Debug builds panic if it was last linked into another one.
+    fn check(&self, handle: NodeHandle<T>) -> NonNull<Node<T>> {
+        #[cfg(debug_assertions)]
+        assert_eq!(
+            unsafe { (*handle.node.as_ptr()).list },
+            self.id,
+            "node does not belong to this list"
+        );
+        handle.node
+    }
+
+    /// Records `node` as linked into this list, for [`check`](Self::check).
+    #[cfg_attr(not(debug_assertions), allow(unused_variables))]
+    fn claim(&self, node: NonNull<Node<T>>) {
+        #[cfg(debug_assertions)]
+        unsafe {
+            (*node.as_ptr()).list = self.id;
+        }
+    }
+
+    /// [`claim`](Self::claim)s `first` and every node after it. Costs a
+    /// walk over them in debug builds and nothing in release builds.
+    #[cfg_attr(not(debug_assertions), allow(unused_variables))]
+    fn claim_from(&self, first: Option<NonNull<Node<T>>>) {
+        #[cfg(debug_assertions)]
+        {
+            let mut cursor = first;
+            while let Some(node) = cursor {
+                self.claim(node);
+                cursor = unsafe { (*node.as_ptr()).next };
+            }
+        }
+    }
+
+    /// Unlinks `node` and frees it, without checking that it is ours.
+    fn free(&mut self, node: NonNull<Node<T>>) -> T {
+        self.unlink(node);
+        self.release(node)
+    }
+
+    /// A detached node holding `val`, from the pool if it has one.
+    fn alloc(&mut self, val: T) -> NonNull<Node<T>> {
+        match self.pool {
+            Some(node) => unsafe {
+                self.pool = (*node.as_ptr()).next;
+                self.stats.pooled -= 1;
+                self.stats.reused += 1;
+                node.as_ptr().write(Node::new(val));
+                node
+            },
+            None => {
+                self.stats.allocated += 1;
+                NonNull::new(Box::into_raw(Box::new(Node::new(val)))).unwrap()
+            }
+        }
+    }
+
+    /// Takes the value out of a detached `node` and pools the node, or
+    /// frees it if the pool is full.
+    fn release(&mut self, node: NonNull<Node<T>>) -> T {
+        if self.stats.pooled == self.pool_capacity {
+            return unsafe { Box::from_raw(node.as_ptr()).into_val() };
+        }
+        unsafe {
+            let val = ptr::read(ptr::addr_of!((*node.as_ptr()).val));
+            (*node.as_ptr()).next = self.pool;
+            // A stale handle to a pooled node then fails `check`.
+            #[cfg(debug_assertions)]
+            {
+                (*node.as_ptr()).list = 0;
+            }
+            self.pool = Some(node);
+            self.stats.pooled += 1;
+            val
+        }
+    }
+
+    /// Hands the pooled nodes back to the allocator.
+    fn free_pool(&mut self) {
+        while let Some(node) = self.pool {
+            unsafe {
+                self.pool = (*node.as_ptr()).next;
+                alloc::dealloc(node.as_ptr().cast(), Layout::new::<Node<T>>());
+            }
+        }
+        self.stats.pooled = 0;
+    }
+
+    /// Links a detached `node` in between `prev` and `next`, which must be
+    /// adjacent; `None` stands for the end of the list on that side.
+    fn link(
+        &mut self,
+        node: NonNull<Node<T>>,
+        prev: Option<NonNull<Node<T>>>,
+        next: Option<NonNull<Node<T>>>,
+    ) {
+        self.claim(node);
+        unsafe {
+            (*node.as_ptr()).prev = prev;
+            (*node.as_ptr()).next = next;
+        }
+        match prev {
+            Some(prev) => unsafe { (*prev.as_ptr()).next = Some(node) },
+            None => self.head = Some(node),
+        }
+        match next {
+            Some(next) => unsafe { (*next.as_ptr()).prev = Some(node) },
+            None => self.tail = Some(node),
+        }
+        self.length += 1;
+    }
+
+    /// Links all of `other` in between `prev` and `next`, which must be
+    /// adjacent, as [`link`](Self::link) does for one node.
+    fn splice_between(
+        &mut self,
+        prev: Option<NonNull<Node<T>>>,
+        next: Option<NonNull<Node<T>>>,
+        other: LinkedList<T>,
+    ) {
+        let (Some(first), Some(last)) = (other.head, other.tail) else {
+            return;
+        };
+        self.claim_from(Some(first));
+        unsafe {
+            (*first.as_ptr()).prev = prev;
+            (*last.as_ptr()).next = next;
+        }
+        match prev {
+            Some(prev) => unsafe { (*prev.as_ptr()).next = Some(first) },
+            None => self.head = Some(first),
+        }
+        match next {
+            Some(next) => unsafe { (*next.as_ptr()).prev = Some(last) },
+            None => self.tail = Some(last),
+        }
+        self.length += other.detach();
+    }
+
+    /// The node at `index`, walking from whichever end is closer.
+    fn node_at(&self, index: usize) -> Option<NonNull<Node<T>>> {
+        if index >= self.length {
+            return None;
+        }
+        unsafe {
+            if index <= self.length / 2 {
+                let mut node = self.head?;
+                for _ in 0..index {
+                    node = (*node.as_ptr()).next?;
+                }
+                Some(node)
+            } else {
+                let mut node = self.tail?;
+                for _ in index + 1..self.length {
+                    node = (*node.as_ptr()).prev?;
+                }
+                Some(node)
+            }
+        }
+    }
+
+    /// Detaches `node` from its neighbours without freeing it.
+    fn unlink(&mut self, node: NonNull<Node<T>>) {
+        let (prev, next) = unsafe { ((*node.as_ptr()).prev, (*node.as_ptr()).next) };
+        self.length -= 1;
+        match prev {
+            Some(prev) => unsafe { (*prev.as_ptr()).next = next },
+            None => self.head = next,
+        }
+        match next {
+            Some(next) => unsafe { (*next.as_ptr()).prev = prev },
+            None => self.tail = prev,
+        }
+    }
+
+    pub fn remove_tail(&mut self) -> Option<T> {
+        self.tail.map(|node| self.free(node))
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        self.head.map(|node| self.free(node))
+    }
+
+    /// The element at `index`, walking from whichever end is closer.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.node_at(index)
+            .map(|node| unsafe { &(*node.as_ptr()).val })
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.node_at(index)
+            .map(|node| unsafe { &mut (*node.as_ptr()).val })
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        self.head.map(|node| unsafe { &(*node.as_ptr()).val })
+    }
+
+    pub fn back(&self) -> Option<&T> {
+        self.tail.map(|node| unsafe { &(*node.as_ptr()).val })
+    }
+
+    // The nodes are owned by the list, so borrowing it mutably makes the
+    // returned reference the only live one into the node.
+    pub fn front_mut(&mut self) -> Option<&mut T> {
+        self.head.map(|node| unsafe { &mut (*node.as_ptr()).val })
+    }
+
+    pub fn back_mut(&mut self) -> Option<&mut T> {
+        self.tail.map(|node| unsafe { &mut (*node.as_ptr()).val })
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            head: self.head,
+            tail: self.tail,
+            len: self.length,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Splits the list in two at `at`, leaving the first `at` elements in
+    /// `self` and returning the rest.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `at > len`.
+    pub fn split_off(&mut self, at: usize) -> LinkedList<T> {
+        let len = self.length;
+        assert!(at <= len, "split index {at} out of bounds for length {len}");
+        let Some(first) = self.node_at(at) else {
+            return LinkedList::new();
+        };
+        unsafe {
+            let last = (*first.as_ptr()).prev;
+            (*first.as_ptr()).prev = None;
+            match last {
+                Some(last) => (*last.as_ptr()).next = None,
+                None => self.head = None,
+            }
+            let mut rest = LinkedList::new();
+            rest.claim_from(Some(first));
+            rest.length = len - at;
+            rest.head = Some(first);
+            rest.tail = self.tail;
+            self.tail = last;
+            self.length = at;
+            rest
+        }
+    }
+
+    /// Moves every element of `other` onto the back of `self` in O(1),
+    /// leaving `other` empty. Debug builds also visit each moved node, to
+    /// record which list it is in for the handle check, so there it is O(n).
+    pub fn append(&mut self, other: &mut LinkedList<T>) {
+        self.splice_between(self.tail, None, other.split_off(0));
+    }
+
+    /// Moves every element of `other` onto the front of `self` in O(1),
+    /// leaving `other` empty. As with [`append`](Self::append), debug builds
+    /// pay O(n) for the handle check.
+    pub fn prepend(&mut self, other: &mut LinkedList<T>) {
+        self.splice_between(None, self.head, other.split_off(0));
+    }
+
+    /// Puts the items in front of the current head, in their own order:
+    /// the chain is built on its own and then linked in with one fixup.
+    pub fn extend_front(&mut self, iter: impl IntoIterator<Item = T>) {
+        let chain = iter.into_iter().collect();
+        self.splice_between(None, self.head, chain);
+    }
+
+    /// Puts the items after the current tail, in order, linking the chain
+    /// in once it is built.
+    pub fn extend_back(&mut self, iter: impl IntoIterator<Item = T>) {
+        let chain = iter.into_iter().collect();
+        self.splice_between(self.tail, None, chain);
+    }
+
+    /// Drops every element for which `keep` returns `false`, in order.
+    pub fn retain(&mut self, mut keep: impl FnMut(&T) -> bool) {
+        self.retain_mut(|val| keep(val));
+    }
+
+    /// [`retain`](Self::retain) with mutable access to each element.
+    pub fn retain_mut(&mut self, mut keep: impl FnMut(&mut T) -> bool) {
+        let mut cursor = self.head;
+        while let Some(node) = cursor {
+            // Each node is judged while still linked, and unlinked only
+            // after `keep` has returned.
+            unsafe {
+                cursor = (*node.as_ptr()).next;
+                if !keep(&mut (*node.as_ptr()).val) {
+                    self.free(node);
+                }
+            }
+        }
+    }
+
+    /// Removes consecutive repeated elements, keeping the first of each run.
+    pub fn dedup(&mut self)
+    where
+        T: PartialEq,
+    {
+        self.dedup_by(|a, b| a == b);
+    }
+
+    /// Removes every element for which `same(element, kept)` is true, where
+    /// `kept` is the last element before it that was not removed. As with
+    /// [`Vec::dedup_by`], the arguments come in the reverse of list order.
+    pub fn dedup_by(&mut self, mut same: impl FnMut(&mut T, &mut T) -> bool) {
+        let Some(mut kept) = self.head else {
+            return;
+        };
+        let mut cursor = unsafe { (*kept.as_ptr()).next };
+        while let Some(node) = cursor {
+            unsafe {
+                cursor = (*node.as_ptr()).next;
+                if same(&mut (*node.as_ptr()).val, &mut (*kept.as_ptr()).val) {
+                    self.free(node);
+                } else {
+                    kept = node;
+                }
+            }
+        }
+    }
+
+    /// An iterator that walks the list front to back, removing and yielding
+    /// the elements for which `pred` returns `true`.
+    ///
+    /// Elements are only looked at as the iterator is driven: dropping it
+    /// early leaves the rest of the list as it was.
+    pub fn extract_if<F>(&mut self, pred: F) -> ExtractIf<'_, T, F>
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        ExtractIf {
+            next: self.head,
+            unvisited: self.length,
+            list: self,
+            pred,
+        }
+    }
+
+    /// An iterator that yields every element front to back, leaving the
+    /// list empty.
+    ///
+    /// The list is emptied up front, so dropping the iterator early drops
+    /// the elements not yet yielded, and leaking it leaks them but still
+    /// leaves the list empty.
+    pub fn drain(&mut self) -> Drain<'_, T> {
+        Drain {
+            list: self.split_off(0),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Reverses the order of the elements in place, by swapping the links
+    /// of every node.
+    pub fn reverse(&mut self) {
+        let mut cursor = self.head;
+        while let Some(node) = cursor {
+            unsafe {
+                let node = node.as_ptr();
+                mem::swap(&mut (*node).next, &mut (*node).prev);
+                cursor = (*node).prev;
+            }
+        }
+        mem::swap(&mut self.head, &mut self.tail);
+    }
+
+    /// Moves the first `n % len` elements to the back, in order, by
+    /// relinking the list into a ring and cutting it at the new head.
+    pub fn rotate_left(&mut self, n: usize) {
+        if self.length == 0 || n.is_multiple_of(self.length) {
+            return;
+        }
+        // `node_at` walks from the nearer end, so this is O(min(n, len - n)).
+        let new_head = self.node_at(n % self.length).unwrap();
+        let (head, tail) = (self.head.unwrap(), self.tail.unwrap());
+        unsafe {
+            let new_tail = (*new_head.as_ptr()).prev;
+            (*tail.as_ptr()).next = Some(head);
+            (*head.as_ptr()).prev = Some(tail);
+            (*new_head.as_ptr()).prev = None;
+            if let Some(new_tail) = new_tail {
+                (*new_tail.as_ptr()).next = None;
+            }
+            self.head = Some(new_head);
+            self.tail = new_tail;
+        }
+    }
+
+    /// Moves the last `n % len` elements to the front, in order.
+    pub fn rotate_right(&mut self, n: usize) {
+        if self.length == 0 {
+            return;
+        }
+        self.rotate_left(self.length - n % self.length);
+    }
+
+    /// Merges the sorted `other` into the sorted `self`, leaving `other`
+    /// empty. See [`merge_by`](Self::merge_by).
+    pub fn merge(&mut self, other: &mut LinkedList<T>)
+    where
+        T: Ord,
+    {
+        self.merge_by(other, T::cmp);
+    }
+
+    /// Merges `other` into `self`, both sorted by `cmp`, by relinking the
+    /// nodes of `other` in between those of `self` without allocating.
+    /// Stable: of equal elements, those of `self` come first.
+    ///
+    /// Nodes move over one at a time, so if `cmp` panics both lists are
+    /// still valid, with the elements split between them.
+    pub fn merge_by(&mut self, other: &mut LinkedList<T>, mut cmp: impl FnMut(&T, &T) -> Ordering) {
+        let mut cursor = self.head;
+        while let (Some(node), Some(first)) = (cursor, other.head) {
+            let order = unsafe { cmp(&(*node.as_ptr()).val, &(*first.as_ptr()).val) };
+            if order == Ordering::Greater {
+                other.unlink(first);
+                let prev = unsafe { (*node.as_ptr()).prev };
+                self.link(first, prev, Some(node));
+            } else {
+                cursor = unsafe { (*node.as_ptr()).next };
+            }
+        }
+        self.append(other);
+    }
+
+    pub fn sort(&mut self)
+    where
+        T: Ord,
+    {
+        self.sort_by(T::cmp);
+    }
+
+    /// Stable bottom-up merge sort that relinks the nodes in place: runs of
+    /// `width` are merged pairwise along the `next` links, doubling `width`
+    /// until one run is left, and the `prev` links are rebuilt at the end.
+    ///
+    /// If `cmp` panics, the nodes are chained back together in some order
+    /// before the panic continues, so the list stays valid.
+    pub fn sort_by(&mut self, mut cmp: impl FnMut(&T, &T) -> Ordering) {
+        if self.length < 2 {
+            return;
+        }
+        let next = |node: NonNull<Node<T>>| unsafe { (*node.as_ptr()).next };
+        let mut list = self.head;
+        let mut width = 1;
+        loop {
+            let mut rest = list;
+            let (mut head, mut tail) = (None, None);
+            let mut merges = 0;
+            while let Some(start) = rest {
+                merges += 1;
+                let mut left = Some(start);
+                let mut left_len = 0;
+                let mut right = Some(start);
+                while let Some(node) = right.filter(|_| left_len < width) {
+                    right = next(node);
+                    left_len += 1;
+                }
+                let mut right_len = width;
+                loop {
+                    let take_left = match (
+                        left.filter(|_| left_len > 0),
+                        right.filter(|_| right_len > 0),
+                    ) {
+                        (None, None) => break,
+                        (Some(_), None) => true,
+                        (None, Some(_)) => false,
+                        (Some(l), Some(r)) => {
+                            let order = panic::catch_unwind(AssertUnwindSafe(|| unsafe {
+                                cmp(&(*l.as_ptr()).val, &(*r.as_ptr()).val)
+                            }));
+                            match order {
+                                Ok(order) => order != Ordering::Greater,
+                                Err(payload) => {
+                                    self.restore(head, tail, left, left_len, right);
+                                    panic::resume_unwind(payload);
+                                }
+                            }
+                        }
+                    };
+                    let node = if take_left {
+                        let node = left.unwrap();
+                        left = next(node);
+                        left_len -= 1;
+                        node
+                    } else {
+                        let node = right.unwrap();
+                        right = next(node);
+                        right_len -= 1;
+                        node
+                    };
+                    match tail {
+                        Some(tail) => unsafe { (*tail.as_ptr()).next = Some(node) },
+                        None => head = Some(node),
+                    }
+                    tail = Some(node);
+                }
+                rest = right;
+            }
+            if let Some(tail) = tail {
+                unsafe { (*tail.as_ptr()).next = None };
+            }
+            list = head;
+            if merges <= 1 {
+                break;
+            }
+            width *= 2;
+        }
+        self.relink(list);
+    }
+
+    /// Puts the list back together after a panic in the middle of a merge:
+    /// the merged nodes, then the rest of the left run, then the right run,
+    /// which is still followed by every node not yet reached.
+    fn restore(
+        &mut self,
+        merged_head: Option<NonNull<Node<T>>>,
+        merged_tail: Option<NonNull<Node<T>>>,
+        mut left: Option<NonNull<Node<T>>>,
+        left_len: usize,
+        right: Option<NonNull<Node<T>>>,
+    ) {
+        let (mut first, mut last) = (merged_head, merged_tail);
+        for _ in 0..left_len {
+            let node = left.unwrap();
+            match last {
+                Some(last) => unsafe { (*last.as_ptr()).next = Some(node) },
+                None => first = Some(node),
+            }
+            last = Some(node);
+            left = unsafe { (*node.as_ptr()).next };
+        }
+        match last {
+            Some(last) => unsafe { (*last.as_ptr()).next = right },
+            None => first = right,
+        }
+        self.relink(first);
+    }
+
+    /// Makes the nodes reachable along `next` from `first` the whole list,
+    /// setting every `prev` link and the tail to match.
+    fn relink(&mut self, first: Option<NonNull<Node<T>>>) {
+        self.head = first;
+        let mut prev = None;
+        let mut cursor = first;
+        while let Some(node) = cursor {
+            unsafe {
+                (*node.as_ptr()).prev = prev;
+                cursor = (*node.as_ptr()).next;
+            }
+            prev = Some(node);
+        }
+        self.tail = prev;
+    }
+
+    /// Forgets the nodes without freeing them, once they have been linked
+    /// into another list. Returns how many there were.
+    fn detach(mut self) -> usize {
+        self.head = None;
+        self.tail = None;
+        mem::take(&mut self.length)
+    }
+
+    /// The elements front to back, freeing every node.
+    pub fn into_vec(mut self) -> Vec<T> {
+        let mut vec = Vec::with_capacity(self.length);
+        while let Some(val) = self.pop_front() {
+            vec.push(val);
+        }
+        vec
+    }
+
+    /// A read-only cursor on the first element, or on the ghost position if
+    /// the list is empty.
+    pub fn cursor_front(&self) -> Cursor<'_, T> {
+        Cursor {
+            index: 0,
+            current: self.head,
+            list: self,
+        }
+    }
+
+    /// A read-only cursor on the last element, or on the ghost position if
+    /// the list is empty.
+    pub fn cursor_back(&self) -> Cursor<'_, T> {
+        Cursor {
+            index: self.length.saturating_sub(1),
+            current: self.tail,
+            list: self,
+        }
+    }
+
+    /// A cursor on the first element, or on the ghost position if the list
+    /// is empty.
+    pub fn cursor_front_mut(&mut self) -> CursorMut<'_, T> {
+        CursorMut {
+            index: 0,
+            current: self.head,
+            list: self,
+        }
+    }
+
+    /// A cursor on the last element, or on the ghost position if the list
+    /// is empty.
+    pub fn cursor_back_mut(&mut self) -> CursorMut<'_, T> {
+        CursorMut {
+            index: self.length.saturating_sub(1),
+            current: self.tail,
+            list: self,
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut {
+            head: self.head,
+            len: self.length,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Default for LinkedList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone> Clone for LinkedList<T> {
+    /// Copies every element into a new list. If a clone panics, the
+    /// partly built list is dropped while unwinding, freeing what was
+    /// copied so far.
+    fn clone(&self) -> Self {
+        let mut list = LinkedList::with_pool(self.pool_capacity);
+        list.extend(self.iter().cloned());
+        list
+    }
+
+    /// Clones into the nodes `self` already has, allocating only for
+    /// elements beyond its length and freeing any it has left over.
+    fn clone_from(&mut self, source: &Self) {
+        if self.length > source.length {
+            drop(self.split_off(source.length));
+        }
+        let mut dest = self.iter_mut();
+        let mut source = source.iter();
+        for (dest, source) in dest.by_ref().zip(source.by_ref()) {
+            dest.clone_from(source);
+        }
+        self.extend(source.cloned());
+    }
+}
+
+impl<T: PartialEq> PartialEq for LinkedList<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.length == other.length && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq> Eq for LinkedList<T> {}
+
+impl<T: PartialOrd> PartialOrd for LinkedList<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other.iter())
+    }
+}
+
+impl<T: Ord> Ord for LinkedList<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+impl<T: Hash> Hash for LinkedList<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(self.length);
+        for val in self.iter() {
+            val.hash(state);
+        }
+    }
+}
+
+impl<T> FromIterator<T> for LinkedList<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = LinkedList::new();
+        list.extend(iter);
+        list
+    }
+}
+
+impl<T> From<Vec<T>> for LinkedList<T> {
+    fn from(vec: Vec<T>) -> Self {
+        vec.into_iter().collect()
+    }
+}
+
+impl<T, const N: usize> From<[T; N]> for LinkedList<T> {
+    fn from(array: [T; N]) -> Self {
+        array.into_iter().collect()
+    }
+}
+
+impl<T> Extend<T> for LinkedList<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for val in iter {
+            self.push_back(val);
+        }
+    }
+}
+
+impl<'a, T: Copy + 'a> Extend<&'a T> for LinkedList<T> {
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied());
+    }
+}
+
+impl<T> Drop for LinkedList<T> {
+    fn drop(&mut self) {
+        struct DropGuard<'a, T>(&'a mut LinkedList<T>);
+        impl<'a, T> Drop for DropGuard<'a, T> {
+            fn drop(&mut self) {
+                while self.0.remove_tail().is_some() {}
+            }
+        }
+
+        self.pool_capacity = 0;
+        self.free_pool();
+        while let Some(node) = self.remove_tail() {
+            let guard = DropGuard(self);
+            drop(node);
+            mem::forget(guard);
+        }
+    }
+}
+
+pub struct Iter<'a, T: 'a> {
+    head: Option<NonNull<Node<T>>>,
+    tail: Option<NonNull<Node<T>>>,
+    len: usize,
+    _marker: PhantomData<&'a Node<T>>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            None
+        } else {
+            self.head.map(|node| {
+                self.len -= 1;
+
+                unsafe {
+                    self.head = (*node.as_ptr()).next;
+                    &(*node.as_ptr()).val
+                }
+            })
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+
+    /// Skips `n` elements along the links, or walks back from the tail
+    /// when that is shorter.
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        if n >= self.len {
+            self.len = 0;
+            return None;
+        }
+        let next = |node: NonNull<Node<T>>| unsafe { (*node.as_ptr()).next };
+        let prev = |node: NonNull<Node<T>>| unsafe { (*node.as_ptr()).prev };
+        let mut node = self.head?;
+        if n <= self.len / 2 {
+            for _ in 0..n {
+                node = next(node)?;
+            }
+        } else {
+            node = self.tail?;
+            for _ in n + 1..self.len {
+                node = prev(node)?;
+            }
+        }
+        self.head = Some(node);
+        self.len -= n;
+        self.next()
+    }
+}
+
+impl<T> DoubleEndedIterator for Iter<'_, T> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            None
+        } else {
+            self.tail.map(|node| {
+                self.len -= 1;
+
+                unsafe {
+                    self.tail = (*node.as_ptr()).prev;
+                    &(*node.as_ptr()).val
+                }
+            })
+        }
+    }
+}
+
+// SAFETY: an `Iter` only hands out `&T`, like a `&LinkedList<T>`.
+unsafe impl<T: Sync> Send for Iter<'_, T> {}
+unsafe impl<T: Sync> Sync for Iter<'_, T> {}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+impl<T> FusedIterator for Iter<'_, T> {}
+
+pub struct IterMut<'a, T: 'a> {
+    head: Option<NonNull<Node<T>>>,
+    len: usize,
+    _marker: PhantomData<&'a mut Node<T>>,
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            None
+        } else {
+            self.head.map(|node| {
+                self.len -= 1;
+
+                unsafe {
+                    self.head = (*node.as_ptr()).next;
+                    &mut (*node.as_ptr()).val
+                }
+            })
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+
+    /// Skips `n` elements along the links without yielding them.
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        if n >= self.len {
+            self.len = 0;
+            return None;
+        }
+        for _ in 0..n {
+            self.head = self.head.and_then(|node| unsafe { (*node.as_ptr()).next });
+        }
+        self.len -= n;
+        self.next()
+    }
+}
+
+// SAFETY: an `IterMut` hands out `&mut T`, like a `&mut LinkedList<T>`.
+unsafe impl<T: Send> Send for IterMut<'_, T> {}
+unsafe impl<T: Sync> Sync for IterMut<'_, T> {}
+
+impl<T> ExactSizeIterator for IterMut<'_, T> {}
+
+impl<T> FusedIterator for IterMut<'_, T> {}
+
+/// A read-only position in a [`LinkedList`] that can move in both
+/// directions.
+///
+/// It has the same ghost position as [`CursorMut`], past the tail and
+/// before the head. Cursors only borrow the list shared, so any number of
+/// them can walk it at once.
+pub struct Cursor<'a, T> {
+    /// Index of `current`; the length of the list at the ghost.
+    index: usize,
+    current: Option<NonNull<Node<T>>>,
+    list: &'a LinkedList<T>,
+}
+
+impl<T> Clone for Cursor<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Cursor<'_, T> {}
+
+// SAFETY: a `Cursor` holds a `&LinkedList<T>` and a pointer to one of its
+// nodes, and only reads through them to hand out `&T`, so it is as safe to
+// send or share as the `&LinkedList<T>` itself.
+unsafe impl<T: Sync> Send for Cursor<'_, T> {}
+unsafe impl<T: Sync> Sync for Cursor<'_, T> {}
+
+impl<'a, T> Cursor<'a, T> {
+    /// The index of the current element, `None` at the ghost.
+    pub fn index(&self) -> Option<usize> {
+        self.current.map(|_| self.index)
+    }
+
+    pub fn move_next(&mut self) {
+        match self.current {
+            None => {
+                self.current = self.list.head;
+                self.index = 0;
+            }
+            Some(node) => {
+                self.current = unsafe { (*node.as_ptr()).next };
+                self.index += 1;
+            }
+        }
+    }
+
+    pub fn move_prev(&mut self) {
+        match self.current {
+            None => {
+                self.current = self.list.tail;
+                self.index = self.list.length.saturating_sub(1);
+            }
+            Some(node) => {
+                self.current = unsafe { (*node.as_ptr()).prev };
+                self.index = match self.current {
+                    Some(_) => self.index - 1,
+                    None => self.list.length,
+                };
+            }
+        }
+    }
+
+    pub fn current(&self) -> Option<&'a T> {
+        self.current.map(|node| unsafe { &(*node.as_ptr()).val })
+    }
+
+    /// The element after the current one; the head at the ghost.
+    pub fn peek_next(&self) -> Option<&'a T> {
+        let next = match self.current {
+            Some(node) => unsafe { (*node.as_ptr()).next },
+            None => self.list.head,
+        };
+        next.map(|node| unsafe { &(*node.as_ptr()).val })
+    }
+
+    /// The element before the current one; the tail at the ghost.
+    pub fn peek_prev(&self) -> Option<&'a T> {
+        let prev = match self.current {
+            Some(node) => unsafe { (*node.as_ptr()).prev },
+            None => self.list.tail,
+        };
+        prev.map(|node| unsafe { &(*node.as_ptr()).val })
+    }
+}
+
+/// A position in a [`LinkedList`] that can move in both directions and
+/// edit the list around it.
+///
+/// Besides the elements there is a "ghost" position, past the tail and
+/// before the head, where [`current`](Self::current) is `None`. Moving off
+/// either end lands on it, and moving on from it wraps to the other end.
+pub struct CursorMut<'a, T> {
+    /// Index of `current`; the length of the list at the ghost.
+    index: usize,
+    current: Option<NonNull<Node<T>>>,
+    list: &'a mut LinkedList<T>,
+}
+
+impl<T> CursorMut<'_, T> {
+    /// The index of the current element, `None` at the ghost.
+    pub fn index(&self) -> Option<usize> {
+        self.current.map(|_| self.index)
+    }
+
+    pub fn move_next(&mut self) {
+        match self.current {
+            None => {
+                self.current = self.list.head;
+                self.index = 0;
+            }
+            Some(node) => {
+                self.current = unsafe { (*node.as_ptr()).next };
+                self.index += 1;
+            }
+        }
+    }
+
+    pub fn move_prev(&mut self) {
+        match self.current {
+            None => {
+                self.current = self.list.tail;
+                self.index = self.list.length.saturating_sub(1);
+            }
+            Some(node) => {
+                self.current = unsafe { (*node.as_ptr()).prev };
+                // Off the front onto the ghost, whose index is the length.
+                self.index = match self.current {
+                    Some(_) => self.index - 1,
+                    None => self.list.length,
+                };
+            }
+        }
+    }
+
+    pub fn current(&mut self) -> Option<&mut T> {
+        self.current
+            .map(|node| unsafe { &mut (*node.as_ptr()).val })
+    }
+
+    /// A handle to the current node, `None` at the ghost.
+    pub fn current_handle(&self) -> Option<NodeHandle<T>> {
+        self.current.map(|node| NodeHandle { node })
+    }
+
+    /// Inserts `val` before the current element, or at the back of the list
+    /// at the ghost. The cursor stays on the same element.
+    pub fn insert_before(&mut self, val: T) {
+        let node = self.list.alloc(val);
+        let prev = match self.current {
+            Some(current) => unsafe { (*current.as_ptr()).prev },
+            None => self.list.tail,
+        };
+        self.list.link(node, prev, self.current);
+        self.index += 1;
+    }
+
+    /// Inserts `val` after the current element, or at the front of the list
+    /// at the ghost. The cursor stays on the same element.
+    pub fn insert_after(&mut self, val: T) {
+        let node = self.list.alloc(val);
+        let next = match self.current {
+            Some(current) => unsafe { (*current.as_ptr()).next },
+            None => self.list.head,
+        };
+        self.list.link(node, self.current, next);
+        if self.current.is_none() {
+            self.index = self.list.length;
+        }
+    }
+
+    /// Links all of `list` in before the current element, or onto the back
+    /// at the ghost, in O(1). The cursor stays on the same element. Debug
+    /// builds walk the spliced nodes to record them for the handle check.
+    pub fn splice_before(&mut self, list: LinkedList<T>) {
+        let added = list.length;
+        let prev = match self.current {
+            Some(current) => unsafe { (*current.as_ptr()).prev },
+            None => self.list.tail,
+        };
+        self.list.splice_between(prev, self.current, list);
+        self.index += added;
+    }
+
+    /// Links all of `list` in after the current element, or onto the front
+    /// at the ghost, in O(1), or O(n) in debug builds as for
+    /// [`splice_before`](Self::splice_before). The cursor stays on the same
+    /// element.
+    pub fn splice_after(&mut self, list: LinkedList<T>) {
+        let next = match self.current {
+            Some(current) => unsafe { (*current.as_ptr()).next },
+            None => self.list.head,
+        };
+        self.list.splice_between(self.current, next, list);
+        if self.current.is_none() {
+            self.index = self.list.length;
+        }
+    }
+
+    /// Removes the current element and moves on to the next one, which is
+    /// the ghost if it was the tail. Returns `None` at the ghost.
+    pub fn remove_current(&mut self) -> Option<T> {
+        let node = self.current?;
+        self.current = unsafe { (*node.as_ptr()).next };
+        Some(self.list.free(node))
+    }
+}
+
+/// An opaque reference to a node of a [`LinkedList`], as returned by
+/// [`LinkedList::find`], [`insert_front_handle`](LinkedList::insert_front_handle)
+/// and [`push_back_handle`](LinkedList::push_back_handle), that can be passed
+/// back to [`remove`](LinkedList::remove),
+/// [`reinsert_front`](LinkedList::reinsert_front) and
+/// [`value`](LinkedList::value).
+///
+/// A handle is only valid for the list its node is in, and only until the
+/// node is removed. Nothing stops a copy from outliving its node, so the
+/// methods that take a handle are `unsafe` and leave both to the caller.
+/// Debug builds still catch a handle used with the wrong list while its
+/// node is allocated, with an id stamped into each node as it is linked.
+pub struct NodeHandle<T> {
+    node: NonNull<Node<T>>,
+}
+
+impl<T> Clone for NodeHandle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for NodeHandle<T> {}
+
+impl<T> PartialEq for NodeHandle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.node == other.node
+    }
+}
+
+impl<T> Eq for NodeHandle<T> {}
+
+impl<T> std::fmt::Debug for NodeHandle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_tuple("NodeHandle").field(&self.node).finish()
+    }
+}
+
+/// The iterator returned by [`LinkedList::extract_if`].
+pub struct ExtractIf<'a, T, F> {
+    list: &'a mut LinkedList<T>,
+    next: Option<NonNull<Node<T>>>,
+    unvisited: usize,
+    pred: F,
+}
+
+impl<T, F> Iterator for ExtractIf<'_, T, F>
+where
+    F: FnMut(&mut T) -> bool,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        while let Some(node) = self.next {
+            unsafe {
+                self.next = (*node.as_ptr()).next;
+                self.unvisited -= 1;
+                if (self.pred)(&mut (*node.as_ptr()).val) {
+                    return Some(self.list.free(node));
+                }
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.unvisited))
+    }
+}
+
+/// The iterator returned by [`LinkedList::drain`].
+pub struct Drain<'a, T> {
+    list: LinkedList<T>,
+    _marker: PhantomData<&'a mut LinkedList<T>>,
+}
+
+impl<T> Iterator for Drain<'_, T> {
+    type Item = T;
+
+    #[inline]
+    fn next(&mut self) -> Option<T> {
+        self.list.pop_front()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.list.len(), Some(self.list.len()))
+    }
+}
+
+impl<T> ExactSizeIterator for Drain<'_, T> {}
+
+impl<T> FusedIterator for Drain<'_, T> {}
+
+/// Yields the elements front to back, popping each off the list. Dropping
+/// it early drops the list along with whatever was not yet taken.
+pub struct IntoIter<T> {
+    list: LinkedList<T>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    #[inline]
+    fn next(&mut self) -> Option<T> {
+        self.list.pop_front()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.list.len(), Some(self.list.len()))
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    #[inline]
+    fn next_back(&mut self) -> Option<T> {
+        self.list.remove_tail()
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+impl<T> FusedIterator for IntoIter<T> {}
+
+impl<T> IntoIterator for LinkedList<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { list: self }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a LinkedList<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut LinkedList<T> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for LinkedList<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// A [`LinkedList`] that holds at most `max_len` elements, dropping the
+/// oldest: pushing onto the front of a full list evicts its tail.
+///
+/// It keeps a pool of one node, so a push that evicts reuses the evicted
+/// node instead of allocating.
+pub struct BoundedList<T> {
+    list: LinkedList<T>,
+    max_len: usize,
+}
+
+impl<T> BoundedList<T> {
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            list: LinkedList::with_pool(1),
+            max_len,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.list.len() >= self.max_len
+    }
+
+    /// Changes the maximum length, returning the elements it no longer has
+    /// room for, oldest last, if it shrank below the current length.
+    pub fn set_max_len(&mut self, max_len: usize) -> LinkedList<T> {
+        self.max_len = max_len;
+        if self.list.len() > max_len {
+            self.list.split_off(max_len)
+        } else {
+            LinkedList::new()
+        }
+    }
+
+    /// Pushes `val` onto the front, returning the tail if the list was full.
+    /// With a maximum length of zero `val` itself comes straight back.
+    pub fn push_front(&mut self, val: T) -> Option<T> {
+        if self.max_len == 0 {
+            return Some(val);
+        }
+        let evicted = if self.is_full() {
+            self.list.remove_tail()
+        } else {
+            None
+        };
+        self.list.insert_front(val);
+        evicted
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        self.list.pop_front()
+    }
+
+    pub fn remove_tail(&mut self) -> Option<T> {
+        self.list.remove_tail()
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        self.list.front()
+    }
+
+    pub fn back(&self) -> Option<&T> {
+        self.list.back()
+    }
+
+    /// Iterates from the newest element to the oldest.
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.list.iter()
+    }
+
+    pub fn into_list(self) -> LinkedList<T> {
+        self.list
+    }
+}
+
+impl<'a, T> IntoIterator for &'a BoundedList<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for BoundedList<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("BoundedList")
+            .field("max_len", &self.max_len)
+            .field("entries", &self.list)
+            .finish()
+    }
+}
+
+#[derive(Clone)]
+struct LRUEntry<K, V> {
+    key: K,
+    value: V,
+    /// As the weigher put it on insert; 0 without one.
+    weight: u64,
+    /// The entry is expired from this instant on.
+    expires_at: Option<Instant>,
+}
+
+impl<K, V> LRUEntry<K, V> {
+    pub fn new(key: K, value: V, weight: u64, expires_at: Option<Instant>) -> Self {
+        Self {
+            key,
+            value,
+            weight,
+            expires_at,
+        }
+    }
+}
+
+impl<K, V: std::fmt::Debug> std::fmt::Debug for LRUEntry<K, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self.value)?;
+        Ok(())
+    }
+}
+
+/// A key of an [`LRUCache`]'s map: a pointer to the one copy of the key, in
+/// its entry. An entry leaves the map before its node is freed, so the
+/// pointer never dangles while it is in the map.
+struct KeyRef<K> {
+    key: NonNull<K>,
+}
+
+impl<K> KeyRef<K> {
+    fn new<V>(entry: NonNull<LRUEntry<K, V>>) -> Self {
+        // Taken without going through a reference, so it shares the node's
+        // provenance and outlives the borrows the list hands out.
+        let key = unsafe { ptr::addr_of_mut!((*entry.as_ptr()).key) };
+        Self {
+            key: unsafe { NonNull::new_unchecked(key) },
+        }
+    }
+
+    fn get(&self) -> &K {
+        unsafe { self.key.as_ref() }
+    }
+}
+
+impl<K: Hash> Hash for KeyRef<K> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.get().hash(state);
+    }
+}
+
+impl<K: PartialEq> PartialEq for KeyRef<K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.get() == other.get()
+    }
+}
+
+impl<K: Eq> Eq for KeyRef<K> {}
+
+/// A borrowed form of a key, as the map is queried with it. `KeyRef<K>`
+/// cannot implement `Borrow<Q>` directly, as that clashes with the blanket
+/// `Borrow<KeyRef<K>>`.
+#[repr(transparent)]
+struct KeyWrapper<Q: ?Sized>(Q);
+
+impl<Q: ?Sized> KeyWrapper<Q> {
+    fn from_ref(key: &Q) -> &Self {
+        // The wrapper is transparent, so it has the layout of `Q`.
+        unsafe { &*(key as *const Q as *const Self) }
+    }
+}
+
+impl<Q: Hash + ?Sized> Hash for KeyWrapper<Q> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
+
+impl<Q: PartialEq + ?Sized> PartialEq for KeyWrapper<Q> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<Q: Eq + ?Sized> Eq for KeyWrapper<Q> {}
+
+impl<K: Borrow<Q>, Q: ?Sized> Borrow<KeyWrapper<Q>> for KeyRef<K> {
+    fn borrow(&self) -> &KeyWrapper<Q> {
+        KeyWrapper::from_ref(self.get().borrow())
+    }
+}
+
+/// Why an entry left an [`LRUCache`], as told to its eviction listener.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionCause {
+    /// Made room for a new key, or no longer fit after the capacity shrank.
+    Capacity,
+    /// Overwritten by an insert of the same key.
+    Replaced,
+    /// Dropped by [`LRUCache::clear`] or [`LRUCache::retain`].
+    Explicit,
+    /// Found past its deadline by a lookup, an eviction or
+    /// [`LRUCache::purge_expired`].
+    Expired,
+}
+
+/// What [`LRUCache::insert_full`] displaced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InsertResult<K, V> {
+    /// Nothing, or only entries the eviction listener took.
+    Inserted,
+    /// The key was already present with this value.
+    Updated(V),
+    /// The key was new, and this entry was evicted to make room. An entry
+    /// that cannot fit at all comes straight back this way.
+    Evicted { key: K, value: V },
+}
+
+impl<K, V> InsertResult<K, V> {
+    /// The displaced value, as [`LRUCache::insert`] returns it.
+    pub fn into_value(self) -> Option<V> {
+        match self {
+            InsertResult::Inserted => None,
+            InsertResult::Updated(value) | InsertResult::Evicted { value, .. } => Some(value),
+        }
+    }
+
+    fn evicted(entry: Option<LRUEntry<K, V>>) -> Self {
+        entry.map_or(InsertResult::Inserted, |entry| InsertResult::Evicted {
+            key: entry.key,
+            value: entry.value,
+        })
+    }
+}
+
+/// Called with each entry an [`LRUCache`] lets go of on its own.
+type EvictionListener<K, V> = Box<dyn FnMut(K, V, EvictionCause)>;
+
+/// Puts a weight on an entry of a weighted [`LRUCache`].
+/// Shared by a cache and its clones.
+type Weigher<K, V> = Rc<dyn Fn(&K, &V) -> u64>;
+
+/// A least recently used cache from `K` to `V`. Lookups take any borrowed
+/// form of the key, so a `String`-keyed cache is queried with a `&str`.
+///
+/// Each key is stored once, next to its value; the map points at it there.
+pub struct LRUCache<K, V> {
+    /// Every handle is for a node in `list`, which is what makes the
+    /// unsafe list calls on them sound; `detach` drops both at once.
+    map: HashMap<KeyRef<K>, NodeHandle<LRUEntry<K, V>>>,
+    list: LinkedList<LRUEntry<K, V>>,
+    capacity: usize,
+    listener: Option<EvictionListener<K, V>>,
+    weigher: Option<Weigher<K, V>>,
+    /// The total weight of the entries.
+    weight: u64,
+    max_weight: u64,
+    clock: Rc<dyn Clock>,
+    /// No entry expires before this; it may be earlier than the earliest
+    /// deadline left, as removing an entry does not update it.
+    next_expiry: Option<Instant>,
+}
+
+/// The byte-keyed cache, as `LRUCache` was before its key type became a
+/// parameter. Through [`Cache`] it still takes `&[u8]` keys; the inherent
+/// `insert` takes a `Vec<u8>` and lookups a `&[u8]`.
+pub type ByteLRUCache<V> = LRUCache<Vec<u8>, V>;
+
+impl<K, V> LRUCache<K, V>
+where
+    K: Hash + Eq,
+{
+    /// A cache of at most `capacity` entries. A cache of capacity 0 holds
+    /// nothing: every insert hands its value straight back, as do
+    /// [`get_or_insert`](Self::get_or_insert),
+    /// [`get_or_insert_with`](Self::get_or_insert_with) and
+    /// [`try_get_or_insert_with`](Self::try_get_or_insert_with), and every
+    /// lookup misses.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            map: HashMap::new(),
+            // Room for the one node freed by an eviction or a replaced
+            // entry, so the insert that follows reuses it.
+            list: LinkedList::with_pool(1),
+            capacity,
+            listener: None,
+            weigher: None,
+            weight: 0,
+            max_weight: u64::MAX,
+            clock: Rc::new(SystemClock),
+            next_expiry: None,
+        }
+    }
+
+    /// A cache that reads the time for [`insert_with_ttl`](Self::insert_with_ttl)
+    /// from `clock`.
+    pub fn with_clock(capacity: usize, clock: impl Clock + 'static) -> Self {
+        Self {
+            clock: Rc::new(clock),
+            ..Self::new(capacity)
+        }
+    }
+
+    /// A cache of `capacity` loaded from `iter` in order, so the last entry
+    /// ends up most recently used. Once the cache is full, each further entry
+    /// evicts the oldest one.
+    pub fn with_capacity_from(capacity: usize, iter: impl IntoIterator<Item = (K, V)>) -> Self {
+        let mut cache = Self::new(capacity);
+        cache.extend(iter);
+        cache
+    }
+
+    /// A cache bounded by the total weight of its entries instead of their
+    /// number. An insert evicts from the least recently used end, as many
+    /// entries as it takes, until the new entry fits; an entry heavier than
+    /// `max_weight` on its own is handed straight back by `insert`.
+    ///
+    /// `insert` returns at most one evicted value, so when it evicts several
+    /// the rest are dropped unless an
+    /// [eviction listener](Self::set_eviction_listener) takes them. Each
+    /// entry is weighed once, on insert: editing a value in place does not
+    /// change its weight.
+    pub fn with_weigher(max_weight: u64, weigher: impl Fn(&K, &V) -> u64 + 'static) -> Self {
+        Self {
+            weigher: Some(Rc::new(weigher)),
+            max_weight,
+            ..Self::new(usize::MAX)
+        }
+    }
+
+    /// A cache that hands every entry it evicts, replaces, clears or finds
+    /// expired to `f`, along with the cause.
+    ///
+    /// The listener then owns those values: `insert` and `set_capacity`
+    /// return `None` and nothing instead of the entries they displaced, and
+    /// `clear` passes its entries on instead of dropping them. Entries taken
+    /// out on purpose, with `remove`, `remove_entry`, `pop_lru`, `pop_mru`
+    /// or `clear_with`, still go to the caller and are not reported.
+    ///
+    /// The cache holds `f`, so `f` cannot reach the cache while it runs.
+    pub fn with_eviction_listener(
+        capacity: usize,
+        f: impl FnMut(K, V, EvictionCause) + 'static,
+    ) -> Self {
+        let mut cache = Self::new(capacity);
+        cache.set_eviction_listener(f);
+        cache
+    }
+
+    /// Installs `f` as the eviction listener, replacing any other; see
+    /// [`with_eviction_listener`](Self::with_eviction_listener).
+    pub fn set_eviction_listener(&mut self, f: impl FnMut(K, V, EvictionCause) + 'static) {
+        self.listener = Some(Box::new(f));
+    }
+
+    /// Returns the previous value for `key`, or else the value evicted to
+    /// make room; [`insert_full`](Self::insert_full) tells the two apart.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.insert_full(key, value).into_value()
+    }
+
+    /// Like [`insert`](Self::insert), but says whether a value was replaced
+    /// or evicted, and for an eviction which key it was under.
+    ///
+    /// When a heavier value replaces a lighter one in a weighted cache, any
+    /// entries evicted besides the replaced one are dropped unless the
+    /// listener takes them; only the replacement is returned.
+    pub fn insert_full(&mut self, key: K, value: V) -> InsertResult<K, V> {
+        let new_entry = self.entry(key, value, None);
+        self.insert_entry(new_entry)
+    }
+
+    /// Inserts `value` to live for `ttl`. From then on it is expired: lookups
+    /// no longer see it and drop it when they come across it, and it is the
+    /// first to go when the cache needs room. Until then it counts towards
+    /// [`len`](Self::len) and shows up in iteration;
+    /// [`purge_expired`](Self::purge_expired) drops every expired entry at
+    /// once. A `ttl` too long to add to the current time, such as
+    /// `Duration::MAX`, never expires.
+    pub fn insert_with_ttl(&mut self, key: K, value: V, ttl: Duration) -> Option<V> {
+        let expires_at = self.clock.now().checked_add(ttl);
+        let new_entry = self.entry(key, value, expires_at);
+        let displaced = self.insert_entry(new_entry).into_value();
+        // Only now: making room may purge, which recomputes the deadline
+        // from the entries already linked.
+        if let Some(expires_at) = expires_at {
+            self.next_expiry = Some(self.next_expiry.map_or(expires_at, |at| at.min(expires_at)));
+        }
+        displaced
+    }
+
+    fn insert_entry(&mut self, new_entry: LRUEntry<K, V>) -> InsertResult<K, V> {
+        if new_entry.weight > self.max_weight {
+            // It could never fit, so the caller keeps it.
+            return InsertResult::evicted(Some(new_entry));
+        }
+
+        match self.live_node(&new_entry.key) {
+            Some(node) => {
+                let old = self.detach(node);
+                // Only a heavier value can need more room than it freed.
+                self.insert_new(new_entry);
+                self.notify(old, EvictionCause::Replaced)
+                    .map_or(InsertResult::Inserted, |old| {
+                        InsertResult::Updated(old.value)
+                    })
+            }
+            None if self.capacity == 0 => {
+                let rejected = self.notify(new_entry, EvictionCause::Capacity);
+                InsertResult::evicted(rejected)
+            }
+            None => {
+                let evicted = self.insert_new(new_entry);
+                InsertResult::evicted(evicted)
+            }
+        }
+    }
+
+    /// Returns the value for `key`, first inserting `f()` if it is missing.
+    /// `f` is only called on a miss. A value the cache cannot hold, as its
+    /// capacity is zero or the value is heavier than the maximum weight, is
+    /// handed back as `Err`, like [`insert`](Self::insert) does.
+    pub fn get_or_insert_with(&mut self, key: K, f: impl FnOnce() -> V) -> Result<&V, V> {
+        match self.try_get_or_insert_with(key, || Ok::<V, Infallible>(f())) {
+            Ok(inserted) => inserted,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Like [`get_or_insert_with`](Self::get_or_insert_with) for a loader
+    /// that can fail. If `f` returns an error, the cache is left exactly as
+    /// it was and the error is passed on; otherwise the inner result is what
+    /// `get_or_insert_with` would return.
+    pub fn try_get_or_insert_with<E>(
+        &mut self,
+        key: K,
+        f: impl FnOnce() -> Result<V, E>,
+    ) -> Result<Result<&V, V>, E> {
+        if let Some(node) = self.live_node(&key) {
+            unsafe { self.list.reinsert_front(node) };
+            return Ok(Ok(unsafe { &self.list.value(node).value }));
+        }
+        // Nothing is evicted or allocated until the value is in hand.
+        let entry = self.entry(key, f()?, None);
+        if self.capacity == 0 || entry.weight > self.max_weight {
+            return Ok(Err(entry.value));
+        }
+        self.insert_new(entry);
+        Ok(Ok(&self.list.front().unwrap().value))
+    }
+
+    /// Like [`get_or_insert_with`](Self::get_or_insert_with) with the value
+    /// already at hand.
+    pub fn get_or_insert(&mut self, key: K, value: V) -> Result<&V, V> {
+        self.get_or_insert_with(key, || value)
+    }
+
+    fn entry(&self, key: K, value: V, expires_at: Option<Instant>) -> LRUEntry<K, V> {
+        let weight = self.weigher.as_ref().map_or(0, |weigh| weigh(&key, &value));
+        LRUEntry::new(key, value, weight, expires_at)
+    }
+
+    fn is_expired(&self, entry: &LRUEntry<K, V>) -> bool {
+        entry.expires_at.is_some_and(|at| self.clock.now() >= at)
+    }
+
+    /// The node for `key` if it has not expired. An expired one is dropped.
+    fn live_node<Q>(&mut self, key: &Q) -> Option<NodeHandle<LRUEntry<K, V>>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let &node = self.map.get(KeyWrapper::from_ref(key))?;
+        if self.is_expired(unsafe { self.list.value(node) }) {
+            let entry = self.detach(node);
+            self.notify(entry, EvictionCause::Expired);
+            return None;
+        }
+        Some(node)
+    }
+
+    /// Drops every expired entry, walking from the least recently used end,
+    /// and returns how many were dropped.
+    pub fn purge_expired(&mut self) -> usize {
+        let now = self.clock.now();
+        let mut expired = Vec::new();
+        let mut next_expiry: Option<Instant> = None;
+        let mut cursor = self.list.cursor_back_mut();
+        while let Some(entry) = cursor.current() {
+            match entry.expires_at {
+                Some(at) if now >= at => expired.push(cursor.current_handle().unwrap()),
+                Some(at) => next_expiry = Some(next_expiry.map_or(at, |next| next.min(at))),
+                None => {}
+            }
+            cursor.move_prev();
+        }
+        self.next_expiry = next_expiry;
+        // Every expired entry leaves the cache before the listener hears of
+        // any, so a panicking listener cannot leave stale handles behind.
+        let expired: Vec<_> = expired.into_iter().map(|node| self.detach(node)).collect();
+        let purged = expired.len();
+        for entry in expired {
+            self.notify(entry, EvictionCause::Expired);
+        }
+        purged
+    }
+
+    /// Puts an entry for a key that is not in the cache, and that is no
+    /// heavier than the maximum weight, at the front. Evicts least recently
+    /// used entries until it fits and returns the first evicted one.
+    fn insert_new(&mut self, entry: LRUEntry<K, V>) -> Option<LRUEntry<K, V>> {
+        let needs_room = |cache: &Self| {
+            !cache.is_empty() && (cache.is_full() || cache.weight > cache.max_weight - entry.weight)
+        };
+        // Expired entries go first, and only if something may have expired.
+        if needs_room(self) && self.next_expiry.is_some_and(|at| self.clock.now() >= at) {
+            self.purge_expired();
+        }
+        let mut evicted = None;
+        while needs_room(self) {
+            let lru = self.pop_lru_entry().unwrap();
+            // Any entry after the first is dropped if the listener does not
+            // take it.
+            let lru = self.notify(lru, EvictionCause::Capacity);
+            evicted = evicted.or(lru);
+        }
+        self.weight += entry.weight;
+        let new_node = self.list.insert_front_handle(entry);
+        let key = KeyRef::new(unsafe { self.list.value_ptr(new_node) });
+        self.map.insert(key, new_node);
+        evicted
+    }
+
+    /// Hands an entry that has left the cache to the listener, or back if
+    /// there is none. Called once the cache is consistent again, so a
+    /// panicking listener leaves nothing half done.
+    fn notify(&mut self, entry: LRUEntry<K, V>, cause: EvictionCause) -> Option<LRUEntry<K, V>> {
+        match &mut self.listener {
+            Some(listener) => {
+                listener(entry.key, entry.value, cause);
+                None
+            }
+            None => Some(entry),
+        }
+    }
+
+    fn pop_lru_entry(&mut self) -> Option<LRUEntry<K, V>> {
+        let node = self.list.back_handle()?;
+        Some(self.detach(node))
+    }
+
+    /// Takes an entry out of the map and then the list. The map's key points
+    /// into the node, so it has to go while the node is still there.
+    fn detach(&mut self, node: NodeHandle<LRUEntry<K, V>>) -> LRUEntry<K, V> {
+        self.map
+            .remove(&KeyRef::new(unsafe { self.list.value_ptr(node) }));
+        let entry = unsafe { self.list.remove(node) };
+        self.weight -= entry.weight;
+        entry
+    }
+
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.live_node(key) {
+            Some(node) => unsafe {
+                self.list.reinsert_front(node);
+                Some(&self.list.value(node).value)
+            },
+            None => None,
+        }
+    }
+
+    /// Like [`get`](Self::get), but lets the value be edited in place.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let node = self.live_node(key)?;
+        // Relinked first, so the returned borrow is the last use of the node.
+        unsafe {
+            self.list.reinsert_front(node);
+            Some(&mut self.list.value_mut(node).value)
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        debug_assert_eq!(self.map.len(), self.list.len(), "map and list out of sync");
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Whether the next insert of a new key evicts an entry to stay within
+    /// the capacity. A weighted cache may also evict to stay within its
+    /// maximum weight.
+    pub fn is_full(&self) -> bool {
+        self.len() >= self.capacity
+    }
+
+    /// The total weight of the entries, always 0 without a weigher.
+    pub fn current_weight(&self) -> u64 {
+        self.weight
+    }
+
+    /// `u64::MAX` without a weigher.
+    pub fn max_weight(&self) -> u64 {
+        self.max_weight
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.remove_entry(key).map(|(_, value)| value)
+    }
+
+    /// Removes `key`, returning the key the cache owned along with its value.
+    /// An expired entry is dropped and not returned.
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let node = self.live_node(key)?;
+        let entry = self.detach(node);
+        Some((entry.key, entry.value))
+    }
+
+    /// Changes how many freed nodes the cache keeps for later inserts. It
+    /// keeps one by default, so an insert into a full cache reuses the node
+    /// of the entry it evicts; with 0 every insert allocates.
+    pub fn set_node_pool(&mut self, nodes: usize) {
+        self.list.set_pool_capacity(nodes);
+    }
+
+    /// Changes the capacity. Shrinking below the current length drops the
+    /// expired entries, then evicts the least recently used ones right away;
+    /// only the latter are returned, in the order they were evicted.
+    pub fn set_capacity(&mut self, capacity: usize) -> Vec<(K, V)> {
+        self.capacity = capacity;
+        if self.len() > capacity {
+            self.purge_expired();
+        }
+        let mut evicted = Vec::with_capacity(self.len().saturating_sub(capacity));
+        while self.len() > capacity {
+            let entry = self.pop_lru_entry().unwrap();
+            if let Some(entry) = self.notify(entry, EvictionCause::Capacity) {
+                evicted.push((entry.key, entry.value));
+            }
+        }
+        evicted
+    }
+
+    /// Removes the least recently used entry, the one the next insert of a
+    /// new key into a full cache would evict. Expired entries on the way are
+    /// dropped.
+    pub fn pop_lru(&mut self) -> Option<(K, V)> {
+        loop {
+            let entry = self.pop_lru_entry()?;
+            if let Some(entry) = self.unless_expired(entry) {
+                return Some((entry.key, entry.value));
+            }
+        }
+    }
+
+    /// Removes the most recently used entry. Expired entries on the way are
+    /// dropped.
+    pub fn pop_mru(&mut self) -> Option<(K, V)> {
+        loop {
+            let node = self.list.front_handle()?;
+            let entry = self.detach(node);
+            if let Some(entry) = self.unless_expired(entry) {
+                return Some((entry.key, entry.value));
+            }
+        }
+    }
+
+    fn unless_expired(&mut self, entry: LRUEntry<K, V>) -> Option<LRUEntry<K, V>> {
+        if !self.is_expired(&entry) {
+            return Some(entry);
+        }
+        self.notify(entry, EvictionCause::Expired);
+        None
+    }
+
+    /// Drops every entry, keeping the capacity.
+    pub fn clear(&mut self) {
+        let mut entries = self.take_entries();
+        if let Some(listener) = &mut self.listener {
+            while let Some(entry) = entries.remove_tail() {
+                listener(entry.key, entry.value, EvictionCause::Explicit);
+            }
+        }
+    }
+
+    /// Empties the cache, handing each entry to `f` from least to most
+    /// recently used.
+    pub fn clear_with(&mut self, mut f: impl FnMut(K, V)) {
+        let mut entries = self.take_entries();
+        while let Some(entry) = entries.remove_tail() {
+            f(entry.key, entry.value);
+        }
+    }
+
+    /// Keeps only the entries for which `f` returns true, visiting them from
+    /// least to most recently used. `f` may edit the values it keeps; the
+    /// survivors stay in the same recency order. Expired entries are purged
+    /// first, without being shown to `f`.
+    pub fn retain(&mut self, mut f: impl FnMut(&K, &mut V) -> bool) {
+        self.purge_expired();
+        let mut rejected = Vec::new();
+        let mut cursor = self.list.cursor_back_mut();
+        while let Some(entry) = cursor.current() {
+            if !f(&entry.key, &mut entry.value) {
+                rejected.push(cursor.current_handle().unwrap());
+            }
+            cursor.move_prev();
+        }
+        // Nothing leaves until `f` is done, so a panicking `f` leaves every
+        // entry in place.
+        let dropped: Vec<_> = rejected.into_iter().map(|node| self.detach(node)).collect();
+        for entry in dropped {
+            self.notify(entry, EvictionCause::Explicit);
+        }
+    }
+
+    /// Unhooks every entry from the cache, so that a panic while they are
+    /// handed out leaves the cache empty and drops the ones still to go.
+    fn take_entries(&mut self) -> LinkedList<LRUEntry<K, V>> {
+        self.map.clear();
+        self.weight = 0;
+        self.next_expiry = None;
+        self.list.split_off(0)
+    }
+
+    /// Iterates from the most to the least recently used entry, or the other
+    /// way round with [`rev`](Iterator::rev), without changing recency.
+    pub fn iter(&self) -> LRUIter<'_, K, V> {
+        LRUIter {
+            entries: self.list.iter(),
+        }
+    }
+
+    /// Iterates from the most to the least recently used entry with the
+    /// values mutable, without changing recency.
+    pub fn iter_mut(&mut self) -> LRUIterMut<'_, K, V> {
+        LRUIterMut {
+            entries: self.list.iter_mut(),
+        }
+    }
+
+    /// The keys from most to least recently used.
+    ///
+    /// ```
+    /// use dsa_rs::lru::LRUCache;
+    ///
+    /// let mut cache = LRUCache::new(2);
+    /// cache.insert("a", 1);
+    /// cache.insert("b", 2);
+    /// cache.get("a");
+    /// assert!(cache.keys().eq(&["a", "b"]));
+    /// ```
+    pub fn keys(&self) -> impl DoubleEndedIterator<Item = &K> + ExactSizeIterator {
+        self.iter().map(|(key, _)| key)
+    }
+
+    /// The values from most to least recently used.
+    ///
+    /// ```
+    /// use dsa_rs::lru::LRUCache;
+    ///
+    /// let mut cache = LRUCache::new(2);
+    /// cache.insert("a", 1);
+    /// cache.insert("b", 2);
+    /// assert_eq!(cache.values().sum::<i32>(), 3);
+    /// assert_eq!(cache.values().last(), Some(&1));
+    /// ```
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = &V> + ExactSizeIterator {
+        self.iter().map(|(_, value)| value)
+    }
+
+    /// The values from most to least recently used, mutable.
+    pub fn values_mut(&mut self) -> impl ExactSizeIterator<Item = &mut V> {
+        self.iter_mut().map(|(_, value)| value)
+    }
+
+    /// Looks `key` up without making it the most recently used entry.
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let &node = self.map.get(KeyWrapper::from_ref(key))?;
+        let entry = unsafe { self.list.value(node) };
+        (!self.is_expired(entry)).then_some(&entry.value)
+    }
+
+    /// Whether `key` has an entry that has not expired, without making it
+    /// the most recently used.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.peek(key).is_some()
+    }
+
+    /// Like [`peek`](Self::peek), but lets the value be edited in place.
+    pub fn peek_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let node = self.live_node(key)?;
+        Some(unsafe { &mut self.list.value_mut(node).value })
+    }
+
+    /// The least recently used entry that has not expired, the one the next
+    /// insert of a new key into a full cache would evict.
+    pub fn peek_lru(&self) -> Option<(&K, &V)> {
+        self.list
+            .iter()
+            .rev()
+            .find(|entry| !self.is_expired(entry))
+            .map(|entry| (&entry.key, &entry.value))
+    }
+
+    /// The most recently used entry that has not expired.
+    pub fn peek_mru(&self) -> Option<(&K, &V)> {
+        self.list
+            .iter()
+            .find(|entry| !self.is_expired(entry))
+            .map(|entry| (&entry.key, &entry.value))
+    }
+
+    /// Makes `key` the most recently used entry without reading it, and
+    /// returns whether it was there.
+    pub fn promote<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let Some(node) = self.live_node(key) else {
+            return false;
+        };
+        unsafe { self.list.reinsert_front(node) };
+        true
+    }
+
+    /// Makes `key` the least recently used entry, so that it is the next one
+    /// evicted, and returns whether it was there. Useful for entries a
+    /// sequential scan is done with.
+    pub fn demote<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let Some(node) = self.live_node(key) else {
+            return false;
+        };
+        unsafe { self.list.reinsert_back(node) };
+        true
+    }
+}
+
+impl<K, V> IntoIterator for LRUCache<K, V> {
+    type Item = (K, V);
+    type IntoIter = LRUIntoIter<K, V>;
+
+    /// Yields the entries from most to least recently used.
+    fn into_iter(self) -> LRUIntoIter<K, V> {
+        LRUIntoIter {
+            entries: self.list.into_iter(),
+        }
+    }
+}
+
+impl<'a, K: Hash + Eq, V> IntoIterator for &'a LRUCache<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = LRUIter<'a, K, V>;
+
+    fn into_iter(self) -> LRUIter<'a, K, V> {
+        self.iter()
+    }
+}
+
+/// Iterates over the entries of an [`LRUCache`], as returned by
+/// [`LRUCache::iter`].
+pub struct LRUIter<'a, K, V> {
+    entries: Iter<'a, LRUEntry<K, V>>,
+}
+
+impl<'a, K, V> Iterator for LRUIter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next().map(|entry| (&entry.key, &entry.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for LRUIter<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.entries
+            .next_back()
+            .map(|entry| (&entry.key, &entry.value))
+    }
+}
+
+impl<K, V> ExactSizeIterator for LRUIter<'_, K, V> {}
+
+impl<K, V> FusedIterator for LRUIter<'_, K, V> {}
+
+impl<'a, K: Hash + Eq, V> IntoIterator for &'a mut LRUCache<K, V> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = LRUIterMut<'a, K, V>;
+
+    fn into_iter(self) -> LRUIterMut<'a, K, V> {
+        self.iter_mut()
+    }
+}
+
+/// Iterates over the entries of an [`LRUCache`] with the values mutable, as
+/// returned by [`LRUCache::iter_mut`].
+pub struct LRUIterMut<'a, K, V> {
+    entries: IterMut<'a, LRUEntry<K, V>>,
+}
+
+impl<'a, K, V> Iterator for LRUIterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries
+            .next()
+            .map(|entry| (&entry.key, &mut entry.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl<K, V> ExactSizeIterator for LRUIterMut<'_, K, V> {}
+
+impl<K, V> FusedIterator for LRUIterMut<'_, K, V> {}
+
+/// Moves the entries out of an [`LRUCache`], from most to least recently
+/// used. Entries left when it is dropped are dropped with it.
+pub struct LRUIntoIter<K, V> {
+    entries: IntoIter<LRUEntry<K, V>>,
+}
+
+impl<K, V> Iterator for LRUIntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next().map(|entry| (entry.key, entry.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for LRUIntoIter<K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.entries
+            .next_back()
+            .map(|entry| (entry.key, entry.value))
+    }
+}
+
+impl<K, V> ExactSizeIterator for LRUIntoIter<K, V> {}
+
+impl<K, V> FusedIterator for LRUIntoIter<K, V> {}
+
+/// Inserts the entries in order, so the last one ends up most recently
+/// used. A cache saved with `into_iter` is restored by extending with the
+/// entries reversed.
+impl<K: Hash + Eq, V> Extend<(K, V)> for LRUCache<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+/// Copies each borrowed key; owned `Vec<u8>` keys are moved in through
+/// `Extend<(K, V)>`.
+impl<'a, V> Extend<(&'a [u8], V)> for ByteLRUCache<V> {
+    fn extend<I: IntoIterator<Item = (&'a [u8], V)>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().map(|(key, value)| (key.to_vec(), value)));
+    }
+}
+
+impl<K, V> Clone for LRUCache<K, V>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+{
+    /// A cache of its own with copies of the entries, in the same recency
+    /// order and with the same deadlines. The clone shares the weigher and
+    /// clock but has no eviction listener, as an `FnMut` cannot be shared.
+    fn clone(&self) -> Self {
+        let mut map = HashMap::with_capacity(self.map.len());
+        let mut list = LinkedList::with_pool(1);
+        for entry in self.list.iter() {
+            let node = list.push_back_handle(entry.clone());
+            map.insert(KeyRef::new(unsafe { list.value_ptr(node) }), node);
+        }
+        Self {
+            map,
+            list,
+            capacity: self.capacity,
+            listener: None,
+            weigher: self.weigher.clone(),
+            weight: self.weight,
+            max_weight: self.max_weight,
+            clock: self.clock.clone(),
+            next_expiry: self.next_expiry,
+        }
+    }
+}
+
+/// How an [`LRUCache`] is serialized: its capacity and its live entries from
+/// least to most recently used, each with the time it has left if any.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "LRUCache")]
+struct Snapshot<K, V> {
+    capacity: usize,
+    entries: Vec<(K, V, Option<Duration>)>,
+}
+
+#[cfg(feature = "serde")]
+impl<K, V> Serialize for LRUCache<K, V>
+where
+    K: Hash + Eq + Serialize,
+    V: Serialize,
+{
+    /// Writes the capacity and the entries that have not expired. Loading
+    /// the result replays them oldest first, so the restored cache evicts in
+    /// the same order this one would have.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let now = self.clock.now();
+        let entries = self
+            .list
+            .iter()
+            .rev()
+            .filter(|entry| !self.is_expired(entry))
+            .map(|entry| {
+                let ttl = entry.expires_at.map(|at| at.duration_since(now));
+                (&entry.key, &entry.value, ttl)
+            })
+            .collect();
+        Snapshot {
+            capacity: self.capacity,
+            entries,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, V> Deserialize<'de> for LRUCache<K, V>
+where
+    K: Hash + Eq + Deserialize<'de>,
+    V: Deserialize<'de>,
+{
+    /// Rebuilds a cache of the saved capacity, with each entry's time left
+    /// counted from now. The weigher, clock and eviction listener are not
+    /// saved: the cache is bounded by count and reads the system clock.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let snapshot = Snapshot::<K, V>::deserialize(deserializer)?;
+        let mut cache = LRUCache::new(snapshot.capacity);
+        for (key, value, ttl) in snapshot.entries {
+            match ttl {
+                Some(ttl) => cache.insert_with_ttl(key, value, ttl),
+                None => cache.insert(key, value),
+            };
+        }
+        Ok(cache)
+    }
+}
+
+impl<K, V> std::fmt::Debug for LRUCache<K, V>
+where
+    K: std::fmt::Debug + 'static,
+    V: std::fmt::Debug,
+{
+    /// Shows the entries from most to least recently used, with byte keys
+    /// as [`DebugKey`] formats them.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("LRUCache")
+            .field("capacity", &self.capacity)
+            .field("len", &self.list.len())
+            .field("entries", &DebugEntries(&self.list))
+            .finish()
+    }
+}
+
+/// Formats entries as a map of their keys to their values, in list order.
+struct DebugEntries<'a, K, V>(&'a LinkedList<LRUEntry<K, V>>);
+
+impl<K, V> std::fmt::Debug for DebugEntries<'_, K, V>
+where
+    K: std::fmt::Debug + 'static,
+    V: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_map()
+            .entries(
+                self.0
+                    .iter()
+                    .map(|entry| (MaybeByteKey(&entry.key), &entry.value)),
+            )
+            .finish()
+    }
+}
+
+/// Formats a key as [`DebugKey`] does if it is a `Vec<u8>`, as in a
+/// [`ByteLRUCache`], and as its own `Debug` does otherwise.
+struct MaybeByteKey<'a, K>(&'a K);
+
+impl<K: std::fmt::Debug + 'static> std::fmt::Debug for MaybeByteKey<'_, K> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match (self.0 as &dyn std::any::Any).downcast_ref::<Vec<u8>>() {
+            Some(bytes) => DebugKey(bytes).fmt(f),
+            None => self.0.fmt(f),
+        }
+    }
+}
+
+/// Formats a byte key as a quoted string when it is valid UTF-8, and as
+/// `0x` and its bytes in hex otherwise.
+pub struct DebugKey<'a>(pub &'a [u8]);
+
+impl std::fmt::Debug for DebugKey<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match std::str::from_utf8(self.0) {
+            Ok(key) => key.fmt(f),
+            Err(_) => {
+                f.write_str("0x")?;
+                self.0.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+            }
+        }
+    }
+}
+
+impl<K: Hash, V> ResidentKeys for LRUCache<K, V> {
+    type Key = K;
+
+    fn for_each_key(&self, f: impl FnMut(&K)) {
+        self.list.iter().map(|entry| &entry.key).for_each(f);
+    }
+}
+
+impl<V> Cache<[u8], V> for ByteLRUCache<V> {
+    fn insert(&mut self, key: &[u8], value: V) -> Option<V> {
+        LRUCache::insert(self, key.to_vec(), value)
+    }
+
+    fn get(&mut self, key: &[u8]) -> Option<&V> {
+        LRUCache::get(self, key)
+    }
+
+    fn remove(&mut self, key: &[u8]) -> Option<V> {
+        LRUCache::remove(self, key)
+    }
+
+    fn len(&self) -> usize {
+        LRUCache::len(self)
+    }
+
+    fn cap(&self) -> usize {
+        self.capacity()
+    }
+
+    fn contains_key(&self, key: &[u8]) -> bool {
+        LRUCache::contains_key(self, key)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    mod test_linkedlist {
+        use super::super::LinkedList;
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        /// Counts its drops in the shared cell.
+        struct Counted(Rc<Cell<usize>>);
+
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        #[test]
+        fn test_insert() {
+            let mut list: LinkedList<i32> = LinkedList::new();
+            list.insert_front(2);
+            list.insert_front(3);
+            list.insert_front(4);
+            let result = format!("{:?}", list);
+            assert_eq!("[4, 3, 2]", result);
+        }
+
+        #[test]
+        fn test_push_back() {
+            let mut list: LinkedList<i32> = LinkedList::new();
+            list.push_back(1);
+            assert_eq!(format!("{:?}", list), "[1]");
+            list.push_back(2);
+            list.insert_front(0);
+            list.push_back(3);
+            assert_eq!(format!("{:?}", list), "[0, 1, 2, 3]");
+
+            assert_eq!(list.remove_tail(), Some(3));
+            assert_eq!(list.remove_tail(), Some(2));
+            list.push_back(4);
+            assert_eq!(format!("{:?}", list), "[0, 1, 4]");
+            assert_eq!(list.remove_tail(), Some(4));
+            assert_eq!(list.remove_tail(), Some(1));
+            assert_eq!(list.remove_tail(), Some(0));
+            assert_eq!(list.remove_tail(), None);
+            assert_eq!(list.iter().count(), 0);
+
+            // Pushing back onto a list emptied from the tail sets the head.
+            list.push_back(5);
+            list.insert_front(6);
+            assert_eq!(format!("{:?}", list), "[6, 5]");
+        }
+
+        #[test]
+        fn test_push_back_handles() {
+            let mut list: LinkedList<i32> = LinkedList::new();
+            let nodes: Vec<_> = (0..4).map(|i| list.push_back_handle(i)).collect();
+            assert_eq!(format!("{:?}", list), "[0, 1, 2, 3]");
+
+            // The tail moves to the front; the front moves nowhere.
+            unsafe { list.reinsert_front(nodes[3]) };
+            unsafe { list.reinsert_front(nodes[3]) };
+            assert_eq!(format!("{:?}", list), "[3, 0, 1, 2]");
+            assert_eq!(unsafe { list.remove(nodes[0]) }, 0);
+            assert_eq!(unsafe { list.remove(nodes[3]) }, 3);
+            list.push_back(4);
+            assert_eq!(format!("{:?}", list), "[1, 2, 4]");
+            assert_eq!(list.remove_tail(), Some(4));
+            assert_eq!(unsafe { list.remove(nodes[1]) }, 1);
+            assert_eq!(list.remove_tail(), Some(2));
+            assert_eq!(list.remove_tail(), None);
+        }
+
+        #[test]
+        fn test_reinsert_back() {
+            let mut list: LinkedList<i32> = LinkedList::new();
+            let nodes: Vec<_> = (0..3).map(|i| list.push_back_handle(i)).collect();
+            unsafe { list.reinsert_back(nodes[0]) };
+            assert_eq!(format!("{:?}", list), "[1, 2, 0]");
+            // The back stays put.
+            unsafe { list.reinsert_back(nodes[0]) };
+            unsafe { list.reinsert_back(nodes[1]) };
+            assert_eq!(format!("{:?}", list), "[2, 0, 1]");
+            assert_eq!(list.front_handle(), Some(nodes[2]));
+            assert_eq!(list.remove_tail(), Some(1));
+            assert_eq!(list.remove_tail(), Some(0));
+            unsafe { list.reinsert_back(nodes[2]) };
+            assert_eq!(format!("{:?}", list), "[2]");
+        }
+
+        #[test]
+        fn test_end_and_cursor_handles() {
+            let mut list: LinkedList<i32> = LinkedList::new();
+            assert_eq!(list.front_handle(), None);
+            assert_eq!(list.back_handle(), None);
+            let nodes: Vec<_> = (0..3).map(|i| list.push_back_handle(i)).collect();
+            assert_eq!(list.front_handle(), Some(nodes[0]));
+            assert_eq!(list.back_handle(), Some(nodes[2]));
+
+            let mut cursor = list.cursor_back_mut();
+            cursor.move_prev();
+            assert_eq!(cursor.current_handle(), Some(nodes[1]));
+            cursor.move_prev();
+            cursor.move_prev();
+            assert_eq!(cursor.current_handle(), None);
+
+            // A value pointer outlives borrows of the list.
+            let one = unsafe { list.value_ptr(nodes[1]) };
+            unsafe { list.reinsert_front(nodes[1]) };
+            unsafe { *list.value_mut(nodes[0]) += 10 };
+            assert_eq!(unsafe { *one.as_ptr() }, 1);
+            assert_eq!(unsafe { list.remove(list.back_handle().unwrap()) }, 2);
+            assert_eq!(format!("{:?}", list), "[1, 10]");
+        }
+
+        #[test]
+        fn test_pop_front() {
+            let mut list: LinkedList<i32> = LinkedList::new();
+            assert_eq!(list.pop_front(), None);
+
+            list.insert_front(1);
+            assert_eq!(list.pop_front(), Some(1));
+            assert_eq!(list.pop_front(), None);
+            assert_eq!(list.remove_tail(), None);
+            assert_eq!(list.iter().count(), 0);
+
+            for i in 0..6 {
+                list.insert_front(i);
+            }
+            assert_eq!(list.pop_front(), Some(5));
+            assert_eq!(list.remove_tail(), Some(0));
+            list.insert_front(6);
+            assert_eq!(list.pop_front(), Some(6));
+            assert_eq!(list.iter().copied().collect::<Vec<_>>(), [4, 3, 2, 1]);
+            assert_eq!(list.remove_tail(), Some(1));
+            assert_eq!(list.pop_front(), Some(4));
+            assert_eq!(list.iter().copied().collect::<Vec<_>>(), [3, 2]);
+            assert_eq!(list.pop_front(), Some(3));
+            // Down to one element, reachable from both ends.
+            assert_eq!(list.iter().copied().collect::<Vec<_>>(), [2]);
+            assert_eq!(list.remove_tail(), Some(2));
+            assert_eq!(list.pop_front(), None);
+
+            list.insert_front(7);
+            list.push_back(8);
+            assert_eq!(list.iter().copied().collect::<Vec<_>>(), [7, 8]);
+        }
+
+        #[test]
+        fn test_front_back() {
+            let mut list: LinkedList<i32> = LinkedList::new();
+            assert_eq!(list.front(), None);
+            assert_eq!(list.back(), None);
+            assert_eq!(list.front_mut(), None);
+            assert_eq!(list.back_mut(), None);
+
+            list.insert_front(1);
+            assert_eq!(list.front(), Some(&1));
+            assert_eq!(list.back(), Some(&1));
+            *list.front_mut().unwrap() += 10;
+            assert_eq!(list.back(), Some(&11));
+
+            list.insert_front(2);
+            list.push_back(3);
+            *list.front_mut().unwrap() *= 10;
+            *list.back_mut().unwrap() *= 100;
+            assert_eq!(list.iter().copied().collect::<Vec<_>>(), [20, 11, 300]);
+            assert_eq!(list.front(), Some(&20));
+            assert_eq!(list.back(), Some(&300));
+
+            list.pop_front();
+            list.remove_tail();
+            assert_eq!(list.front(), Some(&11));
+            assert_eq!(list.back(), Some(&11));
+        }
+
+        #[test]
+        fn test_len() {
+            let mut list: LinkedList<i32> = LinkedList::new();
+            assert_eq!(list.len(), 0);
+            assert!(list.is_empty());
+
+            list.insert_front(0);
+            assert_eq!(list.len(), 1);
+            assert!(!list.is_empty());
+            let a = list.insert_front_handle(1);
+            assert_eq!(list.len(), 2);
+            let b = list.push_back_handle(2);
+            assert_eq!(list.len(), 3);
+            list.push_back(3);
+            assert_eq!(list.len(), 4);
+
+            unsafe { list.reinsert_front(b) };
+            assert_eq!(list.len(), 4);
+            unsafe { list.reinsert_front(b) };
+            assert_eq!(list.len(), 4);
+            assert_eq!(unsafe { list.remove(a) }, 1);
+            assert_eq!(list.len(), 3);
+            assert_eq!(list.remove_tail(), Some(3));
+            assert_eq!(list.len(), 2);
+            assert_eq!(list.pop_front(), Some(2));
+            assert_eq!(list.len(), 1);
+            assert_eq!(list.remove_tail(), Some(0));
+            assert_eq!(list.len(), 0);
+            assert!(list.is_empty());
+
+            // Removing from an empty list leaves the length alone.
+            assert_eq!(list.remove_tail(), None);
+            assert_eq!(list.pop_front(), None);
+            assert_eq!(list.len(), 0);
+        }
+
+        #[test]
+        fn test_into_iter() {
+            let mut list: LinkedList<i32> = LinkedList::new();
+            for i in 0..5 {
+                list.push_back(i);
+            }
+            for value in &mut list {
+                *value *= 2;
+            }
+            let mut seen = Vec::new();
+            for value in &list {
+                seen.push(*value);
+            }
+            assert_eq!(seen, [0, 2, 4, 6, 8]);
+            assert_eq!(list.into_iter().collect::<Vec<_>>(), [0, 2, 4, 6, 8]);
+
+            let empty: LinkedList<i32> = LinkedList::new();
+            assert_eq!(empty.into_iter().next(), None);
+        }
+
+        #[test]
+        fn test_into_iter_dropped_early() {
+            let drops = Rc::new(Cell::new(0));
+
+            let mut list = LinkedList::new();
+            for _ in 0..6 {
+                list.push_back(Counted(drops.clone()));
+            }
+            let mut iter = list.into_iter();
+            drop(iter.next());
+            drop(iter.next());
+            assert_eq!(drops.get(), 2);
+            // The rest go with the iterator.
+            drop(iter);
+            assert_eq!(drops.get(), 6);
+        }
+
+        fn contents(list: &LinkedList<i32>) -> Vec<i32> {
+            let forward: Vec<i32> = list.iter().copied().collect();
+            // Walks the prev links as well, so both directions stay in sync.
+            let mut backward = Vec::new();
+            let mut node = list.tail;
+            while let Some(n) = node {
+                unsafe {
+                    backward.push((*n.as_ptr()).val);
+                    node = (*n.as_ptr()).prev;
+                }
+            }
+            backward.reverse();
+            assert_eq!(forward, backward);
+            assert_eq!(forward.len(), list.len());
+            forward
+        }
+
+        #[test]
+        fn test_cursor_build() {
+            let mut list: LinkedList<i32> = LinkedList::new();
+            let mut cursor = list.cursor_front_mut();
+            assert_eq!(cursor.index(), None);
+            assert_eq!(cursor.current(), None);
+            assert_eq!(cursor.remove_current(), None);
+
+            // At the ghost, insert_before appends and insert_after prepends.
+            cursor.insert_before(2);
+            cursor.insert_before(3);
+            cursor.insert_after(1);
+            cursor.insert_after(0);
+            assert_eq!(cursor.index(), None);
+            cursor.move_next();
+            assert_eq!(cursor.index(), Some(0));
+            assert_eq!(cursor.current(), Some(&mut 0));
+            cursor.move_next();
+            cursor.move_next();
+            cursor.move_next();
+            assert_eq!(cursor.current(), Some(&mut 3));
+            assert_eq!(cursor.index(), Some(3));
+            cursor.move_next();
+            assert_eq!(cursor.index(), None);
+            cursor.move_prev();
+            assert_eq!(cursor.index(), Some(3));
+            cursor.move_next();
+            cursor.move_next();
+            assert_eq!(cursor.index(), Some(0));
+            cursor.move_prev();
+            assert_eq!(cursor.index(), None);
+            assert_eq!(contents(&list), [0, 1, 2, 3]);
+        }
+
+        #[test]
+        fn test_cursor_read_only() {
+            let empty: LinkedList<i32> = LinkedList::new();
+            let cursor = empty.cursor_front();
+            assert_eq!(cursor.index(), None);
+            assert_eq!(cursor.current(), None);
+            assert_eq!((cursor.peek_next(), cursor.peek_prev()), (None, None));
+
+            let list: LinkedList<i32> = (0..4).collect();
+            let mut cursor = list.cursor_front();
+            assert_eq!((cursor.index(), cursor.current()), (Some(0), Some(&0)));
+            assert_eq!((cursor.peek_prev(), cursor.peek_next()), (None, Some(&1)));
+            // Off the front onto the ghost, then on round to the tail.
+            cursor.move_prev();
+            assert_eq!((cursor.index(), cursor.current()), (None, None));
+            assert_eq!(
+                (cursor.peek_prev(), cursor.peek_next()),
+                (Some(&3), Some(&0))
+            );
+            cursor.move_prev();
+            assert_eq!((cursor.index(), cursor.current()), (Some(3), Some(&3)));
+            assert_eq!(cursor.peek_next(), None);
+            // Off the back onto the ghost, then on round to the head.
+            cursor.move_next();
+            assert_eq!(cursor.index(), None);
+            cursor.move_next();
+            assert_eq!(cursor.index(), Some(0));
+
+            let mut back = list.cursor_back();
+            assert_eq!((back.index(), back.current()), (Some(3), Some(&3)));
+            back.move_prev();
+            assert_eq!((back.index(), back.current()), (Some(2), Some(&2)));
+            // A copy moves on its own.
+            let mut ahead = back;
+            ahead.move_next();
+            assert_eq!((ahead.current(), back.current()), (Some(&3), Some(&2)));
+        }
+
+        #[test]
+        fn test_cursor_two_pointers() {
+            fn is_palindrome(list: &LinkedList<char>) -> bool {
+                let (mut front, mut back) = (list.cursor_front(), list.cursor_back());
+                for _ in 0..list.len() / 2 {
+                    if front.current() != back.current() {
+                        return false;
+                    }
+                    front.move_next();
+                    back.move_prev();
+                }
+                true
+            }
+            for (word, expected) in [
+                ("", true),
+                ("a", true),
+                ("abba", true),
+                ("level", true),
+                ("abca", false),
+            ] {
+                let list: LinkedList<char> = word.chars().collect();
+                assert_eq!(is_palindrome(&list), expected, "{word}");
+            }
+            // The values outlive the cursors that found them.
+            let list: LinkedList<i32> = (0..5).collect();
+            let middle = {
+                let mut cursor = list.cursor_front();
+                cursor.move_next();
+                cursor.move_next();
+                cursor.current()
+            };
+            assert_eq!(middle, Some(&2));
+        }
+
+        #[test]
+        fn test_cursor_splice() {
+            let mut list: LinkedList<i32> = LinkedList::new();
+            for i in 0..5 {
+                list.push_back(i * 10);
+            }
+            let mut cursor = list.cursor_front_mut();
+            cursor.move_next();
+            cursor.move_next();
+            assert_eq!(cursor.index(), Some(2));
+            cursor.insert_before(15);
+            cursor.insert_after(25);
+            assert_eq!(cursor.index(), Some(3));
+            *cursor.current().unwrap() += 1;
+            assert_eq!(cursor.remove_current(), Some(21));
+            assert_eq!(cursor.current(), Some(&mut 25));
+            assert_eq!(cursor.index(), Some(3));
+            assert_eq!(contents(&list), [0, 10, 15, 25, 30, 40]);
+
+            // Removing through both ends moves the head and tail.
+            let mut cursor = list.cursor_back_mut();
+            assert_eq!(cursor.index(), Some(5));
+            assert_eq!(cursor.remove_current(), Some(40));
+            assert_eq!(cursor.index(), None);
+            cursor.move_next();
+            assert_eq!(cursor.remove_current(), Some(0));
+            assert_eq!(cursor.index(), Some(0));
+            assert_eq!(cursor.current(), Some(&mut 10));
+            cursor.move_prev();
+            cursor.insert_after(5);
+            cursor.insert_before(35);
+            assert_eq!(cursor.index(), None);
+            assert_eq!(contents(&list), [5, 10, 15, 25, 30, 35]);
+            assert_eq!(list.front(), Some(&5));
+            assert_eq!(list.back(), Some(&35));
+
+            let mut cursor = list.cursor_front_mut();
+            while cursor.remove_current().is_some() {}
+            assert_eq!(cursor.index(), None);
+            assert!(list.is_empty());
+            assert!(contents(&list).is_empty());
+        }
+
+        #[test]
+        fn test_split_off() {
+            for len in 0..8 {
+                for at in 0..=len {
+                    let mut list: LinkedList<i32> = LinkedList::new();
+                    for i in 0..len {
+                        list.push_back(i);
+                    }
+                    let rest = list.split_off(at as usize);
+                    assert_eq!(contents(&list), (0..at).collect::<Vec<_>>());
+                    assert_eq!(contents(&rest), (at..len).collect::<Vec<_>>());
+                    assert_eq!(list.back(), (at > 0).then_some(&(at - 1)));
+                    assert_eq!(rest.front(), (at < len).then_some(&at));
+                }
+            }
+
+            // Both halves stay usable and drop on their own.
+            let mut list: LinkedList<String> = LinkedList::new();
+            for i in 0..6 {
+                list.push_back(i.to_string());
+            }
+            let mut rest = list.split_off(2);
+            list.push_back("x".to_string());
+            rest.insert_front("y".to_string());
+            assert_eq!(list.iter().cloned().collect::<Vec<_>>(), ["0", "1", "x"]);
+            assert_eq!(rest.pop_front().as_deref(), Some("y"));
+            assert_eq!(rest.remove_tail().as_deref(), Some("5"));
+            assert_eq!(rest.len(), 3);
+        }
+
+        #[test]
+        #[should_panic(expected = "split index 4 out of bounds for length 3")]
+        fn test_split_off_out_of_bounds() {
+            let mut list: LinkedList<i32> = LinkedList::new();
+            for i in 0..3 {
+                list.push_back(i);
+            }
+            list.split_off(4);
+        }
+
+        fn from_range(range: std::ops::Range<i32>) -> LinkedList<i32> {
+            range.collect()
+        }
+
+        #[test]
+        fn test_pool() {
+            let drops = Rc::new(Cell::new(0));
+            let mut list = LinkedList::with_pool(2);
+            for _ in 0..4 {
+                list.push_back(Counted(drops.clone()));
+            }
+            for _ in 0..3 {
+                list.pop_front();
+            }
+            // Values are dropped as they leave, pooled node or not.
+            assert_eq!(drops.get(), 3);
+            let stats = list.pool_stats();
+            assert_eq!((stats.allocated, stats.reused, stats.pooled), (4, 0, 2));
+
+            for _ in 0..3 {
+                list.insert_front(Counted(drops.clone()));
+            }
+            let stats = list.pool_stats();
+            assert_eq!((stats.allocated, stats.reused, stats.pooled), (5, 2, 0));
+            assert_eq!(list.len(), 4);
+            list.remove_tail();
+            assert_eq!(list.pool_stats().pooled, 1);
+            drop(list);
+            assert_eq!(drops.get(), 7);
+
+            // Without a pool, every node goes back to the allocator.
+            let mut list = from_range(0..3);
+            list.pop_front();
+            assert_eq!(list.pool_stats().pooled, 0);
+            list.push_back(3);
+            assert_eq!(list.pool_stats().allocated, 4);
+        }
+
+        #[test]
+        fn test_pool_reused_everywhere() {
+            let mut list: LinkedList<i32> = LinkedList::with_pool(8);
+            list.extend(0..6);
+            list.retain(|v| v % 2 == 0);
+            assert_eq!(list.pool_stats().pooled, 3);
+            list.insert(1, 10);
+            list.cursor_front_mut().insert_after(11);
+            list.push_back(12);
+            assert_eq!(contents(&list), [0, 11, 10, 2, 4, 12]);
+            assert_eq!(list.pool_stats().reused, 3);
+
+            // Moving the elements out keeps the pool with the list.
+            let mut other = LinkedList::new();
+            other.append(&mut list);
+            assert_eq!(list.drain().count(), 0);
+            list.extend(other.drain());
+            assert_eq!(contents(&list), [0, 11, 10, 2, 4, 12]);
+            while list.pop_front().is_some() {}
+            assert_eq!(list.pool_stats().pooled, 6);
+            let clone = list.clone();
+            assert_eq!(clone.pool_capacity, 8);
+
+            list.set_pool_capacity(2);
+            assert_eq!(list.pool_stats().pooled, 2);
+            list.extend(0..3);
+            assert_eq!(list.pool_stats().reused, 5);
+        }
+
+        #[test]
+        fn test_append() {
+            // Every combination of empty and non-empty.
+            for (a, b) in [(0..0, 0..0), (0..3, 3..3), (0..0, 0..2), (0..3, 3..5)] {
+                let expected: Vec<i32> = a.clone().chain(b.clone()).collect();
+                let mut front = from_range(a.clone());
+                let mut back = from_range(b.clone());
+                front.append(&mut back);
+                assert_eq!(contents(&front), expected);
+                assert!(contents(&back).is_empty());
+                assert_eq!(back.front(), None);
+
+                let mut front = from_range(a);
+                let mut back = from_range(b);
+                back.prepend(&mut front);
+                assert_eq!(contents(&back), expected);
+                assert!(contents(&front).is_empty());
+
+                // The emptied list is still usable, and independent.
+                front.push_back(9);
+                assert_eq!(contents(&front), [9]);
+                assert_eq!(back.len(), expected.len());
+            }
+        }
+
+        #[test]
+        fn test_append_then_drop() {
+            let drops = Rc::new(Cell::new(0));
+
+            let mut a = LinkedList::new();
+            let mut b = LinkedList::new();
+            for _ in 0..3 {
+                a.push_back(Counted(drops.clone()));
+                b.push_back(Counted(drops.clone()));
+            }
+            a.append(&mut b);
+            assert_eq!((a.len(), b.len()), (6, 0));
+            drop(b);
+            assert_eq!(drops.get(), 0);
+            a.remove_tail();
+            assert_eq!(drops.get(), 1);
+            drop(a);
+            assert_eq!(drops.get(), 6);
+        }
+
+        #[test]
+        fn test_from_iter_extend() {
+            let values = vec![3, 1, 4, 1, 5];
+            let mut list: LinkedList<i32> = values.clone().into_iter().collect();
+            assert_eq!(contents(&list), values);
+            assert_eq!(list.iter().copied().collect::<Vec<_>>(), values);
+
+            list.extend(vec![9, 2]);
+            list.extend(&[6, 5]);
+            assert_eq!(contents(&list), [3, 1, 4, 1, 5, 9, 2, 6, 5]);
+            assert_eq!(list.back(), Some(&5));
+
+            let empty: LinkedList<i32> = std::iter::empty().collect();
+            assert!(empty.is_empty());
+            assert_eq!(empty.front(), None);
+        }
+
+        #[test]
+        fn test_clone() {
+            let original = from_range(0..5);
+            let mut copy = original.clone();
+            assert_eq!(contents(&copy), [0, 1, 2, 3, 4]);
+            *copy.front_mut().unwrap() = 10;
+            copy.push_back(5);
+            assert_eq!(contents(&original), [0, 1, 2, 3, 4]);
+            assert_eq!(contents(&copy), [10, 1, 2, 3, 4, 5]);
+
+            for (dest, source) in [(0..6, 0..3), (0..3, 0..6), (0..4, 0..0), (0..0, 0..4)] {
+                let source = from_range(source.start + 100..source.end + 100);
+                let mut dest = from_range(dest);
+                let reused = dest.front().map(|v| v as *const i32);
+                dest.clone_from(&source);
+                assert_eq!(contents(&dest), contents(&source));
+                // The first node survives when both lists have one.
+                if !source.is_empty() {
+                    if let Some(reused) = reused {
+                        assert_eq!(dest.front().unwrap() as *const i32, reused);
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn test_clone_panic_frees_copies() {
+            use std::panic::{catch_unwind, AssertUnwindSafe};
+
+            // Panics on the clone that would make it the `fail_at`th copy.
+            struct Fragile {
+                drops: Rc<Cell<usize>>,
+                clones: Rc<Cell<usize>>,
+                fail_at: usize,
+            }
+            impl Clone for Fragile {
+                fn clone(&self) -> Self {
+                    let clones = self.clones.get() + 1;
+                    assert!(clones != self.fail_at, "clone failed");
+                    self.clones.set(clones);
+                    Fragile {
+                        drops: self.drops.clone(),
+                        clones: self.clones.clone(),
+                        fail_at: self.fail_at,
+                    }
+                }
+            }
+            impl Drop for Fragile {
+                fn drop(&mut self) {
+                    self.drops.set(self.drops.get() + 1);
+                }
+            }
+
+            let drops = Rc::new(Cell::new(0));
+            let clones = Rc::new(Cell::new(0));
+            let list: LinkedList<Fragile> = (0..5)
+                .map(|_| Fragile {
+                    drops: drops.clone(),
+                    clones: clones.clone(),
+                    fail_at: 4,
+                })
+                .collect();
+            let result = catch_unwind(AssertUnwindSafe(|| list.clone()));
+            assert!(result.is_err());
+            // The three copies made before the panic are gone again.
+            assert_eq!(clones.get(), 3);
+            assert_eq!(drops.get(), 3);
+            drop(list);
+            assert_eq!(drops.get(), 8);
+        }
+
+        #[test]
+        fn test_eq_ord_hash() {
+            use std::collections::HashSet;
+
+            let mut fronted = LinkedList::new();
+            for i in (0..4).rev() {
+                fronted.insert_front(i);
+            }
+            let backed = from_range(0..4);
+            assert_eq!(fronted, backed);
+
+            let prefix = from_range(0..3);
+            assert_ne!(prefix, backed);
+            assert!(prefix < backed);
+            assert!(from_range(1..2) > backed);
+            assert_eq!(backed.cmp(&fronted), std::cmp::Ordering::Equal);
+            assert!(LinkedList::new() < prefix);
+
+            let mut set = HashSet::new();
+            assert!(set.insert(fronted));
+            assert!(!set.insert(backed));
+            assert!(set.insert(prefix));
+            assert!(set.insert(LinkedList::new()));
+            assert!(set.contains(&from_range(0..4)));
+
+            let floats: LinkedList<f64> = [1.0, f64::NAN].into_iter().collect();
+            assert_ne!(floats, floats.clone());
+            assert_eq!(floats.partial_cmp(&floats.clone()), None);
+        }
+
+        #[test]
+        fn test_retain() {
+            let mut list = from_range(0..10);
+            list.retain(|_| true);
+            assert_eq!(contents(&list), (0..10).collect::<Vec<_>>());
+            // Drops the head and the tail along with every other element.
+            list.retain(|&v| v % 2 == 1);
+            assert_eq!(contents(&list), [1, 3, 5, 7, 9]);
+            list.retain_mut(|v| {
+                *v *= 10;
+                *v != 30
+            });
+            assert_eq!(contents(&list), [10, 50, 70, 90]);
+            list.retain(|_| false);
+            assert!(contents(&list).is_empty());
+            assert_eq!((list.front(), list.back()), (None, None));
+            list.push_back(1);
+            assert_eq!(contents(&list), [1]);
+
+            let mut single = from_range(0..1);
+            single.retain(|_| false);
+            assert!(contents(&single).is_empty());
+
+            let drops = Rc::new(Cell::new(0));
+            let mut counted: LinkedList<_> = (0..6).map(|_| Counted(drops.clone())).collect();
+            let mut i = 0;
+            counted.retain(|_| {
+                i += 1;
+                i % 3 != 0
+            });
+            assert_eq!((counted.len(), drops.get()), (4, 2));
+        }
+
+        #[test]
+        fn test_extract_if() {
+            let mut list = from_range(0..10);
+            let evens: Vec<i32> = list.extract_if(|v| *v % 2 == 0).collect();
+            assert_eq!(evens, [0, 2, 4, 6, 8]);
+            assert_eq!(contents(&list), [1, 3, 5, 7, 9]);
+
+            {
+                let mut extract = list.extract_if(|v| *v > 2);
+                assert_eq!(extract.size_hint(), (0, Some(5)));
+                assert_eq!(extract.next(), Some(3));
+                assert_eq!(extract.size_hint(), (0, Some(3)));
+            }
+            // Dropped early: 7 and 9 match but stay.
+            assert_eq!(contents(&list), [1, 5, 7, 9]);
+
+            assert_eq!(list.extract_if(|_| false).count(), 0);
+            assert_eq!(contents(&list), [1, 5, 7, 9]);
+            let all: Vec<i32> = list.extract_if(|_| true).collect();
+            assert_eq!(all, [1, 5, 7, 9]);
+            assert!(contents(&list).is_empty());
+
+            let drops = Rc::new(Cell::new(0));
+            let mut counted: LinkedList<_> = (0..6).map(|_| Counted(drops.clone())).collect();
+            let taken: Vec<Counted> = counted.extract_if(|_| true).take(2).collect();
+            assert_eq!((counted.len(), drops.get()), (4, 0));
+            drop(taken);
+            drop(counted);
+            assert_eq!(drops.get(), 6);
+        }
+
+        #[test]
+        fn test_drain() {
+            let mut list = from_range(0..5);
+            let mut drain = list.drain();
+            assert_eq!(drain.len(), 5);
+            assert_eq!(drain.next(), Some(0));
+            assert_eq!(drain.len(), 4);
+            assert_eq!(drain.collect::<Vec<_>>(), [1, 2, 3, 4]);
+            assert!(contents(&list).is_empty());
+
+            list.push_back(7);
+            list.insert_front(6);
+            let mut other = LinkedList::new();
+            other.extend(list.drain());
+            assert_eq!(contents(&other), [6, 7]);
+            assert!(contents(&list).is_empty());
+            assert_eq!(list.drain().next(), None);
+        }
+
+        #[test]
+        fn test_drain_dropped_early() {
+            let drops = Rc::new(Cell::new(0));
+            let mut list: LinkedList<_> = (0..6).map(|_| Counted(drops.clone())).collect();
+            let taken: Vec<Counted> = list.drain().take(2).collect();
+            // The four not taken are dropped with the iterator.
+            assert_eq!((list.len(), drops.get()), (0, 4));
+            drop(taken);
+            assert_eq!(drops.get(), 6);
+
+            list.extend((0..3).map(|_| Counted(drops.clone())));
+            drop(list.drain());
+            assert!(list.is_empty());
+            assert_eq!(drops.get(), 9);
+        }
+
+        #[test]
+        fn test_reverse() {
+            for len in 0..5 {
+                let mut list = from_range(0..len);
+                list.reverse();
+                assert_eq!(contents(&list), (0..len).rev().collect::<Vec<_>>());
+                list.reverse();
+                assert_eq!(contents(&list), (0..len).collect::<Vec<_>>());
+            }
+
+            let mut list = LinkedList::new();
+            for i in 0..4 {
+                list.insert_front(i);
+            }
+            list.reverse();
+            assert_eq!(contents(&list), [0, 1, 2, 3]);
+            assert_eq!(list.remove_tail(), Some(3));
+            assert_eq!(list.pop_front(), Some(0));
+            list.push_back(4);
+            list.insert_front(5);
+            assert_eq!(contents(&list), [5, 1, 2, 4]);
+        }
+
+        #[test]
+        fn test_find() {
+            let mut list = from_range(0..6);
+            assert!(list.contains(&3));
+            assert!(!list.contains(&6));
+            assert!(!LinkedList::new().contains(&0));
+            assert_eq!(list.find(|&v| v > 9), None);
+
+            let four = list.find(|&v| v == 4).unwrap();
+            assert_eq!(list.find(|&v| v % 4 == 0 && v > 0), Some(four));
+            unsafe { list.reinsert_front(four) };
+            assert_eq!(contents(&list), [4, 0, 1, 2, 3, 5]);
+            let five = list.find(|&v| v == 5).unwrap();
+            unsafe { list.reinsert_front(five) };
+            assert_eq!(unsafe { list.remove(four) }, 4);
+            assert_eq!(contents(&list), [5, 0, 1, 2, 3]);
+            assert!(!list.contains(&4));
+            let zero = list.find(|&v| v == 0).unwrap();
+            assert_eq!(unsafe { list.remove(zero) }, 0);
+            assert_eq!(contents(&list), [5, 1, 2, 3]);
+        }
+
+        #[test]
+        #[cfg(debug_assertions)]
+        #[should_panic(expected = "node does not belong to this list")]
+        fn test_handle_from_other_list() {
+            let mut a = from_range(0..3);
+            let b = from_range(0..3);
+            let handle = b.find(|&v| v == 1).unwrap();
+            // Breaks the contract on purpose; with the node still allocated,
+            // debug builds catch it before anything is touched.
+            unsafe { a.remove(handle) };
+        }
+
+        #[test]
+        #[cfg(debug_assertions)]
+        #[should_panic(expected = "node does not belong to this list")]
+        fn test_reinsert_handle_from_other_list() {
+            let mut a = from_range(0..3);
+            let mut b = LinkedList::new();
+            let handle = b.push_back_handle(7);
+            unsafe { a.reinsert_front(handle) };
+        }
+
+        #[test]
+        fn test_swap_nodes() {
+            for i in 0..5 {
+                for j in 0..5 {
+                    let mut list = from_range(0..5);
+                    let a = list.find(|&v| v == i).unwrap();
+                    let b = list.find(|&v| v == j).unwrap();
+                    unsafe { list.swap_nodes(a, b) };
+                    let mut expected: Vec<i32> = (0..5).collect();
+                    expected.swap(i as usize, j as usize);
+                    assert_eq!(contents(&list), expected, "swap {i} and {j}");
+                    assert_eq!(unsafe { (list.value(a), list.value(b)) }, (&i, &j));
+                    assert_eq!(list.front(), expected.first());
+                    assert_eq!(list.back(), expected.last());
+                }
+            }
+
+            let mut list = from_range(0..2);
+            let (a, b) = (
+                list.find(|&v| v == 0).unwrap(),
+                list.find(|&v| v == 1).unwrap(),
+            );
+            unsafe { list.swap_nodes(b, a) };
+            assert_eq!(contents(&list), [1, 0]);
+            unsafe { list.swap_nodes(a, b) };
+            assert_eq!(contents(&list), [0, 1]);
+        }
+
+        #[test]
+        #[cfg(debug_assertions)]
+        #[should_panic(expected = "node does not belong to this list")]
+        fn test_swap_nodes_across_lists() {
+            let mut a = from_range(0..3);
+            let b = from_range(0..3);
+            let x = a.find(|&v| v == 0).unwrap();
+            let y = b.find(|&v| v == 2).unwrap();
+            unsafe { a.swap_nodes(x, y) };
+        }
+
+        #[test]
+        fn test_handles_follow_moved_nodes() {
+            let mut a = from_range(0..3);
+            let mut b = LinkedList::new();
+            let seven = b.insert_front_handle(7);
+            let eight = b.push_back_handle(8);
+            unsafe { *b.value_mut(seven) += 10 };
+            a.append(&mut b);
+            assert_eq!(unsafe { a.value(seven) }, &17);
+            unsafe { a.reinsert_front(eight) };
+            assert_eq!(contents(&a), [8, 0, 1, 2, 17]);
+
+            let mut rest = a.split_off(3);
+            assert_eq!(unsafe { rest.remove(seven) }, 17);
+            assert_eq!(contents(&rest), [2]);
+            assert_eq!(unsafe { a.remove(eight) }, 8);
+            assert_eq!(contents(&a), [0, 1]);
+        }
+
+        #[test]
+        #[cfg(debug_assertions)]
+        #[should_panic(expected = "node does not belong to this list")]
+        fn test_handle_after_node_moved_away() {
+            let mut a = from_range(0..3);
+            let two = a.find(|&v| v == 2).unwrap();
+            let rest = a.split_off(1);
+            assert_eq!(unsafe { rest.value(two) }, &2);
+            unsafe { a.value(two) };
+        }
+
+        #[test]
+        fn test_sort() {
+            let mut rng = StdRng::seed_from_u64(269);
+            for len in (0..20).chain([100, 257, 1_000]) {
+                for range in [3, 1_000] {
+                    let mut values: Vec<i32> = (0..len).map(|_| rng.gen_range(0..range)).collect();
+                    let mut list: LinkedList<i32> = values.iter().copied().collect();
+                    list.sort();
+                    values.sort();
+                    assert_eq!(contents(&list), values);
+                }
+            }
+
+            let mut list = from_range(0..10);
+            list.sort_by(|a, b| b.cmp(a));
+            assert_eq!(contents(&list), (0..10).rev().collect::<Vec<_>>());
+            list.remove_tail();
+            list.push_back(-1);
+            list.sort();
+            assert_eq!(
+                contents(&list),
+                (-1..10).filter(|&v| v != 0).collect::<Vec<_>>()
+            );
+        }
+
+        #[test]
+        fn test_sort_stable() {
+            let mut rng = StdRng::seed_from_u64(2690);
+            let mut pairs: Vec<(u8, usize)> = (0..500).map(|i| (rng.gen_range(0..5), i)).collect();
+            let mut list: LinkedList<(u8, usize)> = pairs.iter().copied().collect();
+            list.sort_by(|a, b| a.0.cmp(&b.0));
+            pairs.sort_by_key(|&(key, _)| key);
+            assert_eq!(list.iter().copied().collect::<Vec<_>>(), pairs);
+        }
+
+        #[test]
+        fn test_sort_panic_keeps_nodes() {
+            use std::panic::{catch_unwind, AssertUnwindSafe};
+
+            for fail_at in [1, 5, 20, 40] {
+                let mut list: LinkedList<i32> = (0..30).rev().collect();
+                let mut compares = 0;
+                let result = catch_unwind(AssertUnwindSafe(|| {
+                    list.sort_by(|a, b| {
+                        compares += 1;
+                        assert!(compares < fail_at, "compare failed");
+                        a.cmp(b)
+                    })
+                }));
+                assert!(result.is_err());
+                let mut values = contents(&list);
+                values.sort();
+                assert_eq!(values, (0..30).collect::<Vec<_>>());
+                list.sort();
+                assert_eq!(contents(&list), (0..30).collect::<Vec<_>>());
+            }
+        }
+
+        #[test]
+        fn test_merge() {
+            let mut rng = StdRng::seed_from_u64(282);
+            for _ in 0..100 {
+                let mut a: Vec<i32> = (0..rng.gen_range(0..20))
+                    .map(|_| rng.gen_range(0..10))
+                    .collect();
+                let mut b: Vec<i32> = (0..rng.gen_range(0..20))
+                    .map(|_| rng.gen_range(0..10))
+                    .collect();
+                a.sort();
+                b.sort();
+                let mut list: LinkedList<i32> = a.iter().copied().collect();
+                let mut other: LinkedList<i32> = b.iter().copied().collect();
+                list.merge(&mut other);
+                a.extend(b);
+                a.sort();
+                assert_eq!(contents(&list), a);
+                assert!(contents(&other).is_empty());
+            }
+        }
+
+        #[test]
+        fn test_merge_edges() {
+            let mut list = from_range(0..3);
+            list.merge(&mut LinkedList::new());
+            assert_eq!(contents(&list), [0, 1, 2]);
+            let mut empty = LinkedList::new();
+            empty.merge(&mut list);
+            assert_eq!(contents(&empty), [0, 1, 2]);
+            assert!(contents(&list).is_empty());
+
+            // Entirely before, then entirely after.
+            let mut list = from_range(5..8);
+            list.merge(&mut from_range(0..3));
+            assert_eq!(contents(&list), [0, 1, 2, 5, 6, 7]);
+            list.merge(&mut from_range(10..12));
+            assert_eq!(contents(&list), [0, 1, 2, 5, 6, 7, 10, 11]);
+        }
+
+        #[test]
+        fn test_merge_stable() {
+            // Tagged with the list they started in; compared by key alone.
+            let mut list: LinkedList<(u8, char)> = [(1, 'a'), (2, 'a'), (2, 'a'), (4, 'a')].into();
+            let mut other: LinkedList<(u8, char)> = [(0, 'b'), (2, 'b'), (4, 'b')].into();
+            list.merge_by(&mut other, |x, y| x.0.cmp(&y.0));
+            assert_eq!(
+                list.into_vec(),
+                [
+                    (0, 'b'),
+                    (1, 'a'),
+                    (2, 'a'),
+                    (2, 'a'),
+                    (2, 'b'),
+                    (4, 'a'),
+                    (4, 'b')
+                ]
+            );
+
+            // Descending, with a reversed comparator.
+            let mut list: LinkedList<i32> = [9, 5, 1].into();
+            list.merge_by(&mut [8, 5, 0].into(), |a, b| b.cmp(a));
+            assert_eq!(contents(&list), [9, 8, 5, 5, 1, 0]);
+        }
+
+        #[test]
+        fn test_get() {
+            let mut list = from_range(0..9);
+            for (index, expected) in [
+                (0, Some(0)),
+                (3, Some(3)),
+                (4, Some(4)),
+                (5, Some(5)),
+                (8, Some(8)),
+                (9, None),
+                (100, None),
+            ] {
+                assert_eq!(list.get(index).copied(), expected, "get({index})");
+            }
+            *list.get_mut(8).unwrap() = 80;
+            *list.get_mut(0).unwrap() = -1;
+            assert_eq!(list.get_mut(9), None);
+            assert_eq!(contents(&list), [-1, 1, 2, 3, 4, 5, 6, 7, 80]);
+            assert_eq!(LinkedList::<i32>::new().get(0), None);
+        }
+
+        #[test]
+        fn test_iter_nth() {
+            let list = from_range(0..10);
+            for start in 0..10 {
+                for n in 0..12 {
+                    let mut iter = list.iter();
+                    iter.nth(start);
+                    let mut plain = (0..10).skip(start + 1);
+                    assert_eq!(iter.nth(n), plain.nth(n).as_ref(), "nth({start}), nth({n})");
+                    assert_eq!(iter.len(), plain.len());
+                    assert!(iter.copied().eq(plain));
+                }
+            }
+
+            let mut list = from_range(0..10);
+            let mut iter = list.iter_mut();
+            *iter.nth(2).unwrap() = 20;
+            *iter.nth(6).unwrap() = 90;
+            assert_eq!(iter.nth(1), None);
+            assert_eq!(list.iter_mut().nth(10), None);
+            assert_eq!(contents(&list), [0, 1, 20, 3, 4, 5, 6, 7, 8, 90]);
+        }
+
+        #[test]
+        fn test_iter_rev() {
+            let list = from_range(0..6);
+            assert!(list.iter().rev().copied().eq((0..6).rev()));
+            let mut iter = list.iter();
+            assert_eq!(iter.next_back(), Some(&5));
+            assert_eq!(iter.next(), Some(&0));
+            // nth counts from the front of what the back left over.
+            assert_eq!(iter.nth(2), Some(&3));
+            assert_eq!(iter.len(), 1);
+            assert_eq!(iter.next_back(), Some(&4));
+            assert_eq!((iter.next(), iter.next_back()), (None, None));
+            assert_eq!(LinkedList::<i32>::new().iter().next_back(), None);
+        }
+
+        #[test]
+        fn test_insert_remove_at() {
+            let mut list = LinkedList::new();
+            list.insert(0, 1);
+            list.insert(1, 3);
+            list.insert(0, 0);
+            list.insert(2, 2);
+            list.insert(list.len(), 5);
+            list.insert(4, 4);
+            assert_eq!(contents(&list), [0, 1, 2, 3, 4, 5]);
+
+            assert_eq!(list.remove_at(4), 4);
+            assert_eq!(list.remove_at(list.len() - 1), 5);
+            assert_eq!(list.remove_at(0), 0);
+            assert_eq!(contents(&list), [1, 2, 3]);
+            assert_eq!(list.remove_at(1), 2);
+            assert_eq!(list.remove_at(1), 3);
+            assert_eq!(list.remove_at(0), 1);
+            assert!(contents(&list).is_empty());
+
+            // Against a Vec, from both halves of the list.
+            let mut rng = StdRng::seed_from_u64(270);
+            let mut plain = Vec::new();
+            for _ in 0..500 {
+                if plain.is_empty() || rng.gen_bool(0.6) {
+                    let index = rng.gen_range(0..=plain.len());
+                    let val = rng.gen();
+                    list.insert(index, val);
+                    plain.insert(index, val);
+                } else {
+                    let index = rng.gen_range(0..plain.len());
+                    assert_eq!(list.remove_at(index), plain.remove(index));
+                }
+            }
+            assert_eq!(contents(&list), plain);
+        }
+
+        #[test]
+        #[should_panic(expected = "insertion index (is 3) should be <= len (is 2)")]
+        fn test_insert_out_of_bounds() {
+            from_range(0..2).insert(3, 0);
+        }
+
+        #[test]
+        #[should_panic(expected = "removal index (is 2) should be < len (is 2)")]
+        fn test_remove_at_out_of_bounds() {
+            from_range(0..2).remove_at(2);
+        }
+
+        #[test]
+        fn test_send_sync() {
+            fn assert_send<T: Send>() {}
+            fn assert_sync<T: Sync>() {}
+            assert_send::<LinkedList<String>>();
+            assert_sync::<LinkedList<String>>();
+            assert_send::<super::super::Iter<'_, String>>();
+            assert_send::<super::super::IterMut<'_, String>>();
+            assert_send::<super::super::IntoIter<String>>();
+
+            let list: LinkedList<String> = (0..100).map(|i| i.to_string()).collect();
+            let list = std::thread::spawn(move || {
+                let mut list = list;
+                list.retain(|s| s.len() == 1);
+                list.push_back("x".to_string());
+                list
+            })
+            .join()
+            .unwrap();
+            assert_eq!(list.len(), 11);
+            assert_eq!(list.back().map(String::as_str), Some("x"));
+
+            let shared = std::sync::Mutex::new(list);
+            std::thread::scope(|scope| {
+                for _ in 0..4 {
+                    scope.spawn(|| shared.lock().unwrap().pop_front());
+                }
+            });
+            assert_eq!(shared.lock().unwrap().len(), 7);
+        }
+
+        #[test]
+        fn test_rotate() {
+            for len in 0..7 {
+                for n in 0..3 * len + 2 {
+                    let values: Vec<i32> = (0..len as i32).collect();
+                    let mut left = from_range(0..len as i32);
+                    let mut right = from_range(0..len as i32);
+                    left.rotate_left(n);
+                    right.rotate_right(n);
+                    let mut expected = values.clone();
+                    if len > 0 {
+                        expected.rotate_left(n % len);
+                    }
+                    assert_eq!(contents(&left), expected, "rotate_left({n}) of {len}");
+                    let mut expected = values.clone();
+                    if len > 0 {
+                        expected.rotate_right(n % len);
+                    }
+                    assert_eq!(contents(&right), expected, "rotate_right({n}) of {len}");
+                }
+            }
+
+            let mut list = from_range(0..5);
+            list.rotate_left(2);
+            list.push_back(5);
+            assert_eq!(list.remove_tail(), Some(5));
+            list.rotate_right(2);
+            assert_eq!(contents(&list), [0, 1, 2, 3, 4]);
+        }
+
+        #[test]
+        fn test_dedup() {
+            let cases: &[(&[i32], &[i32])] = &[
+                (&[], &[]),
+                (&[1], &[1]),
+                (&[7, 7, 7, 7], &[7]),
+                (&[1, 1, 2, 3, 3, 3, 4], &[1, 2, 3, 4]),
+                (&[0, 1, 1, 1, 1, 2, 2], &[0, 1, 2]),
+                (&[5, 5, 6, 5, 5], &[5, 6, 5]),
+            ];
+            for &(input, expected) in cases {
+                let mut list: LinkedList<i32> = input.iter().copied().collect();
+                list.dedup();
+                assert_eq!(contents(&list), expected, "dedup of {input:?}");
+                assert_eq!(list.front(), expected.first());
+                assert_eq!(list.back(), expected.last());
+            }
+
+            let mut words: LinkedList<String> = ["Foo", "foo", "FOO", "bar", "Bar", "foo"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect();
+            words.dedup_by(|a, b| a.eq_ignore_ascii_case(b));
+            assert_eq!(words.iter().collect::<Vec<_>>(), ["Foo", "bar", "foo"]);
+
+            // The later element comes first, and the kept one can be edited.
+            let mut runs: LinkedList<(char, u32)> = "aaabcc".chars().map(|c| (c, 1)).collect();
+            runs.dedup_by(|later, kept| {
+                let same = later.0 == kept.0;
+                if same {
+                    kept.1 += later.1;
+                }
+                same
+            });
+            assert_eq!(
+                runs.iter().copied().collect::<Vec<_>>(),
+                [('a', 3), ('b', 1), ('c', 2)]
+            );
+        }
+
+        #[test]
+        fn test_cursor_splice_lists() {
+            let mut list = from_range(0..3);
+            let mut cursor = list.cursor_front_mut();
+            cursor.move_next();
+            cursor.splice_after(from_range(10..12));
+            cursor.splice_before(from_range(20..22));
+            assert_eq!(cursor.index(), Some(3));
+            assert_eq!(cursor.current(), Some(&mut 1));
+            cursor.splice_after(LinkedList::new());
+            cursor.splice_before(LinkedList::new());
+            assert_eq!(cursor.index(), Some(3));
+            assert_eq!(contents(&list), [0, 20, 21, 1, 10, 11, 2]);
+
+            // The very front and back: after the ghost, before the ghost,
+            // and next to the head and tail.
+            let mut cursor = list.cursor_front_mut();
+            cursor.splice_before(from_range(-2..0));
+            assert_eq!(cursor.index(), Some(2));
+            cursor.move_prev();
+            cursor.move_prev();
+            cursor.move_prev();
+            assert_eq!(cursor.index(), None);
+            cursor.splice_after(from_range(-4..-2));
+            cursor.splice_before(from_range(30..32));
+            assert_eq!(cursor.index(), None);
+            cursor.move_prev();
+            assert_eq!(cursor.index(), Some(12));
+            cursor.splice_after(from_range(40..41));
+            assert_eq!(
+                contents(&list),
+                [-4, -3, -2, -1, 0, 20, 21, 1, 10, 11, 2, 30, 31, 40]
+            );
+            assert_eq!((list.front(), list.back()), (Some(&-4), Some(&40)));
+
+            let mut empty = LinkedList::new();
+            let mut cursor = empty.cursor_front_mut();
+            cursor.splice_after(from_range(0..2));
+            cursor.splice_before(from_range(2..4));
+            assert_eq!(contents(&empty), [0, 1, 2, 3]);
+
+            // Spliced nodes belong to the new list and drop with it.
+            let drops = Rc::new(Cell::new(0));
+            let donor: LinkedList<_> = (0..3).map(|_| Counted(drops.clone())).collect();
+            let mut owner: LinkedList<_> = (0..2).map(|_| Counted(drops.clone())).collect();
+            owner.cursor_back_mut().splice_after(donor);
+            assert_eq!((owner.len(), drops.get()), (5, 0));
+            drop(owner);
+            assert_eq!(drops.get(), 5);
+        }
+
+        #[test]
+        fn test_vec_conversions() {
+            let list = LinkedList::from(vec![3, 1, 2]);
+            assert_eq!(contents(&list), [3, 1, 2]);
+            let vec = list.into_vec();
+            assert_eq!(vec, [3, 1, 2]);
+            assert_eq!(vec.capacity(), 3);
+
+            let list = LinkedList::from([1, 2, 3, 4]);
+            assert_eq!(contents(&list), [1, 2, 3, 4]);
+            assert_eq!(LinkedList::from(list.clone().into_vec()), list);
+            assert!(LinkedList::<i32>::from([]).into_vec().is_empty());
+
+            let drops = Rc::new(Cell::new(0));
+            let list = LinkedList::from([(); 5].map(|_| Counted(drops.clone())));
+            let vec = list.into_vec();
+            assert_eq!((vec.len(), drops.get()), (5, 0));
+            drop(vec);
+            assert_eq!(drops.get(), 5);
+        }
+
+        #[test]
+        fn test_extend_front_back() {
+            let mut list = LinkedList::from([10]);
+            list.extend_front([1, 2, 3]);
+            assert_eq!(contents(&list), [1, 2, 3, 10]);
+            list.extend_back([11, 12]);
+            list.extend_front([]);
+            list.extend_back([]);
+            assert_eq!(contents(&list), [1, 2, 3, 10, 11, 12]);
+            list.extend_front(vec![0]);
+            assert_eq!(list.front(), Some(&0));
+
+            let mut empty = LinkedList::new();
+            empty.extend_front(0..3);
+            assert_eq!(contents(&empty), [0, 1, 2]);
+            let mut empty = LinkedList::new();
+            empty.extend_back(0..3);
+            empty.extend_front(-2..0);
+            assert_eq!(contents(&empty), [-2, -1, 0, 1, 2]);
+        }
+
+        #[test]
+        fn test_exact_size() {
+            let mut list: LinkedList<i32> = LinkedList::new();
+            for i in 0..4 {
+                list.push_back(i);
+            }
+
+            let mut iter = list.iter();
+            for remaining in (0..4).rev() {
+                assert_eq!(iter.len(), remaining + 1);
+                assert!(iter.next().is_some());
+                assert_eq!(iter.size_hint(), (remaining, Some(remaining)));
+            }
+            assert_eq!(iter.next(), None);
+            assert_eq!(iter.next(), None);
+            assert_eq!(iter.size_hint(), (0, Some(0)));
+
+            let mut iter = list.iter_mut();
+            for remaining in (0..4).rev() {
+                assert!(iter.next().is_some());
+                assert_eq!(iter.size_hint(), (remaining, Some(remaining)));
+            }
+            assert_eq!(iter.next(), None);
+            assert_eq!(iter.next(), None);
+
+            let mut iter = list.into_iter();
+            for remaining in (0..4).rev() {
+                assert!(iter.next().is_some());
+                assert_eq!(iter.len(), remaining);
+            }
+            assert_eq!(iter.next(), None);
+            assert_eq!(iter.next(), None);
+            assert_eq!(iter.size_hint(), (0, Some(0)));
+        }
+    }
+
+    mod test_bounded_list {
+        use super::super::BoundedList;
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+        use std::collections::VecDeque;
+
+        fn contents(list: &BoundedList<i32>) -> Vec<i32> {
+            let vals: Vec<i32> = list.iter().copied().collect();
+            assert_eq!(vals.len(), list.len());
+            assert!(list.len() <= list.max_len());
+            assert_eq!(list.front(), vals.first());
+            assert_eq!(list.back(), vals.last());
+            vals
+        }
+
+        #[test]
+        fn test_drop_oldest() {
+            let mut list = BoundedList::new(3);
+            assert_eq!(list.push_front(1), None);
+            assert_eq!(list.push_front(2), None);
+            assert!(!list.is_full());
+            assert_eq!(list.push_front(3), None);
+            assert!(list.is_full());
+            assert_eq!(list.push_front(4), Some(1));
+            assert_eq!(list.push_front(5), Some(2));
+            assert_eq!(contents(&list), [5, 4, 3]);
+            assert_eq!(
+                format!("{:?}", list),
+                "BoundedList { max_len: 3, entries: [5, 4, 3] }"
+            );
+
+            assert_eq!(list.pop_front(), Some(5));
+            assert_eq!(list.push_front(6), None);
+            assert_eq!(list.remove_tail(), Some(3));
+            assert_eq!(contents(&list), [6, 4]);
+            list.push_front(7);
+            // Once full, each push reuses the node it evicted.
+            let allocated = list.list.pool_stats().allocated;
+            for i in 8..20 {
+                list.push_front(i);
+            }
+            assert_eq!(list.list.pool_stats().allocated, allocated);
+            assert_eq!(list.into_list().into_vec(), [19, 18, 17]);
+        }
+
+        #[test]
+        fn test_shrink_while_full() {
+            let mut list = BoundedList::new(5);
+            for i in 0..5 {
+                list.push_front(i);
+            }
+            let evicted = list.set_max_len(2);
+            assert_eq!(evicted.into_vec(), [2, 1, 0]);
+            assert_eq!(contents(&list), [4, 3]);
+            assert!(list.is_full());
+            assert_eq!(list.push_front(5), Some(3));
+            assert_eq!(contents(&list), [5, 4]);
+
+            // Growing evicts nothing and makes room.
+            assert!(list.set_max_len(4).is_empty());
+            assert_eq!(list.push_front(6), None);
+            assert_eq!(list.push_front(7), None);
+            assert_eq!(list.push_front(8), Some(4));
+            assert_eq!(contents(&list), [8, 7, 6, 5]);
+        }
+
+        #[test]
+        fn test_zero_max_len() {
+            let mut list = BoundedList::new(0);
+            assert!(list.is_full());
+            assert_eq!(list.push_front(1), Some(1));
+            assert!(list.is_empty());
+
+            let mut list = BoundedList::new(2);
+            list.push_front(1);
+            list.push_front(2);
+            assert_eq!(list.set_max_len(0).into_vec(), [2, 1]);
+            assert_eq!(list.push_front(3), Some(3));
+            assert!(contents(&list).is_empty());
+        }
+
+        #[test]
+        fn test_against_vec_deque() {
+            let mut rng = StdRng::seed_from_u64(292);
+            let mut list = BoundedList::new(8);
+            let mut plain: VecDeque<i32> = VecDeque::new();
+            for i in 0..5_000 {
+                match rng.gen_range(0..10) {
+                    0 => {
+                        let max_len = rng.gen_range(0..12);
+                        let evicted = list.set_max_len(max_len);
+                        let expected = plain.split_off(max_len.min(plain.len()));
+                        assert!(evicted.iter().eq(expected.iter()));
+                    }
+                    1 => assert_eq!(list.pop_front(), plain.pop_front()),
+                    2 => assert_eq!(list.remove_tail(), plain.pop_back()),
+                    _ => {
+                        let evicted = if list.max_len() == 0 {
+                            Some(i)
+                        } else {
+                            plain.push_front(i);
+                            if plain.len() > list.max_len() {
+                                plain.pop_back()
+                            } else {
+                                None
+                            }
+                        };
+                        assert_eq!(list.push_front(i), evicted);
+                    }
+                }
+                assert_eq!(contents(&list), Vec::from(plain.clone()));
+            }
+        }
+    }
+
+    mod test_lru_cache {
+        use super::super::LRUCache;
+        use rand::prelude::*;
+
+        #[test]
+        fn test() {
+            let mut lru = LRUCache::new(5);
+            assert!(lru.is_empty());
+            assert_eq!(lru.capacity(), 5);
+            lru.insert(5, 5);
+            lru.insert(0, 0);
+            lru.insert(2, 2);
+            lru.insert(6, 6);
+            assert_eq!(lru.len(), 4);
+            assert!(!lru.is_full());
+            lru.insert(1, 1);
+            assert!(lru.is_full());
+            // Replacing a key does not grow the cache.
+            assert_eq!(lru.insert(6, 6), Some(6));
+            assert_eq!(lru.len(), 5);
+            assert_eq!(lru.insert(8, 8), Some(5));
+            assert_eq!(lru.len(), 5);
+            assert!(!lru.is_empty());
+            assert_eq!(
+                format!("{:?}", lru),
+                "LRUCache { capacity: 5, len: 5, entries: {8: 8, 6: 6, 1: 1, 2: 2, 0: 0} }"
+            );
+        }
+
+        #[test]
+        fn test_borrowed_lookups() {
+            let mut lru: LRUCache<String, i32> = LRUCache::new(2);
+            lru.insert("a".to_string(), 1);
+            lru.insert("b".to_string(), 2);
+            assert_eq!(lru.get("a"), Some(&1));
+            assert!(lru.contains_key("b"));
+            assert_eq!(lru.insert("c".to_string(), 3), Some(2));
+            assert_eq!(lru.remove_entry("a"), Some(("a".to_string(), 1)));
+            assert_eq!(lru.peek("b"), None);
+            assert!(lru.keys().eq(["c"]));
+        }
+
+        #[test]
+        fn test_byte_keys_through_cache() {
+            use super::super::ByteLRUCache;
+            use crate::cache::Cache;
+
+            let mut lru: ByteLRUCache<i32> = LRUCache::new(2);
+            Cache::insert(&mut lru, b"a", 1);
+            lru.insert(b"b".to_vec(), 2);
+            assert_eq!(Cache::get(&mut lru, b"a"), Some(&1));
+            assert!(Cache::contains_key(&lru, b"b"));
+            assert_eq!(Cache::insert(&mut lru, b"c", 3), Some(2));
+            assert_eq!(Cache::remove(&mut lru, b"a"), Some(1));
+            assert_eq!(lru.get(&b"c"[..]), Some(&3));
+        }
+
+        #[test]
+        fn test_byte_keys_debug() {
+            use super::super::{ByteLRUCache, DebugKey};
+            use crate::cache::Cache;
+
+            assert_eq!(format!("{:?}", DebugKey(b"tab\t")), r#""tab\t""#);
+            assert_eq!(format!("{:?}", DebugKey(&[0xff, 0x00, 0x1a])), "0xff001a");
+            assert_eq!(format!("{:?}", DebugKey(b"")), r#""""#);
+
+            let mut lru: ByteLRUCache<i32> = LRUCache::new(3);
+            Cache::insert(&mut lru, b"a", 1);
+            Cache::insert(&mut lru, &[0xc3, 0x28], 2);
+            Cache::insert(&mut lru, "é".as_bytes(), 3);
+            assert_eq!(
+                format!("{:?}", lru),
+                r#"LRUCache { capacity: 3, len: 3, entries: {"é": 3, 0xc328: 2, "a": 1} }"#
+            );
+        }
+
+        #[test]
+        fn test_values_without_debug() {
+            struct Secret(u64);
+
+            let mut lru: LRUCache<i32, Secret> = LRUCache::new(2);
+            lru.insert(1, Secret(7));
+            lru.insert(2, Secret(8));
+            assert_eq!(lru.insert(3, Secret(9)).map(|s| s.0), Some(7));
+            assert_eq!(lru.get(&2).map(|s| s.0), Some(8));
+
+            let mut lru: LRUCache<&str, Box<dyn Fn() -> i32>> = LRUCache::new(1);
+            lru.insert("five", Box::new(|| 5));
+            assert_eq!(lru.get("five").map(|f| f()), Some(5));
+        }
+
+        #[test]
+        fn test_insert_full() {
+            use super::super::InsertResult::{Evicted, Inserted, Updated};
+
+            let mut lru = LRUCache::new(2);
+            assert_eq!(lru.insert_full("a", 1), Inserted);
+            assert_eq!(lru.insert_full("b", 2), Inserted);
+            // Replacing a key on a full cache evicts nothing.
+            assert_eq!(lru.insert_full("a", 10), Updated(1));
+            assert_eq!(lru.insert_full("c", 3), Evicted { key: "b", value: 2 });
+            assert_eq!(lru.insert_full("c", 30).into_value(), Some(3));
+            assert!(lru.keys().eq(&["c", "a"]));
+
+            // A value with nowhere to go is evicted as itself.
+            assert_eq!(lru.set_capacity(0).len(), 2);
+            assert_eq!(lru.insert_full("d", 4), Evicted { key: "d", value: 4 });
+
+            // The listener takes whatever is displaced.
+            let mut lru = LRUCache::with_eviction_listener(1, |_, _, _| {});
+            assert_eq!(lru.insert_full("a", 1), Inserted);
+            assert_eq!(lru.insert_full("a", 2), Inserted);
+            assert_eq!(lru.insert_full("b", 3), Inserted);
+            assert_eq!(lru.peek("b"), Some(&3));
+        }
+
+        #[test]
+        fn test_evicted_node_reused() {
+            let mut lru = LRUCache::new(4);
+            for i in 0..100_i32 {
+                lru.insert(i % 10, i);
+            }
+            // Only the first four inserts allocated; each later one took the
+            // node its eviction or replacement freed.
+            let stats = lru.list.pool_stats();
+            assert_eq!((stats.allocated, stats.reused, stats.pooled), (4, 96, 0));
+            assert_eq!(lru.get(&9), Some(&99));
+            assert_eq!(lru.get(&5), None);
+
+            // Without the spare node every insert allocates.
+            lru.set_node_pool(0);
+            for i in 100..110 {
+                lru.insert(i, i);
+            }
+            assert_eq!(lru.list.pool_stats().allocated, 14);
+        }
+
+        #[test]
+        fn test_peek_keeps_order() {
+            let mut lru = LRUCache::new(3);
+            for i in 0..3 {
+                lru.insert(i, i);
+            }
+            // Peeking the oldest entry leaves it next in line for eviction.
+            assert_eq!(lru.peek(&0), Some(&0));
+            *lru.peek_mut(&0).unwrap() = 10;
+            assert_eq!(lru.peek(&9), None);
+            assert_eq!(lru.peek_mut(&9), None);
+            assert_eq!(
+                format!("{:?}", lru),
+                "LRUCache { capacity: 3, len: 3, entries: {2: 2, 1: 1, 0: 10} }"
+            );
+            assert_eq!(lru.insert(3, 3), Some(10));
+            assert_eq!(lru.peek(&0), None);
+
+            // A get promotes it, so the next eviction takes another entry.
+            assert_eq!(lru.get(&1), Some(&1));
+            assert_eq!(lru.insert(4, 4), Some(2));
+            assert_eq!(lru.peek(&1), Some(&1));
+            assert_eq!(
+                format!("{:?}", lru),
+                "LRUCache { capacity: 3, len: 3, entries: {4: 4, 1: 1, 3: 3} }"
+            );
+        }
+
+        #[test]
+        fn test_get_mut() {
+            let mut lru = LRUCache::new(3);
+            for i in 0..3 {
+                lru.insert(i, 0);
+            }
+            for _ in 0..4 {
+                *lru.get_mut(&0).unwrap() += 1;
+            }
+            assert_eq!(lru.get_mut(&9), None);
+            assert_eq!(lru.peek(&0), Some(&4));
+            // The counter was promoted, so key 1 goes first.
+            assert_eq!(lru.insert(3, 0), Some(0));
+            assert_eq!(lru.peek(&1), None);
+            assert_eq!(
+                format!("{:?}", lru),
+                "LRUCache { capacity: 3, len: 3, entries: {3: 0, 0: 4, 2: 0} }"
+            );
+        }
+
+        #[test]
+        fn test_iter() {
+            let mut rng = StdRng::seed_from_u64(302);
+            let mut lru = LRUCache::new(8);
+            // Keys from most to least recently used.
+            let mut model: Vec<u8> = Vec::new();
+            for _ in 0..1_000 {
+                let k = rng.gen_range(0..12_u8);
+                if rng.gen_bool(0.5) {
+                    lru.insert(k, u32::from(k) * 10);
+                } else if lru.get(&k).is_none() {
+                    continue;
+                }
+                model.retain(|&m| m != k);
+                model.insert(0, k);
+                model.truncate(8);
+            }
+            let keys: Vec<u8> = lru.iter().map(|(&k, _)| k).collect();
+            assert_eq!(keys, model);
+            assert!(lru.iter().all(|(&k, &v)| v == u32::from(k) * 10));
+            let coldest_first: Vec<u8> = lru.iter().rev().map(|(&k, _)| k).collect();
+            assert!(coldest_first.iter().eq(model.iter().rev()));
+            assert_eq!(lru.iter().len(), lru.len());
+
+            // Iterating leaves the eviction order alone.
+            let coldest = model[model.len() - 1];
+            assert_eq!(lru.insert(100, 0), Some(u32::from(coldest) * 10));
+            // Both ends meet in the middle.
+            let mut iter = (&lru).into_iter();
+            assert_eq!(iter.next().map(|(k, _)| k), Some(&100));
+            assert_eq!(iter.next_back().map(|(k, _)| k), Some(&model[6]));
+            assert_eq!(iter.by_ref().count(), 6);
+            assert_eq!((iter.next(), iter.next_back()), (None, None));
+        }
+
+        #[test]
+        fn test_iter_mut() {
+            let mut lru = LRUCache::new(4);
+            for i in 0..4 {
+                lru.insert(i, 100 * i);
+            }
+            lru.get(&1);
+            // Halve every score in place.
+            for (k, v) in lru.iter_mut() {
+                assert_eq!(*v, 100 * k);
+                *v /= 2;
+            }
+            for (_, v) in &mut lru {
+                *v += 1;
+            }
+            let values: Vec<i32> = lru.iter().map(|(_, &v)| v).collect();
+            assert_eq!(values, [51, 151, 101, 1]);
+            assert_eq!(lru.iter_mut().len(), 4);
+
+            // Still evicts 0, then 2.
+            assert_eq!(lru.insert(4, 0), Some(1));
+            assert_eq!(lru.insert(5, 0), Some(101));
+            assert_eq!(lru.get(&1), Some(&51));
+        }
+
+        #[test]
+        fn test_save_and_restore() {
+            let mut lru = LRUCache::new(4);
+            for i in 0..6 {
+                lru.insert(i, i.to_string());
+            }
+            lru.get(&3);
+            let order: Vec<i32> = lru.keys().copied().collect();
+
+            let saved: Vec<(i32, String)> = lru.into_iter().collect();
+            let values: Vec<&str> = saved.iter().map(|(_, v)| v.as_str()).collect();
+            assert_eq!(values, ["3", "5", "4", "2"]);
+
+            let mut restored = LRUCache::new(4);
+            restored.extend(saved.into_iter().rev());
+            let restored_order: Vec<i32> = restored.keys().copied().collect();
+            assert_eq!(restored_order, order);
+            assert_eq!(restored.insert(9, "9".to_string()), Some("2".to_string()));
+        }
+
+        #[test]
+        fn test_retain() {
+            let mut lru = LRUCache::with_capacity_from(10, (0..10).map(|i| (i, i)));
+            lru.get(&4);
+            let mut visited = Vec::new();
+            lru.retain(|&key, value| {
+                visited.push(key);
+                *value *= 10;
+                key % 2 == 0
+            });
+            assert_eq!(visited, [0, 1, 2, 3, 5, 6, 7, 8, 9, 4]);
+            assert_eq!(lru.len(), 5);
+            let entries: Vec<(i32, i32)> = lru.iter().map(|(&k, &v)| (k, v)).collect();
+            assert_eq!(entries, [(4, 40), (8, 80), (6, 60), (2, 20), (0, 0)]);
+            assert!((0..10).all(|i| lru.contains_key(&i) == (i % 2 == 0)));
+            // The dropped entries are gone from the map too, so their keys
+            // come back as new entries.
+            assert_eq!(lru.insert(1, 1), None);
+            assert_eq!(lru.pop_lru(), Some((0, 0)));
+
+            lru.retain(|_, _| false);
+            assert!(lru.is_empty() && lru.iter().next().is_none());
+        }
+
+        #[test]
+        fn test_retain_notifies() {
+            use super::super::EvictionCause;
+            use std::cell::RefCell;
+            use std::rc::Rc;
+
+            type Log = Rc<RefCell<Vec<(&'static str, EvictionCause)>>>;
+            let log: Log = Rc::default();
+            let (mut lru, clock) = clocked(4);
+            let sink = log.clone();
+            lru.set_eviction_listener(move |key, _, cause| sink.borrow_mut().push((key, cause)));
+            lru.insert_with_ttl("stale", 1, std::time::Duration::from_secs(1));
+            lru.insert("keep", 2);
+            lru.insert("drop", 3);
+            clock.advance(std::time::Duration::from_secs(1));
+            lru.retain(|&key, _| {
+                assert_ne!(key, "stale");
+                key == "keep"
+            });
+            assert_eq!(
+                *log.borrow(),
+                [
+                    ("stale", EvictionCause::Expired),
+                    ("drop", EvictionCause::Explicit)
+                ]
+            );
+            assert_eq!(lru.len(), 1);
+        }
+
+        #[test]
+        fn test_promote_demote() {
+            let mut lru = LRUCache::with_capacity_from(4, (0..4).map(|i| (i, i * 10)));
+            assert_eq!(lru.peek_lru(), Some((&0, &0)));
+            assert_eq!(lru.peek_mru(), Some((&3, &30)));
+
+            assert!(lru.promote(&0));
+            assert_eq!(lru.peek_mru(), Some((&0, &0)));
+            assert_eq!(lru.peek_lru(), Some((&1, &10)));
+            assert!(lru.demote(&2));
+            assert_eq!(lru.peek_lru(), Some((&2, &20)));
+            assert!(lru.demote(&0));
+            assert_eq!(lru.peek_lru(), Some((&0, &0)));
+            assert_eq!(lru.peek_mru(), Some((&3, &30)));
+            assert!(!lru.promote(&9) && !lru.demote(&9));
+            assert!(lru.keys().copied().eq([3, 1, 2, 0]));
+
+            // A demoted entry is the next one evicted.
+            assert_eq!(lru.insert(4, 40), Some(0));
+            assert_eq!(lru.insert(5, 50), Some(20));
+            assert_eq!(lru.len(), 4);
+            assert!(lru.keys().copied().eq([5, 4, 3, 1]));
+
+            let empty: LRUCache<i32, i32> = LRUCache::new(2);
+            assert_eq!(empty.peek_lru(), None);
+            assert_eq!(empty.peek_mru(), None);
+        }
+
+        #[test]
+        fn test_peek_ends_skip_expired() {
+            let (mut lru, clock) = clocked(4);
+            lru.insert_with_ttl("old", 1, std::time::Duration::from_secs(1));
+            lru.insert("a", 2);
+            lru.insert_with_ttl("new", 3, std::time::Duration::from_secs(1));
+            clock.advance(std::time::Duration::from_secs(1));
+            assert_eq!(lru.peek_lru(), Some((&"a", &2)));
+            assert_eq!(lru.peek_mru(), Some((&"a", &2)));
+            assert!(!lru.promote("old") && !lru.demote("new"));
+            assert_eq!(lru.len(), 1);
+        }
+
+        #[test]
+        fn test_with_capacity_from() {
+            let lru = LRUCache::with_capacity_from(4, (0..4).map(|i| (i, i * 10)));
+            assert!(lru.is_full());
+            assert!(lru.keys().rev().copied().eq(0..4));
+
+            // Loading twice the capacity leaves the later half.
+            let mut lru = LRUCache::with_capacity_from(4, (0..8).map(|i| (i, i * 10)));
+            assert_eq!(lru.len(), 4);
+            assert!(lru.keys().rev().copied().eq(4..8));
+            assert_eq!(lru.insert(8, 80), Some(40));
+
+            let empty = LRUCache::<i32, i32>::with_capacity_from(4, []);
+            assert!(empty.is_empty());
+        }
+
+        #[test]
+        fn test_extend_populated() {
+            let mut lru = LRUCache::with_capacity_from(4, [(b"a".to_vec(), 1), (b"b".to_vec(), 2)]);
+            // "a" is refreshed in place and moves up; "e" then evicts "b".
+            lru.extend([
+                (&b"c"[..], 3),
+                (&b"a"[..], 10),
+                (&b"d"[..], 4),
+                (&b"e"[..], 5),
+            ]);
+            let keys: Vec<&[u8]> = lru.keys().map(Vec::as_slice).collect();
+            assert_eq!(keys, [&b"e"[..], b"d", b"a", b"c"]);
+            assert_eq!(lru.peek(&b"a"[..]), Some(&10));
+            assert!(!lru.contains_key(&b"b"[..]));
+        }
+
+        #[test]
+        fn test_extend_owned_byte_keys() {
+            use super::super::ByteLRUCache;
+
+            let mut lru: ByteLRUCache<i32> = LRUCache::new(2);
+            lru.extend(vec![
+                (b"a".to_vec(), 1),
+                (b"b".to_vec(), 2),
+                (b"c".to_vec(), 3),
+            ]);
+            lru.extend([(&b"b"[..], 20)]);
+            let keys: Vec<&[u8]> = lru.keys().map(Vec::as_slice).collect();
+            assert_eq!(keys, [&b"b"[..], b"c"]);
+            assert_eq!(lru.peek(&b"b"[..]), Some(&20));
+        }
+
+        #[test]
+        fn test_into_iter_dropped_early() {
+            use std::cell::Cell;
+            use std::rc::Rc;
+
+            #[derive(Debug)]
+            struct Counted(Rc<Cell<usize>>);
+
+            impl Drop for Counted {
+                fn drop(&mut self) {
+                    self.0.set(self.0.get() + 1);
+                }
+            }
+
+            let drops = Rc::new(Cell::new(0));
+            let mut lru = LRUCache::new(5);
+            for i in 0..5_i32 {
+                lru.insert(i, Counted(drops.clone()));
+            }
+            let mut iter = lru.into_iter();
+            assert_eq!(iter.len(), 5);
+            let (key, _) = iter.next().unwrap();
+            assert_eq!(key, 4);
+            let (key, _) = iter.next_back().unwrap();
+            assert_eq!(key, 0);
+            assert_eq!(drops.get(), 2);
+            drop(iter);
+            assert_eq!(drops.get(), 5);
+        }
+
+        #[test]
+        fn test_keys_and_values() {
+            let mut lru = LRUCache::new(4);
+            for i in 0..6_u8 {
+                lru.insert(i, u32::from(i));
+            }
+            lru.get(&3);
+            let (keys, values): (Vec<&u8>, Vec<&u32>) = lru.iter().unzip();
+            assert!(lru.keys().eq(keys.iter().copied()));
+            assert!(lru.values().eq(values.iter().copied()));
+            assert!(lru.keys().rev().eq(keys.iter().rev().copied()));
+            assert_eq!(lru.values().len(), 4);
+
+            lru.values_mut().for_each(|v| *v *= 10);
+            let values: Vec<u32> = lru.values().copied().collect();
+            assert_eq!(values, [30, 50, 40, 20]);
+            assert!(lru.keys().eq(&[3, 5, 4, 2]));
+        }
+
+        #[test]
+        fn test_get_or_insert_with() {
+            let mut lru = LRUCache::new(2);
+            let mut calls = 0;
+            let mut load = |lru: &mut LRUCache<i32, i32>, i: i32| {
+                *lru.get_or_insert_with(i, || {
+                    calls += 1;
+                    i * 10
+                })
+                .unwrap()
+            };
+            assert_eq!(load(&mut lru, 1), 10);
+            assert_eq!(load(&mut lru, 1), 10);
+            assert_eq!(load(&mut lru, 2), 20);
+            assert_eq!(load(&mut lru, 1), 10);
+            // The miss on 3 overflows and evicts 2, which then misses again.
+            assert_eq!(load(&mut lru, 3), 30);
+            assert_eq!(load(&mut lru, 2), 20);
+            assert_eq!(load(&mut lru, 3), 30);
+            assert_eq!(calls, 4);
+            assert_eq!(lru.len(), 2);
+            assert!(lru.keys().eq(&[3, 2]));
+
+            assert_eq!(lru.get_or_insert(3, 99), Ok(&30));
+            assert_eq!(lru.get_or_insert(4, 40), Ok(&40));
+            assert!(lru.keys().eq(&[4, 3]));
+        }
+
+        #[test]
+        fn test_try_get_or_insert_with() {
+            let mut lru = LRUCache::new(2);
+            lru.insert(1, 10);
+            lru.insert(2, 20);
+            let stats = lru.list.pool_stats();
+
+            // A failed load on a full cache evicts nothing and allocates
+            // nothing.
+            let failed: Result<Result<&i32, i32>, &str> =
+                lru.try_get_or_insert_with(3, || Err("disk"));
+            assert_eq!(failed, Err("disk"));
+            assert_eq!(lru.len(), 2);
+            assert!(lru.keys().eq(&[2, 1]));
+            assert_eq!(lru.list.pool_stats(), stats);
+            assert!(lru.is_full());
+
+            // A hit never calls the loader.
+            let hit = lru.try_get_or_insert_with(1, || -> Result<i32, &str> {
+                panic!("loader called on a hit")
+            });
+            assert_eq!(hit, Ok(Ok(&10)));
+
+            // The retry succeeds and evicts as usual.
+            assert_eq!(
+                lru.try_get_or_insert_with(3, || Ok::<_, &str>(30)),
+                Ok(Ok(&30))
+            );
+            assert!(lru.keys().eq(&[3, 1]));
+            assert_eq!(lru.len(), 2);
+        }
+
+        #[test]
+        fn test_get_or_insert_with_zero_capacity() {
+            let mut lru = LRUCache::new(0);
+            assert_eq!(lru.get_or_insert("a", 1), Err(1));
+            assert_eq!(lru.get_or_insert_with("a", || 2), Err(2));
+            assert_eq!(
+                lru.try_get_or_insert_with("a", || Ok::<_, ()>(3)),
+                Ok(Err(3))
+            );
+            assert_eq!(lru.try_get_or_insert_with("a", || Err(())), Err(()));
+            assert!(lru.is_empty());
+            assert_eq!(lru.get("a"), None);
+
+            // Likewise for a value heavier than the whole cache may be.
+            let mut lru = weighted(4);
+            assert_eq!(lru.get_or_insert("big", vec![0; 5]), Err(vec![0; 5]));
+            assert!(lru.is_empty());
+        }
+
+        #[test]
+        fn test_eviction_listener() {
+            use super::super::EvictionCause::{self, Capacity, Explicit, Replaced};
+            use std::cell::RefCell;
+            use std::rc::Rc;
+
+            type Log = Vec<(i32, i32, EvictionCause)>;
+
+            let log: Rc<RefCell<Log>> = Rc::default();
+            let sink = log.clone();
+            let mut lru = LRUCache::with_eviction_listener(2, move |k, v, cause| {
+                sink.borrow_mut().push((k, v, cause))
+            });
+            let take = || std::mem::take(&mut *log.borrow_mut());
+
+            lru.insert(1, 10);
+            lru.insert(2, 20);
+            assert!(take().is_empty());
+            // The listener owns what is displaced, so insert returns nothing.
+            assert_eq!(lru.insert(3, 30), None);
+            assert_eq!(take(), [(1, 10, Capacity)]);
+            assert_eq!(lru.insert(3, 31), None);
+            assert_eq!(take(), [(3, 30, Replaced)]);
+
+            // Entries taken out on purpose go to the caller only.
+            assert_eq!(lru.remove(&2), Some(20));
+            assert_eq!(lru.pop_lru(), Some((3, 31)));
+            assert!(take().is_empty());
+
+            for i in 4..7 {
+                lru.get_or_insert(i, i * 10).unwrap();
+            }
+            assert_eq!(take(), [(4, 40, Capacity)]);
+            assert!(lru.set_capacity(1).is_empty());
+            assert_eq!(take(), [(5, 50, Capacity)]);
+
+            lru.set_capacity(3);
+            lru.insert(7, 70);
+            lru.insert(8, 80);
+            lru.clear();
+            assert_eq!(
+                take(),
+                [(6, 60, Explicit), (7, 70, Explicit), (8, 80, Explicit)]
+            );
+            let mut flushed = Vec::new();
+            lru.insert(9, 90);
+            lru.clear_with(|_, v| flushed.push(v));
+            assert_eq!(flushed, [90]);
+            assert!(take().is_empty());
+            assert!(lru.is_empty());
+        }
+
+        fn clocked<K: std::hash::Hash + Eq>(
+            capacity: usize,
+        ) -> (LRUCache<K, i32>, std::sync::Arc<crate::clock::MockClock>) {
+            let clock = std::sync::Arc::new(crate::clock::MockClock::new());
+            (LRUCache::with_clock(capacity, clock.clone()), clock)
+        }
+
+        #[test]
+        fn test_ttl_lazy_expiry() {
+            use std::time::Duration;
+
+            let (mut lru, clock) = clocked(4);
+            lru.insert_with_ttl("a", 1, Duration::from_secs(10));
+            lru.insert_with_ttl("b", 2, Duration::from_secs(20));
+            lru.insert("forever", 3);
+            clock.advance(Duration::from_secs(9));
+            assert_eq!(lru.get("a"), Some(&1));
+
+            // The deadline itself is already expired.
+            clock.advance(Duration::from_secs(1));
+            assert_eq!(lru.peek("a"), None);
+            assert!(!lru.contains_key("a"));
+            // Peeking leaves it in place; a lookup frees the slot.
+            assert_eq!(lru.len(), 3);
+            assert_eq!(lru.get("a"), None);
+            assert_eq!(lru.len(), 2);
+
+            clock.advance(Duration::from_secs(10));
+            assert_eq!(lru.get_mut("b"), None);
+            assert_eq!(lru.remove("b"), None);
+            assert!(lru.keys().eq(&["forever"]));
+
+            // An insert over an expired value does not hand it back.
+            lru.insert_with_ttl("c", 4, Duration::from_secs(1));
+            clock.advance(Duration::from_secs(1));
+            assert_eq!(lru.insert("c", 5), None);
+            assert_eq!(lru.get("c"), Some(&5));
+            assert_eq!(lru.get_or_insert("forever", 0), Ok(&3));
+        }
+
+        #[test]
+        fn test_ttl_purge_expired() {
+            use std::time::Duration;
+
+            let (mut lru, clock) = clocked(10);
+            for i in 0..8 {
+                lru.insert_with_ttl(i, i, Duration::from_secs(i as u64 % 3));
+            }
+            lru.insert(100, 100);
+            assert_eq!(lru.purge_expired(), 3);
+            clock.advance(Duration::from_secs(1));
+            assert_eq!(lru.purge_expired(), 3);
+            assert_eq!(lru.purge_expired(), 0);
+            assert!(lru.keys().eq(&[100, 5, 2]));
+            clock.advance(Duration::from_secs(1));
+            assert_eq!(lru.purge_expired(), 2);
+            assert_eq!(lru.len(), 1);
         }
-    }
 
-    fn ref_val(&self) -> &T {
-        &self.val
-    }
+        #[test]
+        fn test_ttl_expired_evicted_first() {
+            use super::super::EvictionCause::{self, Capacity, Expired};
+            use std::cell::RefCell;
+            use std::rc::Rc;
+            use std::time::Duration;
 
-    fn into_val(self) -> T {
-        self.val
-    }
-}
+            type Log = Vec<(&'static str, EvictionCause)>;
 
-pub struct LinkedList<T> {
-    length: usize,
-    head: Option<NonNull<Node<T>>>,
-    tail: Option<NonNull<Node<T>>>,
-    _marker: PhantomData<Box<Node<T>>>,
-}
+            let (mut lru, clock) = clocked(3);
+            let evicted: Rc<RefCell<Log>> = Rc::default();
+            let sink = evicted.clone();
+            lru.set_eviction_listener(move |k, _, cause| sink.borrow_mut().push((k, cause)));
+            lru.insert("a", 1);
+            lru.insert_with_ttl("b", 2, Duration::from_secs(5));
+            lru.insert("c", 3);
 
-impl<T> LinkedList<T> {
-    pub fn new() -> Self {
-        Self {
-            length: 0,
-            head: None,
-            tail: None,
-            _marker: PhantomData,
+            // Before "b" expires the least recently used entry goes.
+            lru.insert("d", 4);
+            clock.advance(Duration::from_secs(5));
+            // Then "b" goes although "c" is colder.
+            lru.insert("e", 5);
+            assert_eq!(*evicted.borrow(), [("a", Capacity), ("b", Expired)]);
+            assert!(lru.keys().eq(&["e", "d", "c"]));
+
+            assert_eq!(lru.pop_lru(), Some(("c", 3)));
+            lru.insert_with_ttl("f", 6, Duration::ZERO);
+            assert_eq!(lru.pop_mru(), Some(("e", 5)));
+            assert_eq!(evicted.borrow().last(), Some(&("f", Expired)));
         }
-    }
 
-    pub fn insert_front(&mut self, val: T) {
-        let mut node = Box::new(Node::new(val));
-        let node = NonNull::new(Box::into_raw(node)).unwrap();
-        self.insert_front_raw(node);
-    }
+        #[test]
+        fn test_ttl_survives_purge_on_insert() {
+            use std::time::Duration;
 
-    pub fn insert_front_raw(&mut self, mut node: NonNull<Node<T>>) {
-        unsafe {
-            node.as_mut().next = self.head;
-            node.as_mut().prev = None;
+            let (mut lru, clock) = clocked(2);
+            lru.insert_with_ttl("a", 1, Duration::from_secs(1));
+            lru.insert("b", 2);
+            clock.advance(Duration::from_secs(2));
+            // Purges "a" to make room for "c".
+            lru.insert_with_ttl("c", 3, Duration::from_secs(5));
+            clock.advance(Duration::from_secs(10));
+            // "c" has expired now, so it goes instead of "b".
+            assert_eq!(lru.insert("d", 4), None);
+            assert!(lru.keys().eq(&["d", "b"]));
         }
 
-        match self.head {
-            None => self.tail = Some(node),
-            Some(head) => unsafe { (*head.as_ptr()).prev = Some(node) },
-        }
+        #[test]
+        fn test_ttl_too_long_never_expires() {
+            use std::time::Duration;
 
-        self.head = Some(node);
-        self.length += 1;
-    }
+            let (mut lru, clock) = clocked(2);
+            lru.insert_with_ttl("forever", 1, Duration::MAX);
+            lru.insert_with_ttl("soon", 2, Duration::from_secs(1));
+            clock.advance(Duration::from_secs(1_000_000));
+            assert_eq!(lru.purge_expired(), 1);
+            assert_eq!(lru.get("forever"), Some(&1));
+        }
 
-    pub fn remove(&mut self, mut node: NonNull<Node<T>>) -> T {
-        let node_mut = unsafe { node.as_mut() };
-        self.length -= 1;
-        match node_mut.prev {
-            Some(prev) => unsafe { (*prev.as_ptr()).next = node_mut.next },
-            None => self.head = node_mut.next,
+        fn weighted<K: std::hash::Hash + Eq>(max_weight: u64) -> LRUCache<K, Vec<u8>> {
+            LRUCache::with_weigher(max_weight, |_, value: &Vec<u8>| value.len() as u64)
         }
-        match node_mut.next {
-            Some(next) => unsafe { (*next.as_ptr()).prev = node_mut.prev },
-            None => self.tail = node_mut.prev,
+
+        #[test]
+        fn test_weigher_tiny_values() {
+            let mut lru = weighted(100);
+            for i in 0..1_000_u32 {
+                lru.insert(i, vec![0; 3]);
+                assert!(lru.current_weight() <= 100);
+            }
+            // 33 entries of weight 3 fit, the newest ones.
+            assert_eq!((lru.len(), lru.current_weight()), (33, 99));
+            assert!(lru.keys().copied().eq((967..1_000).rev()));
+            assert!(!lru.is_full());
+            assert_eq!(lru.max_weight(), 100);
+
+            lru.remove(&999);
+            lru.pop_lru();
+            lru.pop_mru();
+            assert_eq!(lru.current_weight(), 90);
+            lru.clear();
+            assert_eq!(lru.current_weight(), 0);
         }
-        unsafe {
-            let n = Box::from_raw(node.as_ptr());
-            n.into_val()
+
+        #[test]
+        fn test_weigher_huge_value() {
+            let mut lru = weighted(10);
+            lru.insert("a", vec![1; 4]);
+            lru.insert("b", vec![2; 4]);
+            // Too heavy for an empty cache: handed back, and nothing evicted.
+            assert_eq!(lru.insert("huge", vec![0; 11]), Some(vec![0; 11]));
+            assert_eq!(lru.len(), 2);
+            assert_eq!(lru.peek("huge"), None);
+
+            // Exactly the maximum fits, once everything else is gone.
+            assert_eq!(lru.insert("full", vec![3; 10]), Some(vec![1; 4]));
+            assert_eq!(lru.len(), 1);
+            assert_eq!(lru.current_weight(), 10);
+            // Weightless entries always fit next to it.
+            assert_eq!(lru.insert("empty", Vec::new()), None);
+            assert_eq!(lru.len(), 2);
         }
-    }
 
-    pub fn reinsert_front(&mut self, node: NonNull<Node<T>>) {
-        self.remove(node);
-        self.insert_front_raw(node);
-    }
+        #[test]
+        fn test_weigher_heavier_replacement() {
+            use super::super::EvictionCause;
+            use std::cell::RefCell;
+            use std::rc::Rc;
 
-    pub fn remove_tail(&mut self) -> Option<T> {
-        self.tail.map(|node| unsafe {
-            self.length -= 1;
-            let node = Box::from_raw(node.as_ptr());
-            self.tail = node.prev;
-            match self.tail {
-                Some(tail) => (*tail.as_ptr()).next = None,
-                None => self.head = None,
+            type Log = Vec<(&'static str, EvictionCause)>;
+
+            let evicted: Rc<RefCell<Log>> = Rc::default();
+            let sink = evicted.clone();
+            let mut lru = weighted(10);
+            lru.set_eviction_listener(move |k, _, cause| sink.borrow_mut().push((k, cause)));
+            for key in ["a", "b", "c", "d", "e"] {
+                lru.insert(key, vec![0; 2]);
             }
-            node.into_val()
-        })
-    }
+            assert_eq!(lru.current_weight(), 10);
 
-    pub fn iter(&self) -> Iter<'_, T> {
-        Iter {
-            head: self.head,
-            tail: self.tail,
-            len: self.length,
-            _marker: PhantomData,
+            // Growing "c" from 2 to 7 leaves 8 for the rest to fit in 3, so
+            // "a", "b" and "d" all go.
+            assert_eq!(lru.insert("c", vec![0; 7]), None);
+            assert_eq!(
+                *evicted.borrow(),
+                [
+                    ("a", EvictionCause::Capacity),
+                    ("b", EvictionCause::Capacity),
+                    ("d", EvictionCause::Capacity),
+                    ("c", EvictionCause::Replaced),
+                ]
+            );
+            assert!(lru.keys().eq(&["c", "e"]));
+            assert_eq!(lru.current_weight(), 9);
+
+            // Without a listener the first eviction comes back.
+            let mut lru = weighted(4);
+            lru.insert("a", vec![1; 2]);
+            lru.insert("b", vec![2; 2]);
+            assert_eq!(lru.insert("c", vec![3; 4]), Some(vec![1; 2]));
+            assert!(lru.keys().eq(&["c"]));
+            // Growing the only entry past the maximum rejects the new value.
+            assert_eq!(lru.insert("c", vec![9; 5]), Some(vec![9; 5]));
+            assert_eq!(lru.peek("c"), Some(&vec![3; 4]));
         }
-    }
-}
 
-impl<T> Drop for LinkedList<T> {
-    fn drop(&mut self) {
-        struct DropGuard<'a, T>(&'a mut LinkedList<T>);
-        impl<'a, T> Drop for DropGuard<'a, T> {
-            fn drop(&mut self) {
-                while self.0.remove_tail().is_some() {}
+        #[test]
+        fn test_remove() {
+            let mut lru = LRUCache::new(4);
+            for i in 0..4 {
+                lru.insert(i, i);
             }
-        }
+            assert_eq!(lru.remove(&9), None);
+            // The most and then the least recently used entry.
+            assert_eq!(lru.remove(&3), Some(3));
+            assert_eq!(lru.remove_entry(&0), Some((0, 0)));
+            assert_eq!(lru.remove(&0), None);
+            assert_eq!(lru.len(), 2);
+            assert_eq!(
+                format!("{:?}", lru),
+                "LRUCache { capacity: 4, len: 2, entries: {2: 2, 1: 1} }"
+            );
 
-        while let Some(node) = self.remove_tail() {
-            let guard = DropGuard(self);
-            drop(node);
-            mem::forget(guard);
+            // The freed room is used before anything is evicted.
+            assert_eq!(lru.insert(4, 4), None);
+            assert_eq!(lru.insert(5, 5), None);
+            assert_eq!(lru.insert(6, 6), Some(1));
+            assert_eq!(
+                format!("{:?}", lru),
+                "LRUCache { capacity: 4, len: 4, entries: {6: 6, 5: 5, 4: 4, 2: 2} }"
+            );
+            for i in [2, 4, 5, 6] {
+                assert_eq!(lru.remove(&i), Some(i));
+            }
+            assert!(lru.is_empty());
+            assert_eq!(lru.insert(7, 7), None);
+            assert_eq!(lru.get(&7), Some(&7));
         }
-    }
-}
 
-pub struct Iter<'a, T: 'a> {
-    head: Option<NonNull<Node<T>>>,
-    tail: Option<NonNull<Node<T>>>,
-    len: usize,
-    _marker: PhantomData<&'a Node<T>>,
-}
+        #[test]
+        fn test_clear() {
+            let mut lru = LRUCache::new(3);
+            for i in 0..5 {
+                lru.insert(i, i);
+            }
+            lru.clear();
+            assert!(lru.is_empty());
+            assert_eq!(lru.capacity(), 3);
+            assert_eq!(lru.get(&4), None);
 
-impl<'a, T> Iterator for Iter<'a, T> {
-    type Item = &'a T;
+            // Refilled, it evicts in the usual order.
+            for i in 10..13 {
+                assert_eq!(lru.insert(i, i), None);
+            }
+            assert_eq!(lru.get(&10), Some(&10));
+            assert_eq!(lru.insert(13, 13), Some(11));
 
-    #[inline]
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.len == 0 {
-            None
-        } else {
-            self.head.map(|node| {
-                self.len -= 1;
+            let mut flushed = Vec::new();
+            lru.clear_with(|k, v| flushed.push((k, v)));
+            let expected: Vec<_> = [12, 10, 13].map(|i| (i, i)).into();
+            assert_eq!(flushed, expected);
+            assert!(lru.is_empty());
+            assert_eq!(lru.insert(1, 1), None);
+            assert_eq!(lru.len(), 1);
+        }
 
-                unsafe {
-                    let node = &*node.as_ptr();
-                    self.head = node.next;
-                    &node.val
-                }
-            })
+        #[test]
+        fn test_set_capacity() {
+            let mut lru = LRUCache::new(2);
+            // Resizing an empty cache just moves the bound.
+            assert!(lru.set_capacity(4).is_empty());
+            for i in 0..4 {
+                assert_eq!(lru.insert(i, i), None);
+            }
+            assert!(lru.is_full());
+            assert!(lru.set_capacity(4).is_empty());
+            assert_eq!(lru.get(&0), Some(&0));
+
+            let evicted = lru.set_capacity(2);
+            assert_eq!(evicted, [(1, 1), (2, 2)]);
+            assert_eq!(lru.capacity(), 2);
+            assert_eq!(
+                format!("{:?}", lru),
+                "LRUCache { capacity: 2, len: 2, entries: {0: 0, 3: 3} }"
+            );
+            // The next insert already evicts under the new bound.
+            assert_eq!(lru.insert(4, 4), Some(3));
+            assert_eq!(lru.len(), 2);
+
+            // Growing evicts nothing and makes room.
+            assert!(lru.set_capacity(3).is_empty());
+            assert_eq!(lru.insert(5, 5), None);
+            assert!(lru.is_full());
         }
-    }
-}
 
-impl<T: std::fmt::Debug> std::fmt::Debug for LinkedList<T> {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        for cur in self.iter() {
-            write!(f, "{:?} ", cur)?;
+        #[test]
+        fn test_set_capacity_drops_expired_first() {
+            use std::time::Duration;
+
+            let (mut lru, clock) = clocked(4);
+            lru.insert_with_ttl("a", 1, Duration::from_secs(1));
+            lru.insert("b", 2);
+            lru.insert_with_ttl("c", 3, Duration::from_secs(1));
+            lru.insert("d", 4);
+            clock.advance(Duration::from_secs(1));
+            // Only the expired entries have to go to fit two.
+            assert!(lru.set_capacity(2).is_empty());
+            assert_eq!(lru.len(), 2);
+            assert_eq!(lru.set_capacity(1), [("b", 2)]);
+            assert!(lru.keys().eq(&["d"]));
         }
-        Ok(())
-    }
-}
 
-struct LRUEntry<T: std::fmt::Debug> {
-    key: Vec<u8>,
-    value: T,
-}
+        #[test]
+        fn test_zero_capacity() {
+            let mut lru = LRUCache::new(3);
+            for i in 0..3 {
+                lru.insert(i, i);
+            }
+            let evicted: Vec<_> = lru.set_capacity(0).into_iter().map(|(_, v)| v).collect();
+            assert_eq!(evicted, [0, 1, 2]);
+            assert!(lru.is_empty());
+            // Nothing fits, so an insert hands the value straight back.
+            assert_eq!(lru.insert(3, 3), Some(3));
+            assert!(lru.is_empty());
+            assert_eq!(lru.get(&3), None);
 
-impl<T> LRUEntry<T>
-where
-    T: std::fmt::Debug,
-{
-    pub fn new(key: &[u8], value: T) -> Self {
-        Self {
-            key: key.to_vec(),
-            value,
+            let mut lru = LRUCache::new(0);
+            assert_eq!(lru.insert(0, 0), Some(0));
+            assert!(lru.is_empty() && lru.is_full());
         }
-    }
-}
 
-impl<T: std::fmt::Debug> std::fmt::Debug for LRUEntry<T> {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{:?}", self.value)?;
-        Ok(())
-    }
-}
+        #[test]
+        fn test_zero_capacity_variants() {
+            use super::super::InsertResult;
+            use std::time::Duration;
 
-pub struct LRUCache<T>
-where
-    T: std::fmt::Debug,
-{
-    map: HashMap<Vec<u8>, NonNull<Node<LRUEntry<T>>>>,
-    list: LinkedList<LRUEntry<T>>,
-    capacity: usize,
-}
+            let (mut lru, _clock) = clocked(0);
+            for i in 0..10 {
+                assert_eq!(lru.insert(i, i), Some(i));
+                assert_eq!(lru.insert_with_ttl(i, i, Duration::from_secs(1)), Some(i));
+                assert_eq!(
+                    lru.insert_full(i, i),
+                    InsertResult::Evicted { key: i, value: i }
+                );
+                lru.extend([(i, i)]);
+                assert_eq!(lru.len(), 0);
+                assert_eq!(lru.get(&i), None);
+                assert_eq!(lru.peek(&i), None);
+            }
+            assert_eq!(lru.purge_expired(), 0);
+            assert_eq!(lru.list.pool_stats().allocated, 0);
 
-impl<T> LRUCache<T>
-where
-    T: std::fmt::Debug,
-{
-    pub fn new(capacity: usize) -> Self {
-        Self {
-            map: HashMap::new(),
-            list: LinkedList::new(),
-            capacity,
+            // A weighted cache shrunk to capacity 0 holds nothing either,
+            // even weightless values.
+            let mut lru = weighted(10);
+            lru.set_capacity(0);
+            assert_eq!(lru.insert("empty", Vec::new()), Some(Vec::new()));
+            assert!(lru.is_empty());
         }
-    }
 
-    pub fn insert(&mut self, key: &[u8], value: T) -> Option<T> {
-        let new_node = LRUEntry::new(key, value);
-        let new_node = Box::new(Node::new(new_node));
-        let new_node = NonNull::new(Box::into_raw(new_node)).unwrap();
+        #[test]
+        fn test_capacity_one() {
+            let mut lru = LRUCache::new(1);
+            assert_eq!(lru.insert("a", 1), None);
+            assert!(lru.is_full());
+            // Each new key evicts the only entry; the same key replaces it.
+            assert_eq!(lru.insert("b", 2), Some(1));
+            assert_eq!(lru.insert("b", 3), Some(2));
+            assert_eq!(lru.get("a"), None);
+            assert_eq!(lru.get_or_insert("c", 4), Ok(&4));
+            assert_eq!(lru.get("b"), None);
+            assert_eq!(lru.pop_lru(), Some(("c", 4)));
+            assert!(lru.is_empty());
+            assert_eq!(lru.get_or_insert("d", 5), Ok(&5));
+            assert_eq!(lru.len(), 1);
+        }
 
-        match self.map.get(key) {
-            Some(&entry) => unsafe {
-                let val = self.list.remove(entry);
-                self.list.insert_front_raw(new_node);
-                self.map.insert(key.to_vec(), new_node);
-                Some(val.value)
-            },
-            None => {
-                let mut val = None;
-                if self.list.length >= self.capacity {
-                    // let removed_key = self.list.remove_tail();
-                    if let Some(entry) = self.list.remove_tail() {
-                        self.map.remove(&entry.key);
-                        val = Some(entry.value);
+        #[test]
+        fn test_clone_diverges() {
+            // Keys from most to least recently used, with their values.
+            type Model = Vec<(u8, u32)>;
+            fn step(rng: &mut StdRng, lru: &mut LRUCache<u8, u32>, model: &mut Model) {
+                let key = rng.gen_range(0..16);
+                let pos = model.iter().position(|&(k, _)| k == key);
+                if rng.gen_bool(0.5) {
+                    let value = rng.gen();
+                    lru.insert(key, value);
+                    if let Some(pos) = pos {
+                        model.remove(pos);
+                    }
+                    model.insert(0, (key, value));
+                    model.truncate(8);
+                } else {
+                    assert_eq!(lru.get(&key).copied(), pos.map(|pos| model[pos].1));
+                    if let Some(pos) = pos {
+                        let entry = model.remove(pos);
+                        model.insert(0, entry);
                     }
                 }
-                self.list.insert_front_raw(new_node);
-                self.map.insert(key.to_vec(), new_node);
-                val
+                assert!(lru.iter().map(|(&k, &v)| (k, v)).eq(model.iter().copied()));
+            }
+
+            let mut rng = StdRng::seed_from_u64(318);
+            let mut lru = LRUCache::new(8);
+            let mut model = Model::new();
+            for _ in 0..200 {
+                step(&mut rng, &mut lru, &mut model);
+            }
+            assert!(lru.is_full());
+            let mut copy = lru.clone();
+            let mut copy_model = model.clone();
+            assert!(copy.iter().eq(lru.iter()));
+
+            // Touching the coldest key in the clone leaves it coldest in the
+            // original, so the next new key evicts a different entry in each.
+            let (coldest, _) = model[7];
+            copy.get(&coldest);
+            copy_model.rotate_right(1);
+            assert_eq!(lru.insert(100, 0), Some(model[7].1));
+            assert_eq!(copy.insert(100, 0), Some(copy_model[7].1));
+            model.pop();
+            model.insert(0, (100, 0));
+            copy_model.pop();
+            copy_model.insert(0, (100, 0));
+            assert!(lru.contains_key(&model[7].0) && !lru.contains_key(&coldest));
+            assert!(copy.contains_key(&coldest));
+
+            for _ in 0..500 {
+                step(&mut rng, &mut lru, &mut model);
+                step(&mut rng, &mut copy, &mut copy_model);
             }
+            drop(lru);
+            while let Some((key, value)) = copy.pop_lru() {
+                assert_eq!(copy_model.pop(), Some((key, value)));
+            }
+            assert!(copy_model.is_empty());
         }
-    }
 
-    pub fn get(&mut self, key: &[u8]) -> Option<&T> {
-        match self.map.get(key) {
-            Some(&node) => unsafe {
-                let value = &node.as_ref().val.value;
-                self.list.reinsert_front(node);
-                Some(value)
-            },
-            None => None,
+        #[cfg(feature = "serde")]
+        #[test]
+        fn test_serde_round_trip() {
+            let mut rng = StdRng::seed_from_u64(320);
+            let mut lru = LRUCache::new(16);
+            for _ in 0..1_000 {
+                let key: u16 = rng.gen_range(0..32);
+                if rng.gen_bool(0.5) {
+                    lru.insert(key, key.to_string());
+                } else {
+                    lru.get(&key);
+                }
+            }
+            let json = serde_json::to_string(&lru).unwrap();
+            let mut restored: LRUCache<u16, String> = serde_json::from_str(&json).unwrap();
+            assert_eq!(restored.capacity(), 16);
+            assert!(restored.iter().eq(lru.iter()));
+            // The next victims are the same ones.
+            for key in 100..116 {
+                assert_eq!(
+                    restored.insert(key, String::new()),
+                    lru.insert(key, String::new())
+                );
+            }
+
+            let empty: LRUCache<String, i32> = serde_json::from_str(
+                &serde_json::to_string(&LRUCache::<String, i32>::new(3)).unwrap(),
+            )
+            .unwrap();
+            assert_eq!(
+                format!("{:?}", empty),
+                "LRUCache { capacity: 3, len: 0, entries: {} }"
+            );
         }
-    }
-}
 
-#[cfg(test)]
-mod test {
-    mod test_linkedlist {
-        use super::super::LinkedList;
+        #[cfg(feature = "serde")]
+        #[test]
+        fn test_serde_time_left() {
+            let (mut lru, clock) = clocked(4);
+            lru.insert("forever", 1);
+            lru.insert_with_ttl("gone", 2, std::time::Duration::from_secs(10));
+            lru.insert_with_ttl("later", 3, std::time::Duration::from_secs(60));
+            clock.advance(std::time::Duration::from_secs(20));
+            // Expired entries are left out and the rest keep what time they
+            // have left.
+            let json = serde_json::to_value(&lru).unwrap();
+            assert_eq!(
+                json,
+                serde_json::json!({
+                    "capacity": 4,
+                    "entries": [
+                        ["forever", 1, null],
+                        ["later", 3, {"secs": 40, "nanos": 0}],
+                    ],
+                })
+            );
+            let mut restored: LRUCache<String, i32> = serde_json::from_value(json).unwrap();
+            assert_eq!(restored.get("later"), Some(&3));
+            assert_eq!(restored.pop_lru(), Some(("forever".to_string(), 1)));
+        }
 
+        #[cfg(feature = "serde")]
         #[test]
-        fn test_insert() {
-            let mut list: LinkedList<i32> = LinkedList::new();
-            list.insert_front(2);
-            list.insert_front(3);
-            list.insert_front(4);
-            let result = format!("{:?}", list);
-            assert_eq!("4 3 2 ", result);
+        fn test_bincode_keeps_recency() {
+            let mut lru = LRUCache::new(4);
+            for key in ["a", "b", "c", "d"] {
+                lru.insert(key.to_string(), key.len());
+            }
+            lru.get("b");
+            lru.get("a");
+            let bytes = bincode::serialize(&lru).unwrap();
+            let mut restored: LRUCache<String, usize> = bincode::deserialize(&bytes).unwrap();
+            let order = |lru: &LRUCache<String, usize>| {
+                lru.iter().map(|(k, _)| k.clone()).collect::<Vec<_>>()
+            };
+            assert_eq!(order(&restored), ["a", "b", "d", "c"]);
+            assert_eq!(order(&restored), order(&lru));
+            assert_eq!(restored.pop_lru(), Some(("c".to_string(), 1)));
         }
-    }
 
-    mod test_lru_cache {
-        use super::super::LRUCache;
-        use rand::prelude::*;
+        #[test]
+        fn test_clone_keeps_weights_and_deadlines() {
+            let mut lru = weighted(10);
+            lru.insert("a", vec![0; 4]);
+            lru.insert("b", vec![0; 4]);
+            let mut copy = lru.clone();
+            assert_eq!(copy.current_weight(), 8);
+            // The clone weighs its own inserts with the shared weigher.
+            assert_eq!(copy.insert("c", vec![0; 4]), Some(vec![0; 4]));
+            assert_eq!(copy.current_weight(), 8);
+            assert_eq!(lru.current_weight(), 8);
+            assert!(lru.contains_key("a") && !copy.contains_key("a"));
+
+            let (mut lru, clock) = clocked(4);
+            lru.insert_with_ttl("short", 1, std::time::Duration::from_secs(1));
+            lru.insert("forever", 2);
+            let mut copy = lru.clone();
+            clock.advance(std::time::Duration::from_secs(1));
+            assert_eq!(copy.get("short"), None);
+            assert_eq!(copy.get("forever"), Some(&2));
+            assert_eq!(lru.purge_expired(), 1);
+        }
 
         #[test]
-        fn test() {
-            println!("single thread.....");
-            let mut lru = LRUCache::new(5);
-            lru.insert(&5_i32.to_le_bytes(), 5);
-            println!("{:?}", lru.list);
-            lru.insert(&0_i32.to_le_bytes(), 0);
-            println!("{:?}", lru.list);
-            lru.insert(&2_i32.to_le_bytes(), 2);
-            println!("{:?}", lru.list);
-            lru.insert(&6_i32.to_le_bytes(), 6);
-            println!("{:?}", lru.list);
-            lru.insert(&1_i32.to_le_bytes(), 1);
-            println!("{:?}", lru.list);
-            lru.insert(&6_i32.to_le_bytes(), 6);
-            println!("{:?}", lru.list);
-            lru.insert(&8_i32.to_le_bytes(), 8);
-            println!("{:?}", lru.list);
-            println!();
+        fn test_pop_lru_and_mru() {
+            let mut lru: LRUCache<i32, i32> = LRUCache::new(5);
+            assert_eq!(lru.pop_lru(), None);
+            assert_eq!(lru.pop_mru(), None);
+
+            for i in 0..5 {
+                lru.insert(i, i);
+            }
+            // Recency order is now 3, 1, 4, 2, 0 from hottest to coldest.
+            lru.get(&2);
+            lru.get(&4);
+            lru.get(&1);
+            lru.get(&3);
+            assert_eq!(lru.pop_lru(), Some((0, 0)));
+            assert_eq!(lru.pop_mru(), Some((3, 3)));
+            assert_eq!(lru.len(), 3);
+            assert!(lru.peek(&0).is_none() && lru.peek(&3).is_none());
+
+            let mut cold = Vec::new();
+            while let Some((_, v)) = lru.pop_lru() {
+                cold.push(v);
+            }
+            assert_eq!(cold, [2, 4, 1]);
+            assert!(lru.is_empty());
+            assert_eq!(lru.pop_lru(), None);
+            assert_eq!(lru.insert(7, 7), None);
+        }
+
+        #[test]
+        fn test_clear_with_panic() {
+            use std::panic::{catch_unwind, AssertUnwindSafe};
+
+            let mut lru = LRUCache::new(4);
+            for i in 0..4_i32 {
+                lru.insert(i, i.to_string());
+            }
+            let result = catch_unwind(AssertUnwindSafe(|| {
+                lru.clear_with(|_, v| assert_ne!(v, "2"));
+            }));
+            assert!(result.is_err());
+            // The entries not yet flushed were dropped, not left behind.
+            assert!(lru.is_empty());
+            assert_eq!(lru.insert(9, "9".to_string()), None);
+            assert_eq!(lru.get(&9).map(String::as_str), Some("9"));
         }
+
         #[test]
         fn test1() {
             let mut lru = LRUCache::new(3);
@@ -384,14 +5564,22 @@ mod test {
                 1, 0, 3, 2, 4, 5, 9, 2, 8, 6, 6, 6,
             ];
             for i in arr.into_iter() {
-                lru.insert(&i.to_le_bytes(), i);
+                lru.insert(i, i);
+                assert!(lru.len() <= lru.capacity());
             }
+            assert!(lru.is_full());
+            assert_eq!(
+                format!("{:?}", lru),
+                "LRUCache { capacity: 3, len: 3, entries: {6: 6, 8: 8, 2: 2} }"
+            );
         }
 
         #[test]
         fn test_insert() {
             let mut lru = LRUCache::new(10);
-            for _i in 0..5000000_usize {
+            // Miri runs this about a million times slower.
+            let inserts = if cfg!(miri) { 200 } else { 5_000_000_usize };
+            for _i in 0..inserts {
                 let mut rng = rand::thread_rng();
                 let n: usize = rng.gen::<usize>() % 100;
                 // let n = if i / 10_usize > 0 {
@@ -399,9 +5587,219 @@ mod test {
                 // } else {
                 //     i
                 // };
-                print!("{n},");
-                lru.insert(&n.to_le_bytes(), n);
+                lru.insert(n, n);
+                assert!(lru.len() <= lru.capacity());
+            }
+            assert!(lru.is_full());
+        }
+    }
+
+    /// Access patterns that mix raw node pointers with references handed
+    /// out by the list, for Miri to check: CI runs them with
+    /// `cargo +nightly miri test --lib lru::test::test_aliasing`.
+    /// `test_get_then_mutate` covers a value borrowed through `get` while
+    /// its node is moved to the front.
+    mod test_aliasing {
+        use super::super::{LRUCache, LinkedList};
+
+        #[test]
+        fn test_get_then_mutate() {
+            let mut lru = LRUCache::new(3);
+            for key in 0..3u8 {
+                lru.insert(key, key.to_string());
+            }
+            // The least recent entry moves to the front while its value is
+            // borrowed; then the front, which stays put.
+            assert_eq!(lru.get(&0).map(String::as_str), Some("0"));
+            assert_eq!(lru.get(&0).map(String::as_str), Some("0"));
+            assert_eq!(lru.get(&1).map(String::as_str), Some("1"));
+            lru.insert(3, "3".to_string());
+            assert_eq!(lru.get(&2), None);
+            assert_eq!(lru.insert(1, "one".to_string()).as_deref(), Some("1"));
+            assert_eq!(lru.get(&1).map(String::as_str), Some("one"));
+            assert_eq!(lru.remove(&0).as_deref(), Some("0"));
+            assert_eq!(lru.get(&3).map(String::as_str), Some("3"));
+            assert_eq!(
+                format!("{:?}", lru),
+                r#"LRUCache { capacity: 3, len: 2, entries: {3: "3", 1: "one"} }"#
+            );
+        }
+
+        #[test]
+        fn test_single_entry_cache() {
+            let mut lru = LRUCache::new(1);
+            lru.insert("a", vec![1]);
+            assert_eq!(lru.get("a"), Some(&vec![1]));
+            assert_eq!(lru.insert("b", vec![2]), Some(vec![1]));
+            assert_eq!(lru.get("b"), Some(&vec![2]));
+            assert_eq!(lru.get("a"), None);
+        }
+
+        #[test]
+        fn test_iterate_while_holding_handles() {
+            let mut list: LinkedList<String> = (0..6).map(|i| i.to_string()).collect();
+            let two = list.find(|s| s == "2").unwrap();
+            let five = list.find(|s| s == "5").unwrap();
+            assert_eq!(list.iter().map(String::len).sum::<usize>(), 6);
+            for s in list.iter_mut() {
+                s.push('!');
+            }
+            *list.front_mut().unwrap() += "?";
+            unsafe { list.reinsert_front(five) };
+            assert_eq!(list.front().map(String::as_str), Some("5!"));
+            assert!(list.iter().any(|s| s == "2!"));
+            assert_eq!(unsafe { list.remove(two) }, "2!");
+
+            let mut cursor = list.cursor_front_mut();
+            cursor.move_next();
+            cursor.current().unwrap().push('#');
+            cursor.insert_after("x".to_string());
+            let five = list.find(|s| s == "5!").unwrap();
+            let collected: Vec<&String> = list.iter().collect();
+            assert_eq!(collected, ["5!", "0!?#", "x", "1!", "3!", "4!"]);
+            assert_eq!(unsafe { list.remove(five) }, "5!");
+            list.sort();
+            assert_eq!(list.pop_front().as_deref(), Some("0!?#"));
+            assert_eq!(list.remove_tail().as_deref(), Some("x"));
+        }
+
+        #[test]
+        fn test_references_across_iterators() {
+            let mut list: LinkedList<Vec<u32>> = (0..4).map(|i| vec![i]).collect();
+            // Shared iterators can overlap each other.
+            let firsts: Vec<&Vec<u32>> = list.iter().collect();
+            let seconds: Vec<&Vec<u32>> = list.iter().collect();
+            assert_eq!(firsts, seconds);
+            let mut refs: Vec<&mut Vec<u32>> = list.iter_mut().collect();
+            let (a, b) = refs.split_at_mut(2);
+            a[1].append(b[0]);
+            assert_eq!(list.iter().map(Vec::len).collect::<Vec<_>>(), [1, 2, 0, 1]);
+            let extracted: Vec<Vec<u32>> = list.extract_if(|v| v.is_empty()).collect();
+            assert_eq!(extracted.len(), 1);
+            assert_eq!(list.into_iter().flatten().collect::<Vec<_>>(), [0, 1, 2, 3]);
+        }
+
+        #[test]
+        fn test_map_keys_point_into_nodes() {
+            use rand::prelude::*;
+
+            // Keys from most to least recently used, with their values.
+            let mut model: Vec<(String, u32)> = Vec::new();
+            let mut lru: LRUCache<String, u32> = LRUCache::new(8);
+            let mut rng = StdRng::seed_from_u64(323);
+            let rounds = if cfg!(miri) { 300 } else { 5_000 };
+            for _ in 0..rounds {
+                let key = format!("key{}", rng.gen_range(0..24));
+                let pos = model.iter().position(|(k, _)| *k == key);
+                match rng.gen_range(0..12) {
+                    0..=4 => {
+                        let value = rng.gen_range(0..100);
+                        lru.insert(key.clone(), value);
+                        if let Some(pos) = pos {
+                            model.remove(pos);
+                        }
+                        model.insert(0, (key, value));
+                        model.truncate(8);
+                    }
+                    5..=7 => {
+                        assert_eq!(lru.get(&key[..]).copied(), pos.map(|pos| model[pos].1));
+                        if let Some(pos) = pos {
+                            let entry = model.remove(pos);
+                            model.insert(0, entry);
+                        }
+                    }
+                    8 => {
+                        let removed = pos.map(|pos| model.remove(pos));
+                        assert_eq!(lru.remove_entry(&key[..]), removed);
+                    }
+                    9 => assert_eq!(lru.pop_lru(), model.pop()),
+                    10 if !model.is_empty() => {
+                        assert_eq!(lru.pop_mru(), Some(model.remove(0)));
+                    }
+                    _ => {
+                        lru.retain(|_, value| *value % 3 != 0);
+                        model.retain(|(_, value)| *value % 3 != 0);
+                    }
+                }
+                assert!(lru
+                    .iter()
+                    .map(|(k, &v)| (k.clone(), v))
+                    .eq(model.iter().cloned()));
+                assert!(model.iter().all(|(k, v)| lru.peek(&k[..]) == Some(v)));
+            }
+            let copy = lru.clone();
+            drop(lru);
+            assert!(model.iter().all(|(k, v)| copy.peek(k.as_str()) == Some(v)));
+        }
+    }
+
+    mod test_key_storage {
+        use super::super::{ByteLRUCache, LRUCache};
+        use crate::cache::Cache;
+        use std::alloc::{GlobalAlloc, Layout, System};
+        use std::cell::Cell;
+
+        /// No other allocation in these tests is this size, so counting
+        /// allocations of it counts copies of keys.
+        const KEY_LEN: usize = 13;
+
+        thread_local! {
+            // Per thread, so tests running alongside do not count.
+            static KEY_ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+        }
+
+        struct CountingAlloc;
+
+        unsafe impl GlobalAlloc for CountingAlloc {
+            unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+                if layout.size() == KEY_LEN {
+                    let _ = KEY_ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+                }
+                unsafe { System.alloc(layout) }
+            }
+
+            unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+                unsafe { System.dealloc(ptr, layout) }
             }
         }
+
+        #[global_allocator]
+        static ALLOCATOR: CountingAlloc = CountingAlloc;
+
+        fn key(i: u32) -> [u8; KEY_LEN] {
+            let mut key = [0; KEY_LEN];
+            key[..4].copy_from_slice(&i.to_le_bytes());
+            key
+        }
+
+        fn key_allocations(f: impl FnOnce()) -> usize {
+            let before = KEY_ALLOCATIONS.with(Cell::get);
+            f();
+            KEY_ALLOCATIONS.with(Cell::get) - before
+        }
+
+        #[test]
+        fn test_insert_copies_key_once() {
+            let mut lru: ByteLRUCache<u32> = LRUCache::new(16);
+            let keys: Vec<_> = (0..100).map(key).collect();
+            // Through `Cache` the key is copied into a `Vec`, and only there.
+            let copies = key_allocations(|| {
+                for (i, key) in keys.iter().enumerate() {
+                    Cache::insert(&mut lru, &key[..], i as u32);
+                }
+            });
+            assert_eq!(copies, keys.len());
+
+            // An owned key is moved into the cache as it is.
+            let owned: Vec<Vec<u8>> = (100..200).map(|i| key(i).to_vec()).collect();
+            let copies = key_allocations(|| {
+                for key in owned {
+                    lru.insert(key, 0);
+                }
+            });
+            assert_eq!(copies, 0);
+            assert_eq!(lru.len(), 16);
+            assert!((184..200).all(|i| lru.contains_key(&key(i)[..])));
+        }
     }
 }
@@ -0,0 +1,276 @@
+use crate::cache::Cache;
+use crate::heap::Heap;
+use std::collections::{HashMap, VecDeque};
+
+struct Entry<T> {
+    value: T,
+    /// Times of the last (up to) K accesses, most recent first.
+    history: VecDeque<u64>,
+}
+
+/// Eviction order: entries seen fewer than K times go first, oldest last
+/// access first; then the rest by oldest K-th most recent access.
+type Priority = (bool, u64);
+
+impl<T> Entry<T> {
+    fn priority(&self, k: usize) -> Priority {
+        match self.history.get(k - 1) {
+            Some(&kth) => (true, kth),
+            None => (false, self.history[0]),
+        }
+    }
+}
+
+/// An LRU-K cache: evicts the entry whose K-th most recent access is the
+/// oldest, so a key needs K hits before it outranks keys seen once.
+///
+/// Entries with fewer than K accesses have no K-th access and are evicted
+/// before any entry that has one, in plain LRU order among themselves.
+/// That keeps a one-off scan from flushing keys that are used repeatedly.
+/// With K = 1 this is exactly LRU. History is dropped with the entry, so a
+/// key that comes back after eviction starts again at one access.
+///
+/// Victims come from a min-heap of priorities. Every access pushes a new
+/// priority rather than updating the old one in place; stale heap entries
+/// are skipped when popped and the heap is rebuilt once they outnumber the
+/// live ones.
+pub struct LruKCache<T> {
+    entries: HashMap<Vec<u8>, Entry<T>>,
+    queue: Heap<(Priority, Vec<u8>)>,
+    k: usize,
+    capacity: usize,
+    clock: u64,
+}
+
+impl<T> LruKCache<T> {
+    /// # Panics
+    ///
+    /// Panics if `k` is zero.
+    pub fn new(capacity: usize, k: usize) -> Self {
+        assert!(k > 0, "k must be at least 1");
+        Self {
+            entries: HashMap::with_capacity(capacity),
+            queue: Heap::new(),
+            k,
+            capacity,
+            clock: 0,
+        }
+    }
+
+    /// Inserts `value` under `key`, counting as an access.
+    ///
+    /// Returns the previous value if the key was present, otherwise the value
+    /// of the evicted victim if the cache was full. A zero-capacity cache
+    /// stores nothing and hands `value` straight back.
+    pub fn insert(&mut self, key: &[u8], value: T) -> Option<T> {
+        if self.capacity == 0 {
+            return Some(value);
+        }
+        if let Some(entry) = self.entries.get_mut(key) {
+            let old = std::mem::replace(&mut entry.value, value);
+            self.touch(key);
+            return Some(old);
+        }
+        let evicted = if self.entries.len() >= self.capacity {
+            self.evict()
+        } else {
+            None
+        };
+        self.entries.insert(
+            key.to_vec(),
+            Entry {
+                value,
+                history: VecDeque::with_capacity(self.k),
+            },
+        );
+        self.touch(key);
+        evicted
+    }
+
+    pub fn get(&mut self, key: &[u8]) -> Option<&T> {
+        if !self.entries.contains_key(key) {
+            return None;
+        }
+        self.touch(key);
+        self.entries.get(key).map(|entry| &entry.value)
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<T> {
+        self.entries.remove(key).map(|entry| entry.value)
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// Records an access to the present `key` and queues its new priority.
+    fn touch(&mut self, key: &[u8]) {
+        self.clock += 1;
+        let entry = self.entries.get_mut(key).unwrap();
+        entry.history.push_front(self.clock);
+        entry.history.truncate(self.k);
+        let priority = entry.priority(self.k);
+        self.queue.push((priority, key.to_vec()));
+
+        if self.queue.len() > 2 * self.entries.len() + 16 {
+            self.rebuild_queue();
+        }
+    }
+
+    fn evict(&mut self) -> Option<T> {
+        while let Some((priority, key)) = self.queue.pop() {
+            let live = self
+                .entries
+                .get(&key)
+                .is_some_and(|entry| entry.priority(self.k) == priority);
+            if live {
+                return self.entries.remove(&key).map(|entry| entry.value);
+            }
+        }
+        None
+    }
+
+    /// Drops stale priorities, keeping one per entry.
+    fn rebuild_queue(&mut self) {
+        self.queue = Heap::new();
+        for (key, entry) in &self.entries {
+            self.queue.push((entry.priority(self.k), key.clone()));
+        }
+    }
+}
+
+impl<T> Cache<[u8], T> for LruKCache<T> {
+    fn insert(&mut self, key: &[u8], value: T) -> Option<T> {
+        LruKCache::insert(self, key, value)
+    }
+
+    fn get(&mut self, key: &[u8]) -> Option<&T> {
+        LruKCache::get(self, key)
+    }
+
+    fn remove(&mut self, key: &[u8]) -> Option<T> {
+        LruKCache::remove(self, key)
+    }
+
+    fn len(&self) -> usize {
+        LruKCache::len(self)
+    }
+
+    fn cap(&self) -> usize {
+        self.capacity
+    }
+
+    fn contains_key(&self, key: &[u8]) -> bool {
+        LruKCache::contains_key(self, key)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::LruKCache;
+    use crate::lru::LRUCache;
+    use rand::prelude::*;
+
+    fn key(i: u32) -> [u8; 4] {
+        i.to_le_bytes()
+    }
+
+    #[test]
+    fn test_k1_is_lru() {
+        let mut rng = StdRng::seed_from_u64(241);
+        for capacity in [1, 2, 5, 16] {
+            let mut cache = LruKCache::new(capacity, 1);
+            let mut lru = LRUCache::new(capacity);
+            for op in 0..5_000_usize {
+                let k = key(rng.gen_range(0..capacity as u32 * 3));
+                if rng.gen_bool(0.5) {
+                    assert_eq!(cache.insert(&k, op), lru.insert(k, op), "op {op}");
+                } else {
+                    assert_eq!(cache.get(&k), lru.get(&k), "op {op}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_lru2_keeps_reused_key() {
+        let mut cache = LruKCache::new(3, 2);
+        let mut lru = LRUCache::new(3);
+        cache.insert(b"hot", 0);
+        cache.get(b"hot");
+        lru.insert(b"hot".to_vec(), 0);
+        lru.get(&b"hot"[..]);
+        // A scan of keys seen once each.
+        for i in 1..=10 {
+            let k = key(i);
+            cache.insert(&k, i);
+            lru.insert(k.to_vec(), i);
+        }
+        assert_eq!(cache.get(b"hot"), Some(&0));
+        assert_eq!(lru.get(&b"hot"[..]), None);
+        // Among the keys seen once, the oldest went first.
+        assert!(cache.contains_key(&key(9)) && cache.contains_key(&key(10)));
+    }
+
+    #[test]
+    fn test_kth_access_ordering() {
+        let mut cache = LruKCache::new(2, 2);
+        cache.insert(b"a", 1); // t1
+        cache.insert(b"b", 2); // t2
+        cache.get(b"b"); // t3: b's 2nd most recent is t2
+        cache.get(b"a"); // t4: a's 2nd most recent is t1
+        cache.get(b"a"); // t5: now t4, newer than b's
+        assert_eq!(cache.insert(b"c", 3), Some(2));
+
+        // c has one access, so it goes before a despite being newer.
+        assert_eq!(cache.insert(b"d", 4), Some(3));
+        assert!(cache.contains_key(b"a"));
+    }
+
+    #[test]
+    fn test_replacement_history() {
+        let mut cache = LruKCache::new(2, 2);
+        cache.insert(b"a", 1);
+        // Overwriting counts as the second access and keeps the history.
+        assert_eq!(cache.insert(b"a", 2), Some(1));
+        assert_eq!(cache.entries[&b"a"[..]].history, [2, 1]);
+        cache.insert(b"b", 3);
+        assert_eq!(cache.insert(b"c", 4), Some(3));
+
+        // Removing forgets the history: a fresh "a" is seen once.
+        assert_eq!(cache.remove(b"a"), Some(2));
+        cache.insert(b"a", 5);
+        assert_eq!(cache.entries[&b"a"[..]].history, [5]);
+        assert_eq!(cache.insert(b"d", 6), Some(4));
+        assert_eq!(cache.get(b"a"), Some(&5));
+    }
+
+    #[test]
+    fn test_stale_priorities_compacted() {
+        let mut cache = LruKCache::new(4, 2);
+        for i in 0..4 {
+            cache.insert(&key(i), i);
+        }
+        for _ in 0..1_000 {
+            cache.get(&key(0));
+        }
+        assert!(cache.queue.len() <= 2 * cache.len() + 16);
+        assert_eq!(cache.insert(&key(9), 9), Some(1));
+    }
+}
@@ -0,0 +1,250 @@
+use crate::heap::sift_down_by;
+use std::cmp::Ordering;
+
+/// Lazily merges sorted iterators into one sorted iterator.
+///
+/// Equal elements come out in source order, and in their order within a
+/// source. Each source is advanced only when its current head is taken, so
+/// nothing is pulled past the elements already yielded except one head per
+/// source, and nothing at all before the first `next`.
+pub fn kway_merge<I, T>(iters: Vec<I>) -> KWayMerge<I, fn(&T, &T) -> Ordering>
+where
+    I: Iterator<Item = T>,
+    T: Ord,
+{
+    kway_merge_by(iters, T::cmp as fn(&T, &T) -> Ordering, false)
+}
+
+/// [`kway_merge`] with the sources sorted by `cmp`. With `dedup`, only the
+/// first of each run of equal elements is yielded, across all sources; the
+/// rest are pulled and dropped.
+pub fn kway_merge_by<I, T, F>(iters: Vec<I>, cmp: F, dedup: bool) -> KWayMerge<I, F>
+where
+    I: Iterator<Item = T>,
+    F: FnMut(&T, &T) -> Ordering,
+{
+    KWayMerge {
+        heap: Vec::with_capacity(iters.len()),
+        sources: iters,
+        started: false,
+        cmp,
+        dedup,
+    }
+}
+
+/// The iterator returned by [`kway_merge`] and [`kway_merge_by`].
+pub struct KWayMerge<I: Iterator, F> {
+    /// Min-heap of each non-empty source's head and the source's index.
+    heap: Vec<(I::Item, usize)>,
+    sources: Vec<I>,
+    /// Whether the heads have been pulled; deferred to the first `next`.
+    started: bool,
+    cmp: F,
+    dedup: bool,
+}
+
+impl<I, F> KWayMerge<I, F>
+where
+    I: Iterator,
+    F: FnMut(&I::Item, &I::Item) -> Ordering,
+{
+    fn push(&mut self, entry: (I::Item, usize)) {
+        self.heap.push(entry);
+        let mut i = self.heap.len() - 1;
+        while i > 0 {
+            let parent = (i - 1) / 2;
+            if !before(&mut self.cmp, &self.heap[i], &self.heap[parent]) {
+                break;
+            }
+            self.heap.swap(i, parent);
+            i = parent;
+        }
+    }
+
+    /// Takes the smallest head and replaces it with the next element of
+    /// its source.
+    fn pop(&mut self) -> Option<I::Item> {
+        if self.heap.is_empty() {
+            return None;
+        }
+        let (value, source) = self.heap.swap_remove(0);
+        let cmp = &mut self.cmp;
+        let end = self.heap.len();
+        sift_down_by(&mut self.heap, 0, end, &mut |parent, child| {
+            before(cmp, child, parent)
+        });
+        if let Some(next) = self.sources[source].next() {
+            self.push((next, source));
+        }
+        Some(value)
+    }
+}
+
+/// Whether `a` comes out before `b`: the smaller element, or the earlier
+/// source on a tie.
+fn before<T, F>(cmp: &mut F, a: &(T, usize), b: &(T, usize)) -> bool
+where
+    F: FnMut(&T, &T) -> Ordering,
+{
+    cmp(&a.0, &b.0).then(a.1.cmp(&b.1)) == Ordering::Less
+}
+
+impl<I, F> Iterator for KWayMerge<I, F>
+where
+    I: Iterator,
+    F: FnMut(&I::Item, &I::Item) -> Ordering,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        if !self.started {
+            self.started = true;
+            for source in 0..self.sources.len() {
+                if let Some(head) = self.sources[source].next() {
+                    self.push((head, source));
+                }
+            }
+        }
+        let value = self.pop()?;
+        if self.dedup {
+            while self
+                .heap
+                .first()
+                .is_some_and(|(head, _)| (self.cmp)(head, &value) == Ordering::Equal)
+            {
+                self.pop();
+            }
+        }
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{kway_merge, kway_merge_by};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_equals_sorted_concatenation() {
+        let mut rng = StdRng::seed_from_u64(245);
+        for _ in 0..200 {
+            let sources: Vec<Vec<u32>> = (0..rng.gen_range(0..12))
+                .map(|_| {
+                    // Some sources empty, some long.
+                    let len = if rng.gen_bool(0.2) {
+                        0
+                    } else {
+                        rng.gen_range(1..60)
+                    };
+                    let mut v: Vec<u32> = (0..len).map(|_| rng.gen_range(0..40)).collect();
+                    v.sort();
+                    v
+                })
+                .collect();
+            let mut expected: Vec<u32> = sources.concat();
+            expected.sort();
+            let merged: Vec<u32> =
+                kway_merge(sources.iter().map(|s| s.iter().copied()).collect()).collect();
+            assert_eq!(merged, expected);
+
+            expected.dedup();
+            let deduped: Vec<u32> = kway_merge_by(
+                sources.iter().map(|s| s.iter().copied()).collect(),
+                u32::cmp,
+                true,
+            )
+            .collect();
+            assert_eq!(deduped, expected);
+        }
+    }
+
+    #[test]
+    fn test_edge_sources() {
+        let none: Vec<std::vec::IntoIter<u8>> = Vec::new();
+        assert_eq!(kway_merge(none).count(), 0);
+
+        let single = vec![vec![1, 1, 2, 5].into_iter()];
+        assert_eq!(kway_merge(single).collect::<Vec<_>>(), [1, 1, 2, 5]);
+
+        let empties = vec![vec![].into_iter(), vec![3].into_iter(), vec![].into_iter()];
+        assert_eq!(kway_merge(empties).collect::<Vec<_>>(), [3]);
+    }
+
+    #[test]
+    fn test_stable_across_sources() {
+        // Tagged with (source, position); compared by the key alone.
+        let sources: Vec<Vec<(u32, usize, usize)>> = (0..4)
+            .map(|s| {
+                [1, 1, 2, 3, 3, 3]
+                    .iter()
+                    .enumerate()
+                    .filter(|&(i, _)| (i + s) % 3 != 0)
+                    .map(|(i, &k)| (k, s, i))
+                    .collect()
+            })
+            .collect();
+        let merged: Vec<_> = kway_merge_by(
+            sources.iter().map(|s| s.iter().copied()).collect(),
+            |a, b| a.0.cmp(&b.0),
+            false,
+        )
+        .collect();
+        let mut expected = sources.concat();
+        // A stable sort by key keeps the concatenation's source order.
+        expected.sort_by_key(|&(k, _, _)| k);
+        assert_eq!(merged, expected);
+
+        // Dedup keeps the first of each key: from the lowest source.
+        let firsts: Vec<_> = kway_merge_by(
+            sources.iter().map(|s| s.iter().copied()).collect(),
+            |a, b| a.0.cmp(&b.0),
+            true,
+        )
+        .collect();
+        assert_eq!(firsts, [(1, 0, 1), (2, 0, 2), (3, 0, 4)]);
+    }
+
+    #[test]
+    fn test_descending_comparator() {
+        let sources = vec![vec![9, 4, 1].into_iter(), vec![8, 4, 3].into_iter()];
+        let merged: Vec<i32> = kway_merge_by(sources, |a: &i32, b| b.cmp(a), false).collect();
+        assert_eq!(merged, [9, 8, 4, 4, 3, 1]);
+    }
+
+    struct Counting<I> {
+        inner: I,
+        pulled: Rc<Cell<usize>>,
+    }
+
+    impl<I: Iterator> Iterator for Counting<I> {
+        type Item = I::Item;
+
+        fn next(&mut self) -> Option<I::Item> {
+            self.pulled.set(self.pulled.get() + 1);
+            self.inner.next()
+        }
+    }
+
+    #[test]
+    fn test_lazy() {
+        let pulled = Rc::new(Cell::new(0));
+        let sources: Vec<_> = (0..5u32)
+            .map(|s| Counting {
+                inner: (0..1_000u32).map(move |i| i * 5 + s),
+                pulled: pulled.clone(),
+            })
+            .collect();
+        let mut merged = kway_merge(sources);
+        assert_eq!(pulled.get(), 0);
+
+        assert_eq!(merged.next(), Some(0));
+        // One head per source, plus the replacement for the one taken.
+        assert_eq!(pulled.get(), 6);
+        let taken: Vec<u32> = merged.by_ref().take(99).collect();
+        assert_eq!(taken, (1..100).collect::<Vec<_>>());
+        assert_eq!(pulled.get(), 105);
+    }
+}
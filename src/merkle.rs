@@ -0,0 +1,465 @@
+//! A binary Merkle tree over byte-string leaves, with inclusion proofs.
+//!
+//! Leaves hash as `H(0x00 || data)` and inner nodes as
+//! `H(0x01 || left || right)`, so a leaf can never pass for a node. When a
+//! level has an odd number of nodes the last one is promoted to the next
+//! level unchanged rather than paired with a copy of itself; duplicating it
+//! would give different leaf lists the same root. The resulting shape, and
+//! with SHA-256 the roots, match the Merkle tree hash of RFC 6962.
+
+/// A hash function with a 32-byte output, fed the concatenation of `parts`.
+///
+/// [`Sha256`] is the built-in one; to use another implementation, such as
+/// the `sha2` crate's, implement this for a wrapper type.
+pub trait Hasher32 {
+    fn hash(&self, parts: &[&[u8]]) -> [u8; 32];
+}
+
+/// A self-contained SHA-256 (FIPS 180-4), so the crate needs no crypto
+/// dependency.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sha256;
+
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+const H0: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+fn compress(state: &mut [u32; 8], block: &[u8]) {
+    let mut w = [0u32; 64];
+    for (i, word) in block.chunks_exact(4).enumerate() {
+        w[i] = u32::from_be_bytes(word.try_into().unwrap());
+    }
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16]
+            .wrapping_add(s0)
+            .wrapping_add(w[i - 7])
+            .wrapping_add(s1);
+    }
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for i in 0..64 {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let choice = (e & f) ^ (!e & g);
+        let t1 = h
+            .wrapping_add(s1)
+            .wrapping_add(choice)
+            .wrapping_add(K[i])
+            .wrapping_add(w[i]);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let majority = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(majority);
+        h = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(t1);
+        d = c;
+        c = b;
+        b = a;
+        a = t1.wrapping_add(t2);
+    }
+    for (s, v) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+        *s = s.wrapping_add(v);
+    }
+}
+
+impl Hasher32 for Sha256 {
+    fn hash(&self, parts: &[&[u8]]) -> [u8; 32] {
+        let mut state = H0;
+        let mut block = [0u8; 64];
+        let mut filled = 0;
+        let mut total: u64 = 0;
+        for part in parts {
+            total += part.len() as u64;
+            let mut rest = *part;
+            while !rest.is_empty() {
+                let n = rest.len().min(64 - filled);
+                block[filled..filled + n].copy_from_slice(&rest[..n]);
+                filled += n;
+                rest = &rest[n..];
+                if filled == 64 {
+                    compress(&mut state, &block);
+                    filled = 0;
+                }
+            }
+        }
+        // Padding: a one bit, zeros, then the message length in bits.
+        block[filled] = 0x80;
+        block[filled + 1..].fill(0);
+        if filled >= 56 {
+            compress(&mut state, &block);
+            block.fill(0);
+        }
+        block[56..].copy_from_slice(&(total * 8).to_be_bytes());
+        compress(&mut state, &block);
+
+        let mut out = [0u8; 32];
+        for (chunk, word) in out.chunks_exact_mut(4).zip(state) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        out
+    }
+}
+
+const LEAF: &[u8] = &[0];
+const NODE: &[u8] = &[1];
+
+fn hash_leaf<H: Hasher32>(hasher: &H, data: &[u8]) -> [u8; 32] {
+    hasher.hash(&[LEAF, data])
+}
+
+fn hash_node<H: Hasher32>(hasher: &H, left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    hasher.hash(&[NODE, left, right])
+}
+
+/// A Merkle tree that keeps every level, so proofs and leaf updates touch
+/// only one root-to-leaf path: O(log n) hashes each.
+#[derive(Debug, Clone)]
+pub struct MerkleTree<H = Sha256> {
+    /// `levels[0]` holds the leaf hashes and the last level the root.
+    levels: Vec<Vec<[u8; 32]>>,
+    hasher: H,
+}
+
+impl MerkleTree {
+    pub fn new<L: AsRef<[u8]>>(leaves: &[L]) -> Self {
+        Self::with_hasher(leaves, Sha256)
+    }
+}
+
+impl<H: Hasher32 + Clone> MerkleTree<H> {
+    pub fn with_hasher<L: AsRef<[u8]>>(leaves: &[L], hasher: H) -> Self {
+        let leaf_hashes: Vec<_> = leaves
+            .iter()
+            .map(|leaf| hash_leaf(&hasher, leaf.as_ref()))
+            .collect();
+        let mut levels = vec![leaf_hashes];
+        while levels.last().unwrap().len() > 1 {
+            let below = levels.last().unwrap();
+            let level: Vec<_> = below
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => hash_node(&hasher, left, right),
+                    [lone] => *lone,
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(level);
+        }
+        Self { levels, hasher }
+    }
+
+    pub fn len(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels[0].is_empty()
+    }
+
+    /// The root hash. An empty tree's root is the hash of no input at all,
+    /// which no leaf or node hash can equal because of their prefixes.
+    pub fn root(&self) -> [u8; 32] {
+        match self.levels.last().unwrap().first() {
+            Some(root) => *root,
+            None => self.hasher.hash(&[]),
+        }
+    }
+
+    /// A proof that leaf `index` is in the tree.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn proof(&self, index: usize) -> MerkleProof<H> {
+        assert!(index < self.len(), "leaf {index} out of {}", self.len());
+        let mut siblings = Vec::new();
+        let mut i = index;
+        for level in &self.levels[..self.levels.len() - 1] {
+            let sibling = i ^ 1;
+            // A promoted node has no sibling to record.
+            if sibling < level.len() {
+                siblings.push(level[sibling]);
+            }
+            i /= 2;
+        }
+        MerkleProof {
+            index,
+            leaf_count: self.len(),
+            siblings,
+            hasher: self.hasher.clone(),
+        }
+    }
+
+    /// Replaces leaf `index` with `data`, rehashing only its path to the
+    /// root.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn update_leaf(&mut self, index: usize, data: &[u8]) {
+        assert!(index < self.len(), "leaf {index} out of {}", self.len());
+        self.levels[0][index] = hash_leaf(&self.hasher, data);
+        let mut i = index;
+        for depth in 1..self.levels.len() {
+            let below = &self.levels[depth - 1];
+            let left = i & !1;
+            let hash = match below.get(left + 1) {
+                Some(right) => hash_node(&self.hasher, &below[left], right),
+                None => below[left],
+            };
+            i /= 2;
+            self.levels[depth][i] = hash;
+        }
+    }
+}
+
+/// The sibling hashes from a leaf up to the root, in that order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleProof<H = Sha256> {
+    index: usize,
+    leaf_count: usize,
+    siblings: Vec<[u8; 32]>,
+    hasher: H,
+}
+
+impl<H: Hasher32> MerkleProof<H> {
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// Whether `leaf` at this proof's index hashes up to `root`. Which side
+    /// each sibling goes on, and which levels have none, follow from the
+    /// index and leaf count, so a proof moved to another index fails.
+    pub fn verify(&self, root: &[u8; 32], leaf: &[u8]) -> bool {
+        if self.index >= self.leaf_count {
+            return false;
+        }
+        let mut hash = hash_leaf(&self.hasher, leaf);
+        let mut siblings = self.siblings.iter();
+        let (mut i, mut len) = (self.index, self.leaf_count);
+        while len > 1 {
+            let promoted = i == len - 1 && len % 2 == 1;
+            if !promoted {
+                let Some(sibling) = siblings.next() else {
+                    return false;
+                };
+                hash = if i % 2 == 0 {
+                    hash_node(&self.hasher, &hash, sibling)
+                } else {
+                    hash_node(&self.hasher, sibling, &hash)
+                };
+            }
+            i /= 2;
+            len = len.div_ceil(2);
+        }
+        siblings.next().is_none() && hash == *root
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{hash_leaf, hash_node, Hasher32, MerkleTree, Sha256};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    /// RFC 6962's recursive definition: split at the largest power of two
+    /// below the length.
+    fn reference_root(leaves: &[Vec<u8>]) -> [u8; 32] {
+        match leaves.len() {
+            0 => Sha256.hash(&[]),
+            1 => hash_leaf(&Sha256, &leaves[0]),
+            n => {
+                let split = n.next_power_of_two() / 2;
+                let left = reference_root(&leaves[..split]);
+                let right = reference_root(&leaves[split..]);
+                hash_node(&Sha256, &left, &right)
+            }
+        }
+    }
+
+    fn leaves(n: usize) -> Vec<Vec<u8>> {
+        (0..n).map(|i| format!("leaf {i}").into_bytes()).collect()
+    }
+
+    #[test]
+    fn test_sha256_vectors() {
+        assert_eq!(
+            hex(&Sha256.hash(&[])),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex(&Sha256.hash(&[b"abc"])),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        // 56 bytes forces the length into a second padding block.
+        let long = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+        assert_eq!(
+            hex(&Sha256.hash(&[long])),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+        // Split input hashes like the concatenation.
+        assert_eq!(
+            Sha256.hash(&[&long[..10], &long[10..]]),
+            Sha256.hash(&[long])
+        );
+        let million = vec![b'a'; 1_000_000];
+        assert_eq!(
+            hex(&Sha256.hash(&[&million])),
+            "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+        );
+    }
+
+    #[test]
+    fn test_matches_reference() {
+        for n in 0..40 {
+            let leaves = leaves(n);
+            let tree = MerkleTree::new(&leaves);
+            assert_eq!(tree.len(), n);
+            assert_eq!(tree.root(), reference_root(&leaves), "{n} leaves");
+        }
+    }
+
+    #[test]
+    fn test_rfc6962_vector() {
+        // The empty leaf's hash from RFC 6962's test data.
+        let tree = MerkleTree::new(&[b""]);
+        assert_eq!(
+            hex(&tree.root()),
+            "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d"
+        );
+    }
+
+    #[test]
+    fn test_proofs_verify() {
+        for n in 1..34 {
+            let leaves = leaves(n);
+            let tree = MerkleTree::new(&leaves);
+            let root = tree.root();
+            for (i, leaf) in leaves.iter().enumerate() {
+                let proof = tree.proof(i);
+                assert!(proof.verify(&root, leaf), "leaf {i} of {n}");
+                assert!(proof.leaf_count() == n && proof.index() == i);
+            }
+        }
+    }
+
+    #[test]
+    fn test_tampered_proofs_fail() {
+        let leaves = leaves(11);
+        let tree = MerkleTree::new(&leaves);
+        let root = tree.root();
+        for i in 0..leaves.len() {
+            let proof = tree.proof(i);
+            assert!(!proof.verify(&root, b"not a leaf"));
+
+            let mut bad_root = root;
+            bad_root[0] ^= 1;
+            assert!(!proof.verify(&bad_root, &leaves[i]));
+
+            for j in 0..leaves.len() {
+                if j != i {
+                    let mut moved = proof.clone();
+                    moved.index = j;
+                    assert!(!moved.verify(&root, &leaves[i]), "{i} moved to {j}");
+                    // Nor does a proof vouch for another index's leaf.
+                    assert!(!proof.verify(&root, &leaves[j]));
+                }
+            }
+
+            let mut moved = proof.clone();
+            moved.index = 11;
+            assert!(!moved.verify(&root, &leaves[i]));
+
+            if !proof.siblings.is_empty() {
+                let mut flipped = proof.clone();
+                flipped.siblings[0][31] ^= 1;
+                assert!(!flipped.verify(&root, &leaves[i]));
+
+                let mut short = proof.clone();
+                short.siblings.pop();
+                assert!(!short.verify(&root, &leaves[i]));
+            }
+            let mut long = proof.clone();
+            long.siblings.push([0; 32]);
+            assert!(!long.verify(&root, &leaves[i]));
+        }
+    }
+
+    #[test]
+    fn test_single_and_empty() {
+        let single = MerkleTree::new(&[b"only"]);
+        assert_eq!(single.root(), hash_leaf(&Sha256, b"only"));
+        let proof = single.proof(0);
+        assert!(proof.siblings.is_empty());
+        assert!(proof.verify(&single.root(), b"only"));
+
+        let empty = MerkleTree::new::<&[u8]>(&[]);
+        assert!(empty.is_empty());
+        assert_eq!(empty.root(), Sha256.hash(&[]));
+    }
+
+    #[test]
+    #[should_panic(expected = "out of")]
+    fn test_proof_out_of_bounds() {
+        MerkleTree::new(&[b"a", b"b"]).proof(2);
+    }
+
+    #[test]
+    fn test_update_leaf_matches_rebuild() {
+        let mut rng = StdRng::seed_from_u64(237);
+        for n in [1, 2, 3, 7, 8, 13, 32] {
+            let mut leaves = leaves(n);
+            let mut tree = MerkleTree::new(&leaves);
+            for round in 0..50 {
+                let i = rng.gen_range(0..n);
+                leaves[i] = format!("update {round}").into_bytes();
+                tree.update_leaf(i, &leaves[i]);
+                assert_eq!(tree.root(), MerkleTree::new(&leaves).root());
+                assert!(tree.proof(i).verify(&tree.root(), &leaves[i]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_custom_hasher() {
+        /// Not a real hash: XORs bytes into position, just to show the tree
+        /// defers to the plugged-in function.
+        #[derive(Clone)]
+        struct Xor;
+
+        impl Hasher32 for Xor {
+            fn hash(&self, parts: &[&[u8]]) -> [u8; 32] {
+                let mut out = [0u8; 32];
+                for (i, b) in parts.iter().flat_map(|p| p.iter()).enumerate() {
+                    out[i % 32] ^= b.rotate_left(i as u32 % 8);
+                }
+                out
+            }
+        }
+
+        let leaves = leaves(5);
+        let tree = MerkleTree::with_hasher(&leaves, Xor);
+        assert_ne!(tree.root(), MerkleTree::new(&leaves).root());
+        assert!(tree.proof(4).verify(&tree.root(), &leaves[4]));
+    }
+}
@@ -0,0 +1,160 @@
+use crate::min_stack::MinStack;
+use std::cmp::{Ordering, Reverse};
+
+/// A FIFO queue that also reports its minimum in O(1), built from two
+/// [`MinStack`]s.
+///
+/// Pushes go on the back stack. Pops come off the front stack, which is
+/// refilled by moving the whole back stack over, reversing it, when it runs
+/// empty; each value moves once, so pops are amortized O(1). The minimum is
+/// the smaller of the two stacks' minimums.
+#[derive(Debug, Clone)]
+pub struct MinQueue<T> {
+    front: MinStack<T>,
+    back: MinStack<T>,
+}
+
+/// A [`MinQueue`] of [`Reverse`]d values, whose `min` is the maximum.
+pub type MaxQueue<T> = MinQueue<Reverse<T>>;
+
+impl<T: Ord> MinQueue<T> {
+    pub fn new() -> Self {
+        Self {
+            front: MinStack::new(),
+            back: MinStack::new(),
+        }
+    }
+
+    pub fn push_back(&mut self, value: T) {
+        self.back.push(value);
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        if self.front.is_empty() {
+            while let Some(value) = self.back.pop() {
+                self.front.push(value);
+            }
+        }
+        self.front.pop()
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        self.front.top().or_else(|| self.back.bottom())
+    }
+
+    pub fn min(&self) -> Option<&T> {
+        match (self.front.min(), self.back.min()) {
+            (Some(a), Some(b)) => match a.cmp(b) {
+                Ordering::Greater => Some(b),
+                _ => Some(a),
+            },
+            (a, b) => a.or(b),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.front.len() + self.back.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.front.is_empty() && self.back.is_empty()
+    }
+}
+
+impl<T: Ord> Default for MinQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MaxQueue, MinQueue};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::cmp::Reverse;
+    use std::collections::VecDeque;
+
+    fn check(queue: &MinQueue<u32>, shadow: &VecDeque<u32>) {
+        assert_eq!(queue.min(), shadow.iter().min());
+        assert_eq!(queue.front(), shadow.front());
+        assert_eq!(queue.len(), shadow.len());
+    }
+
+    #[test]
+    fn test_against_shadow_queue() {
+        let mut rng = StdRng::seed_from_u64(243);
+        let mut queue = MinQueue::new();
+        let mut shadow = VecDeque::new();
+        for _ in 0..20_000 {
+            if rng.gen_bool(0.5) {
+                let value = rng.gen_range(0..100);
+                queue.push_back(value);
+                shadow.push_back(value);
+            } else {
+                assert_eq!(queue.pop_front(), shadow.pop_front());
+            }
+            check(&queue, &shadow);
+        }
+    }
+
+    #[test]
+    fn test_repeated_transfers() {
+        // Short bursts that empty the front stack every few pops, so most
+        // pops land right after a transfer or with both stacks occupied.
+        let mut rng = StdRng::seed_from_u64(2430);
+        let mut queue = MinQueue::new();
+        let mut shadow = VecDeque::new();
+        for _ in 0..2_000 {
+            for _ in 0..rng.gen_range(1..4) {
+                let value = rng.gen_range(0..10);
+                queue.push_back(value);
+                shadow.push_back(value);
+                check(&queue, &shadow);
+            }
+            for _ in 0..rng.gen_range(1..4) {
+                assert_eq!(queue.pop_front(), shadow.pop_front());
+                check(&queue, &shadow);
+            }
+        }
+    }
+
+    #[test]
+    fn test_duplicate_minima() {
+        let mut queue = MinQueue::new();
+        for value in [2, 1, 3, 1, 4] {
+            queue.push_back(value);
+        }
+        let mut mins = Vec::new();
+        while let Some(min) = queue.min().copied() {
+            mins.push(min);
+            queue.pop_front();
+        }
+        assert_eq!(mins, [1, 1, 1, 1, 4]);
+    }
+
+    #[test]
+    fn test_empty() {
+        let mut queue = MinQueue::<u32>::new();
+        assert!(queue.is_empty());
+        assert_eq!(queue.min(), None);
+        assert_eq!(queue.front(), None);
+        assert_eq!(queue.pop_front(), None);
+        queue.push_back(1);
+        assert_eq!(queue.pop_front(), Some(1));
+        assert_eq!(queue.min(), None);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_max_queue() {
+        let mut queue = MaxQueue::new();
+        for value in [1, 5, 2, 4] {
+            queue.push_back(Reverse(value));
+        }
+        assert_eq!(queue.min(), Some(&Reverse(5)));
+        queue.pop_front();
+        queue.pop_front();
+        assert_eq!(queue.min(), Some(&Reverse(4)));
+    }
+}
@@ -0,0 +1,128 @@
+use std::cmp::Reverse;
+
+/// A stack that also reports its minimum in O(1).
+///
+/// Each value is stored with the position of the smallest value at or below
+/// it, so the minimum of what remains after any pop is already known. Ties
+/// keep the lower position.
+#[derive(Debug, Clone)]
+pub struct MinStack<T> {
+    items: Vec<(T, usize)>,
+}
+
+/// A [`MinStack`] of [`Reverse`]d values, whose `min` is the maximum.
+pub type MaxStack<T> = MinStack<Reverse<T>>;
+
+impl<T: Ord> MinStack<T> {
+    pub fn new() -> Self {
+        Self { items: Vec::new() }
+    }
+
+    pub fn push(&mut self, value: T) {
+        let min = match self.items.last() {
+            Some(&(_, min)) if self.items[min].0 <= value => min,
+            _ => self.items.len(),
+        };
+        self.items.push((value, min));
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        self.items.pop().map(|(value, _)| value)
+    }
+
+    pub fn top(&self) -> Option<&T> {
+        self.items.last().map(|(value, _)| value)
+    }
+
+    pub fn min(&self) -> Option<&T> {
+        self.items.last().map(|&(_, min)| &self.items[min].0)
+    }
+
+    /// The value at the bottom of the stack, pushed before all the others.
+    pub fn bottom(&self) -> Option<&T> {
+        self.items.first().map(|(value, _)| value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+impl<T: Ord> Default for MinStack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MaxStack, MinStack};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::cmp::Reverse;
+
+    #[test]
+    fn test_against_shadow_vec() {
+        let mut rng = StdRng::seed_from_u64(243);
+        let mut stack = MinStack::new();
+        let mut shadow = Vec::new();
+        for _ in 0..20_000 {
+            if rng.gen_bool(0.55) {
+                let value = rng.gen_range(0..50);
+                stack.push(value);
+                shadow.push(value);
+            } else {
+                assert_eq!(stack.pop(), shadow.pop());
+            }
+            assert_eq!(stack.min(), shadow.iter().min());
+            assert_eq!(stack.top(), shadow.last());
+            assert_eq!(stack.len(), shadow.len());
+        }
+    }
+
+    #[test]
+    fn test_duplicate_minima() {
+        let mut stack = MinStack::new();
+        for value in [3, 1, 2, 1, 1, 5] {
+            stack.push(value);
+        }
+        let mut mins = Vec::new();
+        while let Some(min) = stack.min().copied() {
+            mins.push(min);
+            stack.pop();
+        }
+        assert_eq!(mins, [1, 1, 1, 1, 1, 3]);
+    }
+
+    #[test]
+    fn test_empty() {
+        let mut stack = MinStack::<i32>::new();
+        assert!(stack.is_empty());
+        assert_eq!(stack.min(), None);
+        assert_eq!(stack.top(), None);
+        assert_eq!(stack.pop(), None);
+        stack.push(4);
+        stack.pop();
+        assert_eq!(stack.min(), None);
+    }
+
+    #[test]
+    fn test_max_stack() {
+        let mut stack = MaxStack::new();
+        for value in [2, 7, 1, 7, 3] {
+            stack.push(Reverse(value));
+        }
+        assert_eq!(stack.min(), Some(&Reverse(7)));
+        stack.pop();
+        stack.pop();
+        assert_eq!(stack.min(), Some(&Reverse(7)));
+        stack.pop();
+        assert_eq!(stack.min(), Some(&Reverse(7)));
+        stack.pop();
+        assert_eq!(stack.min(), Some(&Reverse(2)));
+    }
+}
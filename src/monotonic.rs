@@ -0,0 +1,240 @@
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Max,
+    Min,
+}
+
+/// A FIFO queue that also reports its maximum (or minimum) in O(1).
+///
+/// Next to the values themselves it keeps the positions of the values that
+/// can still become the extreme: a value pushed later and at least as large
+/// (or small) outlives the earlier one, so the earlier one is dropped from
+/// the candidates for good. The candidates are therefore monotonic and the
+/// front one is the extreme. Each value enters and leaves the candidates at
+/// most once, so `push_back` is amortized O(1).
+#[derive(Debug, Clone)]
+pub struct MonotonicQueue<T> {
+    values: VecDeque<T>,
+    /// Positions of the candidates, oldest first. A position counts every
+    /// value ever pushed, so it stays valid as the front is popped.
+    candidates: VecDeque<usize>,
+    /// Position of `values[0]`.
+    head: usize,
+    kind: Kind,
+}
+
+impl<T: Ord> MonotonicQueue<T> {
+    /// A queue whose [`extreme`](Self::extreme) is the maximum.
+    pub fn max() -> Self {
+        Self::with_kind(Kind::Max)
+    }
+
+    /// A queue whose [`extreme`](Self::extreme) is the minimum.
+    pub fn min() -> Self {
+        Self::with_kind(Kind::Min)
+    }
+
+    fn with_kind(kind: Kind) -> Self {
+        Self {
+            values: VecDeque::new(),
+            candidates: VecDeque::new(),
+            head: 0,
+            kind,
+        }
+    }
+
+    pub fn push_back(&mut self, value: T) {
+        while let Some(&back) = self.candidates.back() {
+            if !self.supersedes(&value, &self.values[back - self.head]) {
+                break;
+            }
+            self.candidates.pop_back();
+        }
+        self.candidates.push_back(self.head + self.values.len());
+        self.values.push_back(value);
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        let value = self.values.pop_front()?;
+        if self.candidates.front() == Some(&self.head) {
+            self.candidates.pop_front();
+        }
+        self.head += 1;
+        Some(value)
+    }
+
+    /// The maximum (or minimum) of the queued values.
+    pub fn extreme(&self) -> Option<&T> {
+        self.candidates
+            .front()
+            .map(|&position| &self.values[position - self.head])
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        self.values.front()
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Whether `new` makes `old` unable to ever be the extreme again.
+    fn supersedes(&self, new: &T, old: &T) -> bool {
+        match self.kind {
+            Kind::Max => new >= old,
+            Kind::Min => new <= old,
+        }
+    }
+}
+
+/// The maximum of every window of `k` consecutive values, in order. Empty if
+/// `k` exceeds the length.
+///
+/// # Panics
+///
+/// Panics if `k` is zero.
+pub fn sliding_window_max<T: Ord + Clone>(values: &[T], k: usize) -> Vec<T> {
+    sliding_window(values, k, MonotonicQueue::max())
+}
+
+/// The minimum of every window of `k` consecutive values, in order. Empty if
+/// `k` exceeds the length.
+///
+/// # Panics
+///
+/// Panics if `k` is zero.
+pub fn sliding_window_min<T: Ord + Clone>(values: &[T], k: usize) -> Vec<T> {
+    sliding_window(values, k, MonotonicQueue::min())
+}
+
+fn sliding_window<T: Ord + Clone>(values: &[T], k: usize, mut queue: MonotonicQueue<T>) -> Vec<T> {
+    assert!(k > 0, "window size must be non-zero");
+    let mut extremes = Vec::with_capacity(values.len().saturating_sub(k - 1));
+    for (i, value) in values.iter().enumerate() {
+        queue.push_back(value.clone());
+        if i + 1 > k {
+            queue.pop_front();
+        }
+        if i + 1 >= k {
+            extremes.push(queue.extreme().unwrap().clone());
+        }
+    }
+    extremes
+}
+
+#[cfg(test)]
+mod test {
+    use super::{sliding_window_max, sliding_window_min, MonotonicQueue};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::VecDeque;
+
+    fn brute_force(values: &[u32], k: usize, max: bool) -> Vec<u32> {
+        values
+            .windows(k)
+            .map(|w| {
+                let it = w.iter().copied();
+                if max {
+                    it.max().unwrap()
+                } else {
+                    it.min().unwrap()
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sliding_window() {
+        let values = [1, 3, 3, -1, -3, 5, 3, 6, 7];
+        assert_eq!(sliding_window_max(&values, 3), vec![3, 3, 3, 5, 5, 6, 7]);
+        assert_eq!(
+            sliding_window_min(&values, 3),
+            vec![1, -1, -3, -3, -3, 3, 3]
+        );
+        assert_eq!(sliding_window_max(&values, 1), values.to_vec());
+        assert_eq!(sliding_window_max(&values, 9), vec![7]);
+        assert!(sliding_window_max(&values, 10).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "window size must be non-zero")]
+    fn test_zero_window() {
+        sliding_window_max(&[1, 2], 0);
+    }
+
+    #[test]
+    fn test_sliding_window_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(229);
+        for _ in 0..200 {
+            let len = rng.gen_range(1..60);
+            // A small value range makes duplicates common.
+            let values: Vec<u32> = (0..len).map(|_| rng.gen_range(0..8)).collect();
+            for k in [1, 2, rng.gen_range(1..=len), len] {
+                assert_eq!(
+                    sliding_window_max(&values, k),
+                    brute_force(&values, k, true)
+                );
+                assert_eq!(
+                    sliding_window_min(&values, k),
+                    brute_force(&values, k, false)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_push_pop_sequence() {
+        let mut queue = MonotonicQueue::max();
+        assert_eq!(queue.extreme(), None);
+        assert_eq!(queue.pop_front(), None);
+        queue.push_back(2);
+        queue.push_back(5);
+        queue.push_back(5);
+        queue.push_back(1);
+        assert_eq!(queue.extreme(), Some(&5));
+        assert_eq!(queue.pop_front(), Some(2));
+        assert_eq!(queue.pop_front(), Some(5));
+        // The second 5 is still queued.
+        assert_eq!(queue.extreme(), Some(&5));
+        assert_eq!(queue.pop_front(), Some(5));
+        assert_eq!(queue.extreme(), Some(&1));
+        assert_eq!(queue.pop_front(), Some(1));
+        assert_eq!(queue.pop_front(), None);
+        assert!(queue.is_empty());
+        queue.push_back(0);
+        assert_eq!(queue.extreme(), Some(&0));
+    }
+
+    #[test]
+    fn test_extreme_matches_shadow() {
+        let mut rng = StdRng::seed_from_u64(2290);
+        let mut max = MonotonicQueue::max();
+        let mut min = MonotonicQueue::min();
+        let mut shadow = VecDeque::new();
+
+        for _ in 0..10_000 {
+            // Pops slightly outnumber pushes in some stretches so the queue
+            // keeps draining to empty.
+            if rng.gen_bool(0.48) {
+                let v = rng.gen_range(0..20);
+                max.push_back(v);
+                min.push_back(v);
+                shadow.push_back(v);
+            } else {
+                let expected = shadow.pop_front();
+                assert_eq!(max.pop_front(), expected);
+                assert_eq!(min.pop_front(), expected);
+            }
+            assert_eq!(max.len(), shadow.len());
+            assert_eq!(max.front(), shadow.front());
+            assert_eq!(max.extreme(), shadow.iter().max());
+            assert_eq!(min.extreme(), shadow.iter().min());
+        }
+    }
+}
@@ -0,0 +1,460 @@
+use crate::sync::{fence, spin_loop, AtomicUsize, Ordering, UnsafeCell};
+use parking_lot::{Condvar, Mutex};
+use std::mem::MaybeUninit;
+use std::sync::atomic as std_atomic;
+
+struct Slot<T> {
+    /// The head or tail stamp this slot is waiting for: equal to the tail
+    /// stamp that may write it next while empty, and to that stamp plus one
+    /// once it holds a value.
+    stamp: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// Wakes threads blocked in `push_wait`/`pop_wait`. The waiter count lets the
+/// non-blocking fast paths skip the mutex entirely when nobody is parked.
+struct Parker {
+    lock: Mutex<()>,
+    cond: Condvar,
+    waiters: std_atomic::AtomicUsize,
+}
+
+impl Parker {
+    fn new() -> Self {
+        Self {
+            lock: Mutex::new(()),
+            cond: Condvar::new(),
+            waiters: std_atomic::AtomicUsize::new(0),
+        }
+    }
+
+    /// Retries `op` until it succeeds, parking between attempts.
+    fn wait_until<R>(&self, mut op: impl FnMut() -> Option<R>) -> R {
+        if let Some(r) = op() {
+            return r;
+        }
+        let mut guard = self.lock.lock();
+        self.waiters.fetch_add(1, std_atomic::Ordering::SeqCst);
+        std_atomic::fence(std_atomic::Ordering::SeqCst);
+        let r = loop {
+            // Re-checked under the lock: a notifier that missed our waiter
+            // count must have completed its operation before this attempt.
+            if let Some(r) = op() {
+                break r;
+            }
+            self.cond.wait(&mut guard);
+        };
+        self.waiters.fetch_sub(1, std_atomic::Ordering::SeqCst);
+        r
+    }
+
+    fn notify(&self) {
+        std_atomic::fence(std_atomic::Ordering::SeqCst);
+        if self.waiters.load(std_atomic::Ordering::SeqCst) > 0 {
+            let _guard = self.lock.lock();
+            self.cond.notify_all();
+        }
+    }
+}
+
+/// A fixed-capacity multi-producer multi-consumer queue.
+///
+/// This is Dmitry Vyukov's bounded queue: every slot carries a sequence
+/// number (its stamp) that tells a producer or consumer arriving with a given
+/// tail or head stamp whether the slot is ready for it, so a push or pop is
+/// one CAS on the tail or head plus one release store on the slot.
+///
+/// A stamp packs a lap counter above a slot index, with `one_lap` the
+/// smallest power of two greater than the capacity. The spare index value
+/// keeps "written in this lap" and "free for the next lap" distinct even for
+/// a queue of capacity one, which plain position counters cannot do.
+pub struct BoundedQueue<T> {
+    buffer: Box<[Slot<T>]>,
+    one_lap: usize,
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    not_empty: Parker,
+    not_full: Parker,
+}
+
+unsafe impl<T: Send> Send for BoundedQueue<T> {}
+unsafe impl<T: Send> Sync for BoundedQueue<T> {}
+
+impl<T> BoundedQueue<T> {
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "BoundedQueue capacity must be non-zero");
+        let buffer = (0..capacity)
+            .map(|i| Slot {
+                stamp: AtomicUsize::new(i),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            })
+            .collect();
+        Self {
+            buffer,
+            one_lap: (capacity + 1).next_power_of_two(),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            not_empty: Parker::new(),
+            not_full: Parker::new(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Number of queued values. Only a snapshot while other threads are
+    /// pushing or popping.
+    pub fn len(&self) -> usize {
+        loop {
+            let tail = self.tail.load(Ordering::SeqCst);
+            let head = self.head.load(Ordering::SeqCst);
+            // Retry until head and tail were read as a consistent pair.
+            if self.tail.load(Ordering::SeqCst) == tail {
+                let hix = head & (self.one_lap - 1);
+                let tix = tail & (self.one_lap - 1);
+                return if hix < tix {
+                    tix - hix
+                } else if hix > tix {
+                    self.capacity() - hix + tix
+                } else if tail == head {
+                    0
+                } else {
+                    self.capacity()
+                };
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Pushes `value`, handing it back if the queue is full.
+    pub fn push(&self, value: T) -> Result<(), T> {
+        self.try_push(value)?;
+        self.not_empty.notify();
+        Ok(())
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let value = self.try_pop()?;
+        self.not_full.notify();
+        Some(value)
+    }
+
+    /// Pushes `value`, blocking while the queue is full.
+    pub fn push_wait(&self, value: T) {
+        let mut value = Some(value);
+        self.not_full
+            .wait_until(|| match self.try_push(value.take().unwrap()) {
+                Ok(()) => Some(()),
+                Err(v) => {
+                    value = Some(v);
+                    None
+                }
+            });
+        self.not_empty.notify();
+    }
+
+    /// Pops a value, blocking while the queue is empty.
+    pub fn pop_wait(&self) -> T {
+        let value = self.not_empty.wait_until(|| self.try_pop());
+        self.not_full.notify();
+        value
+    }
+
+    /// The stamp following `stamp`: the next index, or index 0 of the next lap.
+    fn next_stamp(&self, stamp: usize) -> usize {
+        let index = stamp & (self.one_lap - 1);
+        let lap = stamp & !(self.one_lap - 1);
+        if index + 1 < self.capacity() {
+            stamp + 1
+        } else {
+            lap.wrapping_add(self.one_lap)
+        }
+    }
+
+    fn try_push(&self, value: T) -> Result<(), T> {
+        let mut tail = self.tail.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.buffer[tail & (self.one_lap - 1)];
+            // Acquire pairs with the consumer's release in `try_pop`, so the
+            // slot's previous value has been moved out before we overwrite it.
+            let stamp = slot.stamp.load(Ordering::Acquire);
+            if stamp == tail {
+                match self.tail.compare_exchange_weak(
+                    tail,
+                    self.next_stamp(tail),
+                    Ordering::SeqCst,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        slot.value.with_mut(|p| unsafe { (*p).write(value) });
+                        slot.stamp.store(tail + 1, Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(current) => tail = current,
+                }
+            } else if stamp.wrapping_add(self.one_lap) == tail + 1 {
+                // The slot still holds the value written one lap ago. The
+                // queue is full unless a consumer has already claimed it.
+                fence(Ordering::SeqCst);
+                let head = self.head.load(Ordering::Relaxed);
+                if head.wrapping_add(self.one_lap) == tail {
+                    return Err(value);
+                }
+                spin_loop();
+                tail = self.tail.load(Ordering::Relaxed);
+            } else {
+                // Another producer won this stamp; catch up.
+                spin_loop();
+                tail = self.tail.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn try_pop(&self) -> Option<T> {
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.buffer[head & (self.one_lap - 1)];
+            // Acquire pairs with the producer's release in `try_push`, making
+            // the written value visible.
+            let stamp = slot.stamp.load(Ordering::Acquire);
+            if stamp == head + 1 {
+                match self.head.compare_exchange_weak(
+                    head,
+                    self.next_stamp(head),
+                    Ordering::SeqCst,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        let value = slot.value.with(|p| unsafe { (*p).assume_init_read() });
+                        slot.stamp
+                            .store(head.wrapping_add(self.one_lap), Ordering::Release);
+                        return Some(value);
+                    }
+                    Err(current) => head = current,
+                }
+            } else if stamp == head {
+                // Nothing written here this lap. The queue is empty unless a
+                // producer has already claimed the slot.
+                fence(Ordering::SeqCst);
+                let tail = self.tail.load(Ordering::Relaxed);
+                if tail == head {
+                    return None;
+                }
+                spin_loop();
+                head = self.head.load(Ordering::Relaxed);
+            } else {
+                spin_loop();
+                head = self.head.load(Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl<T> Drop for BoundedQueue<T> {
+    fn drop(&mut self) {
+        while self.try_pop().is_some() {}
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::BoundedQueue;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_fifo() {
+        let queue = BoundedQueue::new(3);
+        assert!(queue.is_empty());
+        assert_eq!(queue.push(1), Ok(()));
+        assert_eq!(queue.push(2), Ok(()));
+        assert_eq!(queue.push(3), Ok(()));
+        assert_eq!(queue.push(4), Err(4));
+        assert_eq!(queue.len(), 3);
+
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(queue.push(4), Ok(()));
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.pop(), Some(3));
+        assert_eq!(queue.pop(), Some(4));
+        assert_eq!(queue.pop(), None);
+        assert!(queue.is_empty());
+
+        // Several laps around the buffer.
+        for i in 0..100 {
+            queue.push(i).unwrap();
+            assert_eq!(queue.pop(), Some(i));
+        }
+    }
+
+    #[test]
+    fn test_capacity_one() {
+        let queue = BoundedQueue::new(1);
+        assert_eq!(queue.push("a"), Ok(()));
+        assert_eq!(queue.push("b"), Err("b"));
+        assert_eq!(queue.pop(), Some("a"));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "capacity must be non-zero")]
+    fn test_zero_capacity() {
+        BoundedQueue::<u8>::new(0);
+    }
+
+    #[test]
+    fn test_drop_remaining() {
+        let value = Arc::new(());
+        {
+            let queue = BoundedQueue::new(4);
+            for _ in 0..3 {
+                queue.push(value.clone()).unwrap();
+            }
+            queue.pop();
+            assert_eq!(Arc::strong_count(&value), 3);
+        }
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn test_every_value_received_once() {
+        const PRODUCERS: usize = 4;
+        const CONSUMERS: usize = 4;
+        const PER_PRODUCER: usize = 20_000;
+
+        let queue = Arc::new(BoundedQueue::new(64));
+        let seen: Arc<Vec<AtomicUsize>> = Arc::new(
+            (0..PRODUCERS * PER_PRODUCER)
+                .map(|_| AtomicUsize::new(0))
+                .collect(),
+        );
+
+        let producers: Vec<_> = (0..PRODUCERS)
+            .map(|p| {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    for i in p * PER_PRODUCER..(p + 1) * PER_PRODUCER {
+                        queue.push_wait(i);
+                    }
+                })
+            })
+            .collect();
+        let consumers: Vec<_> = (0..CONSUMERS)
+            .map(|_| {
+                let queue = queue.clone();
+                let seen = seen.clone();
+                thread::spawn(move || {
+                    let mut sum = 0;
+                    for _ in 0..PRODUCERS * PER_PRODUCER / CONSUMERS {
+                        let v = queue.pop_wait();
+                        seen[v].fetch_add(1, Ordering::Relaxed);
+                        sum += v;
+                    }
+                    sum
+                })
+            })
+            .collect();
+
+        producers.into_iter().for_each(|h| h.join().unwrap());
+        let sum: usize = consumers.into_iter().map(|h| h.join().unwrap()).sum();
+
+        let n = PRODUCERS * PER_PRODUCER;
+        assert_eq!(sum, n * (n - 1) / 2);
+        assert!(seen.iter().all(|c| c.load(Ordering::Relaxed) == 1));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_full_empty_races() {
+        // A single slot keeps every operation on the full/empty boundary.
+        let queue = Arc::new(BoundedQueue::new(1));
+        let done = Arc::new(AtomicBool::new(false));
+        let pushed = Arc::new(AtomicUsize::new(0));
+        let popped = Arc::new(AtomicUsize::new(0));
+
+        let mut handles = Vec::new();
+        for _ in 0..2 {
+            let (queue, pushed) = (queue.clone(), pushed.clone());
+            handles.push(thread::spawn(move || {
+                for i in 0..10_000 {
+                    if queue.push(i).is_ok() {
+                        pushed.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }));
+        }
+        let mut poppers = Vec::new();
+        for _ in 0..2 {
+            let (queue, popped, done) = (queue.clone(), popped.clone(), done.clone());
+            poppers.push(thread::spawn(move || loop {
+                match queue.pop() {
+                    Some(_) => {
+                        popped.fetch_add(1, Ordering::Relaxed);
+                    }
+                    None if done.load(Ordering::Acquire) => break,
+                    None => {}
+                }
+            }));
+        }
+        handles.into_iter().for_each(|h| h.join().unwrap());
+        done.store(true, Ordering::Release);
+        poppers.into_iter().for_each(|h| h.join().unwrap());
+
+        let leftover = queue.len();
+        assert!(leftover <= 1);
+        assert_eq!(
+            pushed.load(Ordering::Relaxed),
+            popped.load(Ordering::Relaxed) + leftover
+        );
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_test {
+    use super::BoundedQueue;
+    use loom::sync::Arc;
+    use loom::thread;
+
+    #[test]
+    fn test_push_pop_two_threads() {
+        loom::model(|| {
+            let queue = Arc::new(BoundedQueue::new(1));
+            let producer = {
+                let queue = queue.clone();
+                thread::spawn(move || queue.push(1).is_ok())
+            };
+            let popped = queue.pop();
+            let pushed = producer.join().unwrap();
+            assert!(pushed);
+            let rest = queue.pop();
+            assert_eq!(popped.or(rest), Some(1));
+            assert!(popped.is_none() || rest.is_none());
+        });
+    }
+
+    #[test]
+    fn test_two_producers_one_slot() {
+        loom::model(|| {
+            let queue = Arc::new(BoundedQueue::new(1));
+            let other = {
+                let queue = queue.clone();
+                thread::spawn(move || queue.push(2))
+            };
+            let mine = queue.push(1);
+            let theirs = other.join().unwrap();
+            // Exactly one of the two pushes wins the only slot.
+            assert!(mine.is_ok() != theirs.is_ok());
+            let winner = if mine.is_ok() { 1 } else { 2 };
+            assert_eq!(queue.pop(), Some(winner));
+            assert_eq!(queue.pop(), None);
+        });
+    }
+}
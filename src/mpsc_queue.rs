@@ -0,0 +1,322 @@
+//! An unbounded multi-producer single-consumer queue.
+//!
+//! Loom models for this run with
+//! `RUSTFLAGS="--cfg loom --cfg crossbeam_loom" cargo test --release --lib mpsc_queue`.
+
+use crate::sync::{AtomicPtr, Ordering, UnsafeCell};
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::sync::Arc;
+
+struct Node<T> {
+    next: AtomicPtr<Node<T>>,
+    /// Uninitialized in the stub node the consumer sits on, and moved out by
+    /// the `pop` that makes a node the new stub.
+    val: MaybeUninit<T>,
+}
+
+impl<T> Node<T> {
+    fn alloc(val: MaybeUninit<T>) -> *mut Self {
+        Box::into_raw(Box::new(Node {
+            next: AtomicPtr::new(ptr::null_mut()),
+            val,
+        }))
+    }
+}
+
+struct Inner<T> {
+    /// The node pushed last. Producers swap themselves in here.
+    head: AtomicPtr<Node<T>>,
+    /// The stub, whose successor is the front of the queue. Only the
+    /// consumer touches it.
+    tail: UnsafeCell<*mut Node<T>>,
+}
+
+unsafe impl<T: Send> Send for Inner<T> {}
+unsafe impl<T: Send> Sync for Inner<T> {}
+
+impl<T> Inner<T> {
+    fn push(&self, val: T) {
+        let node = Node::alloc(MaybeUninit::new(val));
+        // AcqRel: acquire the previous node so we may write its link, and
+        // release ours to the producer that swaps in after us.
+        let prev = self.head.swap(node, Ordering::AcqRel);
+        // Until this store the node is pushed but unreachable from the
+        // stub; release publishes its value to the consumer.
+        unsafe { (*prev).next.store(node, Ordering::Release) };
+    }
+}
+
+impl<T> Drop for Inner<T> {
+    fn drop(&mut self) {
+        // Both ends are gone, so every push has finished linking its node.
+        let stub = self.tail.with_mut(|tail| unsafe { *tail });
+        let mut node = unsafe { Box::from_raw(stub) };
+        loop {
+            let next = node.next.load(Ordering::Relaxed);
+            if next.is_null() {
+                break;
+            }
+            node = unsafe { Box::from_raw(next) };
+            unsafe { node.val.assume_init_drop() };
+        }
+    }
+}
+
+/// The consumer end of an unbounded MPSC queue.
+///
+/// This is Dmitry Vyukov's non-intrusive MPSC queue. A push allocates a
+/// node, swaps it into `head` and then links the previous head to it, so
+/// producers never retry and never wait on each other; the consumer walks
+/// the links from a stub node and frees each stub as it moves past it.
+/// Only the consumer ever frees a node, and only once its successor is
+/// linked, which is why no epoch reclamation is needed.
+///
+/// Values from one producer come out in the order it pushed them. The
+/// queue is not linearizable, though: between a producer's swap and its
+/// link, [`pop`](Self::pop) returns `None` even if later pushes have
+/// completed, until that producer carries on.
+///
+/// The handle is `Send` but not `Sync`, which is what keeps `pop`
+/// single-threaded; [`Producer`]s can be cloned and shared freely.
+pub struct MpscQueue<T> {
+    inner: Arc<Inner<T>>,
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+impl<T> MpscQueue<T> {
+    pub fn new() -> Self {
+        let stub = Node::alloc(MaybeUninit::uninit());
+        Self {
+            inner: Arc::new(Inner {
+                head: AtomicPtr::new(stub),
+                tail: UnsafeCell::new(stub),
+            }),
+            _not_sync: PhantomData,
+        }
+    }
+
+    pub fn producer(&self) -> Producer<T> {
+        Producer {
+            inner: self.inner.clone(),
+        }
+    }
+
+    pub fn push(&self, val: T) {
+        self.inner.push(val);
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        self.inner.tail.with_mut(|tail| unsafe {
+            let stub = *tail;
+            let next = (*stub).next.load(Ordering::Acquire);
+            if next.is_null() {
+                return None;
+            }
+            // `next` becomes the stub; its value is ours alone, and no
+            // producer can still reach the old stub.
+            *tail = next;
+            let val = ptr::read((*next).val.as_ptr());
+            drop(Box::from_raw(stub));
+            Some(val)
+        })
+    }
+
+    /// Whether `pop` would return `None` right now.
+    pub fn is_empty(&self) -> bool {
+        self.inner
+            .tail
+            .with(|tail| unsafe { (**tail).next.load(Ordering::Acquire) }.is_null())
+    }
+}
+
+impl<T> Default for MpscQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A handle that pushes onto an [`MpscQueue`]. Cheap to clone and
+/// shareable across threads; it may outlive the queue, whose remaining
+/// values are then dropped with the last handle.
+pub struct Producer<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Producer<T> {
+    pub fn push(&self, val: T) {
+        self.inner.push(val);
+    }
+}
+
+impl<T> Clone for Producer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::MpscQueue;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    struct DropCounter(Arc<AtomicUsize>);
+
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_fifo() {
+        let queue = MpscQueue::new();
+        assert!(queue.is_empty());
+        assert_eq!(queue.pop(), None);
+        let producer = queue.producer();
+        for i in 0..5 {
+            producer.push(i);
+        }
+        queue.push(5);
+        assert!(!queue.is_empty());
+        for i in 0..6 {
+            assert_eq!(queue.pop(), Some(i));
+        }
+        assert_eq!(queue.pop(), None);
+        producer.push(6);
+        assert_eq!(queue.pop(), Some(6));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_stress() {
+        const PRODUCERS: usize = 8;
+        const PER_PRODUCER: usize = if cfg!(miri) { 200 } else { 20_000 };
+        const TOTAL: usize = PRODUCERS * PER_PRODUCER;
+
+        let queue = MpscQueue::new();
+        let handles: Vec<_> = (0..PRODUCERS)
+            .map(|p| {
+                let producer = queue.producer();
+                thread::spawn(move || {
+                    for i in p * PER_PRODUCER..(p + 1) * PER_PRODUCER {
+                        producer.push(i);
+                    }
+                })
+            })
+            .collect();
+
+        // Consume while the producers are still running: every value turns
+        // up exactly once, and each producer's in the order it pushed them.
+        let mut seen = vec![false; TOTAL];
+        let mut last = [None; PRODUCERS];
+        let mut received = 0;
+        while received < TOTAL {
+            match queue.pop() {
+                Some(v) => {
+                    assert!(!seen[v], "{v} popped twice");
+                    seen[v] = true;
+                    let p = v / PER_PRODUCER;
+                    assert!(last[p] < Some(v), "producer {p} out of order");
+                    last[p] = Some(v);
+                    received += 1;
+                }
+                None => thread::yield_now(),
+            }
+        }
+        handles.into_iter().for_each(|h| h.join().unwrap());
+        assert!(seen.iter().all(|&s| s));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn test_no_leaks() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        {
+            let queue = MpscQueue::new();
+            for _ in 0..100 {
+                queue.push(DropCounter(drops.clone()));
+            }
+            for _ in 0..30 {
+                drop(queue.pop());
+            }
+            assert_eq!(drops.load(Ordering::Relaxed), 30);
+        }
+        // The stub holds no value, so exactly the remaining 70 drop here.
+        assert_eq!(drops.load(Ordering::Relaxed), 100);
+    }
+
+    #[test]
+    fn test_producer_outlives_queue() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let queue = MpscQueue::new();
+        let producer = queue.producer();
+        queue.push(DropCounter(drops.clone()));
+        drop(queue);
+        producer.push(DropCounter(drops.clone()));
+        assert_eq!(drops.load(Ordering::Relaxed), 0);
+        drop(producer);
+        assert_eq!(drops.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn assert_send<T: Send>() {}
+        fn assert_sync<T: Sync>() {}
+        assert_send::<MpscQueue<String>>();
+        assert_send::<super::Producer<String>>();
+        assert_sync::<super::Producer<String>>();
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_test {
+    use super::MpscQueue;
+    use loom::thread;
+
+    #[test]
+    fn test_concurrent_push() {
+        loom::model(|| {
+            let queue = MpscQueue::new();
+            let producers: Vec<_> = (0..2)
+                .map(|i| {
+                    let producer = queue.producer();
+                    thread::spawn(move || producer.push(i))
+                })
+                .collect();
+
+            // Whatever the consumer catches while the pushes are in flight
+            // plus what is left afterwards is each value exactly once.
+            let mut got: Vec<_> = queue.pop().into_iter().collect();
+            producers.into_iter().for_each(|h| h.join().unwrap());
+            while let Some(v) = queue.pop() {
+                got.push(v);
+            }
+            got.sort();
+            assert_eq!(got, vec![0, 1]);
+        });
+    }
+
+    #[test]
+    fn test_per_producer_order() {
+        loom::model(|| {
+            let queue = MpscQueue::new();
+            let producer = queue.producer();
+            let other = thread::spawn(move || {
+                producer.push(1);
+                producer.push(2);
+            });
+            let first = queue.pop();
+            other.join().unwrap();
+            let rest: Vec<_> = std::iter::from_fn(|| queue.pop()).collect();
+            let all: Vec<_> = first.into_iter().chain(rest).collect();
+            assert_eq!(all, vec![1, 2]);
+        });
+    }
+}
@@ -0,0 +1,369 @@
+use std::cmp::Ordering;
+use std::ops::{Bound, RangeBounds};
+
+type Link<K> = Option<Box<Node<K>>>;
+
+struct Node<K> {
+    key: K,
+    priority: u64,
+    /// Number of keys in the subtree rooted here, this one included.
+    size: usize,
+    left: Link<K>,
+    right: Link<K>,
+}
+
+impl<K> Node<K> {
+    fn new(key: K, priority: u64) -> Self {
+        Self {
+            key,
+            priority,
+            size: 1,
+            left: None,
+            right: None,
+        }
+    }
+
+    fn update(&mut self) {
+        self.size = 1 + size(&self.left) + size(&self.right);
+    }
+}
+
+fn size<K>(link: &Link<K>) -> usize {
+    link.as_ref().map_or(0, |node| node.size)
+}
+
+/// A sorted multiset with rank queries: a treap whose nodes also store their
+/// subtree size.
+///
+/// Keys are ordered as a binary search tree and random priorities form a
+/// max-heap, which keeps the expected depth logarithmic. The sizes let
+/// [`select`](OrderStatTree::select) and [`rank`](OrderStatTree::rank) walk a
+/// single root-to-leaf path, so every operation is O(log n) expected.
+///
+/// Equal keys are all kept (a multiset), which is what a sliding window of
+/// samples needs; `remove` takes out one occurrence.
+pub struct OrderStatTree<K> {
+    root: Link<K>,
+    /// xorshift64 state for node priorities.
+    seed: u64,
+}
+
+impl<K: Ord> OrderStatTree<K> {
+    pub fn new() -> Self {
+        Self {
+            root: None,
+            seed: 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        size(&self.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    pub fn insert(&mut self, key: K) {
+        let priority = self.next_priority();
+        let (less, rest) = split(self.root.take(), &key, false);
+        let node = Some(Box::new(Node::new(key, priority)));
+        self.root = merge(merge(less, node), rest);
+    }
+
+    /// Removes one occurrence of `key`, returning whether there was one.
+    pub fn remove(&mut self, key: &K) -> bool {
+        let (less, rest) = split(self.root.take(), key, false);
+        let (mut equal, greater) = split(rest, key, true);
+        let found = match equal.take() {
+            Some(node) => {
+                let node = *node;
+                equal = merge(node.left, node.right);
+                true
+            }
+            None => false,
+        };
+        self.root = merge(merge(less, equal), greater);
+        found
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        let mut link = &self.root;
+        while let Some(node) = link {
+            link = match key.cmp(&node.key) {
+                Ordering::Less => &node.left,
+                Ordering::Greater => &node.right,
+                Ordering::Equal => return true,
+            };
+        }
+        false
+    }
+
+    /// The `k`-th smallest key, counting from zero.
+    pub fn select(&self, mut k: usize) -> Option<&K> {
+        let mut link = &self.root;
+        while let Some(node) = link {
+            let left = size(&node.left);
+            match k.cmp(&left) {
+                Ordering::Less => link = &node.left,
+                Ordering::Equal => return Some(&node.key),
+                Ordering::Greater => {
+                    k -= left + 1;
+                    link = &node.right;
+                }
+            }
+        }
+        None
+    }
+
+    /// Number of keys strictly less than `key`.
+    pub fn rank(&self, key: &K) -> usize {
+        self.count_below(key, false)
+    }
+
+    /// Number of keys inside `range`.
+    pub fn range_count<R: RangeBounds<K>>(&self, range: R) -> usize {
+        let below_end = match range.end_bound() {
+            Bound::Included(end) => self.count_below(end, true),
+            Bound::Excluded(end) => self.count_below(end, false),
+            Bound::Unbounded => self.len(),
+        };
+        let below_start = match range.start_bound() {
+            Bound::Included(start) => self.count_below(start, false),
+            Bound::Excluded(start) => self.count_below(start, true),
+            Bound::Unbounded => 0,
+        };
+        below_end.saturating_sub(below_start)
+    }
+
+    /// Keys in ascending order.
+    pub fn iter(&self) -> Iter<'_, K> {
+        let mut iter = Iter {
+            stack: Vec::new(),
+            left: self.len(),
+        };
+        iter.push_left(&self.root);
+        iter
+    }
+
+    /// Keys less than `key`, or less than or equal when `inclusive`.
+    fn count_below(&self, key: &K, inclusive: bool) -> usize {
+        let mut count = 0;
+        let mut link = &self.root;
+        while let Some(node) = link {
+            let goes_left = match key.cmp(&node.key) {
+                Ordering::Less => true,
+                Ordering::Equal => !inclusive,
+                Ordering::Greater => false,
+            };
+            if goes_left {
+                link = &node.left;
+            } else {
+                count += size(&node.left) + 1;
+                link = &node.right;
+            }
+        }
+        count
+    }
+
+    fn next_priority(&mut self) -> u64 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        self.seed
+    }
+}
+
+impl<K: Ord> Default for OrderStatTree<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord> FromIterator<K> for OrderStatTree<K> {
+    fn from_iter<I: IntoIterator<Item = K>>(iter: I) -> Self {
+        let mut tree = Self::new();
+        for key in iter {
+            tree.insert(key);
+        }
+        tree
+    }
+}
+
+/// Splits into keys less than `key` and the rest, or keys less than or
+/// equal to `key` and the rest when `inclusive`.
+fn split<K: Ord>(link: Link<K>, key: &K, inclusive: bool) -> (Link<K>, Link<K>) {
+    let Some(mut node) = link else {
+        return (None, None);
+    };
+    let goes_left = match node.key.cmp(key) {
+        Ordering::Less => true,
+        Ordering::Equal => inclusive,
+        Ordering::Greater => false,
+    };
+    if goes_left {
+        let (mid, right) = split(node.right.take(), key, inclusive);
+        node.right = mid;
+        node.update();
+        (Some(node), right)
+    } else {
+        let (left, mid) = split(node.left.take(), key, inclusive);
+        node.left = mid;
+        node.update();
+        (left, Some(node))
+    }
+}
+
+/// Joins two treaps where every key of `left` sorts before every key of
+/// `right`.
+fn merge<K>(left: Link<K>, right: Link<K>) -> Link<K> {
+    match (left, right) {
+        (None, link) | (link, None) => link,
+        (Some(mut l), Some(mut r)) => {
+            if l.priority >= r.priority {
+                l.right = merge(l.right.take(), Some(r));
+                l.update();
+                Some(l)
+            } else {
+                r.left = merge(Some(l), r.left.take());
+                r.update();
+                Some(r)
+            }
+        }
+    }
+}
+
+pub struct Iter<'a, K> {
+    stack: Vec<&'a Node<K>>,
+    left: usize,
+}
+
+impl<'a, K> Iter<'a, K> {
+    fn push_left(&mut self, mut link: &'a Link<K>) {
+        while let Some(node) = link {
+            self.stack.push(node);
+            link = &node.left;
+        }
+    }
+}
+
+impl<'a, K> Iterator for Iter<'a, K> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.push_left(&node.right);
+        self.left -= 1;
+        Some(&node.key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.left, Some(self.left))
+    }
+}
+
+impl<K> ExactSizeIterator for Iter<'_, K> {}
+
+#[cfg(test)]
+mod test {
+    use super::{Link, OrderStatTree};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// Checks ordering, heap priorities and cached sizes; returns the size.
+    fn validate<K: Ord>(link: &Link<K>, low: Option<&K>, high: Option<&K>) -> usize {
+        let Some(node) = link else {
+            return 0;
+        };
+        assert!(low.is_none_or(|low| low <= &node.key));
+        assert!(high.is_none_or(|high| &node.key <= high));
+        for child in [&node.left, &node.right].into_iter().flatten() {
+            assert!(child.priority <= node.priority);
+        }
+        let left = validate(&node.left, low, Some(&node.key));
+        let right = validate(&node.right, Some(&node.key), high);
+        assert_eq!(node.size, left + right + 1);
+        node.size
+    }
+
+    #[test]
+    fn test_select_rank() {
+        let tree: OrderStatTree<u32> = [50, 10, 40, 20, 30].into_iter().collect();
+        assert_eq!(tree.len(), 5);
+        assert_eq!(tree.select(0), Some(&10));
+        assert_eq!(tree.select(4), Some(&50));
+        assert_eq!(tree.select(5), None);
+        assert_eq!(tree.rank(&10), 0);
+        assert_eq!(tree.rank(&35), 3);
+        assert_eq!(tree.rank(&100), 5);
+        assert_eq!(tree.range_count(20..40), 2);
+        assert_eq!(tree.range_count(20..=40), 3);
+        assert_eq!(tree.range_count(..), 5);
+        let (hi, lo) = (45, 15);
+        assert_eq!(tree.range_count(hi..lo), 0);
+        assert!(tree.iter().copied().eq([10, 20, 30, 40, 50]));
+    }
+
+    #[test]
+    fn test_duplicates_are_kept() {
+        let mut tree: OrderStatTree<u32> = [5, 5, 5, 1, 9].into_iter().collect();
+        assert_eq!(tree.len(), 5);
+        assert_eq!(tree.rank(&5), 1);
+        assert_eq!(tree.range_count(5..=5), 3);
+        assert_eq!(tree.select(3), Some(&5));
+
+        // `remove` takes out one occurrence at a time.
+        assert!(tree.remove(&5));
+        assert_eq!(tree.range_count(5..=5), 2);
+        assert!(tree.remove(&5));
+        assert!(tree.remove(&5));
+        assert!(!tree.remove(&5));
+        assert!(!tree.contains(&5));
+        assert!(tree.iter().copied().eq([1, 9]));
+        validate(&tree.root, None, None);
+    }
+
+    #[test]
+    fn test_differential() {
+        let mut rng = StdRng::seed_from_u64(228);
+        let mut tree = OrderStatTree::new();
+        let mut model: Vec<u32> = Vec::new();
+
+        for _ in 0..5_000 {
+            let key = rng.gen_range(0..300);
+            if rng.gen_bool(0.6) {
+                tree.insert(key);
+                let at = model.partition_point(|&k| k < key);
+                model.insert(at, key);
+            } else {
+                let found = tree.remove(&key);
+                let at = model.binary_search(&key);
+                assert_eq!(found, at.is_ok());
+                if let Ok(at) = at {
+                    model.remove(at);
+                }
+            }
+
+            assert_eq!(validate(&tree.root, None, None), model.len());
+            let k = rng.gen_range(0..=model.len());
+            assert_eq!(tree.select(k), model.get(k));
+            let probe = rng.gen_range(0..300);
+            assert_eq!(tree.rank(&probe), model.partition_point(|&x| x < probe));
+            let (a, b) = (rng.gen_range(0..300), rng.gen_range(0..300));
+            let expected = model.iter().filter(|&&x| a <= x && x < b).count();
+            assert_eq!(tree.range_count(a..b), expected);
+        }
+        assert!(tree.iter().eq(model.iter()));
+    }
+
+    #[test]
+    fn test_sorted_inserts_stay_shallow() {
+        fn depth<K>(link: &Link<K>) -> usize {
+            link.as_ref()
+                .map_or(0, |node| 1 + depth(&node.left).max(depth(&node.right)))
+        }
+        let tree: OrderStatTree<u32> = (0..100_000).collect();
+        assert!(depth(&tree.root) < 60, "depth {}", depth(&tree.root));
+        assert_eq!(tree.select(54_321), Some(&54_321));
+    }
+}
@@ -0,0 +1,676 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::fmt::{self, Debug};
+use std::hash::{BuildHasher, Hash};
+use std::sync::Arc;
+
+const BITS: u32 = 5;
+const MASK: u64 = (1 << BITS) - 1;
+
+struct Leaf<K, V> {
+    hash: u64,
+    key: K,
+    value: V,
+}
+
+enum Node<K, V> {
+    /// Bit `i` of `bitmap` is set when the slot for hash chunk `i` is used;
+    /// `children` holds only the used slots, in chunk order.
+    Branch {
+        bitmap: u32,
+        children: Vec<Child<K, V>>,
+    },
+    /// Distinct keys whose full hashes are equal.
+    Collision {
+        hash: u64,
+        leaves: Vec<Arc<Leaf<K, V>>>,
+    },
+}
+
+enum Child<K, V> {
+    Leaf(Arc<Leaf<K, V>>),
+    Node(Arc<Node<K, V>>),
+}
+
+impl<K, V> Clone for Child<K, V> {
+    fn clone(&self) -> Self {
+        match self {
+            Child::Leaf(leaf) => Child::Leaf(leaf.clone()),
+            Child::Node(node) => Child::Node(node.clone()),
+        }
+    }
+}
+
+/// What removing a key did to a subtree.
+enum Removed<K, V> {
+    NotFound,
+    /// The subtree is now empty.
+    Empty,
+    /// The subtree's replacement, possibly a lone leaf lifted out of it.
+    Child(Child<K, V>),
+}
+
+fn chunk(hash: u64, shift: u32) -> u32 {
+    ((hash >> shift) & MASK) as u32
+}
+
+fn slot(bitmap: u32, bit: u32) -> usize {
+    (bitmap & (bit - 1)).count_ones() as usize
+}
+
+/// A persistent hash map: a hash array mapped trie (HAMT).
+///
+/// Each level consumes five bits of the key's hash and a branch stores only
+/// its used slots, found through a 32-bit bitmap and a popcount. Keys whose
+/// hashes agree on every bit share a collision node. `insert` and `remove`
+/// copy the path from the root to the affected leaf and share the rest with
+/// the old version, and removal collapses branches left holding a single
+/// leaf, so the trie only ever grows as deep as the hashes require.
+pub struct HashMap<K, V, S = RandomState> {
+    root: Arc<Node<K, V>>,
+    len: usize,
+    hasher: S,
+}
+
+impl<K, V> HashMap<K, V>
+where
+    K: Hash + Eq,
+{
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+}
+
+impl<K, V> Default for HashMap<K, V>
+where
+    K: Hash + Eq,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Clone,
+{
+    /// An empty map using `hasher`. Every version derived from it keeps the
+    /// same hasher.
+    pub fn with_hasher(hasher: S) -> Self {
+        Self {
+            root: Arc::new(Node::Branch {
+                bitmap: 0,
+                children: Vec::new(),
+            }),
+            len: 0,
+            hasher,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hasher.hash_one(key);
+        let mut node = &self.root;
+        let mut shift = 0;
+        loop {
+            match &**node {
+                Node::Branch { bitmap, children } => {
+                    let bit = 1 << chunk(hash, shift);
+                    if bitmap & bit == 0 {
+                        return None;
+                    }
+                    match &children[slot(*bitmap, bit)] {
+                        Child::Leaf(leaf) => {
+                            return (leaf.hash == hash && leaf.key.borrow() == key)
+                                .then_some(&leaf.value);
+                        }
+                        Child::Node(child) => node = child,
+                    }
+                    shift += BITS;
+                }
+                Node::Collision { hash: h, leaves } => {
+                    if *h != hash {
+                        return None;
+                    }
+                    return leaves
+                        .iter()
+                        .find(|leaf| leaf.key.borrow() == key)
+                        .map(|leaf| &leaf.value);
+                }
+            }
+        }
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// A new map with `key` set to `value`.
+    pub fn insert(&self, key: K, value: V) -> Self {
+        let hash = self.hasher.hash_one(&key);
+        let leaf = Arc::new(Leaf { hash, key, value });
+        let (root, added) = Self::insert_into(&self.root, 0, leaf);
+        Self {
+            root: Arc::new(root),
+            len: self.len + added as usize,
+            hasher: self.hasher.clone(),
+        }
+    }
+
+    /// A new map without `key`. If `key` is absent the result shares the
+    /// whole trie with this map.
+    pub fn remove<Q>(&self, key: &Q) -> Self
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hasher.hash_one(key);
+        let root = match Self::remove_from(&self.root, 0, hash, key) {
+            Removed::NotFound => return self.clone(),
+            Removed::Empty => Arc::new(Node::Branch {
+                bitmap: 0,
+                children: Vec::new(),
+            }),
+            Removed::Child(Child::Node(node)) => node,
+            Removed::Child(Child::Leaf(_)) => unreachable!("the root is never lifted"),
+        };
+        Self {
+            root,
+            len: self.len - 1,
+            hasher: self.hasher.clone(),
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        let children = match &*self.root {
+            Node::Branch { children, .. } => children.as_slice(),
+            Node::Collision { .. } => unreachable!("the root is always a branch"),
+        };
+        Iter {
+            stack: vec![children.iter()],
+            collision: [].iter(),
+            left: self.len,
+        }
+    }
+
+    /// Inserts `leaf` below the branch `node` whose slots are picked by the
+    /// hash bits at `shift`. Returns the copied branch and whether the key
+    /// is new.
+    fn insert_into(node: &Node<K, V>, shift: u32, leaf: Arc<Leaf<K, V>>) -> (Node<K, V>, bool) {
+        let (bitmap, children) = match node {
+            Node::Branch { bitmap, children } => (*bitmap, children),
+            Node::Collision { .. } => unreachable!("collisions are handled by the parent"),
+        };
+        let bit = 1 << chunk(leaf.hash, shift);
+        let index = slot(bitmap, bit);
+        let mut children = children.clone();
+
+        if bitmap & bit == 0 {
+            children.insert(index, Child::Leaf(leaf));
+            return (
+                Node::Branch {
+                    bitmap: bitmap | bit,
+                    children,
+                },
+                true,
+            );
+        }
+
+        let (child, added) = match &children[index] {
+            Child::Leaf(existing) if existing.key == leaf.key => (Child::Leaf(leaf), false),
+            Child::Leaf(existing) => {
+                let hash = existing.hash;
+                let merged = Self::merge(Child::Leaf(existing.clone()), hash, leaf, shift + BITS);
+                (Child::Node(Arc::new(merged)), true)
+            }
+            Child::Node(sub) => match &**sub {
+                Node::Branch { .. } => {
+                    let (sub, added) = Self::insert_into(sub, shift + BITS, leaf);
+                    (Child::Node(Arc::new(sub)), added)
+                }
+                Node::Collision { hash, leaves } if *hash == leaf.hash => {
+                    let mut leaves = leaves.clone();
+                    let added = match leaves.iter().position(|l| l.key == leaf.key) {
+                        Some(i) => {
+                            leaves[i] = leaf;
+                            false
+                        }
+                        None => {
+                            leaves.push(leaf);
+                            true
+                        }
+                    };
+                    let hash = *hash;
+                    (
+                        Child::Node(Arc::new(Node::Collision { hash, leaves })),
+                        added,
+                    )
+                }
+                Node::Collision { hash, .. } => {
+                    let merged = Self::merge(Child::Node(sub.clone()), *hash, leaf, shift + BITS);
+                    (Child::Node(Arc::new(merged)), true)
+                }
+            },
+        };
+        children[index] = child;
+        (Node::Branch { bitmap, children }, added)
+    }
+
+    /// Builds the smallest subtree holding `existing` (whose hash is
+    /// `existing_hash`) and a leaf with a different key, starting at the hash
+    /// bits at `shift`.
+    fn merge(
+        existing: Child<K, V>,
+        existing_hash: u64,
+        leaf: Arc<Leaf<K, V>>,
+        shift: u32,
+    ) -> Node<K, V> {
+        if existing_hash == leaf.hash {
+            // Only a lone leaf can get here; a collision node with the same
+            // hash takes the new key directly.
+            let Child::Leaf(existing) = existing else {
+                unreachable!("equal-hash collision nodes are extended in place")
+            };
+            return Node::Collision {
+                hash: leaf.hash,
+                leaves: vec![existing, leaf],
+            };
+        }
+        let (a, b) = (chunk(existing_hash, shift), chunk(leaf.hash, shift));
+        if a == b {
+            let below = Self::merge(existing, existing_hash, leaf, shift + BITS);
+            return Node::Branch {
+                bitmap: 1 << a,
+                children: vec![Child::Node(Arc::new(below))],
+            };
+        }
+        let children = if a < b {
+            vec![existing, Child::Leaf(leaf)]
+        } else {
+            vec![Child::Leaf(leaf), existing]
+        };
+        Node::Branch {
+            bitmap: (1 << a) | (1 << b),
+            children,
+        }
+    }
+
+    fn remove_from<Q>(node: &Node<K, V>, shift: u32, hash: u64, key: &Q) -> Removed<K, V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        let (bitmap, children) = match node {
+            Node::Branch { bitmap, children } => (*bitmap, children),
+            Node::Collision { .. } => unreachable!("collisions are handled by the parent"),
+        };
+        let bit = 1 << chunk(hash, shift);
+        if bitmap & bit == 0 {
+            return Removed::NotFound;
+        }
+        let index = slot(bitmap, bit);
+
+        let removed = match &children[index] {
+            Child::Leaf(leaf) if leaf.hash == hash && leaf.key.borrow() == key => Removed::Empty,
+            Child::Leaf(_) => Removed::NotFound,
+            Child::Node(sub) => match &**sub {
+                Node::Branch { .. } => Self::remove_from(sub, shift + BITS, hash, key),
+                Node::Collision { hash: h, leaves } if *h == hash => {
+                    match leaves.iter().position(|leaf| leaf.key.borrow() == key) {
+                        None => Removed::NotFound,
+                        Some(i) => {
+                            let mut leaves = leaves.clone();
+                            leaves.remove(i);
+                            if leaves.len() == 1 {
+                                Removed::Child(Child::Leaf(leaves.pop().unwrap()))
+                            } else {
+                                Removed::Child(Child::Node(Arc::new(Node::Collision {
+                                    hash: *h,
+                                    leaves,
+                                })))
+                            }
+                        }
+                    }
+                }
+                Node::Collision { .. } => Removed::NotFound,
+            },
+        };
+
+        let mut children = children.clone();
+        let bitmap = match removed {
+            Removed::NotFound => return Removed::NotFound,
+            Removed::Empty => {
+                children.remove(index);
+                bitmap & !bit
+            }
+            Removed::Child(child) => {
+                children[index] = child;
+                bitmap
+            }
+        };
+
+        if children.is_empty() {
+            return Removed::Empty;
+        }
+        // A branch left with one leaf or collision node is replaced by that
+        // child, except at the root, which stays a branch. A lone branch
+        // child cannot be lifted since its slots depend on its depth.
+        if shift > 0 && children.len() == 1 {
+            let lift = match &children[0] {
+                Child::Leaf(_) => true,
+                Child::Node(node) => matches!(**node, Node::Collision { .. }),
+            };
+            if lift {
+                return Removed::Child(children.pop().unwrap());
+            }
+        }
+        Removed::Child(Child::Node(Arc::new(Node::Branch { bitmap, children })))
+    }
+}
+
+impl<K, V, S: Clone> Clone for HashMap<K, V, S> {
+    fn clone(&self) -> Self {
+        Self {
+            root: self.root.clone(),
+            len: self.len,
+            hasher: self.hasher.clone(),
+        }
+    }
+}
+
+impl<K, V, S> FromIterator<(K, V)> for HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Clone + Default,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        iter.into_iter()
+            .fold(Self::with_hasher(S::default()), |map, (k, v)| {
+                map.insert(k, v)
+            })
+    }
+}
+
+impl<K, V, S> PartialEq for HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: PartialEq,
+    S: BuildHasher + Clone,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+}
+
+impl<K, V, S> Eq for HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    V: Eq,
+    S: BuildHasher + Clone,
+{
+}
+
+impl<K, V, S> Debug for HashMap<K, V, S>
+where
+    K: Debug + Hash + Eq,
+    V: Debug,
+    S: BuildHasher + Clone,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// Iterates in trie order, which is arbitrary but the same for every
+/// version with the same keys and hasher.
+pub struct Iter<'a, K, V> {
+    /// The unvisited children of each branch on the path to the current one.
+    stack: Vec<std::slice::Iter<'a, Child<K, V>>>,
+    collision: std::slice::Iter<'a, Arc<Leaf<K, V>>>,
+    left: usize,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(leaf) = self.collision.next() {
+                self.left -= 1;
+                return Some((&leaf.key, &leaf.value));
+            }
+            let children = self.stack.last_mut()?;
+            match children.next() {
+                None => {
+                    self.stack.pop();
+                }
+                Some(Child::Leaf(leaf)) => {
+                    self.left -= 1;
+                    return Some((&leaf.key, &leaf.value));
+                }
+                Some(Child::Node(node)) => match &**node {
+                    Node::Branch { children, .. } => self.stack.push(children.iter()),
+                    Node::Collision { leaves, .. } => self.collision = leaves.iter(),
+                },
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.left, Some(self.left))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+#[cfg(test)]
+mod test {
+    use super::{Child, HashMap, Node};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::HashMap as StdHashMap;
+    use std::hash::{BuildHasherDefault, Hash, Hasher};
+    use std::sync::Arc;
+
+    /// Hashes every key into one of four buckets.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct Colliding(u32);
+
+    impl Hash for Colliding {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            (self.0 % 4).hash(state);
+        }
+    }
+
+    /// Uses a `u64` key as its own hash, to lay out the trie by hand.
+    #[derive(Default)]
+    struct IdentityHasher(u64);
+
+    impl Hasher for IdentityHasher {
+        fn finish(&self) -> u64 {
+            self.0
+        }
+
+        fn write(&mut self, _: &[u8]) {
+            unimplemented!("only u64 keys")
+        }
+
+        fn write_u64(&mut self, n: u64) {
+            self.0 = n;
+        }
+    }
+
+    type Identity = BuildHasherDefault<IdentityHasher>;
+
+    fn root_children<K, V, S>(map: &HashMap<K, V, S>) -> &[Child<K, V>] {
+        match &*map.root {
+            Node::Branch { children, .. } => children,
+            Node::Collision { .. } => unreachable!(),
+        }
+    }
+
+    fn depth<K, V>(child: &Child<K, V>) -> usize {
+        match child {
+            Child::Leaf(_) => 1,
+            Child::Node(node) => match &**node {
+                Node::Branch { children, .. } => 1 + children.iter().map(depth).max().unwrap_or(0),
+                Node::Collision { .. } => 1,
+            },
+        }
+    }
+
+    #[test]
+    fn test_insert_get_remove() {
+        let empty = HashMap::new();
+        let one = empty.insert("a", 1);
+        let two = one.insert("b", 2);
+        let replaced = two.insert("a", 10);
+
+        assert_eq!(empty.get("a"), None);
+        assert_eq!(one.get("a"), Some(&1));
+        assert_eq!(two.len(), 2);
+        assert_eq!(replaced.len(), 2);
+        assert_eq!(replaced.get("a"), Some(&10));
+        assert_eq!(two.get("a"), Some(&1));
+
+        let removed = replaced.remove("a");
+        assert_eq!(removed.len(), 1);
+        assert!(!removed.contains_key("a"));
+        assert!(replaced.contains_key("a"));
+        // Removing an absent key shares everything.
+        assert!(Arc::ptr_eq(&removed.root, &removed.remove("zzz").root));
+    }
+
+    #[test]
+    fn test_collisions() {
+        let mut map = HashMap::new();
+        for i in 0..100 {
+            map = map.insert(Colliding(i), i);
+        }
+        assert_eq!(map.len(), 100);
+        for i in 0..100 {
+            assert_eq!(map.get(&Colliding(i)), Some(&i));
+        }
+        assert_eq!(map.get(&Colliding(100)), None);
+        let overwritten = map.insert(Colliding(7), 700);
+        assert_eq!(overwritten.len(), 100);
+        assert_eq!(overwritten.get(&Colliding(7)), Some(&700));
+
+        // Empty three buckets entirely and leave one key in the last: its
+        // collision node shrinks back to a plain leaf.
+        let mut shrunk = map.clone();
+        for i in 0..100 {
+            if i != 3 {
+                shrunk = shrunk.remove(&Colliding(i));
+            }
+        }
+        assert_eq!(shrunk.len(), 1);
+        assert_eq!(shrunk.get(&Colliding(3)), Some(&3));
+        assert!(matches!(root_children(&shrunk), [Child::Leaf(_)]));
+        assert_eq!(map.len(), 100);
+        assert_eq!(map.iter().count(), 100);
+    }
+
+    #[test]
+    fn test_removal_collapses() {
+        // 1 and 33 agree on the low five bits, so they sit one level down.
+        let map: HashMap<u64, u64, Identity> = [(1, 1), (33, 33)].into_iter().collect();
+        assert_eq!(root_children(&map).len(), 1);
+        assert_eq!(depth(&root_children(&map)[0]), 2);
+
+        // Removing one lifts the other straight into the root.
+        let map = map.remove(&33);
+        assert!(matches!(root_children(&map), [Child::Leaf(_)]));
+        assert_eq!(map.get(&1), Some(&1));
+
+        // A long shared prefix collapses all the way up.
+        let deep: HashMap<u64, u64, Identity> = [(7, 0), (7 | 1 << 40, 1)].into_iter().collect();
+        assert_eq!(depth(&root_children(&deep)[0]), 9);
+        let shallow = deep.remove(&7);
+        assert_eq!(depth(&root_children(&shallow)[0]), 1);
+        assert!(shallow.remove(&(7 | 1 << 40)).is_empty());
+    }
+
+    #[test]
+    fn test_structural_sharing() {
+        let base: HashMap<u32, u32> = (0..10_000).map(|i| (i, i)).collect();
+        let updated = base.insert(5, 50);
+        let (old, new) = (root_children(&base), root_children(&updated));
+        assert_eq!(old.len(), 32);
+
+        // Exactly one root slot was copied to reach key 5.
+        let shared = old
+            .iter()
+            .zip(new)
+            .filter(|pair| match pair {
+                (Child::Node(a), Child::Node(b)) => Arc::ptr_eq(a, b),
+                _ => false,
+            })
+            .count();
+        assert_eq!(shared, 31);
+
+        // The shared nodes are referenced once from each version.
+        let counts = |map: &HashMap<u32, u32>| -> Vec<usize> {
+            root_children(map)
+                .iter()
+                .map(|child| match child {
+                    Child::Node(node) => Arc::strong_count(node),
+                    Child::Leaf(leaf) => Arc::strong_count(leaf),
+                })
+                .collect()
+        };
+        assert_eq!(counts(&base).iter().filter(|&&c| c == 2).count(), 31);
+        drop(updated);
+        assert!(counts(&base).iter().all(|&c| c == 1));
+    }
+
+    #[test]
+    fn test_retained_versions_differential() {
+        let mut rng = StdRng::seed_from_u64(226);
+        let mut map = HashMap::new();
+        let mut model = StdHashMap::new();
+        let mut history = Vec::new();
+
+        for step in 0..20_000_u32 {
+            let key = rng.gen_range(0..2_000_u32);
+            if rng.gen_bool(0.6) {
+                map = map.insert(key, step);
+                model.insert(key, step);
+            } else {
+                map = map.remove(&key);
+                model.remove(&key);
+            }
+            if step % 1_000 == 0 {
+                history.push((map.clone(), model.clone()));
+            }
+        }
+        history.push((map, model));
+
+        for (map, model) in &history {
+            assert_eq!(map.len(), model.len());
+            assert_eq!(map.iter().len(), model.len());
+            for (k, v) in map.iter() {
+                assert_eq!(model.get(k), Some(v));
+            }
+            for key in 0..2_000 {
+                assert_eq!(map.get(&key), model.get(&key));
+            }
+        }
+    }
+}
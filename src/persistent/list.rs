@@ -0,0 +1,287 @@
+use std::cmp::Ordering;
+use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+struct Node<T> {
+    value: T,
+    next: Option<Arc<Node<T>>>,
+    /// Length of the list starting at this node.
+    len: usize,
+}
+
+/// A persistent singly linked (cons) list.
+///
+/// `push_front` and `tail` share the rest of the list instead of copying it,
+/// and `clone` just bumps a reference count, so keeping every old version
+/// around costs one node per push.
+pub struct List<T> {
+    head: Option<Arc<Node<T>>>,
+}
+
+impl<T> List<T> {
+    pub fn new() -> Self {
+        Self { head: None }
+    }
+
+    /// A new list with `value` in front of this one, which is left intact.
+    pub fn push_front(&self, value: T) -> Self {
+        Self {
+            head: Some(Arc::new(Node {
+                value,
+                next: self.head.clone(),
+                len: self.len() + 1,
+            })),
+        }
+    }
+
+    pub fn head(&self) -> Option<&T> {
+        self.head.as_ref().map(|node| &node.value)
+    }
+
+    /// Everything after the head, or `None` for the empty list.
+    pub fn tail(&self) -> Option<Self> {
+        self.head.as_ref().map(|node| Self {
+            head: node.next.clone(),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.head.as_ref().map_or(0, |node| node.len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            next: self.head.as_deref(),
+        }
+    }
+
+    /// A reversed copy. Nothing can be shared, so this clones every value.
+    pub fn rev(&self) -> Self
+    where
+        T: Clone,
+    {
+        self.iter()
+            .fold(Self::new(), |list, value| list.push_front(value.clone()))
+    }
+
+    /// Whether both lists are the very same nodes, not just equal values.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (&self.head, &other.head) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<T> Clone for List<T> {
+    fn clone(&self) -> Self {
+        Self {
+            head: self.head.clone(),
+        }
+    }
+}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for List<T> {
+    fn drop(&mut self) {
+        // Unlink the nodes we hold the last reference to one at a time;
+        // letting `Arc` drop them recursively overflows the stack on long
+        // lists. Stop at the first node still shared with another list.
+        let mut cur = self.head.take();
+        while let Some(node) = cur {
+            match Arc::try_unwrap(node) {
+                Ok(mut node) => cur = node.next.take(),
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+impl<T> FromIterator<T> for List<T> {
+    /// Builds a list that iterates in the same order as `iter`.
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let values: Vec<T> = iter.into_iter().collect();
+        values
+            .into_iter()
+            .rev()
+            .fold(Self::new(), |list, value| list.push_front(value))
+    }
+}
+
+pub struct Iter<'a, T> {
+    next: Option<&'a Node<T>>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next.map(|node| {
+            self.next = node.next.as_deref();
+            &node.value
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.next.map_or(0, |node| node.len);
+        (len, Some(len))
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+impl<'a, T> IntoIterator for &'a List<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T: PartialEq> PartialEq for List<T> {
+    fn eq(&self, other: &Self) -> bool {
+        if self.len() != other.len() {
+            return false;
+        }
+        let (mut a, mut b) = (self.head.as_ref(), other.head.as_ref());
+        while let (Some(x), Some(y)) = (a, b) {
+            // A shared suffix is equal without looking at it.
+            if Arc::ptr_eq(x, y) {
+                return true;
+            }
+            if x.value != y.value {
+                return false;
+            }
+            a = x.next.as_ref();
+            b = y.next.as_ref();
+        }
+        true
+    }
+}
+
+impl<T: Eq> Eq for List<T> {}
+
+impl<T: PartialOrd> PartialOrd for List<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other.iter())
+    }
+}
+
+impl<T: Ord> Ord for List<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+impl<T: Hash> Hash for List<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(self.len());
+        for value in self {
+            value.hash(state);
+        }
+    }
+}
+
+impl<T: Debug> Debug for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::List;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_push_head_tail() {
+        let empty = List::new();
+        assert!(empty.is_empty());
+        assert_eq!(empty.head(), None);
+        assert!(empty.tail().is_none());
+
+        let one = empty.push_front(1);
+        let two = one.push_front(2);
+        assert_eq!(two.head(), Some(&2));
+        assert_eq!(two.len(), 2);
+        assert!(two.tail().unwrap().ptr_eq(&one));
+        assert_eq!(two.iter().copied().collect::<Vec<_>>(), vec![2, 1]);
+
+        // Older versions are untouched.
+        assert_eq!(one.iter().copied().collect::<Vec<_>>(), vec![1]);
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_structural_sharing() {
+        let base: List<i32> = (0..3).collect();
+        let node = base.head.clone().unwrap();
+        assert_eq!(Arc::strong_count(&node), 2);
+
+        let a = base.push_front(10);
+        let b = base.push_front(20);
+        // Both new versions point at the same node rather than copies of it.
+        assert_eq!(Arc::strong_count(&node), 4);
+        assert!(a.tail().unwrap().ptr_eq(&b.tail().unwrap()));
+
+        let copy = a.clone();
+        assert!(copy.ptr_eq(&a));
+        drop((a, b, copy));
+        assert_eq!(Arc::strong_count(&node), 2);
+        drop(base);
+        assert_eq!(Arc::strong_count(&node), 1);
+    }
+
+    #[test]
+    fn test_from_iter_and_rev() {
+        let list: List<_> = (1..=5).collect();
+        assert_eq!(list.len(), 5);
+        assert_eq!(list.iter().len(), 5);
+        assert_eq!(
+            list.iter().copied().collect::<Vec<_>>(),
+            vec![1, 2, 3, 4, 5]
+        );
+        let rev = list.rev();
+        assert_eq!(rev.iter().copied().collect::<Vec<_>>(), vec![5, 4, 3, 2, 1]);
+        assert_eq!(rev.rev(), list);
+        assert!(List::<i32>::new().rev().is_empty());
+    }
+
+    #[test]
+    fn test_eq_ord() {
+        let a: List<_> = [1, 2, 3].into_iter().collect();
+        let b: List<_> = [1, 2, 3].into_iter().collect();
+        let shorter: List<_> = [1, 2].into_iter().collect();
+        let bigger: List<_> = [1, 3].into_iter().collect();
+
+        assert_eq!(a, b);
+        assert_ne!(a, shorter);
+        assert!(shorter < a);
+        assert!(a < bigger);
+        assert_eq!(a.cmp(&b), std::cmp::Ordering::Equal);
+        // Equal heads in front of a shared tail.
+        assert_eq!(a.push_front(0), a.push_front(0));
+        assert_eq!(format!("{:?}", a), "[1, 2, 3]");
+    }
+
+    #[test]
+    fn test_drop_long_list() {
+        let list: List<u32> = (0..1_000_000).collect();
+        let shared = list.tail().unwrap();
+        drop(list);
+        assert_eq!(shared.len(), 999_999);
+        drop(shared);
+    }
+}
@@ -0,0 +1,10 @@
+//! Immutable collections with structural sharing: every update returns a
+//! new version and old versions stay valid, sharing whatever did not change.
+
+mod hash_map;
+mod list;
+mod vector;
+
+pub use hash_map::HashMap;
+pub use list::List;
+pub use vector::{Builder, Vector};
@@ -0,0 +1,549 @@
+use std::fmt::{self, Debug};
+use std::ops::Index;
+use std::sync::Arc;
+
+const BITS: usize = 5;
+const WIDTH: usize = 1 << BITS;
+const MASK: usize = WIDTH - 1;
+
+enum Node<T> {
+    Branch(Vec<Arc<Node<T>>>),
+    /// Always exactly `WIDTH` values; a partial last chunk lives in the tail.
+    Leaf(Vec<T>),
+}
+
+impl<T> Node<T> {
+    fn children(&self) -> &[Arc<Node<T>>] {
+        match self {
+            Node::Branch(children) => children,
+            Node::Leaf(_) => unreachable!("descended past the leaves"),
+        }
+    }
+
+    fn values(&self) -> &[T] {
+        match self {
+            Node::Leaf(values) => values,
+            Node::Branch(_) => unreachable!("stopped above the leaves"),
+        }
+    }
+}
+
+/// A persistent vector: a 32-way bit-partitioned trie with a tail buffer, as
+/// in Clojure.
+///
+/// Index `i` is found by taking its bits five at a time from the top, so
+/// `get` and `update` touch one node per level (at most seven for a 64-bit
+/// index). Updates copy only the path from the root to the changed leaf and
+/// share everything else with the old version. The last, partially filled
+/// chunk is kept out of the trie in `tail`, so most pushes and pops only copy
+/// that one chunk; the trie is touched once per 32 pushes.
+pub struct Vector<T> {
+    len: usize,
+    /// Bit offset of the root's children: `BITS * (depth - 1)` with the
+    /// leaves at depth 1.
+    shift: usize,
+    root: Arc<Node<T>>,
+    tail: Arc<Vec<T>>,
+}
+
+impl<T: Clone> Vector<T> {
+    pub fn new() -> Self {
+        Self {
+            len: 0,
+            shift: BITS,
+            root: Arc::new(Node::Branch(Vec::new())),
+            tail: Arc::new(Vec::new()),
+        }
+    }
+
+    pub fn builder() -> Builder<T> {
+        Builder { values: Vec::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.len {
+            return None;
+        }
+        Some(&self.chunk_for(index)[index & MASK])
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        self.tail.last()
+    }
+
+    /// A new vector with `value` appended.
+    pub fn push(&self, value: T) -> Self {
+        if self.tail.len() < WIDTH {
+            let mut tail = Vec::with_capacity(self.tail.len() + 1);
+            tail.extend_from_slice(&self.tail);
+            tail.push(value);
+            return Self {
+                len: self.len + 1,
+                shift: self.shift,
+                root: self.root.clone(),
+                tail: Arc::new(tail),
+            };
+        }
+
+        // The tail is full: move it into the trie and start a new one.
+        let leaf = Arc::new(Node::Leaf(self.tail.as_ref().clone()));
+        let (root, shift) = if (self.len >> BITS) > (1 << self.shift) {
+            // No room under the current root; grow a level.
+            let root = Node::Branch(vec![self.root.clone(), new_path(self.shift, leaf)]);
+            (Arc::new(root), self.shift + BITS)
+        } else {
+            (self.push_leaf(self.shift, &self.root, leaf), self.shift)
+        };
+        Self {
+            len: self.len + 1,
+            shift,
+            root,
+            tail: Arc::new(vec![value]),
+        }
+    }
+
+    /// A new vector with the value at `index` replaced, or `None` if `index`
+    /// is out of bounds.
+    pub fn update(&self, index: usize, value: T) -> Option<Self> {
+        if index >= self.len {
+            return None;
+        }
+        if index >= self.tail_offset() {
+            let mut tail = self.tail.as_ref().clone();
+            tail[index & MASK] = value;
+            return Some(Self {
+                tail: Arc::new(tail),
+                ..self.clone()
+            });
+        }
+        Some(Self {
+            root: Self::update_path(self.shift, &self.root, index, value),
+            ..self.clone()
+        })
+    }
+
+    /// A new vector without the last value, or `None` if this one is empty.
+    pub fn pop(&self) -> Option<Self> {
+        match self.len {
+            0 => return None,
+            1 => return Some(Self::new()),
+            _ => {}
+        }
+        if self.tail.len() > 1 {
+            let tail = self.tail[..self.tail.len() - 1].to_vec();
+            return Some(Self {
+                len: self.len - 1,
+                tail: Arc::new(tail),
+                ..self.clone()
+            });
+        }
+
+        // The tail empties: the trie's last leaf becomes the new tail.
+        let tail = self.chunk_for(self.len - 2).to_vec();
+        let mut root = self
+            .pop_leaf(self.shift, &self.root)
+            .unwrap_or_else(|| Arc::new(Node::Branch(Vec::new())));
+        let mut shift = self.shift;
+        if shift > BITS && root.children().len() == 1 {
+            // Drop a level the root no longer needs.
+            root = root.children()[0].clone();
+            shift -= BITS;
+        }
+        Some(Self {
+            len: self.len - 1,
+            shift,
+            root,
+            tail: Arc::new(tail),
+        })
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            vector: self,
+            index: 0,
+            chunk: &[],
+        }
+    }
+
+    /// Start index of the tail, i.e. the number of values in the trie.
+    fn tail_offset(&self) -> usize {
+        self.len - self.tail.len()
+    }
+
+    /// The leaf or tail holding `index`.
+    fn chunk_for(&self, index: usize) -> &[T] {
+        if index >= self.tail_offset() {
+            return &self.tail;
+        }
+        let mut node = &self.root;
+        let mut level = self.shift;
+        while level > 0 {
+            node = &node.children()[(index >> level) & MASK];
+            level -= BITS;
+        }
+        node.values()
+    }
+
+    /// Copies the rightmost path below `node` and hangs `leaf` off it.
+    fn push_leaf(&self, level: usize, node: &Node<T>, leaf: Arc<Node<T>>) -> Arc<Node<T>> {
+        // Index of the tail's first value, which is where the leaf goes.
+        let index = ((self.len - 1) >> level) & MASK;
+        let mut children = node.children().to_vec();
+        let child = if level == BITS {
+            leaf
+        } else if let Some(existing) = children.get(index) {
+            self.push_leaf(level - BITS, existing, leaf)
+        } else {
+            new_path(level - BITS, leaf)
+        };
+        if index < children.len() {
+            children[index] = child;
+        } else {
+            children.push(child);
+        }
+        Arc::new(Node::Branch(children))
+    }
+
+    fn update_path(level: usize, node: &Node<T>, index: usize, value: T) -> Arc<Node<T>> {
+        if level == 0 {
+            let mut values = node.values().to_vec();
+            values[index & MASK] = value;
+            return Arc::new(Node::Leaf(values));
+        }
+        let mut children = node.children().to_vec();
+        let slot = (index >> level) & MASK;
+        children[slot] = Self::update_path(level - BITS, &children[slot], index, value);
+        Arc::new(Node::Branch(children))
+    }
+
+    /// Copies the rightmost path below `node` without its last leaf; `None`
+    /// if that leaves the node empty.
+    fn pop_leaf(&self, level: usize, node: &Node<T>) -> Option<Arc<Node<T>>> {
+        // `len - 2` becomes the last index; its leaf is the one leaving.
+        let index = ((self.len - 2) >> level) & MASK;
+        let mut children = node.children()[..=index].to_vec();
+        if level > BITS {
+            match self.pop_leaf(level - BITS, &children[index]) {
+                Some(child) => children[index] = child,
+                None => {
+                    children.pop();
+                }
+            }
+        } else {
+            children.pop();
+        }
+        if children.is_empty() {
+            None
+        } else {
+            Some(Arc::new(Node::Branch(children)))
+        }
+    }
+}
+
+fn new_path<T>(level: usize, leaf: Arc<Node<T>>) -> Arc<Node<T>> {
+    if level == 0 {
+        leaf
+    } else {
+        Arc::new(Node::Branch(vec![new_path(level - BITS, leaf)]))
+    }
+}
+
+impl<T> Clone for Vector<T> {
+    fn clone(&self) -> Self {
+        Self {
+            len: self.len,
+            shift: self.shift,
+            root: self.root.clone(),
+            tail: self.tail.clone(),
+        }
+    }
+}
+
+impl<T: Clone> Default for Vector<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone> Index<usize> for Vector<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        match self.get(index) {
+            Some(value) => value,
+            None => panic!("index {index} out of bounds for length {}", self.len),
+        }
+    }
+}
+
+impl<T: Clone> FromIterator<T> for Vector<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut builder = Self::builder();
+        builder.extend(iter);
+        builder.build()
+    }
+}
+
+impl<T: Clone + PartialEq> PartialEq for Vector<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Clone + Eq> Eq for Vector<T> {}
+
+impl<T: Clone + Debug> Debug for Vector<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+pub struct Iter<'a, T> {
+    vector: &'a Vector<T>,
+    index: usize,
+    /// The rest of the current leaf, refilled once per chunk.
+    chunk: &'a [T],
+}
+
+impl<'a, T: Clone> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.chunk.is_empty() {
+            if self.index >= self.vector.len {
+                return None;
+            }
+            self.chunk = &self.vector.chunk_for(self.index)[self.index & MASK..];
+        }
+        let (first, rest) = self.chunk.split_first()?;
+        self.chunk = rest;
+        self.index += 1;
+        Some(first)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.vector.len - self.index;
+        (left, Some(left))
+    }
+}
+
+impl<T: Clone> ExactSizeIterator for Iter<'_, T> {}
+
+impl<'a, T: Clone> IntoIterator for &'a Vector<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Bulk construction for [`Vector`]. Values are buffered flat and the trie is
+/// built bottom-up in one pass by [`build`](Builder::build), instead of
+/// copying a tail or path per push.
+pub struct Builder<T> {
+    values: Vec<T>,
+}
+
+impl<T: Clone> Builder<T> {
+    pub fn push(&mut self, value: T) {
+        self.values.push(value);
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Freezes the values into a persistent vector with exactly the shape
+    /// repeated `push` would have produced.
+    pub fn build(mut self) -> Vector<T> {
+        let len = self.values.len();
+        if len == 0 {
+            return Vector::new();
+        }
+        // Same split as `push`: the last 1..=32 values form the tail.
+        let tail = self.values.split_off((len - 1) & !MASK);
+
+        let mut values = self.values.into_iter();
+        let mut nodes: Vec<Arc<Node<T>>> = Vec::new();
+        while values.len() > 0 {
+            nodes.push(Arc::new(Node::Leaf(values.by_ref().take(WIDTH).collect())));
+        }
+
+        let mut shift = BITS;
+        let root = loop {
+            let mut parents = Vec::with_capacity(nodes.len().div_ceil(WIDTH));
+            let mut children = nodes.into_iter();
+            loop {
+                let branch: Vec<_> = children.by_ref().take(WIDTH).collect();
+                if branch.is_empty() {
+                    break;
+                }
+                parents.push(Arc::new(Node::Branch(branch)));
+            }
+            match parents.len() {
+                0 => break Arc::new(Node::Branch(Vec::new())),
+                1 => break parents.pop().unwrap(),
+                _ => {
+                    nodes = parents;
+                    shift += BITS;
+                }
+            }
+        };
+
+        Vector {
+            len,
+            shift,
+            root,
+            tail: Arc::new(tail),
+        }
+    }
+}
+
+impl<T: Clone> Extend<T> for Builder<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.values.extend(iter);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Vector;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::sync::Arc;
+
+    fn check(vector: &Vector<usize>, model: &[usize]) {
+        assert_eq!(vector.len(), model.len());
+        assert_eq!(vector.last(), model.last());
+        for (i, expected) in model.iter().enumerate() {
+            assert_eq!(
+                vector.get(i),
+                Some(expected),
+                "index {i} of {}",
+                model.len()
+            );
+        }
+        assert_eq!(vector.get(model.len()), None);
+        assert!(vector.iter().eq(model.iter()));
+    }
+
+    #[test]
+    fn test_boundaries() {
+        let mut vector = Vector::new();
+        let mut model = Vec::new();
+        for i in 0..33_000 {
+            vector = vector.push(i);
+            model.push(i);
+            if [31, 32, 33, 1023, 1024, 1025, 1056, 1057, 32_800, 32_801].contains(&(i + 1)) {
+                check(&vector, &model);
+            }
+        }
+        check(&vector, &model);
+
+        // And back down across the same boundaries.
+        while let Some(popped) = vector.pop() {
+            vector = popped;
+            model.pop();
+            if [32_800, 1056, 1025, 1024, 1023, 33, 32, 31, 0].contains(&model.len()) {
+                check(&vector, &model);
+            }
+        }
+        assert!(model.is_empty());
+        assert!(Vector::<usize>::new().pop().is_none());
+    }
+
+    #[test]
+    fn test_builder_matches_push() {
+        for len in [0, 1, 31, 32, 33, 64, 1023, 1024, 1025, 1056, 1057, 33_000] {
+            let built: Vector<usize> = (0..len).collect();
+            let pushed = (0..len).fold(Vector::new(), |v, i| v.push(i));
+            check(&built, &(0..len).collect::<Vec<_>>());
+            assert_eq!(built, pushed);
+            assert_eq!(built.shift, pushed.shift, "len {len}");
+
+            // Same shape, so it keeps working as a starting point.
+            let more = (len..len + 40).fold(built, |v, i| v.push(i));
+            check(&more, &(0..len + 40).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_update() {
+        let vector: Vector<usize> = (0..100).collect();
+        let updated = vector.update(5, 500).unwrap();
+        let updated = updated.update(99, 9900).unwrap();
+        assert_eq!(updated[5], 500);
+        assert_eq!(updated[99], 9900);
+        assert_eq!(vector[5], 5);
+        assert_eq!(vector[99], 99);
+        assert!(vector.update(100, 0).is_none());
+    }
+
+    #[test]
+    fn test_structural_sharing() {
+        let vector: Vector<usize> = (0..2000).collect();
+        let updated = vector.update(0, 1).unwrap();
+        // Only the path to index 0 is copied; the root's other children and
+        // the tail are the same nodes.
+        let (old, new) = (vector.root.children(), updated.root.children());
+        assert!(!Arc::ptr_eq(&old[0], &new[0]));
+        assert!(old[1..]
+            .iter()
+            .zip(&new[1..])
+            .all(|(a, b)| Arc::ptr_eq(a, b)));
+        assert!(Arc::ptr_eq(&vector.tail, &updated.tail));
+
+        let pushed = vector.push(2000);
+        assert!(Arc::ptr_eq(&vector.root, &pushed.root));
+    }
+
+    #[test]
+    fn test_retained_versions_differential() {
+        let mut rng = StdRng::seed_from_u64(225);
+        let mut vector = Vector::new();
+        let mut model: Vec<usize> = Vec::new();
+        let mut history = Vec::new();
+
+        for step in 0..20_000 {
+            match rng.gen_range(0..10) {
+                0..=5 => {
+                    vector = vector.push(step);
+                    model.push(step);
+                }
+                6 | 7 if !model.is_empty() => {
+                    let i = rng.gen_range(0..model.len());
+                    vector = vector.update(i, step).unwrap();
+                    model[i] = step;
+                }
+                _ => {
+                    if let Some(popped) = vector.pop() {
+                        vector = popped;
+                        model.pop();
+                    }
+                }
+            }
+            if step % 500 == 0 {
+                history.push((vector.clone(), model.clone()));
+            }
+        }
+        history.push((vector, model));
+
+        // Every retained version still reads exactly as it did when saved.
+        for (vector, model) in &history {
+            check(vector, model);
+        }
+    }
+}
@@ -0,0 +1,289 @@
+use std::collections::BTreeMap;
+use std::ops::{Add, Range, Sub};
+
+/// A set of values stored as disjoint half-open ranges.
+///
+/// Stored ranges never overlap and never touch: inserting `0..5` and then
+/// `5..10` leaves the single range `0..10`. Ranges are kept in a `BTreeMap`
+/// from start to end, so every operation is O(log n) plus the number of
+/// stored ranges it merges or cuts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeSet<T> {
+    ranges: BTreeMap<T, T>,
+}
+
+impl<T: Ord + Copy> RangeSet<T> {
+    pub fn new() -> Self {
+        Self {
+            ranges: BTreeMap::new(),
+        }
+    }
+
+    /// Adds every value in `range`, merging with any stored range it
+    /// overlaps or touches. Empty ranges are ignored.
+    pub fn insert(&mut self, range: Range<T>) {
+        if range.is_empty() {
+            return;
+        }
+        let (mut start, mut end) = (range.start, range.end);
+        // Ranges starting at or before `end` and ending at or after `start`
+        // overlap or touch the new one.
+        let touching: Vec<(T, T)> = self
+            .ranges
+            .range(..=end)
+            .rev()
+            .take_while(|(_, &e)| e >= start)
+            .map(|(&s, &e)| (s, e))
+            .collect();
+        for (s, e) in touching {
+            self.ranges.remove(&s);
+            start = start.min(s);
+            end = end.max(e);
+        }
+        self.ranges.insert(start, end);
+    }
+
+    /// Removes every value in `range`, trimming or splitting stored ranges
+    /// that straddle its ends.
+    pub fn remove(&mut self, range: Range<T>) {
+        if range.is_empty() {
+            return;
+        }
+        let overlapping: Vec<(T, T)> = self
+            .ranges
+            .range(..range.end)
+            .rev()
+            .take_while(|(_, &e)| e > range.start)
+            .map(|(&s, &e)| (s, e))
+            .collect();
+        for (s, e) in overlapping {
+            self.ranges.remove(&s);
+            // A stored range strictly containing `range` contributes both.
+            if s < range.start {
+                self.ranges.insert(s, range.start);
+            }
+            if e > range.end {
+                self.ranges.insert(range.end, e);
+            }
+        }
+    }
+
+    pub fn contains(&self, value: T) -> bool {
+        self.ranges
+            .range(..=value)
+            .next_back()
+            .is_some_and(|(_, &end)| value < end)
+    }
+
+    /// Whether every value of `range` is in the set. True for empty ranges.
+    pub fn contains_range(&self, range: Range<T>) -> bool {
+        if range.is_empty() {
+            return true;
+        }
+        self.ranges
+            .range(..=range.start)
+            .next_back()
+            .is_some_and(|(_, &end)| range.end <= end)
+    }
+
+    /// The maximal ranges inside `bounds` that are not in the set, in order.
+    pub fn gaps(&self, bounds: Range<T>) -> impl Iterator<Item = Range<T>> + '_ {
+        let Range { start, end } = bounds;
+        let mut done = start >= end;
+        // Start from the stored range containing `start`, if any.
+        let first = if done {
+            end
+        } else {
+            self.ranges
+                .range(..=start)
+                .next_back()
+                .map_or(start, |(&s, _)| s)
+        };
+        let mut cursor = start;
+        self.ranges
+            .range(first..end)
+            .map(|(&s, &e)| Some((s, e)))
+            .chain(std::iter::once(None))
+            .filter_map(move |stored| {
+                if done {
+                    return None;
+                }
+                let gap = match stored {
+                    Some((s, e)) => {
+                        let gap = cursor..s.min(end);
+                        cursor = cursor.max(e);
+                        if cursor >= end {
+                            done = true;
+                        }
+                        gap
+                    }
+                    None => {
+                        done = true;
+                        cursor..end
+                    }
+                };
+                (!gap.is_empty()).then_some(gap)
+            })
+    }
+
+    /// The stored ranges in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = Range<T>> + '_ {
+        self.ranges.iter().map(|(&start, &end)| start..end)
+    }
+
+    /// Number of stored (normalized) ranges.
+    pub fn range_count(&self) -> usize {
+        self.ranges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.ranges.clear();
+    }
+
+    /// Number of values covered, for integer-like `T`. The sum is computed
+    /// in `T`, so it must fit in it.
+    pub fn len(&self) -> T
+    where
+        T: Add<Output = T> + Sub<Output = T> + Default,
+    {
+        self.ranges
+            .iter()
+            .fold(T::default(), |total, (&start, &end)| total + (end - start))
+    }
+}
+
+impl<T: Ord + Copy> Default for RangeSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord + Copy> FromIterator<Range<T>> for RangeSet<T> {
+    fn from_iter<I: IntoIterator<Item = Range<T>>>(iter: I) -> Self {
+        let mut set = Self::new();
+        for range in iter {
+            set.insert(range);
+        }
+        set
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RangeSet;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::BTreeSet;
+
+    fn ranges(set: &RangeSet<u32>) -> Vec<(u32, u32)> {
+        set.iter().map(|r| (r.start, r.end)).collect()
+    }
+
+    #[test]
+    fn test_adjacent_ranges_merge() {
+        let mut set = RangeSet::new();
+        set.insert(0..5);
+        set.insert(5..10);
+        assert_eq!(ranges(&set), vec![(0, 10)]);
+
+        set.insert(20..30);
+        set.insert(12..14);
+        // Bridges 10..20 by touching both neighbours.
+        set.insert(10..20);
+        assert_eq!(ranges(&set), vec![(0, 30)]);
+        set.insert(3..3);
+        assert_eq!(set.range_count(), 1);
+        assert_eq!(set.len(), 30);
+    }
+
+    #[test]
+    fn test_remove_splits() {
+        let mut set = RangeSet::new();
+        set.insert(0..100);
+        set.remove(40..60);
+        assert_eq!(ranges(&set), vec![(0, 40), (60, 100)]);
+        set.remove(30..70);
+        assert_eq!(ranges(&set), vec![(0, 30), (70, 100)]);
+        set.remove(0..30);
+        set.remove(90..200);
+        assert_eq!(ranges(&set), vec![(70, 90)]);
+        set.remove(0..1000);
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn test_contains() {
+        let set: RangeSet<u32> = [2..5, 8..10].into_iter().collect();
+        assert!(!set.contains(1));
+        assert!(set.contains(2));
+        assert!(set.contains(4));
+        assert!(!set.contains(5));
+        assert!(set.contains_range(2..5));
+        assert!(set.contains_range(3..4));
+        assert!(!set.contains_range(4..9));
+        assert!(set.contains_range(7..7));
+    }
+
+    #[test]
+    fn test_gaps() {
+        let set: RangeSet<u32> = [2..5, 8..10, 15..20].into_iter().collect();
+        let gaps = |bounds| {
+            set.gaps(bounds)
+                .map(|r| (r.start, r.end))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(gaps(0..25), vec![(0, 2), (5, 8), (10, 15), (20, 25)]);
+        // Bounds starting or ending inside stored ranges.
+        assert_eq!(gaps(3..17), vec![(5, 8), (10, 15)]);
+        assert_eq!(gaps(8..10), vec![]);
+        assert_eq!(gaps(11..13), vec![(11, 13)]);
+        assert_eq!(gaps(5..5), vec![]);
+        let (hi, lo) = (17, 3);
+        assert_eq!(gaps(hi..lo), vec![]);
+        assert_eq!(
+            RangeSet::<u32>::new().gaps(1..4).collect::<Vec<_>>(),
+            vec![1..4]
+        );
+    }
+
+    #[test]
+    fn test_differential() {
+        const DOMAIN: u32 = 64;
+        let mut rng = StdRng::seed_from_u64(227);
+        for _ in 0..200 {
+            let mut set = RangeSet::new();
+            let mut model = BTreeSet::new();
+            for _ in 0..50 {
+                let a = rng.gen_range(0..=DOMAIN);
+                let b = rng.gen_range(0..=DOMAIN);
+                let range = a.min(b)..a.max(b);
+                if rng.gen_bool(0.6) {
+                    set.insert(range.clone());
+                    model.extend(range);
+                } else {
+                    set.remove(range.clone());
+                    for v in range {
+                        model.remove(&v);
+                    }
+                }
+
+                assert_eq!(set.len() as usize, model.len());
+                for v in 0..DOMAIN {
+                    assert_eq!(set.contains(v), model.contains(&v));
+                }
+                // Normalized: sorted, non-empty, and neither overlapping nor
+                // touching.
+                let stored = ranges(&set);
+                assert!(stored.iter().all(|(s, e)| s < e));
+                assert!(stored.windows(2).all(|w| w[0].1 < w[1].0));
+                // Gaps are exactly the complement.
+                let gaps: BTreeSet<u32> = set.gaps(0..DOMAIN).flatten().collect();
+                assert_eq!(gaps, (0..DOMAIN).filter(|v| !model.contains(v)).collect());
+            }
+        }
+    }
+}
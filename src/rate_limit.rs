@@ -0,0 +1,473 @@
+use crate::clock::{Clock, SystemClock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Token amounts are kept in nano-tokens, so refilling for one nanosecond at
+/// `rate` tokens per second adds exactly `rate` units and no fraction of a
+/// token is ever rounded away.
+const SCALE: u128 = 1_000_000_000;
+
+/// Nanoseconds until `missing` nano-tokens accrue at `rate` tokens per second.
+fn wait_for(missing: u128, rate: u64) -> Duration {
+    if missing == 0 {
+        return Duration::ZERO;
+    }
+    if rate == 0 {
+        return Duration::MAX;
+    }
+    let nanos = missing.div_ceil(rate as u128);
+    u64::try_from(nanos).map_or(Duration::MAX, Duration::from_nanos)
+}
+
+/// A token bucket: holds up to `capacity` tokens and gains `rate` of them per
+/// second, so bursts of up to `capacity` pass and the long-run average is
+/// held to `rate`.
+///
+/// Starts full. All accounting is integer, so with a
+/// [`MockClock`](crate::clock::MockClock) the number of grants is exact.
+pub struct TokenBucket<C = SystemClock> {
+    capacity: u64,
+    rate: u64,
+    /// Current tokens in nano-tokens.
+    tokens: u128,
+    last_refill: Instant,
+    clock: C,
+}
+
+impl TokenBucket {
+    pub fn new(capacity: u64, rate: u64) -> Self {
+        Self::with_clock(capacity, rate, SystemClock)
+    }
+}
+
+impl<C: Clock> TokenBucket<C> {
+    pub fn with_clock(capacity: u64, rate: u64, clock: C) -> Self {
+        Self {
+            capacity,
+            rate,
+            tokens: capacity as u128 * SCALE,
+            last_refill: clock.now(),
+            clock,
+        }
+    }
+
+    /// Takes `n` tokens if they are all there; takes nothing otherwise.
+    pub fn try_acquire(&mut self, n: u64) -> bool {
+        self.refill();
+        let wanted = n as u128 * SCALE;
+        if wanted > self.tokens {
+            return false;
+        }
+        self.tokens -= wanted;
+        true
+    }
+
+    /// How long until `try_acquire(n)` would succeed, without taking
+    /// anything. [`Duration::MAX`] if it never can, because `n` exceeds the
+    /// capacity or the rate is zero.
+    pub fn acquire_delay(&mut self, n: u64) -> Duration {
+        if n > self.capacity {
+            return Duration::MAX;
+        }
+        self.refill();
+        wait_for((n as u128 * SCALE).saturating_sub(self.tokens), self.rate)
+    }
+
+    /// Whole tokens available right now.
+    pub fn available(&mut self) -> u64 {
+        self.refill();
+        (self.tokens / SCALE) as u64
+    }
+
+    fn refill(&mut self) {
+        let now = self.clock.now();
+        let elapsed = now.saturating_duration_since(self.last_refill).as_nanos();
+        let earned = elapsed.saturating_mul(self.rate as u128);
+        let full = self.capacity as u128 * SCALE;
+        self.tokens = self.tokens.saturating_add(earned).min(full);
+        self.last_refill = self.last_refill.max(now);
+    }
+}
+
+/// A leaky bucket used as a meter: every acquisition pours `n` units in, the
+/// bucket drains at `rate` units per second, and an acquisition that would
+/// overflow `capacity` is refused.
+///
+/// It admits exactly what a [`TokenBucket`] does; the level counts used
+/// capacity instead of remaining tokens. Starts empty.
+pub struct LeakyBucket<C = SystemClock> {
+    capacity: u64,
+    rate: u64,
+    /// Current level in nano-units.
+    level: u128,
+    last_leak: Instant,
+    clock: C,
+}
+
+impl LeakyBucket {
+    pub fn new(capacity: u64, rate: u64) -> Self {
+        Self::with_clock(capacity, rate, SystemClock)
+    }
+}
+
+impl<C: Clock> LeakyBucket<C> {
+    pub fn with_clock(capacity: u64, rate: u64, clock: C) -> Self {
+        Self {
+            capacity,
+            rate,
+            level: 0,
+            last_leak: clock.now(),
+            clock,
+        }
+    }
+
+    /// Pours `n` units in if they fit; pours nothing otherwise.
+    pub fn try_acquire(&mut self, n: u64) -> bool {
+        self.leak();
+        let level = self.level + n as u128 * SCALE;
+        if level > self.capacity as u128 * SCALE {
+            return false;
+        }
+        self.level = level;
+        true
+    }
+
+    /// How long until `try_acquire(n)` would succeed, without pouring
+    /// anything. [`Duration::MAX`] if it never can, because `n` exceeds the
+    /// capacity or the rate is zero.
+    pub fn acquire_delay(&mut self, n: u64) -> Duration {
+        if n > self.capacity {
+            return Duration::MAX;
+        }
+        self.leak();
+        let level = self.level + n as u128 * SCALE;
+        wait_for(
+            level.saturating_sub(self.capacity as u128 * SCALE),
+            self.rate,
+        )
+    }
+
+    /// Whole units currently in the bucket, rounded up.
+    pub fn level(&mut self) -> u64 {
+        self.leak();
+        self.level.div_ceil(SCALE) as u64
+    }
+
+    fn leak(&mut self) {
+        let now = self.clock.now();
+        let elapsed = now.saturating_duration_since(self.last_leak).as_nanos();
+        let drained = elapsed.saturating_mul(self.rate as u128);
+        self.level = self.level.saturating_sub(drained);
+        self.last_leak = self.last_leak.max(now);
+    }
+}
+
+const TIME_BITS: u32 = 40;
+const TIME_MASK: u64 = (1 << TIME_BITS) - 1;
+
+/// A [`TokenBucket`] that can be shared between threads.
+///
+/// The whole state is one `AtomicU64` updated by compare-and-swap: whole
+/// tokens in the top 24 bits and the last refill time, in microseconds since
+/// creation, in the low 40. So the capacity is limited to 2^24 - 1 tokens.
+///
+/// Fractions of a token are kept by moving the refill time forward only by
+/// the time the whole tokens it granted took to earn. The time field wraps
+/// after about 12.7 days, and gaps over half of that are taken to be a
+/// reading from just before another thread's refill; so an idle gap of more
+/// than about 6.4 days can refill too little, never too much.
+pub struct AtomicTokenBucket<C = SystemClock> {
+    capacity: u64,
+    rate: u64,
+    state: AtomicU64,
+    origin: Instant,
+    clock: C,
+}
+
+impl AtomicTokenBucket {
+    pub fn new(capacity: u64, rate: u64) -> Self {
+        Self::with_clock(capacity, rate, SystemClock)
+    }
+}
+
+impl<C: Clock> AtomicTokenBucket<C> {
+    /// The largest supported capacity.
+    pub const MAX_CAPACITY: u64 = (1 << (64 - TIME_BITS)) - 1;
+
+    /// # Panics
+    ///
+    /// Panics if `capacity` exceeds [`MAX_CAPACITY`](Self::MAX_CAPACITY).
+    pub fn with_clock(capacity: u64, rate: u64, clock: C) -> Self {
+        assert!(
+            capacity <= Self::MAX_CAPACITY,
+            "capacity {capacity} exceeds {}",
+            Self::MAX_CAPACITY
+        );
+        Self {
+            capacity,
+            rate,
+            state: AtomicU64::new(pack(capacity, 0)),
+            origin: clock.now(),
+            clock,
+        }
+    }
+
+    pub fn try_acquire(&self, n: u64) -> bool {
+        let now = self.now_micros();
+        let mut current = self.state.load(Ordering::Acquire);
+        loop {
+            let (tokens, last) = self.refilled(current, now);
+            if tokens < n {
+                return false;
+            }
+            match self.state.compare_exchange_weak(
+                current,
+                pack(tokens - n, last),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return true,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    /// How long until `try_acquire(n)` would succeed, assuming no other
+    /// thread takes tokens meanwhile. [`Duration::MAX`] if it never can.
+    pub fn acquire_delay(&self, n: u64) -> Duration {
+        if n > self.capacity {
+            return Duration::MAX;
+        }
+        let now = self.now_micros();
+        let (tokens, last) = self.refilled(self.state.load(Ordering::Acquire), now);
+        // The partial token already earned since `last` counts toward it.
+        let partial = match now.wrapping_sub(last) & TIME_MASK {
+            elapsed if elapsed > TIME_MASK / 2 => 0,
+            elapsed => elapsed as u128 * 1_000 * self.rate as u128,
+        };
+        let missing = (n.saturating_sub(tokens) as u128 * SCALE).saturating_sub(partial);
+        wait_for(missing, self.rate)
+    }
+
+    /// Whole tokens available right now.
+    pub fn available(&self) -> u64 {
+        self.refilled(self.state.load(Ordering::Acquire), self.now_micros())
+            .0
+    }
+
+    /// The state as of `now`: tokens after refilling, and the refill time
+    /// with any unspent fraction of a token still ahead of it.
+    fn refilled(&self, state: u64, now: u64) -> (u64, u64) {
+        let (tokens, last) = unpack(state);
+        let elapsed = now.wrapping_sub(last) & TIME_MASK;
+        // A thread that read the clock before another one refilled sees its
+        // `now` slightly behind `last`, which wraps to a huge gap.
+        if elapsed > TIME_MASK / 2 {
+            return (tokens, last);
+        }
+        let earned = elapsed as u128 * self.rate as u128 / 1_000_000;
+        let room = (self.capacity - tokens) as u128;
+        if earned >= room {
+            return (self.capacity, now);
+        }
+        // Rounded up, so the kept fraction is never more than was earned.
+        let spent = (earned * 1_000_000).div_ceil(self.rate.max(1) as u128) as u64;
+        (tokens + earned as u64, last.wrapping_add(spent) & TIME_MASK)
+    }
+
+    fn now_micros(&self) -> u64 {
+        let elapsed = self.clock.now().saturating_duration_since(self.origin);
+        elapsed.as_micros() as u64 & TIME_MASK
+    }
+}
+
+fn pack(tokens: u64, time: u64) -> u64 {
+    (tokens << TIME_BITS) | time
+}
+
+fn unpack(state: u64) -> (u64, u64) {
+    (state >> TIME_BITS, state & TIME_MASK)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AtomicTokenBucket, LeakyBucket, TokenBucket};
+    use crate::clock::MockClock;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Barrier;
+    use std::thread;
+    use std::time::Duration;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn test_token_accounting() {
+        let clock = MockClock::new();
+        let mut bucket = TokenBucket::with_clock(10, 4, &clock);
+        assert!(bucket.try_acquire(6));
+        assert_eq!(bucket.available(), 4);
+        assert!(!bucket.try_acquire(5));
+        // A refused acquisition takes nothing.
+        assert!(bucket.try_acquire(4));
+        assert_eq!(bucket.available(), 0);
+
+        clock.advance(SECOND);
+        assert_eq!(bucket.available(), 4);
+        clock.advance(SECOND * 10);
+        // Refills stop at capacity.
+        assert_eq!(bucket.available(), 10);
+        assert!(bucket.try_acquire(10));
+        assert!(!bucket.try_acquire(1));
+    }
+
+    #[test]
+    fn test_burst_then_throttle() {
+        let clock = MockClock::new();
+        let mut bucket = TokenBucket::with_clock(5, 10, &clock);
+        let granted = (0..100).filter(|_| bucket.try_acquire(1)).count();
+        assert_eq!(granted, 5);
+
+        // Afterwards exactly one token per 100ms.
+        let mut granted = 0;
+        for _ in 0..1_000 {
+            clock.advance(Duration::from_millis(10));
+            granted += (0..3).filter(|_| bucket.try_acquire(1)).count();
+        }
+        assert_eq!(granted, 100);
+    }
+
+    #[test]
+    fn test_fractional_refill() {
+        let clock = MockClock::new();
+        let mut bucket = TokenBucket::with_clock(3, 3, &clock);
+        assert!(bucket.try_acquire(3));
+        // A third of a second earns one token; each step earns a ninth.
+        for _ in 0..2 {
+            clock.advance(SECOND / 9);
+            assert!(!bucket.try_acquire(1));
+        }
+        clock.advance(SECOND / 9);
+        // 3 * (SECOND / 9) is 1ns short of a third of a second.
+        assert!(!bucket.try_acquire(1));
+        assert_eq!(bucket.acquire_delay(1), Duration::from_nanos(1));
+        clock.advance(Duration::from_nanos(1));
+        assert!(bucket.try_acquire(1));
+    }
+
+    #[test]
+    fn test_acquire_delay() {
+        let clock = MockClock::new();
+        let mut bucket = TokenBucket::with_clock(10, 2, &clock);
+        assert_eq!(bucket.acquire_delay(10), Duration::ZERO);
+        assert!(bucket.try_acquire(10));
+        assert_eq!(bucket.acquire_delay(1), SECOND / 2);
+        assert_eq!(bucket.acquire_delay(4), SECOND * 2);
+        assert_eq!(bucket.acquire_delay(11), Duration::MAX);
+        clock.advance(bucket.acquire_delay(4));
+        assert!(bucket.try_acquire(4));
+
+        let mut stalled = TokenBucket::with_clock(1, 0, &clock);
+        assert!(stalled.try_acquire(1));
+        assert_eq!(stalled.acquire_delay(1), Duration::MAX);
+    }
+
+    #[test]
+    fn test_leaky_bucket() {
+        let clock = MockClock::new();
+        let mut bucket = LeakyBucket::with_clock(5, 10, &clock);
+        let granted = (0..100).filter(|_| bucket.try_acquire(1)).count();
+        assert_eq!(granted, 5);
+        assert_eq!(bucket.level(), 5);
+        assert_eq!(bucket.acquire_delay(1), Duration::from_millis(100));
+        assert_eq!(bucket.acquire_delay(5), Duration::from_millis(500));
+        assert_eq!(bucket.acquire_delay(6), Duration::MAX);
+
+        clock.advance(Duration::from_millis(250));
+        // Two and a half units drained; the level rounds up.
+        assert_eq!(bucket.level(), 3);
+        assert!(bucket.try_acquire(2));
+        assert!(!bucket.try_acquire(1));
+        clock.advance(Duration::from_millis(50));
+        assert!(bucket.try_acquire(1));
+        clock.advance(SECOND * 10);
+        assert_eq!(bucket.level(), 0);
+    }
+
+    #[test]
+    fn test_leaky_matches_token_bucket() {
+        let clock = MockClock::new();
+        let mut tokens = TokenBucket::with_clock(7, 3, &clock);
+        let mut leaky = LeakyBucket::with_clock(7, 3, &clock);
+        for step in 0..2_000u64 {
+            clock.advance(Duration::from_millis(step * 37 % 101));
+            let n = step % 4;
+            assert_eq!(tokens.try_acquire(n), leaky.try_acquire(n));
+            assert_eq!(tokens.acquire_delay(n), leaky.acquire_delay(n));
+        }
+    }
+
+    #[test]
+    fn test_atomic_accounting() {
+        let clock = MockClock::new();
+        let bucket = AtomicTokenBucket::with_clock(10, 4, &clock);
+        assert!(bucket.try_acquire(6));
+        assert!(!bucket.try_acquire(5));
+        assert!(bucket.try_acquire(4));
+        assert_eq!(bucket.acquire_delay(1), SECOND / 4);
+
+        // Fractions carry over between refills.
+        for _ in 0..3 {
+            clock.advance(SECOND / 8);
+            assert!(!bucket.try_acquire(2));
+        }
+        assert_eq!(bucket.available(), 1);
+        assert_eq!(bucket.acquire_delay(2), SECOND / 8);
+        clock.advance(SECOND / 8);
+        assert!(bucket.try_acquire(2));
+
+        clock.advance(SECOND * 100);
+        assert_eq!(bucket.available(), 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "exceeds")]
+    fn test_atomic_capacity_limit() {
+        AtomicTokenBucket::new(1 << 24, 1);
+    }
+
+    #[test]
+    fn test_atomic_concurrent_grants_bounded() {
+        const CAPACITY: u64 = 50;
+        const RATE: u64 = 1_000;
+        let clock = MockClock::new();
+        let bucket = AtomicTokenBucket::with_clock(CAPACITY, RATE, &clock);
+        let granted = AtomicU64::new(0);
+        let barrier = Barrier::new(5);
+        let steps = 200u64;
+        let step = Duration::from_millis(3);
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    barrier.wait();
+                    for _ in 0..20_000 {
+                        if bucket.try_acquire(1) {
+                            granted.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                });
+            }
+            barrier.wait();
+            for _ in 0..steps {
+                clock.advance(step);
+                thread::yield_now();
+            }
+        });
+
+        let elapsed = clock.elapsed().as_secs_f64();
+        let bound = CAPACITY + (RATE as f64 * elapsed) as u64;
+        let total = granted.load(Ordering::Relaxed) + bucket.available();
+        assert!(total <= bound, "{total} > {bound}");
+        assert_eq!(clock.elapsed(), step * steps as u32);
+    }
+}
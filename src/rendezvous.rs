@@ -0,0 +1,222 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// Maps keys onto a set of nodes. Implemented by every placement strategy
+/// so callers can swap them, including behind a `dyn NodeSelector<N>`.
+pub trait NodeSelector<N> {
+    /// The node owning `key`, or `None` when there are no nodes.
+    fn get(&self, key: &[u8]) -> Option<&N>;
+
+    /// Up to `n` distinct nodes for `key`, most preferred first.
+    fn get_n(&self, key: &[u8], n: usize) -> Vec<&N>;
+}
+
+/// Rendezvous (highest random weight) hashing.
+///
+/// Every node gets a pseudo-random score per key and the key belongs to the
+/// highest-scoring node. Removing a node therefore only moves the keys it
+/// owned. Weights use the logarithmic method: with `h` the key/node hash
+/// mapped into (0, 1), the score is `-weight / ln(h)`, which gives each node
+/// a share of keys proportional to its weight.
+pub struct RendezvousHasher<N> {
+    nodes: Vec<(N, f64)>,
+}
+
+impl<N> RendezvousHasher<N>
+where
+    N: Hash + Eq,
+{
+    pub fn new() -> Self {
+        Self { nodes: Vec::new() }
+    }
+
+    pub fn add_node(&mut self, node: N) {
+        self.add_weighted_node(node, 1.0);
+    }
+
+    /// Adds `node` with the given relative weight, replacing the weight if
+    /// the node is already present.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `weight` is not finite and strictly positive.
+    pub fn add_weighted_node(&mut self, node: N, weight: f64) {
+        assert!(
+            weight.is_finite() && weight > 0.0,
+            "node weight must be finite and positive, got {weight}"
+        );
+        match self.nodes.iter_mut().find(|(n, _)| *n == node) {
+            Some(entry) => entry.1 = weight,
+            None => self.nodes.push((node, weight)),
+        }
+    }
+
+    pub fn remove_node(&mut self, node: &N) -> bool {
+        let len = self.nodes.len();
+        self.nodes.retain(|(n, _)| n != node);
+        self.nodes.len() != len
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &N> {
+        self.nodes.iter().map(|(n, _)| n)
+    }
+
+    fn score(key: &[u8], node: &N, weight: f64) -> f64 {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(key);
+        node.hash(&mut hasher);
+        // Map to the open interval (0, 1) so ln never sees 0 or 1.
+        let h = ((hasher.finish() >> 11) as f64 + 0.5) / (1u64 << 53) as f64;
+        -weight / h.ln()
+    }
+}
+
+impl<N> Default for RendezvousHasher<N>
+where
+    N: Hash + Eq,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<N> NodeSelector<N> for RendezvousHasher<N>
+where
+    N: Hash + Eq,
+{
+    fn get(&self, key: &[u8]) -> Option<&N> {
+        self.nodes
+            .iter()
+            .map(|(node, weight)| (node, Self::score(key, node, *weight)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(node, _)| node)
+    }
+
+    fn get_n(&self, key: &[u8], n: usize) -> Vec<&N> {
+        let mut scored: Vec<(&N, f64)> = self
+            .nodes
+            .iter()
+            .map(|(node, weight)| (node, Self::score(key, node, *weight)))
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored.into_iter().take(n).map(|(node, _)| node).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{NodeSelector, RendezvousHasher};
+    use std::collections::HashMap;
+
+    fn hasher(nodes: &[&'static str]) -> RendezvousHasher<&'static str> {
+        let mut hasher = RendezvousHasher::new();
+        for &node in nodes {
+            hasher.add_node(node);
+        }
+        hasher
+    }
+
+    #[test]
+    fn test_empty() {
+        let hasher: RendezvousHasher<u32> = RendezvousHasher::new();
+        assert_eq!(hasher.get(b"key"), None);
+        assert!(hasher.get_n(b"key", 3).is_empty());
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let a = hasher(&["a", "b", "c", "d"]);
+        let b = hasher(&["d", "c", "b", "a"]);
+        for i in 0..1000_u32 {
+            let key = i.to_le_bytes();
+            assert_eq!(a.get(&key), a.get(&key));
+            // Insertion order of nodes does not matter.
+            assert_eq!(a.get(&key), b.get(&key));
+            assert_eq!(a.get_n(&key, 4), b.get_n(&key, 4));
+        }
+    }
+
+    #[test]
+    fn test_get_n() {
+        let hasher = hasher(&["a", "b", "c", "d"]);
+        for i in 0..100_u32 {
+            let key = i.to_le_bytes();
+            let top = hasher.get_n(&key, 3);
+            assert_eq!(top.len(), 3);
+            assert_eq!(Some(top[0]), hasher.get(&key));
+            assert!(top[0] != top[1] && top[1] != top[2] && top[0] != top[2]);
+            assert_eq!(hasher.get_n(&key, 10).len(), 4);
+        }
+    }
+
+    #[test]
+    fn test_removal_only_moves_owned_keys() {
+        let mut hasher = hasher(&["a", "b", "c", "d", "e"]);
+        let before: Vec<Vec<&str>> = (0..5000_u32)
+            .map(|i| {
+                hasher
+                    .get_n(&i.to_le_bytes(), 2)
+                    .into_iter()
+                    .copied()
+                    .collect()
+            })
+            .collect();
+        assert!(hasher.remove_node(&"c"));
+        assert!(!hasher.remove_node(&"c"));
+        for (i, ranked) in before.into_iter().enumerate() {
+            let now = *hasher.get(&(i as u32).to_le_bytes()).unwrap();
+            if ranked[0] == "c" {
+                // The node that used to be second choice takes over.
+                assert_eq!(now, ranked[1]);
+            } else {
+                assert_eq!(now, ranked[0]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_weighted_distribution() {
+        let mut hasher = RendezvousHasher::new();
+        hasher.add_weighted_node("light", 1.0);
+        hasher.add_weighted_node("medium", 2.0);
+        hasher.add_weighted_node("heavy", 5.0);
+
+        let samples = 80_000_u32;
+        let mut counts: HashMap<&str, u32> = HashMap::new();
+        for i in 0..samples {
+            *counts
+                .entry(hasher.get(&i.to_le_bytes()).unwrap())
+                .or_default() += 1;
+        }
+        for (node, weight) in [("light", 1.0), ("medium", 2.0), ("heavy", 5.0)] {
+            let share = counts[node] as f64 / samples as f64;
+            let expected = weight / 8.0;
+            assert!(
+                (share - expected).abs() < 0.01,
+                "{node}: share {share}, expected {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_trait_object_agrees_with_generic() {
+        fn generic<S: NodeSelector<&'static str>>(s: &S, key: &[u8]) -> Option<&'static str> {
+            s.get(key).copied()
+        }
+
+        let hasher = hasher(&["a", "b", "c"]);
+        let dynamic: &dyn NodeSelector<&'static str> = &hasher;
+        for i in 0..1000_u32 {
+            let key = i.to_le_bytes();
+            assert_eq!(dynamic.get(&key).copied(), generic(&hasher, &key));
+            assert_eq!(dynamic.get_n(&key, 2), hasher.get_n(&key, 2));
+        }
+    }
+}
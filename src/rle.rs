@@ -0,0 +1,291 @@
+use std::iter;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Run<T> {
+    value: T,
+    /// One past the index of the run's last element.
+    end: usize,
+}
+
+/// A sequence stored as runs of equal values, for long stretches of the
+/// same value with random access that does not expand them.
+///
+/// Runs are kept maximal: no two neighbours hold equal values. Each run
+/// stores where it ends rather than its length, so [`get`](Self::get) is a
+/// binary search over the runs and [`set`](Self::set) only has to touch the
+/// runs around the index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RleVec<T> {
+    runs: Vec<Run<T>>,
+}
+
+impl<T: PartialEq> RleVec<T> {
+    pub fn new() -> Self {
+        Self { runs: Vec::new() }
+    }
+
+    pub fn push(&mut self, value: T) {
+        let len = self.len();
+        match self.runs.last_mut() {
+            Some(last) if last.value == value => last.end += 1,
+            _ => self.runs.push(Run {
+                value,
+                end: len + 1,
+            }),
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        let run = self.runs.partition_point(|run| run.end <= index);
+        self.runs.get(run).map(|run| &run.value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.runs.last().map_or(0, |run| run.end)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+
+    pub fn run_count(&self) -> usize {
+        self.runs.len()
+    }
+
+    /// Every element, each run repeated as it is reached.
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        self.runs()
+            .flat_map(|(value, len)| iter::repeat_n(value, len))
+    }
+
+    /// The runs as `(value, length)`, in order.
+    pub fn runs(&self) -> impl Iterator<Item = (&T, usize)> + '_ {
+        let starts = iter::once(0).chain(self.runs.iter().map(|run| run.end));
+        self.runs
+            .iter()
+            .zip(starts)
+            .map(|(run, start)| (&run.value, run.end - start))
+    }
+
+    fn start(&self, run: usize) -> usize {
+        match run {
+            0 => 0,
+            _ => self.runs[run - 1].end,
+        }
+    }
+}
+
+impl<T: PartialEq + Clone> RleVec<T> {
+    /// Replaces the element at `index`, splitting its run or merging with
+    /// a neighbouring run as needed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn set(&mut self, index: usize, value: T) {
+        let len = self.len();
+        assert!(index < len, "index {index} out of bounds for length {len}");
+        let r = self.runs.partition_point(|run| run.end <= index);
+        if self.runs[r].value == value {
+            return;
+        }
+        let (start, end) = (self.start(r), self.runs[r].end);
+        let joins_prev = index == start && r > 0 && self.runs[r - 1].value == value;
+        let joins_next =
+            index == end - 1 && r + 1 < self.runs.len() && self.runs[r + 1].value == value;
+
+        if end - start == 1 {
+            // The whole run changes value. A following run extends back by
+            // itself, since runs start where the previous one ends.
+            match (joins_prev, joins_next) {
+                (true, true) => {
+                    self.runs[r - 1].end = self.runs[r + 1].end;
+                    self.runs.drain(r..=r + 1);
+                }
+                (true, false) => {
+                    self.runs[r - 1].end = end;
+                    self.runs.remove(r);
+                }
+                (false, true) => {
+                    self.runs.remove(r);
+                }
+                (false, false) => self.runs[r].value = value,
+            }
+        } else if index == start {
+            if joins_prev {
+                self.runs[r - 1].end += 1;
+            } else {
+                self.runs.insert(
+                    r,
+                    Run {
+                        value,
+                        end: index + 1,
+                    },
+                );
+            }
+        } else if index == end - 1 {
+            self.runs[r].end -= 1;
+            if !joins_next {
+                self.runs.insert(r + 1, Run { value, end });
+            }
+        } else {
+            let rest = Run {
+                value: self.runs[r].value.clone(),
+                end,
+            };
+            self.runs[r].end = index;
+            self.runs.splice(
+                r + 1..r + 1,
+                [
+                    Run {
+                        value,
+                        end: index + 1,
+                    },
+                    rest,
+                ],
+            );
+        }
+    }
+}
+
+impl<T: PartialEq> Default for RleVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: PartialEq> FromIterator<T> for RleVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut rle = RleVec::new();
+        rle.extend(iter);
+        rle
+    }
+}
+
+impl<T: PartialEq> Extend<T> for RleVec<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.push(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RleVec;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn check(rle: &RleVec<u8>, plain: &[u8]) {
+        assert_eq!(rle.len(), plain.len());
+        assert!(rle.iter().eq(plain.iter()));
+        // Runs stay maximal.
+        let runs: Vec<_> = rle.runs().collect();
+        assert!(runs.iter().all(|&(_, len)| len > 0));
+        assert!(runs.windows(2).all(|w| w[0].0 != w[1].0));
+    }
+
+    #[test]
+    fn test_against_vec() {
+        let mut rng = StdRng::seed_from_u64(246);
+        for _ in 0..50 {
+            let mut rle = RleVec::new();
+            let mut plain = Vec::new();
+            for _ in 0..2_000 {
+                let value = rng.gen_range(0..3);
+                match rng.gen_range(0..3) {
+                    0 => {
+                        // Pushes in short streaks, so runs form.
+                        for _ in 0..rng.gen_range(1..6) {
+                            rle.push(value);
+                            plain.push(value);
+                        }
+                    }
+                    1 if !plain.is_empty() => {
+                        let i = rng.gen_range(0..plain.len());
+                        rle.set(i, value);
+                        plain[i] = value;
+                    }
+                    _ => {
+                        let i = rng.gen_range(0..plain.len() + 2);
+                        assert_eq!(rle.get(i), plain.get(i));
+                    }
+                }
+            }
+            check(&rle, &plain);
+        }
+    }
+
+    #[test]
+    fn test_set_positions() {
+        let base = || RleVec::from_iter([1, 1, 1, 2, 2, 2, 3, 3, 3]);
+        // (index, value, runs afterwards)
+        type Case = (usize, u8, &'static [(u8, usize)]);
+        let cases: &[Case] = &[
+            // Middle of a run: split in three.
+            (4, 9, &[(1, 3), (2, 1), (9, 1), (2, 1), (3, 3)]),
+            // Start and end of a run, no merge.
+            (3, 9, &[(1, 3), (9, 1), (2, 2), (3, 3)]),
+            (5, 9, &[(1, 3), (2, 2), (9, 1), (3, 3)]),
+            // Start and end of the whole sequence.
+            (0, 9, &[(9, 1), (1, 2), (2, 3), (3, 3)]),
+            (8, 9, &[(1, 3), (2, 3), (3, 2), (9, 1)]),
+            // Joining a neighbour.
+            (3, 1, &[(1, 4), (2, 2), (3, 3)]),
+            (5, 3, &[(1, 3), (2, 2), (3, 4)]),
+            // Same value: nothing changes.
+            (4, 2, &[(1, 3), (2, 3), (3, 3)]),
+        ];
+        for &(index, value, expected) in cases {
+            let mut rle = base();
+            rle.set(index, value);
+            let runs: Vec<(u8, usize)> = rle.runs().map(|(&v, len)| (v, len)).collect();
+            assert_eq!(runs, expected, "set({index}, {value})");
+        }
+    }
+
+    #[test]
+    fn test_set_single_element_runs() {
+        let mut rle = RleVec::from_iter([1, 2, 1, 3, 4]);
+        // Fills the gap between two equal runs: all three merge.
+        rle.set(1, 1);
+        assert_eq!(rle.runs().collect::<Vec<_>>(), [(&1, 3), (&3, 1), (&4, 1)]);
+        rle.set(3, 1);
+        assert_eq!(rle.runs().collect::<Vec<_>>(), [(&1, 4), (&4, 1)]);
+        rle.set(3, 4);
+        assert_eq!(rle.runs().collect::<Vec<_>>(), [(&1, 3), (&4, 2)]);
+        rle.set(4, 7);
+        rle.set(3, 7);
+        assert_eq!(rle.runs().collect::<Vec<_>>(), [(&1, 3), (&7, 2)]);
+    }
+
+    #[test]
+    fn test_all_equal_is_one_run() {
+        let rle: RleVec<u64> = std::iter::repeat_n(7, 1_000_000).collect();
+        assert_eq!(rle.len(), 1_000_000);
+        assert_eq!(rle.run_count(), 1);
+        assert!(rle.runs.capacity() * std::mem::size_of::<super::Run<u64>>() <= 64);
+        assert_eq!(rle.get(999_999), Some(&7));
+        assert_eq!(rle.get(1_000_000), None);
+    }
+
+    #[test]
+    fn test_iterators() {
+        let values = [5, 5, 0, 0, 0, 5, 1];
+        let mut rle: RleVec<i32> = values.iter().copied().collect();
+        assert!(rle.iter().eq(values.iter()));
+        assert_eq!(
+            rle.runs().collect::<Vec<_>>(),
+            [(&5, 2), (&0, 3), (&5, 1), (&1, 1)]
+        );
+        rle.extend([1, 1, 2]);
+        assert_eq!(rle.runs().last(), Some((&2, 1)));
+        assert_eq!(rle.run_count(), 5);
+        assert_eq!(rle.iter().count(), 10);
+
+        let empty = RleVec::<i32>::new();
+        assert!(empty.is_empty());
+        assert_eq!(empty.iter().next(), None);
+        assert_eq!(empty.get(0), None);
+    }
+}
@@ -0,0 +1,398 @@
+use crate::cache::Cache;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Hits an entry can bank before its counter saturates.
+const MAX_FREQ: u8 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Queue {
+    Small,
+    Main,
+}
+
+struct Entry<T> {
+    value: T,
+    freq: AtomicU8,
+    queue: Queue,
+    /// Tells this entry's queue slot apart from those of earlier entries
+    /// under the same key that were removed.
+    id: u64,
+}
+
+impl<T> Entry<T> {
+    fn hit(&self) {
+        let freq = self.freq.load(Ordering::Relaxed);
+        if freq < MAX_FREQ {
+            self.freq.store(freq + 1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// An S3-FIFO cache: entries start in a small probationary FIFO and only
+/// reach the main FIFO if they were hit while on probation.
+///
+/// Each entry carries a hit counter, 0 to 3. When the small queue has hit
+/// its share (a tenth of the capacity), its oldest entry moves to main if
+/// its counter is non-zero and is evicted otherwise, leaving its key in a
+/// ghost queue; a key that misses while it is a ghost goes straight to
+/// main. Main evicts its oldest entry with a zero counter, decrementing and
+/// requeueing the ones it passes over. One-hit wonders, such as the keys of
+/// a large scan, never get past the small queue.
+///
+/// `remove` leaves the key's queue slot behind; stale slots are skipped
+/// when they reach the front and compacted away once they pile up.
+pub struct S3FifoCache<T> {
+    entries: HashMap<Vec<u8>, Entry<T>>,
+    small: VecDeque<(Vec<u8>, u64)>,
+    main: VecDeque<(Vec<u8>, u64)>,
+    small_len: usize,
+    main_len: usize,
+    /// Recently evicted keys, with the stamp of their slot in `ghost_order`.
+    ghost: HashMap<Vec<u8>, u64>,
+    ghost_order: VecDeque<(Vec<u8>, u64)>,
+    small_target: usize,
+    ghost_capacity: usize,
+    capacity: usize,
+    next_id: u64,
+}
+
+impl<T> S3FifoCache<T> {
+    pub fn new(capacity: usize) -> Self {
+        let small_target = (capacity / 10).max(1).min(capacity);
+        Self {
+            entries: HashMap::with_capacity(capacity),
+            small: VecDeque::new(),
+            main: VecDeque::new(),
+            small_len: 0,
+            main_len: 0,
+            ghost: HashMap::new(),
+            ghost_order: VecDeque::new(),
+            small_target,
+            ghost_capacity: capacity - small_target,
+            capacity,
+            next_id: 0,
+        }
+    }
+
+    /// Inserts `value` under `key`, counting as a hit if it was present.
+    ///
+    /// Returns the previous value if the key was present, otherwise the value
+    /// of the evicted victim if the cache was full. A zero-capacity cache
+    /// stores nothing and hands `value` straight back.
+    pub fn insert(&mut self, key: &[u8], value: T) -> Option<T> {
+        if self.capacity == 0 {
+            return Some(value);
+        }
+        if let Some(entry) = self.entries.get_mut(key) {
+            entry.hit();
+            return Some(std::mem::replace(&mut entry.value, value));
+        }
+
+        let evicted = self.make_room();
+        let id = self.next_id;
+        self.next_id += 1;
+        let queue = if self.ghost.remove(key).is_some() {
+            self.main.push_back((key.to_vec(), id));
+            self.main_len += 1;
+            Queue::Main
+        } else {
+            self.small.push_back((key.to_vec(), id));
+            self.small_len += 1;
+            Queue::Small
+        };
+        self.entries.insert(
+            key.to_vec(),
+            Entry {
+                value,
+                freq: AtomicU8::new(0),
+                queue,
+                id,
+            },
+        );
+        evicted
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&T> {
+        self.entries.get(key).map(|entry| {
+            entry.hit();
+            &entry.value
+        })
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<T> {
+        let entry = self.entries.remove(key)?;
+        match entry.queue {
+            Queue::Small => self.small_len -= 1,
+            Queue::Main => self.main_len -= 1,
+        }
+        Self::compact(&mut self.small, &self.entries, self.small_len);
+        Self::compact(&mut self.main, &self.entries, self.main_len);
+        Some(entry.value)
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Evicts until there is room for one more entry, returning the value
+    /// evicted, if any.
+    fn make_room(&mut self) -> Option<T> {
+        while self.entries.len() >= self.capacity {
+            let evicted = if self.small_len >= self.small_target || self.main_len == 0 {
+                self.evict_small()
+            } else {
+                self.evict_main()
+            };
+            if evicted.is_some() {
+                return evicted;
+            }
+        }
+        None
+    }
+
+    /// Takes the oldest small-queue entry: promoted to main if it was hit,
+    /// evicted to the ghost queue otherwise.
+    fn evict_small(&mut self) -> Option<T> {
+        while let Some((key, id)) = self.small.pop_front() {
+            let Some(entry) = self.entries.get_mut(&key).filter(|e| e.id == id) else {
+                continue;
+            };
+            self.small_len -= 1;
+            if *entry.freq.get_mut() > 0 {
+                *entry.freq.get_mut() = 0;
+                entry.queue = Queue::Main;
+                self.main.push_back((key, id));
+                self.main_len += 1;
+                return None;
+            }
+            let value = self.entries.remove(&key).unwrap().value;
+            self.add_ghost(key);
+            return Some(value);
+        }
+        None
+    }
+
+    /// Evicts the oldest main-queue entry with no hits left, spending one
+    /// hit of each entry passed over.
+    fn evict_main(&mut self) -> Option<T> {
+        while let Some((key, id)) = self.main.pop_front() {
+            let Some(entry) = self.entries.get_mut(&key).filter(|e| e.id == id) else {
+                continue;
+            };
+            if *entry.freq.get_mut() > 0 {
+                *entry.freq.get_mut() -= 1;
+                self.main.push_back((key, id));
+                continue;
+            }
+            self.main_len -= 1;
+            return self.entries.remove(&key).map(|entry| entry.value);
+        }
+        None
+    }
+
+    fn add_ghost(&mut self, key: Vec<u8>) {
+        let stamp = self.next_id;
+        self.next_id += 1;
+        self.ghost.insert(key.clone(), stamp);
+        self.ghost_order.push_back((key, stamp));
+        while self.ghost.len() > self.ghost_capacity {
+            let (key, stamp) = self.ghost_order.pop_front().unwrap();
+            if self.ghost.get(&key) == Some(&stamp) {
+                self.ghost.remove(&key);
+            }
+        }
+        // Ghost hits leave their slot behind, like `remove` does.
+        if self.ghost_order.len() > 2 * self.ghost.len() + 16 {
+            let ghost = &self.ghost;
+            self.ghost_order
+                .retain(|(key, stamp)| ghost.get(key) == Some(stamp));
+        }
+    }
+
+    /// Drops stale slots from `queue` once they outnumber the `live` ones.
+    fn compact(
+        queue: &mut VecDeque<(Vec<u8>, u64)>,
+        entries: &HashMap<Vec<u8>, Entry<T>>,
+        live: usize,
+    ) {
+        if queue.len() > 2 * live + 16 {
+            queue.retain(|(key, id)| entries.get(key).is_some_and(|e| e.id == *id));
+        }
+    }
+}
+
+impl<T> Cache<[u8], T> for S3FifoCache<T> {
+    fn insert(&mut self, key: &[u8], value: T) -> Option<T> {
+        S3FifoCache::insert(self, key, value)
+    }
+
+    fn get(&mut self, key: &[u8]) -> Option<&T> {
+        S3FifoCache::get(self, key)
+    }
+
+    fn remove(&mut self, key: &[u8]) -> Option<T> {
+        S3FifoCache::remove(self, key)
+    }
+
+    fn len(&self) -> usize {
+        S3FifoCache::len(self)
+    }
+
+    fn cap(&self) -> usize {
+        self.capacity
+    }
+
+    fn contains_key(&self, key: &[u8]) -> bool {
+        S3FifoCache::contains_key(self, key)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Queue, S3FifoCache, MAX_FREQ};
+    use crate::cache::{replay, Access};
+    use crate::lru::LRUCache;
+    use rand::prelude::*;
+    use std::sync::atomic::Ordering;
+
+    fn key(i: u32) -> [u8; 4] {
+        i.to_le_bytes()
+    }
+
+    fn check_invariants<T>(cache: &S3FifoCache<T>) {
+        let small = cache
+            .entries
+            .values()
+            .filter(|e| e.queue == Queue::Small)
+            .count();
+        assert_eq!(cache.small_len, small);
+        assert_eq!(cache.main_len, cache.len() - small);
+        assert!(cache.len() <= cache.capacity);
+        assert!(cache.ghost.len() <= cache.ghost_capacity);
+        assert!(cache.entries.keys().all(|k| !cache.ghost.contains_key(k)));
+        assert!(cache.small.len() >= cache.small_len && cache.main.len() >= cache.main_len);
+        assert!(cache
+            .entries
+            .values()
+            .all(|e| e.freq.load(Ordering::Relaxed) <= MAX_FREQ));
+    }
+
+    #[test]
+    fn test_invariants_randomized() {
+        let mut rng = StdRng::seed_from_u64(242);
+        for capacity in [1, 2, 3, 10, 50] {
+            let mut cache = S3FifoCache::new(capacity);
+            for op in 0..10_000_u32 {
+                let k = key(rng.gen_range(0..capacity as u32 * 4));
+                match rng.gen_range(0..10) {
+                    0..=4 => {
+                        cache.insert(&k, op);
+                    }
+                    5..=8 => {
+                        cache.get(&k);
+                    }
+                    _ => {
+                        cache.remove(&k);
+                    }
+                }
+                check_invariants(&cache);
+            }
+        }
+    }
+
+    #[test]
+    fn test_ghost_hit_goes_to_main() {
+        let mut cache = S3FifoCache::new(10);
+        cache.insert(b"a", 1);
+        for i in 0..9 {
+            cache.insert(&key(i), 0);
+        }
+        // Full, and "a" is the oldest unhit entry on probation.
+        assert_eq!(cache.insert(b"b", 2), Some(1));
+        assert!(cache.ghost.contains_key(&b"a"[..]));
+
+        assert_eq!(cache.insert(b"a", 3), Some(0));
+        assert_eq!(cache.entries[&b"a"[..]].queue, Queue::Main);
+        assert!(!cache.ghost.contains_key(&b"a"[..]));
+    }
+
+    #[test]
+    fn test_hit_promotes_and_main_decrements() {
+        let mut cache = S3FifoCache::new(10);
+        cache.insert(b"hot", 0);
+        cache.get(b"hot");
+        for i in 0..100 {
+            cache.insert(&key(i), i);
+            check_invariants(&cache);
+        }
+        // Promoted on its way out of probation, then left alone since the
+        // scan never pushes main past its share.
+        assert_eq!(cache.entries[&b"hot"[..]].queue, Queue::Main);
+        assert_eq!(cache.get(b"hot"), Some(&0));
+    }
+
+    #[test]
+    fn test_beats_lru_on_scan_and_loop() {
+        let mut trace = Vec::new();
+        let mut next_scan = 1_000;
+        for _ in 0..20 {
+            for _ in 0..3 {
+                trace.extend((0..50u32).map(|i| Access::new(&key(i))));
+            }
+            trace.extend((next_scan..next_scan + 200).map(|i| Access::new(&key(i))));
+            next_scan += 200;
+        }
+        let lru = replay::<u32, _>(trace.iter().cloned(), &mut LRUCache::new(100));
+        let s3 = replay::<u32, _>(trace.iter().cloned(), &mut S3FifoCache::new(100));
+        assert!(
+            s3.hit_ratio() > lru.hit_ratio() + 0.1,
+            "s3fifo {} vs lru {}",
+            s3.hit_ratio(),
+            lru.hit_ratio()
+        );
+    }
+
+    #[test]
+    fn test_capacity_edges() {
+        let mut cache = S3FifoCache::new(0);
+        assert_eq!(cache.insert(b"a", 1), Some(1));
+        assert!(cache.is_empty());
+
+        let mut cache = S3FifoCache::new(1);
+        assert_eq!(cache.insert(b"a", 1), None);
+        cache.get(b"a");
+        // A hit moves "a" to main, which then has to evict it anyway.
+        assert_eq!(cache.insert(b"b", 2), Some(1));
+        assert_eq!(cache.insert(b"c", 3), Some(2));
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(b"c"), Some(&3));
+        check_invariants(&cache);
+    }
+
+    #[test]
+    fn test_remove_then_reinsert() {
+        let mut cache = S3FifoCache::new(3);
+        cache.insert(b"a", 1);
+        assert_eq!(cache.remove(b"a"), Some(1));
+        cache.insert(b"a", 2);
+        cache.insert(b"b", 3);
+        cache.insert(b"c", 4);
+        // The stale slot from the first "a" is skipped, not taken for the
+        // new one.
+        assert_eq!(cache.insert(b"d", 5), Some(2));
+        check_invariants(&cache);
+    }
+}
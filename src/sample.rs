@@ -0,0 +1,558 @@
+use rand::Rng;
+use std::{error::Error, fmt};
+
+/// A uniform random sample of up to `k` items from a stream of unknown
+/// length, kept in O(k) space.
+///
+/// Uses Li's Algorithm L: instead of flipping a coin for every item, it
+/// draws how many items to skip before the next one that enters the
+/// sample, so a stream of n items needs O(k (1 + log(n / k))) random draws.
+pub struct Reservoir<T, R> {
+    samples: Vec<T>,
+    k: usize,
+    seen: u64,
+    /// Items still to pass over before the next replacement.
+    skip: u64,
+    /// The largest of the random keys of the sampled items, in Algorithm L's
+    /// formulation where the sample holds the k items with the smallest keys.
+    w: f64,
+    rng: R,
+}
+
+impl<T, R: Rng> Reservoir<T, R> {
+    pub fn new(k: usize, rng: R) -> Self {
+        Self {
+            samples: Vec::with_capacity(k),
+            k,
+            seen: 0,
+            skip: 0,
+            w: 0.0,
+            rng,
+        }
+    }
+
+    pub fn observe(&mut self, item: T) {
+        self.seen += 1;
+        if self.samples.len() < self.k {
+            self.samples.push(item);
+            if self.samples.len() == self.k {
+                self.w = self.uniform().powf(1.0 / self.k as f64);
+                self.draw_skip();
+            }
+            return;
+        }
+        if self.k == 0 {
+            return;
+        }
+        if self.skip > 0 {
+            self.skip -= 1;
+            return;
+        }
+        let slot = self.rng.gen_range(0..self.k);
+        self.samples[slot] = item;
+        self.w *= self.uniform().powf(1.0 / self.k as f64);
+        self.draw_skip();
+    }
+
+    /// The current sample, in no particular order.
+    pub fn samples(&self) -> &[T] {
+        &self.samples
+    }
+
+    pub fn into_samples(self) -> Vec<T> {
+        self.samples
+    }
+
+    /// Number of items observed, including those merged in.
+    pub fn seen(&self) -> u64 {
+        self.seen
+    }
+
+    /// Combines with a reservoir over a disjoint stream, leaving a uniform
+    /// sample of both streams together, as if every item had been observed
+    /// here. Observing can go on afterwards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the two reservoirs have different sizes.
+    pub fn merge<R2>(&mut self, other: Reservoir<T, R2>) {
+        assert_eq!(self.k, other.k, "reservoirs must have the same size");
+        let total = self.seen + other.seen;
+        let mut ours = std::mem::take(&mut self.samples);
+        let mut theirs = other.samples;
+        if total <= self.k as u64 {
+            // Neither is full, so both hold everything they saw.
+            ours.append(&mut theirs);
+            self.samples = ours;
+            self.seen = total;
+            if self.samples.len() == self.k && self.k > 0 {
+                self.w = self.uniform().powf(1.0 / self.k as f64);
+                self.draw_skip();
+            }
+            return;
+        }
+
+        // Draw k times without replacement from the two streams, picking a
+        // side by how many of its items are still undrawn. Each side's sample
+        // is a uniform subset of its stream, so taking a random element of it
+        // stands in for a random undrawn item of that stream.
+        let (mut left_ours, mut left_theirs) = (self.seen, other.seen);
+        let mut merged = Vec::with_capacity(self.k);
+        for _ in 0..self.k {
+            let from = if self.rng.gen_range(0..left_ours + left_theirs) < left_ours {
+                left_ours -= 1;
+                &mut ours
+            } else {
+                left_theirs -= 1;
+                &mut theirs
+            };
+            let i = self.rng.gen_range(0..from.len());
+            merged.push(from.swap_remove(i));
+        }
+        self.samples = merged;
+        self.seen = total;
+        self.w = self.kth_smallest_key();
+        self.draw_skip();
+    }
+
+    /// A uniform draw from (0, 1], which keeps logarithms finite.
+    fn uniform(&mut self) -> f64 {
+        1.0 - self.rng.gen::<f64>()
+    }
+
+    fn draw_skip(&mut self) {
+        let skip = self.uniform().ln() / (1.0 - self.w).ln();
+        // Saturates for huge values, as does an infinite quotient.
+        self.skip = skip.floor() as u64;
+    }
+
+    /// A fresh draw of the k-th smallest of `seen` uniform keys, which is
+    /// what `w` is after observing `seen` items one by one. Built from the
+    /// k largest of `seen` keys of the mirrored distribution, O(k).
+    fn kth_smallest_key(&mut self) -> f64 {
+        let mut largest = 1.0;
+        for j in 0..self.k as u64 {
+            largest *= self.uniform().powf(1.0 / (self.seen - j) as f64);
+        }
+        1.0 - largest
+    }
+}
+
+/// A uniform sample of up to `k` items of `iter`.
+pub fn reservoir_sample<T, R, I>(iter: I, k: usize, rng: &mut R) -> Vec<T>
+where
+    R: Rng + ?Sized,
+    I: IntoIterator<Item = T>,
+{
+    let mut reservoir = Reservoir::new(k, rng);
+    for item in iter {
+        reservoir.observe(item);
+    }
+    reservoir.into_samples()
+}
+
+/// Why a set of weights cannot be sampled from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WeightError {
+    /// There were no weights at all.
+    Empty,
+    /// A weight was negative, NaN or infinite.
+    Invalid,
+    /// Every weight was zero.
+    AllZero,
+}
+
+impl fmt::Display for WeightError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Empty => "no weights given",
+            Self::Invalid => "weights must be finite and non-negative",
+            Self::AllZero => "at least one weight must be positive",
+        })
+    }
+}
+
+impl Error for WeightError {}
+
+/// Draws indices with probability proportional to fixed weights in O(1) per
+/// draw, using Vose's alias method.
+///
+/// Each of the n columns holds a threshold and an alias: a draw picks a
+/// column uniformly and returns it if a uniform coin lands below its
+/// threshold, or its alias otherwise. Building the columns is O(n).
+#[derive(Debug, Clone)]
+pub struct AliasTable {
+    threshold: Vec<f64>,
+    alias: Vec<usize>,
+}
+
+impl AliasTable {
+    pub fn new(weights: &[f64]) -> Result<Self, WeightError> {
+        if weights.is_empty() {
+            return Err(WeightError::Empty);
+        }
+        if weights.iter().any(|w| !w.is_finite() || *w < 0.0) {
+            return Err(WeightError::Invalid);
+        }
+        let total: f64 = weights.iter().sum();
+        if total == 0.0 {
+            return Err(WeightError::AllZero);
+        }
+
+        // Scale so the average column is exactly full.
+        let n = weights.len();
+        let mut threshold: Vec<f64> = weights.iter().map(|w| w * n as f64 / total).collect();
+        let mut alias: Vec<usize> = (0..n).collect();
+        let (mut small, mut large): (Vec<usize>, Vec<usize>) =
+            (0..n).partition(|&i| threshold[i] < 1.0);
+        // Top up each underfull column from an overfull one, which may then
+        // become underfull itself.
+        while let (Some(&s), Some(&l)) = (small.last(), large.last()) {
+            small.pop();
+            alias[s] = l;
+            threshold[l] -= 1.0 - threshold[s];
+            if threshold[l] < 1.0 {
+                large.pop();
+                small.push(l);
+            }
+        }
+        // Whatever is left is full up to rounding error. A zero weight can
+        // only be left over through rounding; point it at a real column
+        // rather than letting it be drawn.
+        let positive = weights.iter().position(|&w| w > 0.0).unwrap();
+        for i in small.into_iter().chain(large) {
+            if weights[i] > 0.0 {
+                threshold[i] = 1.0;
+            } else {
+                threshold[i] = 0.0;
+                alias[i] = positive;
+            }
+        }
+        Ok(Self { threshold, alias })
+    }
+
+    pub fn len(&self) -> usize {
+        self.threshold.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.threshold.is_empty()
+    }
+
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> usize {
+        let column = rng.gen_range(0..self.threshold.len());
+        if rng.gen::<f64>() < self.threshold[column] {
+            column
+        } else {
+            self.alias[column]
+        }
+    }
+}
+
+/// An [`AliasTable`] for the Zipf distribution over ranks `0..n`: rank `i`
+/// has weight `1 / (i + 1)^s`.
+///
+/// # Panics
+///
+/// Panics if `n` is zero or `s` is not finite.
+pub fn zipf(n: usize, s: f64) -> AliasTable {
+    assert!(n > 0, "zipf needs at least one rank");
+    assert!(s.is_finite(), "zipf exponent must be finite");
+    let weights: Vec<f64> = (1..=n).map(|rank| (rank as f64).powf(-s)).collect();
+    AliasTable::new(&weights).expect("zipf weights are positive")
+}
+
+/// Draws indices with probability proportional to weights that can change,
+/// in O(log n) per draw or update.
+///
+/// Weights live in a Fenwick tree of prefix sums, and a draw descends it to
+/// the index whose prefix range contains a uniform point. Weights are
+/// integers so that updates never accumulate rounding error, and a zero
+/// weight is never drawn.
+#[derive(Debug, Clone)]
+pub struct WeightedIndexTree {
+    /// Fenwick tree, 1-based: `tree[i]` sums the weights of the
+    /// `i & i.wrapping_neg()` indices ending at `i - 1`.
+    tree: Vec<u64>,
+    weights: Vec<u64>,
+    total: u64,
+}
+
+impl WeightedIndexTree {
+    /// All-zero weights are allowed here, since they can be raised later;
+    /// drawing from them is what fails.
+    pub fn new(weights: &[u64]) -> Self {
+        let n = weights.len();
+        let mut tree = vec![0; n + 1];
+        for (i, &w) in weights.iter().enumerate() {
+            tree[i + 1] += w;
+            let parent = (i + 1) + ((i + 1) & (i + 1).wrapping_neg());
+            if parent <= n {
+                tree[parent] += tree[i + 1];
+            }
+        }
+        Self {
+            tree,
+            weights: weights.to_vec(),
+            total: weights.iter().sum(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.weights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.weights.is_empty()
+    }
+
+    pub fn weight(&self, i: usize) -> u64 {
+        self.weights[i]
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    pub fn update_weight(&mut self, i: usize, weight: u64) {
+        let old = std::mem::replace(&mut self.weights[i], weight);
+        self.total = self.total - old + weight;
+        let mut j = i + 1;
+        while j < self.tree.len() {
+            self.tree[j] = self.tree[j] - old + weight;
+            j += j & j.wrapping_neg();
+        }
+    }
+
+    /// An index drawn in proportion to its weight.
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Result<usize, WeightError> {
+        if self.weights.is_empty() {
+            return Err(WeightError::Empty);
+        }
+        if self.total == 0 {
+            return Err(WeightError::AllZero);
+        }
+        // Find the first index whose inclusive prefix sum exceeds `target`.
+        let mut target = rng.gen_range(0..self.total);
+        let mut position = 0;
+        let mut step = (self.tree.len() - 1).next_power_of_two();
+        while step > 0 {
+            let next = position + step;
+            if next < self.tree.len() && self.tree[next] <= target {
+                target -= self.tree[next];
+                position = next;
+            }
+            step /= 2;
+        }
+        Ok(position)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{reservoir_sample, zipf, AliasTable, Reservoir, WeightError, WeightedIndexTree};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    /// Pearson's chi-square statistic of `counts` against a uniform
+    /// expectation.
+    fn chi_square(counts: &[u64]) -> f64 {
+        let expected = counts.iter().sum::<u64>() as f64 / counts.len() as f64;
+        counts
+            .iter()
+            .map(|&c| (c as f64 - expected).powi(2) / expected)
+            .sum()
+    }
+
+    // Critical values at p = 0.001.
+    const CHI_SQUARE_9: f64 = 27.88;
+    const CHI_SQUARE_49: f64 = 85.35;
+
+    #[test]
+    fn test_small_streams() {
+        let mut rng = StdRng::seed_from_u64(234);
+        let mut sample = reservoir_sample(0..3, 5, &mut rng);
+        sample.sort();
+        assert_eq!(sample, vec![0, 1, 2]);
+        assert!(reservoir_sample(0..100, 0, &mut rng).is_empty());
+        assert!(reservoir_sample(0..0, 4, &mut rng).is_empty());
+
+        let mut reservoir = Reservoir::new(0, &mut rng);
+        reservoir.observe(1);
+        assert_eq!(reservoir.seen(), 1);
+        assert!(reservoir.samples().is_empty());
+    }
+
+    #[test]
+    fn test_uniform_selection() {
+        let mut rng = StdRng::seed_from_u64(2340);
+        for (n, k) in [(10, 3), (50, 5)] {
+            let mut counts = vec![0u64; n];
+            for _ in 0..20_000 {
+                let sample = reservoir_sample(0..n, k, &mut rng);
+                assert_eq!(sample.len(), k);
+                let mut distinct = sample.clone();
+                distinct.sort();
+                distinct.dedup();
+                assert_eq!(distinct.len(), k);
+                for i in sample {
+                    counts[i] += 1;
+                }
+            }
+            let critical = if n == 10 { CHI_SQUARE_9 } else { CHI_SQUARE_49 };
+            let chi = chi_square(&counts);
+            assert!(chi < critical, "n = {n}: chi-square {chi}, {counts:?}");
+        }
+    }
+
+    #[test]
+    fn test_long_stream_is_uniform() {
+        // Long enough that most items are passed over by skipping.
+        let mut rng = StdRng::seed_from_u64(2341);
+        let mut counts = vec![0u64; 10];
+        for _ in 0..2_000 {
+            for i in reservoir_sample(0..10_000, 4, &mut rng) {
+                counts[i / 1_000] += 1;
+            }
+        }
+        let chi = chi_square(&counts);
+        assert!(chi < CHI_SQUARE_9, "chi-square {chi}, {counts:?}");
+    }
+
+    #[test]
+    fn test_merge_matches_concatenated_stream() {
+        let mut rng = StdRng::seed_from_u64(2342);
+        // Uneven halves, a half smaller than k, and then more items
+        // observed after the merge.
+        for (split, end, after) in [(7, 50, 50), (2, 50, 50), (25, 30, 50)] {
+            let mut counts = vec![0u64; after];
+            for _ in 0..20_000 {
+                let mut left = Reservoir::new(5, StdRng::from_rng(&mut rng).unwrap());
+                let mut right = Reservoir::new(5, StdRng::from_rng(&mut rng).unwrap());
+                (0..split).for_each(|i| left.observe(i));
+                (split..end).for_each(|i| right.observe(i));
+                left.merge(right);
+                (end..after).for_each(|i| left.observe(i));
+                assert_eq!(left.seen(), after as u64);
+                assert_eq!(left.samples().len(), 5);
+                for &i in left.samples() {
+                    counts[i] += 1;
+                }
+            }
+            let chi = chi_square(&counts);
+            assert!(chi < CHI_SQUARE_49, "split {split}: chi-square {chi}");
+        }
+    }
+
+    #[test]
+    fn test_merge_of_partial_reservoirs() {
+        let mut rng = StdRng::seed_from_u64(2343);
+        let mut left = Reservoir::new(10, &mut rng);
+        (0..3).for_each(|i| left.observe(i));
+        let mut right = Reservoir::new(10, StdRng::seed_from_u64(0));
+        (3..7).for_each(|i| right.observe(i));
+        left.merge(right);
+        let mut sample = left.samples().to_vec();
+        sample.sort();
+        assert_eq!(sample, (0..7).collect::<Vec<_>>());
+        (7..100).for_each(|i| left.observe(i));
+        assert_eq!(left.samples().len(), 10);
+    }
+
+    const DRAWS: usize = 1_000_000;
+
+    /// Checks each index's share of `counts` against its weight to within
+    /// 1% of all draws, and that zero weights were never drawn.
+    fn assert_frequencies(counts: &[u64], weights: &[f64]) {
+        let total: f64 = weights.iter().sum();
+        let draws = counts.iter().sum::<u64>() as f64;
+        for (i, (&count, &w)) in counts.iter().zip(weights).enumerate() {
+            if w == 0.0 {
+                assert_eq!(count, 0, "zero-weight index {i} was drawn");
+            }
+            let (got, want) = (count as f64 / draws, w / total);
+            assert!((got - want).abs() < 0.01, "index {i}: {got} vs {want}");
+        }
+    }
+
+    #[test]
+    fn test_alias_frequencies() {
+        let mut rng = StdRng::seed_from_u64(235);
+        let cases: [&[f64]; 4] = [
+            &[1.0; 8],
+            &[1.0, 2.0, 3.0, 4.0],
+            &[100.0, 0.0, 1.0, 0.0, 0.5, 30.0],
+            &[0.0, 0.0, 7.0],
+        ];
+        for weights in cases {
+            let table = AliasTable::new(weights).unwrap();
+            let mut counts = vec![0u64; weights.len()];
+            for _ in 0..DRAWS {
+                counts[table.sample(&mut rng)] += 1;
+            }
+            assert_frequencies(&counts, weights);
+        }
+    }
+
+    #[test]
+    fn test_zipf() {
+        let mut rng = StdRng::seed_from_u64(2350);
+        let table = zipf(100, 1.1);
+        assert_eq!(table.len(), 100);
+        let weights: Vec<f64> = (1..=100).map(|r| (r as f64).powf(-1.1)).collect();
+        let mut counts = vec![0u64; 100];
+        for _ in 0..DRAWS {
+            counts[table.sample(&mut rng)] += 1;
+        }
+        assert_frequencies(&counts, &weights);
+        assert!(counts.windows(2).take(10).all(|w| w[0] > w[1]));
+    }
+
+    #[test]
+    fn test_rejected_weights() {
+        assert_eq!(AliasTable::new(&[]).unwrap_err(), WeightError::Empty);
+        assert_eq!(
+            AliasTable::new(&[0.0, 0.0]).unwrap_err(),
+            WeightError::AllZero
+        );
+        assert_eq!(
+            AliasTable::new(&[1.0, -1.0]).unwrap_err(),
+            WeightError::Invalid
+        );
+        assert_eq!(
+            AliasTable::new(&[f64::NAN]).unwrap_err(),
+            WeightError::Invalid
+        );
+
+        let mut rng = StdRng::seed_from_u64(2351);
+        let mut tree = WeightedIndexTree::new(&[0, 0]);
+        assert_eq!(tree.sample(&mut rng), Err(WeightError::AllZero));
+        tree.update_weight(1, 3);
+        assert_eq!(tree.sample(&mut rng), Ok(1));
+        let empty = WeightedIndexTree::new(&[]);
+        assert_eq!(empty.sample(&mut rng), Err(WeightError::Empty));
+    }
+
+    #[test]
+    fn test_weighted_tree_updates() {
+        let mut rng = StdRng::seed_from_u64(2352);
+        let mut weights = [5u64, 0, 1, 10, 0, 3, 3];
+        let mut tree = WeightedIndexTree::new(&weights);
+        assert_eq!(tree.total(), 22);
+
+        for (i, w) in [(None, 0), (Some(3), 0), (Some(1), 20), (Some(6), 0)] {
+            if let Some(i) = i {
+                // The very next draws see the new weight.
+                tree.update_weight(i, w);
+                weights[i] = w;
+            }
+            assert_eq!(tree.total(), weights.iter().sum::<u64>());
+            let mut counts = vec![0u64; weights.len()];
+            for _ in 0..DRAWS / 4 {
+                counts[tree.sample(&mut rng).unwrap()] += 1;
+            }
+            let as_f64: Vec<f64> = weights.iter().map(|&w| w as f64).collect();
+            assert_frequencies(&counts, &as_f64);
+        }
+    }
+}
@@ -0,0 +1,226 @@
+//! Binary search variants that return positions instead of `Result`, so the
+//! answers can be used directly as insertion points and to count matches.
+
+use std::ops::Range;
+
+/// The first index `i` where `pred(&slice[i])` is false, for a slice where
+/// `pred` is true for a prefix and false after it. The length if it never
+/// turns false.
+pub fn partition_point_by<T, F>(slice: &[T], mut pred: F) -> usize
+where
+    F: FnMut(&T) -> bool,
+{
+    let (mut lo, mut hi) = (0, slice.len());
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if pred(&slice[mid]) {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    lo
+}
+
+/// The first index whose element is not less than `x`: where `x` would be
+/// inserted before any equal elements.
+pub fn lower_bound<T: Ord>(slice: &[T], x: &T) -> usize {
+    partition_point_by(slice, |e| e < x)
+}
+
+/// The first index whose element is greater than `x`: where `x` would be
+/// inserted after any equal elements.
+pub fn upper_bound<T: Ord>(slice: &[T], x: &T) -> usize {
+    partition_point_by(slice, |e| e <= x)
+}
+
+/// The indices of the elements equal to `x`, empty (at the insertion point)
+/// if there are none.
+pub fn equal_range<T: Ord>(slice: &[T], x: &T) -> Range<usize> {
+    let start = lower_bound(slice, x);
+    let end = start + upper_bound(&slice[start..], x);
+    start..end
+}
+
+/// The indices of the elements whose key is `key`, in a slice sorted by
+/// `f`.
+pub fn binary_search_by_key_range<T, K, F>(slice: &[T], key: &K, mut f: F) -> Range<usize>
+where
+    K: Ord,
+    F: FnMut(&T) -> K,
+{
+    let start = partition_point_by(slice, |e| f(e) < *key);
+    let end = start + partition_point_by(&slice[start..], |e| f(e) <= *key);
+    start..end
+}
+
+/// The first index `i` where `pred(i)` is false, for a predicate over all
+/// indices that is true up to some point and false from then on.
+///
+/// Probes 1, 2, 4, ... until `pred` fails and then bisects the last
+/// doubling, so it takes O(log i) calls for an answer `i` without knowing
+/// any upper bound. Panics if `pred` is still true when the probe index
+/// overflows.
+pub fn exponential_search_by<F>(mut pred: F) -> usize
+where
+    F: FnMut(usize) -> bool,
+{
+    if !pred(0) {
+        return 0;
+    }
+    // `pred(lo)` holds; find `hi` where it fails.
+    let mut lo = 0;
+    let mut hi = 1;
+    while pred(hi) {
+        lo = hi;
+        hi = hi.checked_mul(2).expect("predicate never turned false");
+    }
+    // Bisect `(lo, hi]`, knowing `pred(lo)` and `!pred(hi)`.
+    while hi - lo > 1 {
+        let mid = lo + (hi - lo) / 2;
+        if pred(mid) {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    hi
+}
+
+/// [`lower_bound`] by galloping from the front: O(log i) comparisons for
+/// an answer `i`, which beats plain bisection when the answer is near the
+/// start of a long slice.
+pub fn exponential_search<T: Ord>(slice: &[T], x: &T) -> usize {
+    exponential_search_by(|i| i < slice.len() && slice[i] < *x)
+}
+
+/// [`lower_bound`] for sorted integer keys, guessing each probe by linear
+/// interpolation between the ends of the remaining range.
+///
+/// O(log log n) probes expected when keys are uniformly distributed. Skewed
+/// keys can make it probe every element, O(n).
+pub fn interpolation_search(slice: &[u64], x: u64) -> usize {
+    // Everything before `lo` is below `x` and everything from `hi` is not.
+    let (mut lo, mut hi) = (0, slice.len());
+    while lo < hi {
+        let (first, last) = (slice[lo], slice[hi - 1]);
+        if x <= first {
+            return lo;
+        }
+        if last < x {
+            return hi;
+        }
+        // Here first < x <= last, so the guess lands in `lo..hi`.
+        let offset = (x - first) as u128 * (hi - 1 - lo) as u128 / (last - first) as u128;
+        let probe = lo + offset as usize;
+        if slice[probe] < x {
+            lo = probe + 1;
+        } else {
+            hi = probe;
+        }
+    }
+    lo
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        binary_search_by_key_range, equal_range, exponential_search, exponential_search_by,
+        interpolation_search, lower_bound, partition_point_by, upper_bound,
+    };
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn linear_lower(slice: &[u64], x: u64) -> usize {
+        slice.iter().take_while(|&&e| e < x).count()
+    }
+
+    fn linear_upper(slice: &[u64], x: u64) -> usize {
+        slice.iter().take_while(|&&e| e <= x).count()
+    }
+
+    #[test]
+    fn test_matches_linear_scan() {
+        let mut rng = StdRng::seed_from_u64(233);
+        for _ in 0..500 {
+            let len = rng.gen_range(0..80);
+            // Few distinct values, so runs of duplicates are long.
+            let max = rng.gen_range(1..10);
+            let mut slice: Vec<u64> = (0..len).map(|_| rng.gen_range(0..max) * 3).collect();
+            slice.sort();
+
+            // Probe every value, between values, and outside the range.
+            for x in 0..=max * 3 + 1 {
+                let lower = linear_lower(&slice, x);
+                let upper = linear_upper(&slice, x);
+                assert_eq!(lower_bound(&slice, &x), lower);
+                assert_eq!(upper_bound(&slice, &x), upper);
+                assert_eq!(equal_range(&slice, &x), lower..upper);
+                assert_eq!(exponential_search(&slice, &x), lower);
+                assert_eq!(interpolation_search(&slice, x), lower);
+                assert_eq!(partition_point_by(&slice, |&e| e < x), lower);
+            }
+        }
+    }
+
+    #[test]
+    fn test_edge_slices() {
+        let empty: [u64; 0] = [];
+        assert_eq!(lower_bound(&empty, &5), 0);
+        assert_eq!(equal_range(&empty, &5), 0..0);
+        assert_eq!(exponential_search(&empty, &5), 0);
+        assert_eq!(interpolation_search(&empty, 5), 0);
+
+        let same = [4u64; 9];
+        assert_eq!(equal_range(&same, &4), 0..9);
+        assert_eq!(equal_range(&same, &3), 0..0);
+        assert_eq!(equal_range(&same, &5), 9..9);
+        assert_eq!(interpolation_search(&same, 4), 0);
+        assert_eq!(interpolation_search(&same, 5), 9);
+
+        let extremes = [0, 0, u64::MAX, u64::MAX];
+        assert_eq!(interpolation_search(&extremes, 1), 2);
+        assert_eq!(interpolation_search(&extremes, u64::MAX), 2);
+    }
+
+    #[test]
+    fn test_key_range() {
+        let records = [(1, 'a'), (3, 'b'), (3, 'c'), (3, 'd'), (7, 'e')];
+        assert_eq!(binary_search_by_key_range(&records, &3, |r| r.0), 1..4);
+        assert_eq!(binary_search_by_key_range(&records, &5, |r| r.0), 4..4);
+        assert_eq!(binary_search_by_key_range(&records, &9, |r| r.0), 5..5);
+        assert_eq!(binary_search_by_key_range(&records, &0, |r| r.0), 0..0);
+    }
+
+    #[test]
+    fn test_exponential_search_unbounded() {
+        for answer in [0, 1, 2, 3, 1_000, 1 << 40] {
+            let mut calls = 0;
+            let found = exponential_search_by(|i| {
+                calls += 1;
+                i < answer
+            });
+            assert_eq!(found, answer);
+            // About two probes per bit of the answer.
+            assert!(calls <= 2 * (usize::BITS - answer.leading_zeros()) + 2);
+        }
+    }
+
+    #[test]
+    fn test_uniform_keys() {
+        let mut rng = StdRng::seed_from_u64(2330);
+        let mut keys: Vec<u64> = (0..10_000).map(|_| rng.gen()).collect();
+        keys.sort();
+        for _ in 0..1_000 {
+            let x = rng.gen();
+            assert_eq!(
+                interpolation_search(&keys, x),
+                keys.partition_point(|&k| k < x)
+            );
+        }
+        for (i, &k) in keys.iter().enumerate().step_by(97) {
+            assert_eq!(keys[interpolation_search(&keys, k)], k);
+            assert!(interpolation_search(&keys, k) <= i);
+        }
+    }
+}
@@ -0,0 +1,423 @@
+use std::{fmt, iter::FusedIterator, marker::PhantomData, mem, ptr::NonNull};
+
+struct Node<T> {
+    val: T,
+    next: Option<NonNull<Node<T>>>,
+}
+
+/// A singly linked list: one pointer per node, for stacks and free lists
+/// where [`crate::lru::LinkedList`]'s `prev` links would go unused.
+///
+/// The list also tracks its last node, so [`push_back`](Self::push_back)
+/// and [`append`](Self::append) are O(1); only removal is limited to the
+/// front. As in `lru.rs`, nodes are only reached through `as_ptr`, field
+/// by field, so references handed out for values stay valid while the
+/// links around them are rewritten.
+pub struct SinglyLinkedList<T> {
+    len: usize,
+    head: Option<NonNull<Node<T>>>,
+    tail: Option<NonNull<Node<T>>>,
+    _marker: PhantomData<Box<Node<T>>>,
+}
+
+// SAFETY: the list owns its nodes as a `Box<Node<T>>` would, and they are
+// only reachable through it.
+unsafe impl<T: Send> Send for SinglyLinkedList<T> {}
+unsafe impl<T: Sync> Sync for SinglyLinkedList<T> {}
+
+impl<T> SinglyLinkedList<T> {
+    pub fn new() -> Self {
+        Self {
+            len: 0,
+            head: None,
+            tail: None,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push_front(&mut self, val: T) {
+        let node = Self::alloc(val);
+        unsafe { (*node.as_ptr()).next = self.head };
+        if self.head.is_none() {
+            self.tail = Some(node);
+        }
+        self.head = Some(node);
+        self.len += 1;
+    }
+
+    pub fn push_back(&mut self, val: T) {
+        let node = Self::alloc(val);
+        match self.tail {
+            Some(tail) => unsafe { (*tail.as_ptr()).next = Some(node) },
+            None => self.head = Some(node),
+        }
+        self.tail = Some(node);
+        self.len += 1;
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        self.head.map(|node| unsafe {
+            let node = Box::from_raw(node.as_ptr());
+            self.head = node.next;
+            if self.head.is_none() {
+                self.tail = None;
+            }
+            self.len -= 1;
+            node.val
+        })
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.head.map(|node| unsafe { &(*node.as_ptr()).val })
+    }
+
+    pub fn peek_mut(&mut self) -> Option<&mut T> {
+        self.head.map(|node| unsafe { &mut (*node.as_ptr()).val })
+    }
+
+    pub fn back(&self) -> Option<&T> {
+        self.tail.map(|node| unsafe { &(*node.as_ptr()).val })
+    }
+
+    /// Moves every element of `other` onto the back of `self` in O(1),
+    /// leaving `other` empty.
+    pub fn append(&mut self, other: &mut SinglyLinkedList<T>) {
+        let Some(first) = other.head.take() else {
+            return;
+        };
+        match self.tail {
+            Some(tail) => unsafe { (*tail.as_ptr()).next = Some(first) },
+            None => self.head = Some(first),
+        }
+        self.tail = other.tail.take();
+        self.len += mem::take(&mut other.len);
+    }
+
+    /// Reverses the order of the elements in place by turning every link
+    /// around.
+    pub fn reverse(&mut self) {
+        let mut prev = None;
+        let mut cursor = self.head;
+        while let Some(node) = cursor {
+            unsafe {
+                cursor = (*node.as_ptr()).next;
+                (*node.as_ptr()).next = prev;
+            }
+            prev = Some(node);
+        }
+        self.tail = self.head;
+        self.head = prev;
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            next: self.head,
+            len: self.len,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut {
+            next: self.head,
+            len: self.len,
+            _marker: PhantomData,
+        }
+    }
+
+    fn alloc(val: T) -> NonNull<Node<T>> {
+        NonNull::new(Box::into_raw(Box::new(Node { val, next: None }))).unwrap()
+    }
+}
+
+impl<T> Default for SinglyLinkedList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for SinglyLinkedList<T> {
+    fn drop(&mut self) {
+        // Keeps freeing the rest if dropping one value panics.
+        struct DropGuard<'a, T>(&'a mut SinglyLinkedList<T>);
+        impl<T> Drop for DropGuard<'_, T> {
+            fn drop(&mut self) {
+                while self.0.pop_front().is_some() {}
+            }
+        }
+
+        while let Some(val) = self.pop_front() {
+            let guard = DropGuard(self);
+            drop(val);
+            mem::forget(guard);
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for SinglyLinkedList<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T> FromIterator<T> for SinglyLinkedList<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = SinglyLinkedList::new();
+        list.extend(iter);
+        list
+    }
+}
+
+impl<T> Extend<T> for SinglyLinkedList<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for val in iter {
+            self.push_back(val);
+        }
+    }
+}
+
+impl<T> IntoIterator for SinglyLinkedList<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { list: self }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a SinglyLinkedList<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut SinglyLinkedList<T> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+pub struct Iter<'a, T> {
+    next: Option<NonNull<Node<T>>>,
+    len: usize,
+    _marker: PhantomData<&'a Node<T>>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.next.map(|node| unsafe {
+            self.next = (*node.as_ptr()).next;
+            self.len -= 1;
+            &(*node.as_ptr()).val
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+impl<T> FusedIterator for Iter<'_, T> {}
+
+// SAFETY: the iterators stand for a borrow of the list, so they are as
+// shareable and sendable as `&T` and `&mut T`.
+unsafe impl<T: Sync> Send for Iter<'_, T> {}
+unsafe impl<T: Sync> Sync for Iter<'_, T> {}
+
+pub struct IterMut<'a, T> {
+    next: Option<NonNull<Node<T>>>,
+    len: usize,
+    _marker: PhantomData<&'a mut Node<T>>,
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<&'a mut T> {
+        self.next.map(|node| unsafe {
+            self.next = (*node.as_ptr()).next;
+            self.len -= 1;
+            &mut (*node.as_ptr()).val
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T> ExactSizeIterator for IterMut<'_, T> {}
+
+impl<T> FusedIterator for IterMut<'_, T> {}
+
+unsafe impl<T: Send> Send for IterMut<'_, T> {}
+unsafe impl<T: Sync> Sync for IterMut<'_, T> {}
+
+pub struct IntoIter<T> {
+    list: SinglyLinkedList<T>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.list.pop_front()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.list.len, Some(self.list.len))
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+impl<T> FusedIterator for IntoIter<T> {}
+
+#[cfg(test)]
+mod test {
+    use super::SinglyLinkedList;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::cell::Cell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+
+    /// The values front to back, after checking `len` and the tail.
+    fn contents(list: &SinglyLinkedList<i32>) -> Vec<i32> {
+        let values: Vec<i32> = list.iter().copied().collect();
+        assert_eq!(values.len(), list.len());
+        assert_eq!(list.back(), values.last());
+        values
+    }
+
+    #[test]
+    fn test_against_vec_deque() {
+        let mut rng = StdRng::seed_from_u64(286);
+        let mut list = SinglyLinkedList::new();
+        let mut plain = VecDeque::new();
+        for i in 0..5_000 {
+            match rng.gen_range(0..5) {
+                0 => {
+                    list.push_front(i);
+                    plain.push_front(i);
+                }
+                1 => {
+                    list.push_back(i);
+                    plain.push_back(i);
+                }
+                2 | 3 => assert_eq!(list.pop_front(), plain.pop_front()),
+                _ => {
+                    list.reverse();
+                    plain.make_contiguous().reverse();
+                }
+            }
+            assert_eq!(list.peek(), plain.front());
+        }
+        assert_eq!(contents(&list), Vec::from(plain));
+    }
+
+    #[test]
+    fn test_stack() {
+        let mut list = SinglyLinkedList::new();
+        assert_eq!(list.peek(), None);
+        assert_eq!(list.pop_front(), None);
+        list.push_front(1);
+        list.push_front(2);
+        *list.peek_mut().unwrap() *= 10;
+        assert_eq!(format!("{:?}", list), "[20, 1]");
+        assert_eq!(list.pop_front(), Some(20));
+        assert_eq!(list.pop_front(), Some(1));
+        assert!(list.is_empty());
+        assert_eq!(list.back(), None);
+        // Pushing at the back again after emptying finds the new tail.
+        list.push_back(3);
+        list.push_front(2);
+        list.push_back(4);
+        assert_eq!(contents(&list), [2, 3, 4]);
+    }
+
+    #[test]
+    fn test_reverse() {
+        for len in 0..5 {
+            let mut list: SinglyLinkedList<i32> = (0..len).collect();
+            list.reverse();
+            assert_eq!(contents(&list), (0..len).rev().collect::<Vec<_>>());
+            list.push_back(len);
+            list.reverse();
+            assert_eq!(list.peek(), Some(&len));
+        }
+    }
+
+    #[test]
+    fn test_append() {
+        for (a, b) in [(0..0, 0..0), (0..3, 3..3), (0..0, 0..2), (0..3, 3..5)] {
+            let expected: Vec<i32> = a.clone().chain(b.clone()).collect();
+            let mut front: SinglyLinkedList<i32> = a.collect();
+            let mut back: SinglyLinkedList<i32> = b.collect();
+            front.append(&mut back);
+            assert_eq!(contents(&front), expected);
+            assert!(contents(&back).is_empty());
+            front.push_back(9);
+            back.push_back(9);
+            assert_eq!(front.back(), Some(&9));
+            assert_eq!(contents(&back), [9]);
+        }
+    }
+
+    #[test]
+    fn test_iterators() {
+        let mut list: SinglyLinkedList<i32> = (0..4).collect();
+        for val in &mut list {
+            *val *= 2;
+        }
+        assert_eq!((&list).into_iter().len(), 4);
+        assert_eq!(list.iter_mut().len(), 4);
+        assert_eq!(list.into_iter().collect::<Vec<_>>(), [0, 2, 4, 6]);
+
+        let mut into_iter = SinglyLinkedList::from_iter(0..3).into_iter();
+        assert_eq!(into_iter.next(), Some(0));
+        assert_eq!(into_iter.len(), 2);
+    }
+
+    /// Counts its drops, and panics on drop if asked to.
+    struct Counted(Rc<Cell<usize>>, bool);
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+            assert!(!self.1, "drop failed");
+        }
+    }
+
+    #[test]
+    fn test_drop() {
+        let drops = Rc::new(Cell::new(0));
+        let list: SinglyLinkedList<_> = (0..5).map(|i| Counted(drops.clone(), i == 1)).collect();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| drop(list)));
+        assert!(result.is_err());
+        assert_eq!(drops.get(), 5);
+
+        // Long enough that dropping node by node recursively would overflow.
+        let len = if cfg!(miri) { 1_000 } else { 200_000 };
+        let long: SinglyLinkedList<u8> = std::iter::repeat_n(0, len).collect();
+        drop(long);
+    }
+}
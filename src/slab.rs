@@ -0,0 +1,378 @@
+use std::mem;
+use std::ops::{Index, IndexMut};
+
+/// A handle to a value in a [`Slab`]. The generation ties the key to one
+/// particular occupant of the slot, so a key outlives its value safely: once
+/// the value is removed every lookup with the key misses, even after the slot
+/// is reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Key {
+    index: usize,
+    generation: u64,
+}
+
+impl Key {
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+}
+
+enum Entry<T> {
+    Occupied(T),
+    /// Links to the next free slot.
+    Vacant(Option<usize>),
+}
+
+struct Slot<T> {
+    generation: u64,
+    entry: Entry<T>,
+}
+
+/// Slot storage with generational keys.
+///
+/// Values live in one `Vec`, vacant slots form an intrusive free list, and
+/// insert and remove are O(1). Removing a value bumps its slot's generation,
+/// which invalidates every outstanding key for it. The most recently freed
+/// slot is reused first.
+pub struct Slab<T> {
+    slots: Vec<Slot<T>>,
+    free_head: Option<usize>,
+    len: usize,
+}
+
+impl<T> Slab<T> {
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            slots: Vec::with_capacity(capacity),
+            free_head: None,
+            len: 0,
+        }
+    }
+
+    pub fn insert(&mut self, value: T) -> Key {
+        self.len += 1;
+        match self.free_head {
+            Some(index) => {
+                let slot = &mut self.slots[index];
+                match mem::replace(&mut slot.entry, Entry::Occupied(value)) {
+                    Entry::Vacant(next) => self.free_head = next,
+                    Entry::Occupied(_) => unreachable!("free list points at an occupied slot"),
+                }
+                Key {
+                    index,
+                    generation: slot.generation,
+                }
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    entry: Entry::Occupied(value),
+                });
+                Key {
+                    index: self.slots.len() - 1,
+                    generation: 0,
+                }
+            }
+        }
+    }
+
+    pub fn get(&self, key: Key) -> Option<&T> {
+        match self.slots.get(key.index) {
+            Some(Slot {
+                generation,
+                entry: Entry::Occupied(value),
+            }) if *generation == key.generation => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, key: Key) -> Option<&mut T> {
+        match self.slots.get_mut(key.index) {
+            Some(Slot {
+                generation,
+                entry: Entry::Occupied(value),
+            }) if *generation == key.generation => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Mutable references to two distinct live values, or `None` if either
+    /// key is stale or both name the same value.
+    pub fn get2_mut(&mut self, a: Key, b: Key) -> Option<(&mut T, &mut T)> {
+        if a.index == b.index || !self.contains(a) || !self.contains(b) {
+            return None;
+        }
+        let (low, high) = (a.index.min(b.index), a.index.max(b.index));
+        let (left, right) = self.slots.split_at_mut(high);
+        let (low, high) = match (&mut left[low].entry, &mut right[0].entry) {
+            (Entry::Occupied(l), Entry::Occupied(h)) => (l, h),
+            _ => unreachable!("checked by contains"),
+        };
+        if a.index < b.index {
+            Some((low, high))
+        } else {
+            Some((high, low))
+        }
+    }
+
+    pub fn contains(&self, key: Key) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn remove(&mut self, key: Key) -> Option<T> {
+        if !self.contains(key) {
+            return None;
+        }
+        Some(self.vacate(key.index))
+    }
+
+    /// Keeps only the values for which `f` returns true. Removed values'
+    /// keys become stale exactly as with `remove`.
+    pub fn retain(&mut self, mut f: impl FnMut(Key, &mut T) -> bool) {
+        for index in 0..self.slots.len() {
+            let slot = &mut self.slots[index];
+            let keep = match &mut slot.entry {
+                Entry::Occupied(value) => f(
+                    Key {
+                        index,
+                        generation: slot.generation,
+                    },
+                    value,
+                ),
+                Entry::Vacant(_) => true,
+            };
+            if !keep {
+                self.vacate(index);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Drops every value. Slots are kept for reuse and all existing keys
+    /// become stale.
+    pub fn clear(&mut self) {
+        for index in 0..self.slots.len() {
+            if let Entry::Occupied(_) = self.slots[index].entry {
+                self.vacate(index);
+            }
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Key, &T)> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(index, slot)| match &slot.entry {
+                Entry::Occupied(value) => Some((
+                    Key {
+                        index,
+                        generation: slot.generation,
+                    },
+                    value,
+                )),
+                Entry::Vacant(_) => None,
+            })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Key, &mut T)> {
+        self.slots
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, slot)| match &mut slot.entry {
+                Entry::Occupied(value) => Some((
+                    Key {
+                        index,
+                        generation: slot.generation,
+                    },
+                    value,
+                )),
+                Entry::Vacant(_) => None,
+            })
+    }
+
+    /// Empties an occupied slot, pushes it on the free list and bumps its
+    /// generation.
+    fn vacate(&mut self, index: usize) -> T {
+        let slot = &mut self.slots[index];
+        let value = match mem::replace(&mut slot.entry, Entry::Vacant(self.free_head)) {
+            Entry::Occupied(value) => value,
+            Entry::Vacant(_) => unreachable!("vacating a vacant slot"),
+        };
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free_head = Some(index);
+        self.len -= 1;
+        value
+    }
+}
+
+impl<T> Default for Slab<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Index<Key> for Slab<T> {
+    type Output = T;
+
+    fn index(&self, key: Key) -> &T {
+        self.get(key).expect("stale slab key")
+    }
+}
+
+impl<T> IndexMut<Key> for Slab<T> {
+    fn index_mut(&mut self, key: Key) -> &mut T {
+        self.get_mut(key).expect("stale slab key")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Slab;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    struct DropCounter(Rc<Cell<usize>>);
+
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn test_insert_get_remove() {
+        let mut slab = Slab::new();
+        let a = slab.insert("a");
+        let b = slab.insert("b");
+        assert_eq!(slab.len(), 2);
+        assert_eq!(slab.get(a), Some(&"a"));
+        assert_eq!(slab[b], "b");
+
+        *slab.get_mut(a).unwrap() = "A";
+        assert_eq!(slab.remove(a), Some("A"));
+        assert_eq!(slab.remove(a), None);
+        assert_eq!(slab.len(), 1);
+        assert!(!slab.contains(a));
+        assert!(slab.contains(b));
+    }
+
+    #[test]
+    fn test_stale_key_after_reuse() {
+        let mut slab = Slab::new();
+        let old = slab.insert(1);
+        slab.remove(old);
+        let new = slab.insert(2);
+
+        assert_eq!(new.index(), old.index());
+        assert_ne!(new.generation(), old.generation());
+        assert_eq!(slab.get(old), None);
+        assert_eq!(slab.get_mut(old), None);
+        assert_eq!(slab.remove(old), None);
+        assert_eq!(slab.get(new), Some(&2));
+    }
+
+    #[test]
+    fn test_free_list_reuse_order() {
+        let mut slab = Slab::new();
+        let keys: Vec<_> = (0..5).map(|i| slab.insert(i)).collect();
+        slab.remove(keys[1]);
+        slab.remove(keys[3]);
+        slab.remove(keys[2]);
+
+        // Most recently freed first, then fresh slots at the end.
+        let reused: Vec<_> = (0..4).map(|i| slab.insert(10 + i).index()).collect();
+        assert_eq!(reused, vec![2, 3, 1, 5]);
+    }
+
+    #[test]
+    fn test_iter_skips_vacant() {
+        let mut slab = Slab::new();
+        let keys: Vec<_> = (0..6).map(|i| slab.insert(i)).collect();
+        for &key in keys.iter().step_by(2) {
+            slab.remove(key);
+        }
+        let live: Vec<_> = slab.iter().map(|(k, &v)| (k, v)).collect();
+        assert_eq!(live, vec![(keys[1], 1), (keys[3], 3), (keys[5], 5)]);
+
+        for (_, v) in slab.iter_mut() {
+            *v *= 10;
+        }
+        assert_eq!(
+            slab.iter().map(|(_, &v)| v).collect::<Vec<_>>(),
+            [10, 30, 50]
+        );
+    }
+
+    #[test]
+    fn test_retain() {
+        let mut slab = Slab::new();
+        let keys: Vec<_> = (0..6).map(|i| slab.insert(i)).collect();
+        slab.retain(|key, v| {
+            assert_eq!(key, keys[*v]);
+            *v % 3 == 0
+        });
+        assert_eq!(slab.len(), 2);
+        for (i, &key) in keys.iter().enumerate() {
+            assert_eq!(slab.get(key).is_some(), i % 3 == 0);
+        }
+
+        // Slots freed by retain are reused with fresh generations.
+        let key = slab.insert(100);
+        assert!(keys.iter().all(|&old| slab.get(old) != Some(&100)));
+        assert_eq!(slab[key], 100);
+    }
+
+    #[test]
+    fn test_get2_mut() {
+        let mut slab = Slab::new();
+        let a = slab.insert(1);
+        let b = slab.insert(2);
+        {
+            let (x, y) = slab.get2_mut(b, a).unwrap();
+            assert_eq!((*x, *y), (2, 1));
+            std::mem::swap(x, y);
+        }
+        assert_eq!((slab[a], slab[b]), (2, 1));
+        assert!(slab.get2_mut(a, a).is_none());
+
+        slab.remove(b);
+        assert!(slab.get2_mut(a, b).is_none());
+    }
+
+    #[test]
+    fn test_drop_on_clear_and_drop() {
+        let drops = Rc::new(Cell::new(0));
+        let mut slab = Slab::new();
+        let keys: Vec<_> = (0..10)
+            .map(|_| slab.insert(DropCounter(drops.clone())))
+            .collect();
+        drop(slab.remove(keys[0]));
+        assert_eq!(drops.get(), 1);
+
+        slab.clear();
+        assert_eq!(drops.get(), 10);
+        assert!(slab.is_empty());
+        assert!(keys.iter().all(|&k| !slab.contains(k)));
+
+        for _ in 0..4 {
+            slab.insert(DropCounter(drops.clone()));
+        }
+        drop(slab);
+        assert_eq!(drops.get(), 14);
+    }
+}
@@ -0,0 +1,365 @@
+//! A doubly linked list without `unsafe`, for code that forbids it.
+//!
+//! Nodes live in one `Vec` and link to each other by `u32` index, with
+//! removed slots kept on a free list for reuse. The API follows
+//! [`crate::lru::LinkedList`], except that inserting returns a [`Handle`]:
+//! a 4-byte id to pass back to [`remove`](LinkedList::remove) and
+//! [`reinsert_front`](LinkedList::reinsert_front) in place of a raw node
+//! pointer.
+
+use std::fmt;
+use std::iter::FusedIterator;
+use std::mem;
+
+/// Marks the end of the list, and of the free list.
+const NIL: u32 = u32::MAX;
+
+/// Identifies an element of a [`LinkedList`] until it is removed.
+///
+/// Handles carry no generation: once an element is removed, its slot is
+/// reused by a later insert, and an old handle to it then refers to the new
+/// element. Like a `Vec` index, a handle should be dropped with its element.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handle(u32);
+
+enum Slot<T> {
+    Occupied { val: T, prev: u32, next: u32 },
+    Vacant { next_free: u32 },
+}
+
+pub struct LinkedList<T> {
+    slots: Vec<Slot<T>>,
+    head: u32,
+    tail: u32,
+    free: u32,
+    len: usize,
+}
+
+impl<T> LinkedList<T> {
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            slots: Vec::with_capacity(capacity),
+            head: NIL,
+            tail: NIL,
+            free: NIL,
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn insert_front(&mut self, val: T) -> Handle {
+        let index = self.alloc(val);
+        self.link(index, NIL, self.head);
+        Handle(index)
+    }
+
+    pub fn push_back(&mut self, val: T) -> Handle {
+        let index = self.alloc(val);
+        self.link(index, self.tail, NIL);
+        Handle(index)
+    }
+
+    /// Removes the element `handle` refers to, or returns `None` if it has
+    /// already been removed.
+    pub fn remove(&mut self, handle: Handle) -> Option<T> {
+        self.get(handle)?;
+        self.unlink(handle.0);
+        Some(self.release(handle.0))
+    }
+
+    /// Moves the element `handle` refers to to the front.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the element has been removed.
+    pub fn reinsert_front(&mut self, handle: Handle) {
+        assert!(self.get(handle).is_some(), "{handle:?} has been removed");
+        self.unlink(handle.0);
+        self.link(handle.0, NIL, self.head);
+    }
+
+    pub fn remove_tail(&mut self) -> Option<T> {
+        let tail = Handle(self.tail);
+        self.remove(tail)
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        let head = Handle(self.head);
+        self.remove(head)
+    }
+
+    pub fn get(&self, handle: Handle) -> Option<&T> {
+        match self.slots.get(handle.0 as usize)? {
+            Slot::Occupied { val, .. } => Some(val),
+            Slot::Vacant { .. } => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, handle: Handle) -> Option<&mut T> {
+        match self.slots.get_mut(handle.0 as usize)? {
+            Slot::Occupied { val, .. } => Some(val),
+            Slot::Vacant { .. } => None,
+        }
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        self.get(Handle(self.head))
+    }
+
+    pub fn back(&self) -> Option<&T> {
+        self.get(Handle(self.tail))
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            list: self,
+            next: self.head,
+            len: self.len,
+        }
+    }
+
+    /// Puts `val` in a free slot, reusing the most recently freed one.
+    fn alloc(&mut self, val: T) -> u32 {
+        let slot = Slot::Occupied {
+            val,
+            prev: NIL,
+            next: NIL,
+        };
+        if self.free == NIL {
+            assert!(self.slots.len() < NIL as usize, "too many elements");
+            self.slots.push(slot);
+            return self.slots.len() as u32 - 1;
+        }
+        let index = self.free;
+        match mem::replace(&mut self.slots[index as usize], slot) {
+            Slot::Vacant { next_free } => self.free = next_free,
+            Slot::Occupied { .. } => unreachable!("free list points at an occupied slot"),
+        }
+        index
+    }
+
+    /// Frees the slot of an unlinked element and returns its value.
+    fn release(&mut self, index: u32) -> T {
+        let vacant = Slot::Vacant {
+            next_free: self.free,
+        };
+        self.free = index;
+        match mem::replace(&mut self.slots[index as usize], vacant) {
+            Slot::Occupied { val, .. } => val,
+            Slot::Vacant { .. } => unreachable!("released a vacant slot"),
+        }
+    }
+
+    fn links(&mut self, index: u32) -> (&mut u32, &mut u32) {
+        match &mut self.slots[index as usize] {
+            Slot::Occupied { prev, next, .. } => (prev, next),
+            Slot::Vacant { .. } => unreachable!("linked a vacant slot"),
+        }
+    }
+
+    /// Links `index` in between `prev` and `next`, which must be adjacent;
+    /// `NIL` stands for the end of the list on that side.
+    fn link(&mut self, index: u32, prev: u32, next: u32) {
+        *self.links(index).0 = prev;
+        *self.links(index).1 = next;
+        match prev {
+            NIL => self.head = index,
+            prev => *self.links(prev).1 = index,
+        }
+        match next {
+            NIL => self.tail = index,
+            next => *self.links(next).0 = index,
+        }
+        self.len += 1;
+    }
+
+    fn unlink(&mut self, index: u32) {
+        let (&mut prev, &mut next) = self.links(index);
+        match prev {
+            NIL => self.head = next,
+            prev => *self.links(prev).1 = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            next => *self.links(next).0 = prev,
+        }
+        self.len -= 1;
+    }
+}
+
+impl<T> Default for LinkedList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for LinkedList<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T> FromIterator<T> for LinkedList<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = LinkedList::new();
+        list.extend(iter);
+        list
+    }
+}
+
+impl<T> Extend<T> for LinkedList<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for val in iter {
+            self.push_back(val);
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a LinkedList<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+pub struct Iter<'a, T> {
+    list: &'a LinkedList<T>,
+    next: u32,
+    len: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        match self.list.slots.get(self.next as usize)? {
+            Slot::Occupied { val, next, .. } => {
+                self.next = *next;
+                self.len -= 1;
+                Some(val)
+            }
+            Slot::Vacant { .. } => unreachable!("list links to a vacant slot"),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+impl<T> FusedIterator for Iter<'_, T> {}
+
+#[cfg(test)]
+mod test {
+    use super::{Handle, LinkedList};
+    use crate::lru;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_against_pointer_list() {
+        let mut rng = StdRng::seed_from_u64(280);
+        let mut slab = LinkedList::new();
+        let mut pointers = lru::LinkedList::new();
+        let mut handles: Vec<(Handle, u32)> = Vec::new();
+        for i in 0..5_000u32 {
+            match rng.gen_range(0..6) {
+                0 => {
+                    handles.push((slab.insert_front(i), i));
+                    pointers.insert_front(i);
+                }
+                1 => {
+                    handles.push((slab.push_back(i), i));
+                    pointers.push_back(i);
+                }
+                2 => {
+                    let val = slab.remove_tail();
+                    assert_eq!(val, pointers.remove_tail());
+                    handles.retain(|&(_, v)| Some(v) != val);
+                }
+                3 => {
+                    let val = slab.pop_front();
+                    assert_eq!(val, pointers.pop_front());
+                    handles.retain(|&(_, v)| Some(v) != val);
+                }
+                4 if !handles.is_empty() => {
+                    let (handle, val) = handles.swap_remove(rng.gen_range(0..handles.len()));
+                    assert_eq!(slab.remove(handle), Some(val));
+                    pointers.retain(|&v| v != val);
+                }
+                _ if !handles.is_empty() => {
+                    let (handle, val) = handles[rng.gen_range(0..handles.len())];
+                    slab.reinsert_front(handle);
+                    pointers.retain(|&v| v != val);
+                    pointers.insert_front(val);
+                }
+                _ => {}
+            }
+            assert_eq!(slab.len(), pointers.len());
+        }
+        assert!(slab.iter().eq(pointers.iter()));
+        assert_eq!(slab.front(), pointers.front());
+        assert_eq!(slab.back(), pointers.back());
+    }
+
+    #[test]
+    fn test_handles() {
+        assert_eq!(std::mem::size_of::<Handle>(), 4);
+        let mut list = LinkedList::new();
+        let a = list.push_back("a");
+        let b = list.push_back("b");
+        let c = list.insert_front("c");
+        assert_eq!(format!("{:?}", list), r#"["c", "a", "b"]"#);
+        *list.get_mut(b).unwrap() = "B";
+        list.reinsert_front(b);
+        assert_eq!(list.iter().copied().collect::<Vec<_>>(), ["B", "c", "a"]);
+        assert_eq!(list.remove(c), Some("c"));
+        assert_eq!(list.remove(c), None);
+        assert_eq!(list.get(c), None);
+        assert_eq!(list.get(a), Some(&"a"));
+        assert_eq!(list.iter().len(), 2);
+    }
+
+    #[test]
+    fn test_slots_reused() {
+        let mut list: LinkedList<u32> = (0..16).collect();
+        for i in 16..10_000 {
+            list.remove_tail();
+            list.insert_front(i);
+        }
+        assert_eq!(list.slots.len(), 16);
+        assert_eq!(list.len(), 16);
+        assert!(list.iter().copied().eq((9_984..10_000).rev()));
+
+        while list.pop_front().is_some() {}
+        assert!(list.is_empty());
+        assert_eq!((list.front(), list.back()), (None, None));
+        list.push_back(1);
+        assert_eq!(list.slots.len(), 16);
+        assert_eq!(list.front(), list.back());
+    }
+
+    #[test]
+    #[should_panic(expected = "has been removed")]
+    fn test_reinsert_removed() {
+        let mut list = LinkedList::new();
+        let handle = list.push_back(1);
+        list.remove(handle);
+        list.reinsert_front(handle);
+    }
+}
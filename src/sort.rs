@@ -0,0 +1,363 @@
+//! Classic sorting algorithms, for study and for benchmarking against the
+//! standard library's sorts (see `benches/sort.rs`).
+
+use crate::heap::sift_down_by;
+use std::cmp::Ordering;
+
+/// Slices this short are finished with insertion sort.
+const INSERTION_CUTOFF: usize = 16;
+
+/// Stable insertion sort: O(n^2) comparisons, fast for short or nearly
+/// sorted slices.
+fn insertion_sort_by<T, F>(v: &mut [T], compare: &mut F)
+where
+    F: FnMut(&T, &T) -> Ordering,
+{
+    for i in 1..v.len() {
+        let mut j = i;
+        while j > 0 && compare(&v[j - 1], &v[j]) == Ordering::Greater {
+            v.swap(j - 1, j);
+            j -= 1;
+        }
+    }
+}
+
+/// Top-down merge sort.
+///
+/// Stable. O(n log n) comparisons in every case, O(n) extra space for the
+/// clones of left halves being merged, and O(log n) recursion depth.
+pub fn merge_sort<T: Ord + Clone>(v: &mut [T]) {
+    merge_sort_by(v, T::cmp);
+}
+
+/// [`merge_sort`] with a comparator.
+pub fn merge_sort_by<T, F>(v: &mut [T], mut compare: F)
+where
+    T: Clone,
+    F: FnMut(&T, &T) -> Ordering,
+{
+    let mut buffer = Vec::with_capacity(v.len() / 2 + 1);
+    merge_sort_rec(v, &mut buffer, &mut compare);
+}
+
+fn merge_sort_rec<T, F>(v: &mut [T], buffer: &mut Vec<T>, compare: &mut F)
+where
+    T: Clone,
+    F: FnMut(&T, &T) -> Ordering,
+{
+    if v.len() <= INSERTION_CUTOFF {
+        insertion_sort_by(v, compare);
+        return;
+    }
+    let mid = v.len() / 2;
+    merge_sort_rec(&mut v[..mid], buffer, compare);
+    merge_sort_rec(&mut v[mid..], buffer, compare);
+    if compare(&v[mid - 1], &v[mid]) != Ordering::Greater {
+        return;
+    }
+
+    // Merge the buffered left half and the in-place right half front to
+    // back. The write position never overtakes the right half's read
+    // position, so nothing unread is overwritten.
+    buffer.clear();
+    buffer.extend_from_slice(&v[..mid]);
+    let (mut left, mut right, mut out) = (0, mid, 0);
+    while left < buffer.len() && right < v.len() {
+        // Ties take from the left, which is what keeps the sort stable.
+        if compare(&v[right], &buffer[left]) == Ordering::Less {
+            v.swap(out, right);
+            right += 1;
+        } else {
+            v[out] = buffer[left].clone();
+            left += 1;
+        }
+        out += 1;
+    }
+    for value in &buffer[left..] {
+        v[out] = value.clone();
+        out += 1;
+    }
+}
+
+/// Quicksort with a median-of-three pivot and insertion sort for short
+/// ranges.
+///
+/// Not stable. O(n log n) comparisons expected and O(n^2) in the worst
+/// case; elements equal to the pivot stop both partition scans, so
+/// all-equal input still splits evenly. Only the smaller side of each
+/// partition is recursed into and the larger one is looped on, so the
+/// recursion depth is at most log2 n whatever the input.
+pub fn quick_sort<T: Ord>(v: &mut [T]) {
+    let max_depth = usize::BITS - v.len().leading_zeros();
+    quick_sort_rec(v, max_depth);
+}
+
+fn quick_sort_rec<T: Ord>(mut v: &mut [T], depth_left: u32) {
+    debug_assert!(depth_left > 0 || v.len() <= 1, "recursion too deep");
+    while v.len() > INSERTION_CUTOFF {
+        let pivot = partition(v);
+        let (left, right) = std::mem::take(&mut v).split_at_mut(pivot);
+        let right = &mut right[1..];
+        if left.len() < right.len() {
+            quick_sort_rec(left, depth_left - 1);
+            v = right;
+        } else {
+            quick_sort_rec(right, depth_left - 1);
+            v = left;
+        }
+    }
+    insertion_sort_by(v, &mut T::cmp);
+}
+
+/// Partitions around the median of the first, middle and last elements and
+/// returns the pivot's final index. Needs at least three elements.
+fn partition<T: Ord>(v: &mut [T]) -> usize {
+    let (a, b, c) = (0, v.len() / 2, v.len() - 1);
+    // Order v[a] <= v[b] <= v[c], leaving the median in the middle.
+    if v[b] < v[a] {
+        v.swap(a, b);
+    }
+    if v[c] < v[b] {
+        v.swap(b, c);
+        if v[b] < v[a] {
+            v.swap(a, b);
+        }
+    }
+    v.swap(0, b);
+
+    // Hoare-style scans from both ends with the pivot parked at v[0].
+    let (mut i, mut j) = (0, v.len());
+    loop {
+        i += 1;
+        while i < v.len() && v[i] < v[0] {
+            i += 1;
+        }
+        j -= 1;
+        while v[0] < v[j] {
+            j -= 1;
+        }
+        if i >= j {
+            break;
+        }
+        v.swap(i, j);
+    }
+    v.swap(0, j);
+    j
+}
+
+/// Heapsort on a max-heap built in place.
+///
+/// Not stable. O(n log n) comparisons in every case and O(1) extra space.
+/// Uses the same sift-down as [`Heap`](crate::heap::Heap).
+pub fn heap_sort<T: Ord>(v: &mut [T]) {
+    let is_below = &mut |parent: &T, child: &T| parent < child;
+    let len = v.len();
+    for i in (0..len / 2).rev() {
+        sift_down_by(v, i, len, is_below);
+    }
+    for end in (1..len).rev() {
+        v.swap(0, end);
+        sift_down_by(v, 0, end, is_below);
+    }
+}
+
+/// LSD radix sort of `u64`s, one byte per pass.
+///
+/// Stable (which is moot for plain integers). O(n) per pass for at most
+/// eight passes, skipping passes where every value has the same byte, and
+/// O(n) extra space.
+pub fn radix_sort_u64(v: &mut [u64]) {
+    let mut buffer = vec![0; v.len()];
+    let mut from_buffer = false;
+    for shift in (0..64).step_by(8) {
+        let (src, dst): (&[u64], &mut [u64]) = if from_buffer {
+            (&buffer, v)
+        } else {
+            (v, &mut buffer)
+        };
+        if scatter(src, dst, |&x| x, shift) {
+            from_buffer = !from_buffer;
+        }
+    }
+    if from_buffer {
+        v.copy_from_slice(&buffer);
+    }
+}
+
+/// LSD radix sort by a `u64` key, one byte per pass.
+///
+/// Stable. Keys are computed once each; the passes sort `(key, index)`
+/// pairs and the slice is then permuted in place, so `T` needs no `Clone`.
+/// O(n) per pass for at most eight passes plus one permutation, and O(n)
+/// extra space.
+pub fn radix_sort_by_key<T, F>(v: &mut [T], mut key: F)
+where
+    F: FnMut(&T) -> u64,
+{
+    let mut pairs: Vec<(u64, usize)> = v.iter().map(&mut key).zip(0..).collect();
+    let mut buffer = vec![(0, 0); v.len()];
+    for shift in (0..64).step_by(8) {
+        if scatter(&pairs, &mut buffer, |&(key, _)| key, shift) {
+            std::mem::swap(&mut pairs, &mut buffer);
+        }
+    }
+
+    // `pairs[k].1` is the index of the element that belongs at `k`; turn
+    // that into where each element goes and follow the cycles.
+    let mut destination = vec![0; v.len()];
+    for (k, &(_, index)) in pairs.iter().enumerate() {
+        destination[index] = k;
+    }
+    for i in 0..v.len() {
+        while destination[i] != i {
+            let d = destination[i];
+            v.swap(i, d);
+            destination.swap(i, d);
+        }
+    }
+}
+
+/// One counting-sort pass on the byte at `shift`, from `src` into `dst`.
+/// Returns false, leaving `dst` untouched, if every byte is the same and
+/// the pass would not move anything.
+fn scatter<T: Copy>(src: &[T], dst: &mut [T], key: impl Fn(&T) -> u64, shift: u32) -> bool {
+    let byte = |x: &T| (key(x) >> shift) as u8 as usize;
+    let mut counts = [0usize; 256];
+    for x in src {
+        counts[byte(x)] += 1;
+    }
+    if counts.contains(&src.len()) {
+        return false;
+    }
+    let mut offset = 0;
+    for count in counts.iter_mut() {
+        let here = *count;
+        *count = offset;
+        offset += here;
+    }
+    for x in src {
+        let slot = &mut counts[byte(x)];
+        dst[*slot] = *x;
+        *slot += 1;
+    }
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        heap_sort, merge_sort, merge_sort_by, quick_sort, radix_sort_by_key, radix_sort_u64,
+    };
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::cell::Cell;
+    use std::cmp::Ordering;
+
+    type Sort = fn(&mut [u64]);
+
+    fn inputs(rng: &mut StdRng) -> Vec<Vec<u64>> {
+        let mut inputs = vec![vec![], vec![7], vec![2, 1], vec![5; 40]];
+        for len in [3, 16, 17, 100, 1_000] {
+            inputs.push((0..len).map(|_| rng.gen()).collect());
+            inputs.push((0..len).map(|_| rng.gen_range(0..4)).collect());
+            inputs.push((0..len).collect());
+            inputs.push((0..len).rev().collect());
+        }
+        inputs
+    }
+
+    #[test]
+    fn test_matches_std_sort() {
+        let mut rng = StdRng::seed_from_u64(232);
+        let sorts: [(&str, Sort); 5] = [
+            ("merge", merge_sort),
+            ("quick", quick_sort),
+            ("heap", heap_sort),
+            ("radix", radix_sort_u64),
+            ("radix_by_key", |v| radix_sort_by_key(v, |&x| x)),
+        ];
+        for _ in 0..20 {
+            for input in inputs(&mut rng) {
+                let mut expected = input.clone();
+                expected.sort();
+                for (name, sort) in sorts {
+                    let mut v = input.clone();
+                    sort(&mut v);
+                    assert_eq!(v, expected, "{name} on {input:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_merge_sort_is_stable() {
+        let mut rng = StdRng::seed_from_u64(2320);
+        for len in [10, 17, 500, 5_000] {
+            // Few distinct keys, tagged with their original position.
+            let mut v: Vec<(u8, usize)> = (0..len).map(|i| (rng.gen_range(0..5), i)).collect();
+            let mut expected = v.clone();
+            expected.sort_by_key(|&(key, _)| key);
+            merge_sort_by(&mut v, |a, b| a.0.cmp(&b.0));
+            assert_eq!(v, expected);
+        }
+    }
+
+    #[test]
+    fn test_radix_sort_by_key_is_stable() {
+        let mut rng = StdRng::seed_from_u64(2321);
+        let mut v: Vec<(u64, String)> = (0..2_000)
+            .map(|i| (rng.gen_range(0..50) << 40, i.to_string()))
+            .collect();
+        let mut expected = v.clone();
+        expected.sort_by_key(|(key, _)| *key);
+        radix_sort_by_key(&mut v, |(key, _)| *key);
+        assert_eq!(v, expected);
+    }
+
+    thread_local! {
+        static COMPARISONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// Counts every comparison made between values.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct Counted(u32);
+
+    impl PartialOrd for Counted {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for Counted {
+        fn cmp(&self, other: &Self) -> Ordering {
+            COMPARISONS.with(|c| c.set(c.get() + 1));
+            self.0.cmp(&other.0)
+        }
+    }
+
+    #[test]
+    fn test_quick_sort_adversarial_inputs() {
+        const LEN: u32 = 50_000;
+        let half = LEN / 2;
+        let cases: [(&str, Vec<u32>); 5] = [
+            ("all equal", vec![3; LEN as usize]),
+            ("sorted", (0..LEN).collect()),
+            ("reversed", (0..LEN).rev().collect()),
+            ("organ pipe", (0..half).chain((0..half).rev()).collect()),
+            ("two values", (0..LEN).map(|i| i % 2).collect()),
+        ];
+        // n log2 n is about 780k here; quadratic behavior would be ~10^9.
+        let budget = 4 * LEN as usize * (u32::BITS - LEN.leading_zeros()) as usize;
+        for (name, input) in cases {
+            let mut v: Vec<Counted> = input.iter().copied().map(Counted).collect();
+            COMPARISONS.with(|c| c.set(0));
+            // Recursion depth is checked by a debug assertion inside.
+            quick_sort(&mut v);
+            let comparisons = COMPARISONS.with(Cell::get);
+            assert!(comparisons < budget, "{name}: {comparisons} comparisons");
+            let mut expected = input;
+            expected.sort();
+            assert!(v.iter().map(|c| c.0).eq(expected), "{name}");
+        }
+    }
+}
@@ -0,0 +1,363 @@
+//! Bit vectors with constant-time rank and fast select, the base layer of
+//! succinct structures such as wavelet trees.
+
+use crate::bitvec::BitVec;
+
+const SUPERBLOCK_BITS: usize = 2048;
+const BLOCK_BITS: usize = 512;
+const WORDS_PER_BLOCK: usize = BLOCK_BITS / 64;
+const BLOCKS_PER_SUPERBLOCK: usize = SUPERBLOCK_BITS / BLOCK_BITS;
+/// Every this many ones (or zeros), the superblock holding it is recorded.
+const SELECT_SAMPLE: usize = 4096;
+
+/// An immutable bit vector answering `rank` (how many ones come before a
+/// position) and `select` (where the k-th one is) without scanning it.
+///
+/// Ranks come from a two-level directory: an absolute count of ones before
+/// every 2048-bit superblock, and a count relative to its superblock before
+/// every 512-bit block. A rank adds the two and popcounts at most eight
+/// words. The directory costs 6.25% on top of the bits; the select samples
+/// a fraction of a percent more.
+///
+/// A select starts from the superblock sampled for every 4096th one,
+/// binary searches the superblocks up to the next sample, then scans the
+/// four blocks, the eight words and finally the bits of one word.
+#[derive(Debug, Clone)]
+pub struct RankSelectBitVec {
+    words: Vec<u64>,
+    len: usize,
+    ones: usize,
+    /// Ones before each superblock, with one more than needed so a rank at
+    /// `len` has an entry.
+    superblocks: Vec<u64>,
+    /// Ones before each block since its superblock began.
+    blocks: Vec<u16>,
+    /// Superblock of every `SELECT_SAMPLE`-th one, then of every such zero.
+    select1_samples: Vec<u32>,
+    select0_samples: Vec<u32>,
+}
+
+impl RankSelectBitVec {
+    pub fn new(bits: &BitVec) -> Self {
+        let words = bits.words().to_vec();
+        let len = bits.len();
+        let block_count = len / BLOCK_BITS + 1;
+        let mut superblocks = Vec::with_capacity(block_count.div_ceil(BLOCKS_PER_SUPERBLOCK));
+        let mut blocks = Vec::with_capacity(block_count);
+        let mut ones = 0u64;
+        for block in 0..block_count {
+            if block % BLOCKS_PER_SUPERBLOCK == 0 {
+                superblocks.push(ones);
+            }
+            blocks.push((ones - superblocks[block / BLOCKS_PER_SUPERBLOCK]) as u16);
+            let first_word = block * WORDS_PER_BLOCK;
+            ones += words
+                .iter()
+                .skip(first_word)
+                .take(WORDS_PER_BLOCK)
+                .map(|w| w.count_ones() as u64)
+                .sum::<u64>();
+        }
+
+        let mut rs = Self {
+            words,
+            len,
+            ones: ones as usize,
+            superblocks,
+            blocks,
+            select1_samples: Vec::new(),
+            select0_samples: Vec::new(),
+        };
+        rs.select1_samples = rs.samples(true);
+        rs.select0_samples = rs.samples(false);
+        rs
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<bool> {
+        (index < self.len).then(|| self.words[index / 64] >> (index % 64) & 1 == 1)
+    }
+
+    pub fn count_ones(&self) -> usize {
+        self.ones
+    }
+
+    pub fn count_zeros(&self) -> usize {
+        self.len - self.ones
+    }
+
+    /// The number of ones in positions `0..index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index > len`.
+    pub fn rank1(&self, index: usize) -> usize {
+        assert!(
+            index <= self.len,
+            "rank index {index} out of bounds for length {}",
+            self.len
+        );
+        let block = index / BLOCK_BITS;
+        let mut rank =
+            self.superblocks[index / SUPERBLOCK_BITS] as usize + self.blocks[block] as usize;
+        let last_word = index / 64;
+        for word in &self.words[block * WORDS_PER_BLOCK..last_word] {
+            rank += word.count_ones() as usize;
+        }
+        if !index.is_multiple_of(64) {
+            let mask = (1u64 << (index % 64)) - 1;
+            rank += (self.words[last_word] & mask).count_ones() as usize;
+        }
+        rank
+    }
+
+    /// The number of zeros in positions `0..index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index > len`.
+    pub fn rank0(&self, index: usize) -> usize {
+        index - self.rank1(index)
+    }
+
+    /// The position of the one with `k` ones before it, counting from 0.
+    pub fn select1(&self, k: usize) -> Option<usize> {
+        (k < self.ones).then(|| self.select(k, true))
+    }
+
+    /// The position of the zero with `k` zeros before it, counting from 0.
+    pub fn select0(&self, k: usize) -> Option<usize> {
+        (k < self.count_zeros()).then(|| self.select(k, false))
+    }
+
+    /// How many ones, or zeros, come before superblock `s`.
+    fn superblock_rank(&self, s: usize, ones: bool) -> usize {
+        let rank = self.superblocks[s] as usize;
+        if ones {
+            rank
+        } else {
+            s * SUPERBLOCK_BITS - rank
+        }
+    }
+
+    fn samples(&self, ones: bool) -> Vec<u32> {
+        let total = if ones { self.ones } else { self.count_zeros() };
+        let last = self.superblocks.len() - 1;
+        (0..total)
+            .step_by(SELECT_SAMPLE)
+            .map(|k| self.find_superblock(0, last, k, ones) as u32)
+            .collect()
+    }
+
+    /// The last superblock in `lo..=hi` with at most `k` ones (or zeros)
+    /// before it, given that `lo` has.
+    fn find_superblock(&self, mut lo: usize, mut hi: usize, k: usize, ones: bool) -> usize {
+        while lo < hi {
+            let mid = lo + (hi - lo).div_ceil(2);
+            if self.superblock_rank(mid, ones) <= k {
+                lo = mid;
+            } else {
+                hi = mid - 1;
+            }
+        }
+        lo
+    }
+
+    /// Finds the `k`-th one (or zero), which must exist.
+    fn select(&self, k: usize, ones: bool) -> usize {
+        let samples = if ones {
+            &self.select1_samples
+        } else {
+            &self.select0_samples
+        };
+        // The superblock holding it lies between the samples on either side
+        // of `k`.
+        let sample = k / SELECT_SAMPLE;
+        let hi = samples
+            .get(sample + 1)
+            .map_or(self.superblocks.len() - 1, |&s| s as usize);
+        let superblock = self.find_superblock(samples[sample] as usize, hi, k, ones);
+        let mut k = k - self.superblock_rank(superblock, ones);
+
+        let first_block = superblock * BLOCKS_PER_SUPERBLOCK;
+        let last_block = (first_block + BLOCKS_PER_SUPERBLOCK).min(self.blocks.len());
+        let block = (first_block + 1..last_block)
+            .take_while(|&b| self.block_rank(b, ones) <= k)
+            .last()
+            .unwrap_or(first_block);
+        k -= self.block_rank(block, ones);
+
+        let mut word = block * WORDS_PER_BLOCK;
+        loop {
+            let bits = if ones {
+                self.words[word]
+            } else {
+                !self.words[word]
+            };
+            let count = bits.count_ones() as usize;
+            if k < count {
+                return word * 64 + select_in_word(bits, k as u32);
+            }
+            k -= count;
+            word += 1;
+        }
+    }
+
+    /// How many ones, or zeros, come before block `b` within its superblock.
+    fn block_rank(&self, b: usize, ones: bool) -> usize {
+        let rank = self.blocks[b] as usize;
+        if ones {
+            rank
+        } else {
+            (b % BLOCKS_PER_SUPERBLOCK) * BLOCK_BITS - rank
+        }
+    }
+}
+
+impl From<&BitVec> for RankSelectBitVec {
+    fn from(bits: &BitVec) -> Self {
+        Self::new(bits)
+    }
+}
+
+impl FromIterator<bool> for RankSelectBitVec {
+    fn from_iter<I: IntoIterator<Item = bool>>(iter: I) -> Self {
+        Self::new(&iter.into_iter().collect())
+    }
+}
+
+/// The position of the set bit with `k` set bits below it.
+fn select_in_word(mut word: u64, k: u32) -> usize {
+    for _ in 0..k {
+        word &= word - 1;
+    }
+    word.trailing_zeros() as usize
+}
+
+#[cfg(test)]
+mod test {
+    use super::{RankSelectBitVec, BLOCK_BITS, SUPERBLOCK_BITS};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn random_bits(len: usize, density: f64, rng: &mut StdRng) -> Vec<bool> {
+        (0..len).map(|_| rng.gen_bool(density)).collect()
+    }
+
+    fn check_exhaustive(bits: &[bool]) {
+        let rs: RankSelectBitVec = bits.iter().copied().collect();
+        let mut ones = Vec::new();
+        let mut zeros = Vec::new();
+        for (i, &bit) in bits.iter().enumerate() {
+            assert_eq!(rs.rank1(i), ones.len(), "rank1({i})");
+            assert_eq!(rs.rank0(i), zeros.len(), "rank0({i})");
+            assert_eq!(rs.get(i), Some(bit));
+            if bit {
+                ones.push(i);
+            } else {
+                zeros.push(i);
+            }
+        }
+        assert_eq!(rs.rank1(bits.len()), ones.len());
+        for (k, &pos) in ones.iter().enumerate() {
+            assert_eq!(rs.select1(k), Some(pos), "select1({k})");
+        }
+        for (k, &pos) in zeros.iter().enumerate() {
+            assert_eq!(rs.select0(k), Some(pos), "select0({k})");
+        }
+        assert_eq!(rs.select1(ones.len()), None);
+        assert_eq!(rs.select0(zeros.len()), None);
+    }
+
+    #[test]
+    fn test_exhaustive_random() {
+        let mut rng = StdRng::seed_from_u64(247);
+        for len in [
+            0, 1, 63, 64, 65, 511, 512, 513, 2047, 2048, 2049, 5_000, 9_000,
+        ] {
+            for density in [0.02, 0.5, 0.97] {
+                check_exhaustive(&random_bits(len, density, &mut rng));
+            }
+        }
+    }
+
+    #[test]
+    fn test_uniform_inputs() {
+        for len in [0, 100, SUPERBLOCK_BITS * 3, 10_000] {
+            check_exhaustive(&vec![false; len]);
+            check_exhaustive(&vec![true; len]);
+        }
+        let ones: RankSelectBitVec = std::iter::repeat_n(true, 10_000).collect();
+        assert_eq!(ones.select1(9_999), Some(9_999));
+        assert_eq!(ones.select0(0), None);
+    }
+
+    #[test]
+    fn test_boundaries() {
+        let mut rng = StdRng::seed_from_u64(2470);
+        let bits = random_bits(SUPERBLOCK_BITS * 5 + 7, 0.3, &mut rng);
+        let rs: RankSelectBitVec = bits.iter().copied().collect();
+        let naive = |i: usize| bits[..i].iter().filter(|&&b| b).count();
+        for boundary in (0..=bits.len()).step_by(64) {
+            for i in boundary.saturating_sub(1)..=(boundary + 1).min(bits.len()) {
+                assert_eq!(rs.rank1(i), naive(i), "rank1({i})");
+            }
+        }
+        // Ones placed exactly on the first bits of blocks and superblocks.
+        let mut sparse = vec![false; SUPERBLOCK_BITS * 4];
+        for i in (0..sparse.len()).step_by(BLOCK_BITS) {
+            sparse[i] = true;
+        }
+        let rs: RankSelectBitVec = sparse.iter().copied().collect();
+        for k in 0..rs.count_ones() {
+            assert_eq!(rs.select1(k), Some(k * BLOCK_BITS));
+            assert_eq!(rs.rank1(k * BLOCK_BITS), k);
+            assert_eq!(rs.rank1(k * BLOCK_BITS + 1), k + 1);
+        }
+    }
+
+    #[test]
+    fn test_large_spot_checks() {
+        let mut rng = StdRng::seed_from_u64(2471);
+        let len = 3_000_000;
+        let bits = random_bits(len, 0.1, &mut rng);
+        let rs: RankSelectBitVec = bits.iter().copied().collect();
+        let mut prefix = Vec::with_capacity(len + 1);
+        prefix.push(0);
+        for &bit in &bits {
+            prefix.push(prefix.last().unwrap() + bit as usize);
+        }
+        for _ in 0..2_000 {
+            let i = rng.gen_range(0..=len);
+            assert_eq!(rs.rank1(i), prefix[i]);
+            assert_eq!(rs.rank0(i), i - prefix[i]);
+
+            let k = rng.gen_range(0..rs.count_ones());
+            let pos = rs.select1(k).unwrap();
+            assert!(bits[pos] && prefix[pos] == k);
+            let k = rng.gen_range(0..rs.count_zeros());
+            let pos = rs.select0(k).unwrap();
+            assert!(!bits[pos] && pos - prefix[pos] == k);
+        }
+
+        let directory = rs.superblocks.len() * 64
+            + rs.blocks.len() * 16
+            + (rs.select1_samples.len() + rs.select0_samples.len()) * 32;
+        let overhead = directory as f64 / len as f64;
+        assert!(overhead < 0.075, "overhead {overhead}");
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_rank_past_end() {
+        let rs: RankSelectBitVec = [true, false].into_iter().collect();
+        rs.rank1(3);
+    }
+}
@@ -0,0 +1,338 @@
+use crate::clock::{Clock, SystemClock};
+use crate::heap::Heap;
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+struct TtlEntry<V> {
+    value: V,
+    /// `None` for a TTL too long to add to the insert time.
+    deadline: Option<Instant>,
+    seq: u64,
+}
+
+impl<V> TtlEntry<V> {
+    fn is_live(&self, now: Instant) -> bool {
+        self.deadline.is_none_or(|deadline| now < deadline)
+    }
+}
+
+/// A pending expiry. `seq` ties it to one particular insert, so a deadline
+/// left behind by an overwritten or removed entry is recognised as stale.
+struct Deadline<K> {
+    at: Instant,
+    seq: u64,
+    key: K,
+}
+
+impl<K> PartialEq for Deadline<K> {
+    fn eq(&self, other: &Self) -> bool {
+        (self.at, self.seq) == (other.at, other.seq)
+    }
+}
+
+impl<K> PartialOrd for Deadline<K> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        (self.at, self.seq).partial_cmp(&(other.at, other.seq))
+    }
+}
+
+/// A map whose entries expire after a per-insert TTL.
+///
+/// An entry inserted at `t` with TTL `d` is live for `t <= now < t + d` and
+/// expired from `t + d` on (the deadline itself is exclusive). Expired
+/// entries are never returned; their memory is reclaimed by
+/// [`purge_expired`](TtlMap::purge_expired), which `insert` also runs, at a
+/// cost proportional to the number of expired deadlines.
+pub struct TtlMap<K, V, C = SystemClock> {
+    map: HashMap<K, TtlEntry<V>>,
+    deadlines: Heap<Deadline<K>>,
+    clock: C,
+    seq: u64,
+}
+
+impl<K, V> TtlMap<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    pub fn new() -> Self {
+        Self::with_clock(SystemClock)
+    }
+}
+
+impl<K, V> Default for TtlMap<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, C> TtlMap<K, V, C>
+where
+    K: Hash + Eq + Clone,
+    C: Clock,
+{
+    pub fn with_clock(clock: C) -> Self {
+        Self {
+            map: HashMap::new(),
+            deadlines: Heap::new(),
+            clock,
+            seq: 0,
+        }
+    }
+
+    /// Inserts `value` under `key` for `ttl`, returning the previous value if
+    /// it had not yet expired. Re-inserting a key resets its deadline. A
+    /// `ttl` too long to add to the current time, such as `Duration::MAX`,
+    /// never expires.
+    pub fn insert(&mut self, key: K, value: V, ttl: Duration) -> Option<V> {
+        let now = self.clock.now();
+        self.purge_expired(now);
+
+        self.seq += 1;
+        let deadline = now.checked_add(ttl);
+        if let Some(at) = deadline {
+            self.deadlines.push(Deadline {
+                at,
+                seq: self.seq,
+                key: key.clone(),
+            });
+        }
+        let entry = TtlEntry {
+            value,
+            deadline,
+            seq: self.seq,
+        };
+        // The old entry's deadline stays in the heap and is skipped as stale.
+        self.map.insert(key, entry).map(|old| old.value)
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = self.clock.now();
+        self.map
+            .get(key)
+            .filter(|entry| entry.is_live(now))
+            .map(|entry| &entry.value)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = self.clock.now();
+        self.map
+            .get_mut(key)
+            .filter(|entry| entry.is_live(now))
+            .map(|entry| &mut entry.value)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Time left before `key` expires, if it is still live; `Duration::MAX`
+    /// if it never does.
+    pub fn ttl<Q>(&self, key: &Q) -> Option<Duration>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = self.clock.now();
+        self.map
+            .get(key)
+            .filter(|entry| entry.is_live(now))
+            .map(|entry| {
+                entry
+                    .deadline
+                    .map_or(Duration::MAX, |deadline| deadline - now)
+            })
+    }
+
+    /// Removes `key`, returning its value if it had not yet expired.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = self.clock.now();
+        self.map
+            .remove(key)
+            .filter(|entry| entry.is_live(now))
+            .map(|entry| entry.value)
+    }
+
+    /// Drops every entry whose deadline is at or before `now` and returns how
+    /// many were dropped.
+    pub fn purge_expired(&mut self, now: Instant) -> usize {
+        let mut purged = 0;
+        while self.deadlines.peek().is_some_and(|d| d.at <= now) {
+            let deadline = self.deadlines.pop().unwrap();
+            if self
+                .map
+                .get(&deadline.key)
+                .is_some_and(|entry| entry.seq == deadline.seq)
+            {
+                self.map.remove(&deadline.key);
+                purged += 1;
+            }
+        }
+        purged
+    }
+
+    /// Number of stored entries, including expired ones not yet purged.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.deadlines = Heap::new();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TtlMap;
+    use crate::clock::{Clock, MockClock};
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    struct DropCounter(Rc<Cell<usize>>);
+
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn test_deadline_is_exclusive() {
+        let clock = MockClock::new();
+        let mut map = TtlMap::with_clock(&clock);
+        map.insert("a", 1, SECOND);
+
+        clock.advance(SECOND - Duration::from_nanos(1));
+        assert_eq!(map.get("a"), Some(&1));
+        assert_eq!(map.ttl("a"), Some(Duration::from_nanos(1)));
+
+        clock.advance(Duration::from_nanos(1));
+        assert_eq!(map.get("a"), None);
+        assert!(!map.contains_key("a"));
+        assert_eq!(map.remove("a"), None);
+    }
+
+    #[test]
+    fn test_ttl_too_long_never_expires() {
+        let clock = MockClock::new();
+        let mut map = TtlMap::with_clock(&clock);
+        map.insert("forever", 1, Duration::MAX);
+        map.insert("soon", 2, SECOND);
+        assert_eq!(map.ttl("forever"), Some(Duration::MAX));
+
+        clock.advance(SECOND * 1_000_000);
+        assert_eq!(map.purge_expired(clock.now()), 1);
+        assert_eq!(map.get("forever"), Some(&1));
+        assert_eq!(map.remove("forever"), Some(1));
+    }
+
+    #[test]
+    fn test_reinsert_resets_deadline() {
+        let clock = MockClock::new();
+        let mut map = TtlMap::with_clock(&clock);
+        map.insert("a", 1, SECOND);
+        clock.advance(SECOND / 2);
+        assert_eq!(map.insert("a", 2, SECOND), Some(1));
+
+        // The first deadline passes but only refers to the overwritten entry.
+        clock.advance(SECOND / 2);
+        assert_eq!(map.purge_expired(clock.now()), 0);
+        assert_eq!(map.get("a"), Some(&2));
+        assert_eq!(map.deadlines.len(), 1);
+
+        clock.advance(SECOND / 2);
+        assert_eq!(map.get("a"), None);
+        assert_eq!(map.purge_expired(clock.now()), 1);
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn test_purge_only_expired() {
+        let clock = MockClock::new();
+        let mut map = TtlMap::with_clock(&clock);
+        for i in 0..10_u32 {
+            map.insert(i, i, SECOND * (i + 1));
+        }
+        clock.advance(SECOND * 4);
+        assert_eq!(map.purge_expired(clock.now()), 4);
+        assert_eq!(map.len(), 6);
+        for i in 0..10 {
+            assert_eq!(map.get(&i).is_some(), i >= 4);
+        }
+    }
+
+    #[test]
+    fn test_insert_reclaims_lazily() {
+        let clock = MockClock::new();
+        let mut map = TtlMap::with_clock(&clock);
+        map.insert(1, 1, SECOND);
+        map.insert(2, 2, SECOND * 3);
+        clock.advance(SECOND * 2);
+        assert_eq!(map.len(), 2);
+        map.insert(3, 3, SECOND);
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get(&1), None);
+    }
+
+    #[test]
+    fn test_removed_key_reinserted() {
+        let clock = MockClock::new();
+        let mut map = TtlMap::with_clock(&clock);
+        map.insert("a", 1, SECOND);
+        assert_eq!(map.remove("a"), Some(1));
+        map.insert("a", 2, SECOND * 2);
+
+        // The deadline of the removed entry must not take the new one with it.
+        clock.advance(SECOND);
+        assert_eq!(map.purge_expired(clock.now()), 0);
+        assert_eq!(map.get("a"), Some(&2));
+    }
+
+    #[test]
+    fn test_memory_reclaimed() {
+        let drops = Rc::new(Cell::new(0));
+        let clock = MockClock::new();
+        {
+            let mut map = TtlMap::with_clock(&clock);
+            for i in 0..100 {
+                map.insert(i, DropCounter(drops.clone()), SECOND * (i % 2 + 1));
+            }
+            clock.advance(SECOND);
+            assert_eq!(map.purge_expired(clock.now()), 50);
+            assert_eq!(drops.get(), 50);
+
+            // Overwriting drops the old value immediately.
+            map.insert(1, DropCounter(drops.clone()), SECOND);
+            assert_eq!(drops.get(), 51);
+        }
+        assert_eq!(drops.get(), 101);
+    }
+}
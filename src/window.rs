@@ -0,0 +1,442 @@
+use crate::clock::{Clock, SystemClock};
+use std::marker::PhantomData;
+use std::ops::Add;
+use std::time::{Duration, Instant};
+
+/// How samples are folded into a bucket and how buckets are recombined.
+///
+/// `State` is what a bucket stores, so it must summarize any number of
+/// samples in constant space.
+pub trait Aggregate<T> {
+    type State: Clone;
+    type Output;
+
+    fn empty() -> Self::State;
+    fn add(state: &mut Self::State, value: T);
+    fn combine(state: &mut Self::State, other: &Self::State);
+    fn finish(state: &Self::State) -> Self::Output;
+}
+
+/// Sum of the samples; zero for an empty window.
+#[derive(Debug)]
+pub struct Sum;
+
+impl<T: Add<Output = T> + Default + Copy> Aggregate<T> for Sum {
+    type State = T;
+    type Output = T;
+
+    fn empty() -> T {
+        T::default()
+    }
+
+    fn add(state: &mut T, value: T) {
+        *state = *state + value;
+    }
+
+    fn combine(state: &mut T, other: &T) {
+        *state = *state + *other;
+    }
+
+    fn finish(state: &T) -> T {
+        *state
+    }
+}
+
+/// Number of samples.
+#[derive(Debug)]
+pub struct Count;
+
+impl<T> Aggregate<T> for Count {
+    type State = u64;
+    type Output = u64;
+
+    fn empty() -> u64 {
+        0
+    }
+
+    fn add(state: &mut u64, _: T) {
+        *state += 1;
+    }
+
+    fn combine(state: &mut u64, other: &u64) {
+        *state += other;
+    }
+
+    fn finish(state: &u64) -> u64 {
+        *state
+    }
+}
+
+/// Arithmetic mean of the samples; `None` for an empty window.
+#[derive(Debug)]
+pub struct Mean;
+
+impl<T: Into<f64>> Aggregate<T> for Mean {
+    type State = (f64, u64);
+    type Output = Option<f64>;
+
+    fn empty() -> (f64, u64) {
+        (0.0, 0)
+    }
+
+    fn add(state: &mut (f64, u64), value: T) {
+        state.0 += value.into();
+        state.1 += 1;
+    }
+
+    fn combine(state: &mut (f64, u64), other: &(f64, u64)) {
+        state.0 += other.0;
+        state.1 += other.1;
+    }
+
+    fn finish(&(sum, count): &(f64, u64)) -> Option<f64> {
+        (count > 0).then(|| sum / count as f64)
+    }
+}
+
+/// Largest sample; `None` for an empty window.
+#[derive(Debug)]
+pub struct Max;
+
+impl<T: Ord + Clone> Aggregate<T> for Max {
+    type State = Option<T>;
+    type Output = Option<T>;
+
+    fn empty() -> Option<T> {
+        None
+    }
+
+    fn add(state: &mut Option<T>, value: T) {
+        if state.as_ref().is_none_or(|max| value > *max) {
+            *state = Some(value);
+        }
+    }
+
+    fn combine(state: &mut Option<T>, other: &Option<T>) {
+        if let Some(value) = other {
+            Self::add(state, value.clone());
+        }
+    }
+
+    fn finish(state: &Option<T>) -> Option<T> {
+        state.clone()
+    }
+}
+
+/// Smallest sample; `None` for an empty window.
+#[derive(Debug)]
+pub struct Min;
+
+impl<T: Ord + Clone> Aggregate<T> for Min {
+    type State = Option<T>;
+    type Output = Option<T>;
+
+    fn empty() -> Option<T> {
+        None
+    }
+
+    fn add(state: &mut Option<T>, value: T) {
+        if state.as_ref().is_none_or(|min| value < *min) {
+            *state = Some(value);
+        }
+    }
+
+    fn combine(state: &mut Option<T>, other: &Option<T>) {
+        if let Some(value) = other {
+            Self::add(state, value.clone());
+        }
+    }
+
+    fn finish(state: &Option<T>) -> Option<T> {
+        state.clone()
+    }
+}
+
+struct Bucket<S> {
+    /// Index of the time slice this bucket currently holds, counted in
+    /// bucket widths since the aggregator's origin.
+    slice: u64,
+    state: S,
+}
+
+/// Aggregates a stream of timestamped samples over a trailing time window.
+///
+/// Time is cut into slices of `bucket_width`, and a ring keeps one
+/// [`Aggregate::State`] for each recent slice, enough to cover windows of up
+/// to `bucket_count` widths. Memory therefore does not grow with the number
+/// of samples. A bucket that still holds an older slice is reset the next
+/// time a sample lands in it, and ignored by queries, so nothing has to run
+/// as time passes.
+///
+/// Answers are exact up to one bucket: a query covers whole buckets, so it
+/// may include up to one `bucket_width` of samples older than the window.
+pub struct TimeWindowAggregator<T, A: Aggregate<T>, C = SystemClock> {
+    buckets: Vec<Bucket<A::State>>,
+    bucket_width: Duration,
+    origin: Instant,
+    clock: C,
+    _marker: PhantomData<fn(T)>,
+}
+
+impl<T, A: Aggregate<T>> TimeWindowAggregator<T, A> {
+    pub fn new(bucket_width: Duration, bucket_count: usize) -> Self {
+        Self::with_clock(bucket_width, bucket_count, SystemClock)
+    }
+}
+
+impl<T, A: Aggregate<T>, C: Clock> TimeWindowAggregator<T, A, C> {
+    /// Samples older than the clock's current time when this is called are
+    /// counted as recorded at that time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bucket_width` or `bucket_count` is zero.
+    pub fn with_clock(bucket_width: Duration, bucket_count: usize, clock: C) -> Self {
+        assert!(!bucket_width.is_zero(), "bucket width must be non-zero");
+        assert!(bucket_count > 0, "bucket count must be non-zero");
+        // One more than asked for, since the current bucket is only partly
+        // elapsed and a full window reaches into the bucket before the last.
+        let buckets = (0..=bucket_count)
+            .map(|_| Bucket {
+                // No slice maps here until time reaches it.
+                slice: u64::MAX,
+                state: A::empty(),
+            })
+            .collect();
+        Self {
+            buckets,
+            bucket_width,
+            origin: clock.now(),
+            clock,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The longest window a query can cover.
+    pub fn span(&self) -> Duration {
+        self.bucket_width * (self.buckets.len() - 1) as u32
+    }
+
+    /// Adds a sample taken at `now`. Timestamps may arrive out of order; a
+    /// sample whose bucket has already been reused for a newer slice is
+    /// dropped and `false` is returned.
+    pub fn record(&mut self, now: Instant, value: T) -> bool {
+        let slice = self.slice(now);
+        let len = self.buckets.len() as u64;
+        let bucket = &mut self.buckets[(slice % len) as usize];
+        if bucket.slice != slice {
+            if bucket.slice != u64::MAX && bucket.slice > slice {
+                return false;
+            }
+            bucket.slice = slice;
+            bucket.state = A::empty();
+        }
+        A::add(&mut bucket.state, value);
+        true
+    }
+
+    /// [`record`](Self::record) at the clock's current time.
+    pub fn record_now(&mut self, value: T) -> bool {
+        let now = self.clock.now();
+        self.record(now, value)
+    }
+
+    /// The aggregate of the samples in the `window` before `now`, widened
+    /// to whole buckets and capped at [`span`](Self::span).
+    pub fn aggregate_over(&self, now: Instant, window: Duration) -> A::Output {
+        let current = self.slice(now);
+        // The slice holding `now - window`, unless that is past the ring.
+        let start = now.checked_sub(window).unwrap_or(self.origin);
+        let oldest = self
+            .slice(start)
+            .max(current.saturating_sub(self.buckets.len() as u64 - 1));
+        let mut state = A::empty();
+        for bucket in &self.buckets {
+            if (oldest..=current).contains(&bucket.slice) {
+                A::combine(&mut state, &bucket.state);
+            }
+        }
+        A::finish(&state)
+    }
+
+    /// [`aggregate_over`](Self::aggregate_over) at the clock's current time.
+    pub fn aggregate(&self, window: Duration) -> A::Output {
+        self.aggregate_over(self.clock.now(), window)
+    }
+
+    fn slice(&self, now: Instant) -> u64 {
+        let elapsed = now.saturating_duration_since(self.origin);
+        (elapsed.as_nanos() / self.bucket_width.as_nanos()) as u64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Count, Max, Mean, Min, Sum, TimeWindowAggregator};
+    use crate::clock::{Clock, MockClock};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::time::{Duration, Instant};
+
+    const SECOND: Duration = Duration::from_secs(1);
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn test_aggregates() {
+        let clock = MockClock::new();
+        let mut sum = TimeWindowAggregator::<u64, Sum, _>::with_clock(SECOND, 60, &clock);
+        let mut count = TimeWindowAggregator::<u64, Count, _>::with_clock(SECOND, 60, &clock);
+        let mut mean = TimeWindowAggregator::<u32, Mean, _>::with_clock(SECOND, 60, &clock);
+        let mut max = TimeWindowAggregator::<u64, Max, _>::with_clock(SECOND, 60, &clock);
+        let mut min = TimeWindowAggregator::<u64, Min, _>::with_clock(SECOND, 60, &clock);
+        assert_eq!(mean.aggregate(MINUTE), None);
+        assert_eq!(max.aggregate(MINUTE), None);
+
+        for v in [4, 8, 1, 7] {
+            sum.record_now(v);
+            count.record_now(v);
+            mean.record_now(v as u32);
+            max.record_now(v);
+            min.record_now(v);
+            clock.advance(SECOND * 10);
+        }
+        // Samples at 0s, 10s, 20s and 30s; now is 40s.
+        assert_eq!(sum.aggregate(MINUTE), 20);
+        assert_eq!(count.aggregate(MINUTE), 4);
+        assert_eq!(mean.aggregate(MINUTE), Some(5.0));
+        assert_eq!(max.aggregate(MINUTE), Some(8));
+        assert_eq!(min.aggregate(MINUTE), Some(1));
+
+        // The last 15 seconds hold only the 30s sample.
+        assert_eq!(sum.aggregate(SECOND * 15), 7);
+        assert_eq!(max.aggregate(SECOND * 15), Some(7));
+        assert_eq!(min.aggregate(SECOND * 25), Some(1));
+
+        // At 75s the 0s and 10s samples have left the minute.
+        clock.advance(SECOND * 35);
+        assert_eq!(sum.aggregate(MINUTE), 8);
+        assert_eq!(max.aggregate(MINUTE), Some(7));
+        assert_eq!(mean.aggregate(MINUTE), Some(4.0));
+    }
+
+    #[test]
+    fn test_matches_naive_within_one_bucket() {
+        let mut rng = StdRng::seed_from_u64(230);
+        let clock = MockClock::new();
+        let mut sum = TimeWindowAggregator::<u64, Sum, _>::with_clock(SECOND, 60, &clock);
+        let mut max = TimeWindowAggregator::<u64, Max, _>::with_clock(SECOND, 60, &clock);
+        let mut samples: Vec<(Instant, u64)> = Vec::new();
+
+        // Samples in `(now - window, now]`, treating the whole history as
+        // inside when the window reaches back past the origin.
+        let within = |samples: &[(Instant, u64)], now: Instant, window: Duration| {
+            let values = samples
+                .iter()
+                .filter(|&&(at, _)| at <= now && now.checked_duration_since(at).unwrap() < window);
+            values.map(|&(_, v)| v).collect::<Vec<_>>()
+        };
+
+        for _ in 0..5_000 {
+            clock.advance(Duration::from_millis(rng.gen_range(0..400)));
+            let v = rng.gen_range(0..1_000);
+            let now = clock.now();
+            sum.record(now, v);
+            max.record(now, v);
+            samples.push((now, v));
+
+            let window = Duration::from_millis(rng.gen_range(1..=60_000));
+            let narrow: u64 = within(&samples, now, window).into_iter().sum();
+            let wide: u64 = within(&samples, now, window + SECOND).into_iter().sum();
+            let got = sum.aggregate(window);
+            assert!(narrow <= got && got <= wide, "{narrow} <= {got} <= {wide}");
+
+            let narrow = within(&samples, now, window).into_iter().max();
+            let wide = within(&samples, now, window + SECOND).into_iter().max();
+            let got = max.aggregate(window);
+            assert!(
+                narrow <= got && got <= wide,
+                "{narrow:?} <= {got:?} <= {wide:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_jump_past_window_clears() {
+        let clock = MockClock::new();
+        let mut sum = TimeWindowAggregator::<u64, Sum, _>::with_clock(SECOND, 60, &clock);
+        let mut max = TimeWindowAggregator::<u64, Max, _>::with_clock(SECOND, 60, &clock);
+        for _ in 0..120 {
+            sum.record_now(5);
+            max.record_now(5);
+            clock.advance(SECOND / 2);
+        }
+        assert_eq!(sum.aggregate(MINUTE), 600);
+
+        clock.advance(MINUTE + SECOND);
+        assert_eq!(sum.aggregate(MINUTE), 0);
+        assert_eq!(max.aggregate(MINUTE), None);
+
+        // Stale buckets are reset, not added to, when reused.
+        sum.record_now(3);
+        assert_eq!(sum.aggregate(MINUTE), 3);
+        // A jump of several whole spans lands on the same ring position.
+        clock.advance(MINUTE * 5);
+        sum.record_now(4);
+        assert_eq!(sum.aggregate(MINUTE), 4);
+    }
+
+    #[test]
+    fn test_out_of_order_samples() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        let mut sum = TimeWindowAggregator::<u64, Sum, _>::with_clock(SECOND, 10, &clock);
+
+        clock.advance(SECOND * 5);
+        assert!(sum.record_now(1));
+        // Late samples still inside the ring land in their own buckets.
+        assert!(sum.record(start + SECOND * 3, 10));
+        assert!(sum.record(start + SECOND, 100));
+        assert_eq!(sum.aggregate(SECOND * 10), 111);
+        assert_eq!(sum.aggregate(SECOND * 3), 11);
+
+        // After the bucket for 1s is reused for 12s, older samples are
+        // dropped rather than mixed into the newer slice.
+        clock.advance(SECOND * 7);
+        assert!(sum.record_now(1_000));
+        assert!(!sum.record(start + SECOND, 100));
+        assert_eq!(sum.aggregate(SECOND * 10), 1_011);
+
+        // Samples from before the origin count as taken at the origin, which
+        // is already outside the window.
+        assert!(sum.record(start - SECOND, 1));
+        assert_eq!(sum.aggregate(SECOND * 10), 1_011);
+    }
+
+    #[test]
+    fn test_counters_never_negative() {
+        let mut rng = StdRng::seed_from_u64(2300);
+        let clock = MockClock::new();
+        let mut sum = TimeWindowAggregator::<i64, Sum, _>::with_clock(SECOND, 30, &clock);
+        let mut count = TimeWindowAggregator::<i64, Count, _>::with_clock(SECOND, 30, &clock);
+        for _ in 0..10_000 {
+            if rng.gen_bool(0.01) {
+                clock.advance(SECOND * rng.gen_range(10..100));
+            } else {
+                clock.advance(Duration::from_millis(rng.gen_range(0..200)));
+            }
+            let v = rng.gen_range(0..10);
+            sum.record_now(v);
+            count.record_now(v);
+            let window = SECOND * rng.gen_range(1..40);
+            let total = sum.aggregate(window);
+            assert!(total >= 0);
+            assert!(total <= 9 * count.aggregate(window) as i64);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "bucket count must be non-zero")]
+    fn test_zero_buckets() {
+        TimeWindowAggregator::<u64, Sum>::new(SECOND, 0);
+    }
+}
@@ -0,0 +1,269 @@
+//! Xor filters: static approximate sets built once from a complete list of
+//! key hashes, smaller and faster to query than a Bloom filter.
+//!
+//! Each key maps to three slots, one in each third of a fingerprint array,
+//! and the fingerprints are chosen so the three slots xor to the key's own
+//! fingerprint. The 8-bit filter takes about 9.84 bits per key for a false
+//! positive rate of 1/256; the 16-bit one doubles that for 1/65536.
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Seeds tried before construction gives up. Each attempt fails with a
+/// small constant probability, so running out means the input is broken.
+const MAX_ATTEMPTS: u32 = 64;
+
+/// The unsigned integer type stored per slot.
+pub trait Fingerprint: Copy + Default + Eq + std::ops::BitXor<Output = Self> {
+    fn from_hash(hash: u64) -> Self;
+}
+
+impl Fingerprint for u8 {
+    fn from_hash(hash: u64) -> Self {
+        (hash ^ (hash >> 32)) as u8
+    }
+}
+
+impl Fingerprint for u16 {
+    fn from_hash(hash: u64) -> Self {
+        (hash ^ (hash >> 32)) as u16
+    }
+}
+
+/// Construction found no seed for which every key could be peeled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConstructionError {
+    pub attempts: u32,
+}
+
+impl fmt::Display for ConstructionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "xor filter construction failed after {} seeds",
+            self.attempts
+        )
+    }
+}
+
+impl std::error::Error for ConstructionError {}
+
+/// A static set of `u64` key hashes answering "definitely absent" or
+/// "maybe present".
+///
+/// Keys are remixed with the filter's seed, so any 64-bit hash will do,
+/// but two items whose hashes collide are indistinguishable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct XorFilter<F> {
+    seed: u64,
+    block_length: u32,
+    fingerprints: Vec<F>,
+}
+
+pub type XorFilter8 = XorFilter<u8>;
+pub type XorFilter16 = XorFilter<u16>;
+
+impl<F: Fingerprint> XorFilter<F> {
+    /// Builds a filter containing every hash in `keys`. Duplicates are
+    /// allowed and are stored once.
+    ///
+    /// Returns an error only if no seed out of a fixed number of attempts
+    /// produced a peelable layout, which in practice does not happen.
+    pub fn new(keys: &[u64]) -> Result<Self, ConstructionError> {
+        let mut keys = keys.to_vec();
+        keys.sort_unstable();
+        keys.dedup();
+
+        let capacity = 32 + (1.23 * keys.len() as f64).ceil() as usize;
+        let block_length = capacity / 3;
+        let mut seed_state = 0x726f_6368_6576_7270;
+
+        for _ in 0..MAX_ATTEMPTS {
+            let seed = splitmix64(&mut seed_state);
+            let layout = Layout {
+                seed,
+                block_length: block_length as u32,
+            };
+            if let Some(order) = layout.peel(&keys) {
+                let mut fingerprints = vec![F::default(); block_length * 3];
+                // Assign in reverse peeling order: each key's own slot is
+                // still zero, and its other two are final.
+                for &(hash, slot) in order.iter().rev() {
+                    let [a, b, c] = layout.slots(hash);
+                    fingerprints[slot] =
+                        F::from_hash(hash) ^ fingerprints[a] ^ fingerprints[b] ^ fingerprints[c];
+                }
+                return Ok(Self {
+                    seed,
+                    block_length: block_length as u32,
+                    fingerprints,
+                });
+            }
+        }
+        Err(ConstructionError {
+            attempts: MAX_ATTEMPTS,
+        })
+    }
+
+    /// False only if `key` was not in the construction set.
+    pub fn contains(&self, key: u64) -> bool {
+        let layout = Layout {
+            seed: self.seed,
+            block_length: self.block_length,
+        };
+        let hash = layout.hash(key);
+        let [a, b, c] = layout.slots(hash);
+        F::from_hash(hash) == self.fingerprints[a] ^ self.fingerprints[b] ^ self.fingerprints[c]
+    }
+
+    /// Size of the fingerprint array in bits.
+    pub fn num_bits(&self) -> usize {
+        self.fingerprints.len() * std::mem::size_of::<F>() * 8
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Layout {
+    seed: u64,
+    block_length: u32,
+}
+
+impl Layout {
+    fn hash(&self, key: u64) -> u64 {
+        mix(key.wrapping_add(self.seed))
+    }
+
+    fn slots(&self, hash: u64) -> [usize; 3] {
+        let block = self.block_length as usize;
+        [
+            reduce(hash as u32, self.block_length),
+            reduce(hash.rotate_left(21) as u32, self.block_length) + block,
+            reduce(hash.rotate_left(42) as u32, self.block_length) + 2 * block,
+        ]
+    }
+
+    /// Repeatedly removes a key that is alone in one of its slots. Returns
+    /// the removed `(hash, slot)` pairs in order if every key came off.
+    fn peel(&self, keys: &[u64]) -> Option<Vec<(u64, usize)>> {
+        let len = self.block_length as usize * 3;
+        // Per slot: how many keys map there, and the xor of their hashes,
+        // which is the remaining key's hash once the count drops to one.
+        let mut counts = vec![0u32; len];
+        let mut xors = vec![0u64; len];
+        for &key in keys {
+            let hash = self.hash(key);
+            for slot in self.slots(hash) {
+                counts[slot] += 1;
+                xors[slot] ^= hash;
+            }
+        }
+
+        let mut alone: Vec<usize> = (0..len).filter(|&s| counts[s] == 1).collect();
+        let mut order = Vec::with_capacity(keys.len());
+        while let Some(slot) = alone.pop() {
+            if counts[slot] != 1 {
+                continue;
+            }
+            let hash = xors[slot];
+            order.push((hash, slot));
+            for other in self.slots(hash) {
+                counts[other] -= 1;
+                xors[other] ^= hash;
+                if counts[other] == 1 {
+                    alone.push(other);
+                }
+            }
+        }
+        (order.len() == keys.len()).then_some(order)
+    }
+}
+
+/// Maps `x` uniformly onto `0..n` without a division.
+fn reduce(x: u32, n: u32) -> usize {
+    ((x as u64 * n as u64) >> 32) as usize
+}
+
+/// The splitmix64 finalizer.
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    mix(*state)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{mix, XorFilter16, XorFilter8};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn keys(n: usize, seed: u64) -> Vec<u64> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..n).map(|_| rng.gen()).collect()
+    }
+
+    #[test]
+    fn test_no_false_negatives() {
+        for n in [0, 1, 2, 10, 1_000, 50_000] {
+            let keys = keys(n, 238);
+            let filter8 = XorFilter8::new(&keys).unwrap();
+            let filter16 = XorFilter16::new(&keys).unwrap();
+            assert!(keys.iter().all(|&k| filter8.contains(k)), "n = {n}");
+            assert!(keys.iter().all(|&k| filter16.contains(k)), "n = {n}");
+        }
+    }
+
+    #[test]
+    fn test_false_positive_rate() {
+        let n = 100_000;
+        let keys: Vec<u64> = (0..n as u64).map(mix).collect();
+        let queries = (n as u64..n as u64 + 1_000_000).map(mix);
+        let filter = XorFilter8::new(&keys).unwrap();
+        let false_positives = queries.filter(|&q| filter.contains(q)).count();
+        let rate = false_positives as f64 / 1_000_000.0;
+        assert!((rate - 1.0 / 256.0).abs() < 0.0008, "rate {rate}");
+
+        let bits_per_key = filter.num_bits() as f64 / n as f64;
+        assert!(bits_per_key < 9.9, "{bits_per_key} bits per key");
+
+        let filter = XorFilter16::new(&keys).unwrap();
+        let queries = (n as u64..n as u64 + 1_000_000).map(mix);
+        assert!(queries.filter(|&q| filter.contains(q)).count() < 60);
+    }
+
+    #[test]
+    fn test_duplicate_keys() {
+        let mut keys = keys(5_000, 2380);
+        keys.extend_from_within(..1_000);
+        keys.extend([7, 7, 7]);
+        let filter = XorFilter8::new(&keys).unwrap();
+        assert!(keys.iter().all(|&k| filter.contains(k)));
+        // Sized for the distinct keys only.
+        let distinct = XorFilter8::new(
+            &keys[..5_000]
+                .iter()
+                .chain(&[7])
+                .copied()
+                .collect::<Vec<_>>(),
+        )
+        .unwrap();
+        assert_eq!(filter.num_bits(), distinct.num_bits());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let keys = keys(2_000, 2381);
+        let filter = XorFilter16::new(&keys).unwrap();
+        let json = serde_json::to_string(&filter).unwrap();
+        let restored: XorFilter16 = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, filter);
+        assert!(keys.iter().all(|&k| restored.contains(k)));
+    }
+}